@@ -0,0 +1,281 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{Method, StatusCode, Uri, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body::{Frame, SizeHint};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use crate::AppState;
+
+// One log line per request with ACCESS_LOG=1, written once the response has been sent in full
+// (for a chat stream, when the stream ends):
+//   [request id] POST /api/chat/stream 200 5321ms in=812B out=20480B
+// "aborted" is added when the client went away before the end. ACCESS_LOG_BODIES=1 also logs
+// JSON request bodies of up to 64 KiB.
+//
+// LOG_REDACT_FIELDS lists the JSON keys and query parameters whose values are replaced by
+// their length, so requests can be debugged without private chats ending up in the logs. The
+// default covers messages, prompts, search queries, tool arguments and passwords; "none" logs
+// everything.
+
+const MAX_LOGGED_BODY: usize = 64 * 1024;
+
+const DEFAULT_REDACTED: &[&str] = &[
+    "message",
+    "messages",
+    "history",
+    "content",
+    "text",
+    "input",
+    "prompt",
+    "system_prompt",
+    "query",
+    "q",
+    "arguments",
+    "audio",
+    "image",
+    "password",
+];
+
+#[derive(Clone)]
+pub struct AccessLog {
+    bodies: bool,
+    redacted: Vec<String>,
+}
+
+impl AccessLog {
+    pub fn from_env() -> Option<Self> {
+        if !crate::env_flag("ACCESS_LOG") {
+            return None;
+        }
+        let redacted = match crate::config::var("LOG_REDACT_FIELDS") {
+            Ok(v) if v.trim().eq_ignore_ascii_case("none") => Vec::new(),
+            Ok(v) if !v.trim().is_empty() => v
+                .split(',')
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+                .collect(),
+            _ => DEFAULT_REDACTED.iter().map(|f| f.to_string()).collect(),
+        };
+        Some(Self {
+            bodies: crate::env_flag("ACCESS_LOG_BODIES"),
+            redacted,
+        })
+    }
+
+    fn redacts(&self, key: &str) -> bool {
+        self.redacted.iter().any(|f| f.eq_ignore_ascii_case(key))
+    }
+
+    fn target(&self, uri: &Uri) -> String {
+        let Some(query) = uri.query() else {
+            return uri.path().to_string();
+        };
+        let query: Vec<String> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, value)) if self.redacts(key) => {
+                    format!("{key}=[{} chars]", value.len())
+                }
+                _ => pair.to_string(),
+            })
+            .collect();
+        format!("{}?{}", uri.path(), query.join("&"))
+    }
+
+    fn redact(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self.redacts(key) {
+                        let len = match &*value {
+                            serde_json::Value::String(s) => s.chars().count(),
+                            other => other.to_string().len(),
+                        };
+                        *value = format!("[{len} chars]").into();
+                    } else {
+                        self.redact(value);
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|v| self.redact(v)),
+            _ => {}
+        }
+    }
+}
+
+pub async fn log_requests(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(config) = state.access_log.clone() else {
+        return next.run(req).await;
+    };
+    let started = Instant::now();
+    let method = req.method().clone();
+    let target = config.target(req.uri());
+    let received = Arc::new(AtomicU64::new(0));
+
+    let (parts, body) = req.into_parts();
+    let is_json = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/json"));
+    let small = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<usize>().ok())
+        .is_some_and(|len| len <= MAX_LOGGED_BODY);
+    let body = if config.bodies && is_json && small {
+        let Ok(bytes) = axum::body::to_bytes(body, MAX_LOGGED_BODY).await else {
+            return (StatusCode::BAD_REQUEST, "could not read request body").into_response();
+        };
+        received.store(bytes.len() as u64, Ordering::Relaxed);
+        let logged = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+            Ok(mut json) => {
+                config.redact(&mut json);
+                json.to_string()
+            }
+            Err(_) => format!("[{} bytes, not JSON]", bytes.len()),
+        };
+        log!("{method} {target} body: {logged}");
+        Body::from(bytes)
+    } else {
+        Body::new(CountedBody {
+            inner: body,
+            bytes: Arc::clone(&received),
+        })
+    };
+
+    let response = next.run(Request::from_parts(parts, body)).await;
+    let (parts, body) = response.into_parts();
+    let body = LoggedBody {
+        inner: body,
+        sent: 0,
+        finished: false,
+        entry: Entry {
+            request_id: crate::request_id::current(),
+            method,
+            target,
+            status: parts.status,
+            started,
+            received,
+        },
+    };
+    Response::from_parts(parts, Body::new(body))
+}
+
+// Counts the request body's bytes as the handler reads them.
+struct CountedBody {
+    inner: Body,
+    bytes: Arc<AtomicU64>,
+}
+
+impl http_body::Body for CountedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(data) = frame.data_ref()
+        {
+            self.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+struct Entry {
+    request_id: Option<String>,
+    method: Method,
+    target: String,
+    status: StatusCode,
+    started: Instant,
+    received: Arc<AtomicU64>,
+}
+
+// The response body, which writes the log line when it is dropped: after the last frame, or
+// earlier if the client disconnects.
+struct LoggedBody {
+    inner: Body,
+    sent: u64,
+    finished: bool,
+    entry: Entry,
+}
+
+impl http_body::Body for LoggedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.sent += data.len() as u64;
+                }
+            }
+            Poll::Ready(None) => self.finished = true,
+            _ => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        let entry = &self.entry;
+        let aborted = if self.finished || http_body::Body::is_end_stream(&self.inner) {
+            ""
+        } else {
+            " aborted"
+        };
+        // Dropped outside the request's task, so the id is added here rather than by log!.
+        let id = entry
+            .request_id
+            .as_deref()
+            .map(|id| format!("[{id}] "))
+            .unwrap_or_default();
+        eprintln!(
+            "{id}{} {} {} {}ms in={}B out={}B{aborted}",
+            entry.method,
+            entry.target,
+            entry.status.as_u16(),
+            entry.started.elapsed().as_millis(),
+            entry.received.load(Ordering::Relaxed),
+            self.sent,
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use axum::{
+    Json, Router,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::audit;
+use crate::config;
+use crate::rbac::Role;
+use crate::swap;
+use crate::users;
+use crate::{AppState, LiveState};
+
+// Operator endpoints under /api/admin. They exist when ADMIN_TOKEN is set or sign-in is on
+// (see rbac.rs), and every request must send the token as `Authorization: Bearer <token>` or
+// come from a caller with the admin role.
+
+pub fn router(state: LiveState) -> Router<LiveState> {
+    Router::new()
+        .route("/slots", get(slots_handler))
+        .route(
+            "/config",
+            get(config::get_config_handler).patch(config::patch_config_handler),
+        )
+        .route("/reload", post(config::reload_handler))
+        .route("/models/:alias/load", post(swap::load_model_handler))
+        .route("/models/unload", post(swap::unload_model_handler))
+        .route("/audit", get(audit::audit_handler))
+        .route("/users", get(users::list_users_handler))
+        .route("/users/:username", put(users::set_role_handler))
+        .route_layer(middleware::from_fn_with_state(state, require_admin))
+}
+
+async fn require_admin(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let sign_in = users::sign_in_enabled(&state);
+    if state.admin_token.is_none() && !sign_in {
+        return (StatusCode::NOT_FOUND, "admin API is disabled").into_response();
+    }
+    let presented = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if let Some(expected) = &state.admin_token
+        && constant_time_eq(presented.trim().as_bytes(), expected.as_bytes())
+    {
+        return next.run(req).await;
+    }
+    if sign_in {
+        match users::identify(&state, req.headers()).await {
+            Ok(Some(identity)) if identity.role == Role::Admin => return next.run(req).await,
+            Ok(Some(_)) => return (StatusCode::FORBIDDEN, "admin role required").into_response(),
+            Ok(None) => {}
+            Err(response) => return response,
+        }
+    }
+    (StatusCode::UNAUTHORIZED, "invalid admin token").into_response()
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// ---------- Slots ----------
+
+// llama-server's /slots (processing state, context size, decoded tokens, ...) per slot, with
+// the conversation pinned to it when slot pinning is on.
+async fn slots_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let resp = reqwest::Client::new()
+        .get(format!("{}/slots", state.llama_base_url))
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|err| {
+            log!("llama /slots request failed: {err:?}");
+            (
+                StatusCode::BAD_GATEWAY,
+                "could not reach llama-server".to_string(),
+            )
+        })?;
+    let status = resp.status();
+    if !status.is_success() {
+        // llama-server answers 501 when started with --no-slots.
+        let body = resp.text().await.unwrap_or_default();
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            format!("llama-server /slots returned {status}: {body}"),
+        ));
+    }
+    let mut slots: Vec<serde_json::Value> = resp.json().await.map_err(|err| {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("unexpected /slots response: {err}"),
+        )
+    })?;
+
+    let assignments = state
+        .slot_pinning
+        .as_ref()
+        .map(|p| p.assignments())
+        .unwrap_or_default();
+    for slot in &mut slots {
+        let id = slot["id"].as_u64().map(|id| id as usize);
+        if let Some(assignment) = assignments.iter().find(|a| Some(a.id) == id) {
+            slot["pinning"] = serde_json::to_value(assignment).unwrap_or_default();
+        }
+    }
+    Ok(Json(serde_json::json!({
+        "pinning_enabled": state.slot_pinning.is_some(),
+        "slots": slots,
+    })))
+}
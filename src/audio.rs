@@ -0,0 +1,327 @@
+use axum::{
+    Json,
+    extract::{Multipart, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::AppState;
+
+// ---------- Whisper transcription ----------
+
+#[derive(Clone, Debug)]
+pub struct WhisperConfig {
+    // whisper.cpp `/inference` or an OpenAI-style `/v1/audio/transcriptions` URL.
+    pub url: String,
+    pub model: Option<String>,
+    pub language: Option<String>,
+}
+
+impl WhisperConfig {
+    pub fn from_env() -> Option<Self> {
+        let url = crate::config::var("WHISPER_URL")
+            .ok()
+            .filter(|u| !u.trim().is_empty())?;
+        Some(Self {
+            url: url.trim().to_string(),
+            model: crate::config::var("WHISPER_MODEL").ok(),
+            language: crate::config::var("WHISPER_LANGUAGE").ok(),
+        })
+    }
+}
+
+// Audio attached to a chat request: base64 bytes plus an optional MIME type.
+#[derive(Debug, Deserialize)]
+pub struct AudioInput {
+    pub data: String,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+}
+
+impl AudioInput {
+    pub fn decode(&self) -> anyhow::Result<Vec<u8>> {
+        // Accept both raw base64 and data: URLs.
+        let raw = match self.data.split_once(";base64,") {
+            Some((_, b64)) => b64,
+            None => self.data.as_str(),
+        };
+        Ok(base64::engine::general_purpose::STANDARD.decode(raw.trim())?)
+    }
+}
+
+#[derive(Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+pub async fn transcribe(
+    config: &WhisperConfig,
+    bytes: Vec<u8>,
+    mime_type: Option<&str>,
+    file_name: &str,
+) -> anyhow::Result<String> {
+    let mut part = reqwest::multipart::Part::bytes(bytes).file_name(file_name.to_string());
+    if let Some(mime) = mime_type {
+        part = part.mime_str(mime)?;
+    }
+    let mut form = reqwest::multipart::Form::new()
+        .part("file", part)
+        .text("response_format", "json");
+    if let Some(model) = &config.model {
+        form = form.text("model", model.clone());
+    }
+    if let Some(language) = &config.language {
+        form = form.text("language", language.clone());
+    }
+
+    let resp = reqwest::Client::new()
+        .post(&config.url)
+        .bearer_auth("no-key")
+        .multipart(form)
+        .send()
+        .await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("whisper backend error {}: {}", status, body);
+    }
+
+    let parsed: TranscriptionResponse = resp.json().await?;
+    Ok(parsed.text.trim().to_string())
+}
+
+fn audio_file_name(mime_type: Option<&str>) -> &'static str {
+    match mime_type.unwrap_or_default() {
+        m if m.contains("mpeg") || m.contains("mp3") => "audio.mp3",
+        m if m.contains("ogg") => "audio.ogg",
+        m if m.contains("webm") => "audio.webm",
+        m if m.contains("mp4") || m.contains("m4a") => "audio.m4a",
+        m if m.contains("flac") => "audio.flac",
+        _ => "audio.wav",
+    }
+}
+
+pub async fn transcribe_input(
+    config: &WhisperConfig,
+    input: &AudioInput,
+) -> anyhow::Result<String> {
+    let bytes = input.decode()?;
+    let mime = input.mime_type.as_deref();
+    transcribe(config, bytes, mime, audio_file_name(mime)).await
+}
+
+// ---------- HTTP endpoint ----------
+
+#[derive(Serialize)]
+pub struct TranscribeResponse {
+    text: String,
+}
+
+// Multipart `file` with the recorded audio.
+pub async fn transcribe_handler(
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> Result<Json<TranscribeResponse>, (StatusCode, String)> {
+    let Some(whisper) = &state.whisper else {
+        return Err((
+            StatusCode::NOT_IMPLEMENTED,
+            "transcription is not configured".into(),
+        ));
+    };
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("invalid multipart body: {e}"),
+        )
+    })? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let content_type = field.content_type().map(str::to_string);
+        let file_name = field
+            .file_name()
+            .map(str::to_string)
+            .unwrap_or_else(|| audio_file_name(content_type.as_deref()).to_string());
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("failed to read file: {e}")))?;
+
+        let text = transcribe(whisper, bytes.to_vec(), content_type.as_deref(), &file_name)
+            .await
+            .map_err(|err| {
+                log!("transcription failed: {err:?}");
+                (
+                    StatusCode::BAD_GATEWAY,
+                    "transcription failed (see server logs)".into(),
+                )
+            })?;
+        return Ok(Json(TranscribeResponse { text }));
+    }
+
+    Err((StatusCode::BAD_REQUEST, "missing file field".into()))
+}
+
+// ---------- Text-to-speech ----------
+
+#[derive(Clone, Debug)]
+pub struct TtsConfig {
+    // OpenAI-style `/v1/audio/speech` endpoint (kokoro-fastapi, openedai-speech, piper wrappers).
+    pub url: String,
+    pub model: String,
+    pub voice: String,
+    pub format: String,
+}
+
+impl TtsConfig {
+    pub fn from_env() -> Option<Self> {
+        let url = crate::config::var("TTS_URL")
+            .ok()
+            .filter(|u| !u.trim().is_empty())?;
+        Some(Self {
+            url: url.trim().to_string(),
+            model: crate::config::var("TTS_MODEL").unwrap_or_else(|_| "tts-1".into()),
+            voice: crate::config::var("TTS_VOICE").unwrap_or_else(|_| "af_heart".into()),
+            format: crate::config::var("TTS_FORMAT").unwrap_or_else(|_| "mp3".into()),
+        })
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self.format.as_str() {
+            "wav" => "audio/wav",
+            "opus" => "audio/ogg",
+            "flac" => "audio/flac",
+            "aac" => "audio/aac",
+            "pcm" => "audio/L16",
+            _ => "audio/mpeg",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SpeechRequest<'a> {
+    model: &'a str,
+    input: &'a str,
+    voice: &'a str,
+    response_format: &'a str,
+}
+
+pub async fn synthesize(
+    client: &reqwest::Client,
+    config: &TtsConfig,
+    text: &str,
+    voice: Option<&str>,
+) -> anyhow::Result<Vec<u8>> {
+    let body = SpeechRequest {
+        model: &config.model,
+        input: text,
+        voice: voice.unwrap_or(&config.voice),
+        response_format: &config.format,
+    };
+
+    let resp = client
+        .post(&config.url)
+        .bearer_auth("no-key")
+        .json(&body)
+        .send()
+        .await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("tts backend error {}: {}", status, body);
+    }
+
+    Ok(resp.bytes().await?.to_vec())
+}
+
+// Buffers streamed text and hands out complete sentences for synthesis.
+#[derive(Default)]
+pub struct SentenceSplitter {
+    buffer: String,
+}
+
+impl SentenceSplitter {
+    const MIN_CHARS: usize = 24;
+
+    pub fn push(&mut self, delta: &str) -> Vec<String> {
+        self.buffer.push_str(delta);
+
+        let mut sentences = Vec::new();
+        loop {
+            let boundary = self.buffer.char_indices().find_map(|(i, c)| {
+                let after = i + c.len_utf8();
+                let ends_sentence = matches!(c, '.' | '!' | '?' | '\n')
+                    && self.buffer[after..].starts_with(char::is_whitespace);
+                (ends_sentence && self.buffer[..after].trim().chars().count() >= Self::MIN_CHARS)
+                    .then_some(after)
+            });
+            let Some(end) = boundary else {
+                break;
+            };
+            let sentence = self.buffer[..end].trim().to_string();
+            self.buffer = self.buffer[end..].to_string();
+            sentences.push(sentence);
+        }
+        sentences
+    }
+
+    pub fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        let rest = rest.trim();
+        (!rest.is_empty()).then(|| rest.to_string())
+    }
+}
+
+pub fn audio_event_payload(config: &TtsConfig, index: usize, bytes: &[u8]) -> serde_json::Value {
+    serde_json::json!({
+        "index": index,
+        "format": config.format,
+        "mime_type": config.mime_type(),
+        "data": base64::engine::general_purpose::STANDARD.encode(bytes),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct TtsRequest {
+    text: String,
+    #[serde(default)]
+    voice: Option<String>,
+}
+
+pub async fn tts_handler(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<TtsRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let Some(tts) = &state.tts else {
+        return Err((
+            StatusCode::NOT_IMPLEMENTED,
+            "text-to-speech is not configured".into(),
+        ));
+    };
+    if req.text.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "text is required".into()));
+    }
+
+    let audio = synthesize(
+        &reqwest::Client::new(),
+        tts,
+        &req.text,
+        req.voice.as_deref(),
+    )
+    .await
+    .map_err(|err| {
+        log!("tts failed: {err:?}");
+        (
+            StatusCode::BAD_GATEWAY,
+            "speech synthesis failed (see server logs)".into(),
+        )
+    })?;
+
+    Ok(([(header::CONTENT_TYPE, tts.mime_type())], audio))
+}
@@ -0,0 +1,252 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+use crate::{AppState, ToolCall};
+
+// Who asked what and what the assistant did about it, for shared deployments. With AUDIT_LOG
+// set to a file path, one JSON line is appended per event and never rewritten:
+//   chat       a chat request: user, conversation, model, the message and the tools on offer
+//   tool_call  every tool the model called, with its arguments and whether it succeeded
+//   fetch      every page the scraper requested (search excerpts, open_url), with its status
+// Each line carries the chat and request ids, so a chat's tool calls and fetches can be followed from its
+// message. Messages and arguments are stored in full; keep the file as private as the chats.
+// Off when AUDIT_LOG is unset or empty. GET /api/admin/audit reads it back, newest first.
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+// The chat an event belongs to, carried in ToolContext.
+#[derive(Clone, Default)]
+pub struct Scope {
+    pub request_id: Option<String>,
+    pub user: Option<String>,
+    pub conversation_id: Option<String>,
+    pub chat_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuditEntry {
+    at: String,
+    event: String,
+    #[serde(default)]
+    request_id: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    chat_id: Option<String>,
+    #[serde(flatten)]
+    details: serde_json::Map<String, serde_json::Value>,
+}
+
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn from_env() -> Self {
+        let path = crate::config::var("AUDIT_LOG")
+            .ok()
+            .filter(|p| !p.trim().is_empty())
+            .map(PathBuf::from);
+        Self {
+            path,
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub async fn chat(&self, scope: &Scope, details: serde_json::Value) {
+        self.record(scope, "chat", details).await;
+    }
+
+    pub async fn tool_call(&self, scope: &Scope, call: &ToolCall, error: Option<String>) {
+        let arguments = serde_json::from_str::<serde_json::Value>(&call.function.arguments)
+            .unwrap_or_else(|_| call.function.arguments.clone().into());
+        let details = serde_json::json!({
+            "tool": call.function.name,
+            "arguments": arguments,
+            "success": error.is_none(),
+            "error": error,
+        });
+        self.record(scope, "tool_call", details).await;
+    }
+
+    // `status` is the HTTP status, or None when no response came back.
+    pub async fn fetch(&self, scope: &Scope, url: &str, status: Option<u16>) {
+        let details = serde_json::json!({ "url": url, "status": status });
+        self.record(scope, "fetch", details).await;
+    }
+
+    async fn record(&self, scope: &Scope, event: &str, details: serde_json::Value) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let entry = AuditEntry {
+            at: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            event: event.to_string(),
+            request_id: scope.request_id.clone(),
+            user: scope.user.clone(),
+            conversation_id: scope.conversation_id.clone(),
+            chat_id: scope.chat_id.clone(),
+            details: match details {
+                serde_json::Value::Object(map) => map,
+                _ => serde_json::Map::new(),
+            },
+        };
+        if let Err(err) = append(path, &entry, &self.write_lock).await {
+            log!("failed to write audit log: {err:?}");
+        }
+    }
+}
+
+async fn append(
+    path: &Path,
+    entry: &AuditEntry,
+    write_lock: &tokio::sync::Mutex<()>,
+) -> anyhow::Result<()> {
+    let line = serde_json::to_string(entry)? + "\n";
+    let _guard = write_lock.lock().await;
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+// ---------- Query ----------
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    // YYYY-MM-DD (whole day, UTC) or an RFC 3339 timestamp; both ends are inclusive.
+    #[serde(default)]
+    from: Option<String>,
+    #[serde(default)]
+    to: Option<String>,
+    // chat, tool_call or fetch.
+    #[serde(default)]
+    event: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    chat_id: Option<String>,
+    #[serde(default)]
+    request_id: Option<String>,
+    // Exact tool name, or a substring of the fetched URL.
+    #[serde(default)]
+    tool: Option<String>,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+pub async fn audit_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let Some(path) = &state.audit.path else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "audit log is disabled (set AUDIT_LOG)".into(),
+        ));
+    };
+    let bad_request = |msg: String| (StatusCode::BAD_REQUEST, msg);
+    let from = query
+        .from
+        .as_deref()
+        .map(|raw| crate::usage::parse_bound(raw, false))
+        .transpose()
+        .map_err(bad_request)?;
+    let to = query
+        .to
+        .as_deref()
+        .map(|raw| crate::usage::parse_bound(raw, true))
+        .transpose()
+        .map_err(bad_request)?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Json(
+                serde_json::json!({ "entries": [], "truncated": false }),
+            ));
+        }
+        Err(err) => {
+            log!("failed to open audit log: {err:?}");
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "could not read the audit log".into(),
+            ));
+        }
+    };
+    // Read front to back, keeping the newest `limit` matches.
+    let mut matched = std::collections::VecDeque::new();
+    let mut truncated = false;
+    let mut lines = tokio::io::BufReader::new(file).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) else {
+            continue;
+        };
+        if !query.matches(&entry, from, to) {
+            continue;
+        }
+        if matched.len() == limit {
+            matched.pop_front();
+            truncated = true;
+        }
+        matched.push_back(entry);
+    }
+    let entries: Vec<AuditEntry> = matched.into_iter().rev().collect();
+    Ok(Json(serde_json::json!({
+        "entries": entries,
+        // More entries matched than `limit`; narrow the range or raise the limit.
+        "truncated": truncated,
+    })))
+}
+
+impl AuditQuery {
+    fn matches(
+        &self,
+        entry: &AuditEntry,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> bool {
+        let Ok(at) = DateTime::parse_from_rfc3339(&entry.at) else {
+            return false;
+        };
+        let equals = |filter: &Option<String>, value: Option<&str>| {
+            filter.as_deref().is_none_or(|f| value == Some(f))
+        };
+        let detail = |key: &str| entry.details.get(key).and_then(|v| v.as_str());
+        from.is_none_or(|from| at >= from)
+            && to.is_none_or(|to| at <= to)
+            && equals(&self.event, Some(&entry.event))
+            && equals(&self.user, entry.user.as_deref())
+            && equals(&self.conversation_id, entry.conversation_id.as_deref())
+            && equals(&self.chat_id, entry.chat_id.as_deref())
+            && equals(&self.request_id, entry.request_id.as_deref())
+            && equals(&self.tool, detail("tool"))
+            && self
+                .url
+                .as_deref()
+                .is_none_or(|f| detail("url").is_some_and(|url| url.contains(f)))
+    }
+}
@@ -0,0 +1,211 @@
+use axum::{Json, extract::State, http::StatusCode};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::AppState;
+use crate::tools::budget;
+
+// Thin passthroughs to llama-server's own utility endpoints, and a combined view of its /props,
+// /slots and /metrics (the last needs llama-server started with --metrics).
+
+async fn forward(
+    state: &AppState,
+    path: &str,
+    body: &serde_json::Value,
+) -> Result<serde_json::Value, (StatusCode, String)> {
+    let resp = crate::request_id::tag(
+        reqwest::Client::new().post(format!("{}{path}", state.llama_base_url)),
+    )
+    .timeout(Duration::from_secs(10))
+    .json(body)
+    .send()
+    .await
+    .map_err(|err| {
+        log!("llama {path} request failed: {err:?}");
+        (
+            StatusCode::BAD_GATEWAY,
+            "could not reach llama-server".to_string(),
+        )
+    })?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            format!("llama-server {path} returned {status}: {body}"),
+        ));
+    }
+    resp.json().await.map_err(|err| {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("unexpected {path} response: {err}"),
+        )
+    })
+}
+
+// ---------- Tokenize ----------
+
+#[derive(Deserialize)]
+pub struct TokenizeRequest {
+    content: String,
+    #[serde(default)]
+    add_special: bool,
+    // Also return each token's text.
+    #[serde(default)]
+    with_pieces: bool,
+}
+
+// Token ids and count for a draft message, plus the context size so the UI can warn before a
+// message no longer fits.
+pub async fn tokenize_handler(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<TokenizeRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let body = serde_json::json!({
+        "content": req.content,
+        "add_special": req.add_special,
+        "with_pieces": req.with_pieces,
+    });
+    let resp = forward(&state, "/tokenize", &body).await?;
+    let tokens = resp["tokens"].clone();
+    let count = tokens.as_array().map(Vec::len).unwrap_or_default();
+    let context_size = match &state.token_budget {
+        Some(budget) => Some(budget.context_tokens(&state).await),
+        None => budget::fetch_context_tokens(&state).await.ok(),
+    };
+    Ok(Json(serde_json::json!({
+        "tokens": tokens,
+        "count": count,
+        "context_size": context_size,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct DetokenizeRequest {
+    tokens: Vec<i64>,
+}
+
+pub async fn detokenize_handler(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<DetokenizeRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let resp = forward(
+        &state,
+        "/detokenize",
+        &serde_json::json!({ "tokens": req.tokens }),
+    )
+    .await?;
+    Ok(Json(serde_json::json!({ "content": resp["content"] })))
+}
+
+// ---------- Stats ----------
+
+// What is actually loaded and how fast it runs: context size, GPU offload, slot usage and
+// throughput. /slots and /metrics are optional; their fields are null when llama-server does
+// not expose them.
+pub async fn stats_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let loaded = state
+        .model_swap
+        .as_ref()
+        .and_then(|swap| swap.loaded_backend());
+    let (alias, base_url, configured_gpu_layers) = match loaded {
+        Some((alias, url, gpu_layers)) => (Some(alias), url, gpu_layers),
+        None => (None, state.llama_base_url.clone(), None),
+    };
+    let client = reqwest::Client::new();
+    let get = |path: &str| {
+        client
+            .get(format!("{base_url}{path}"))
+            .timeout(Duration::from_secs(10))
+            .send()
+    };
+    let (props, slots, metrics) = tokio::join!(get("/props"), get("/slots"), get("/metrics"));
+
+    let props: serde_json::Value = match props.and_then(reqwest::Response::error_for_status) {
+        Ok(resp) => resp.json().await.map_err(|err| {
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("unexpected /props response: {err}"),
+            )
+        })?,
+        Err(err) => {
+            log!("llama /props request failed: {err:?}");
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                "could not reach llama-server".to_string(),
+            ));
+        }
+    };
+    let slots: Option<Vec<serde_json::Value>> = match slots {
+        Ok(resp) if resp.status().is_success() => resp.json().await.ok(),
+        _ => None,
+    };
+    let metrics = match metrics {
+        Ok(resp) if resp.status().is_success() => resp.text().await.ok().map(|t| parse_metrics(&t)),
+        _ => None,
+    };
+    let metric = |name: &str| {
+        metrics
+            .as_ref()
+            .and_then(|m| m.get(&format!("llamacpp:{name}")).copied())
+    };
+
+    let settings = &props["default_generation_settings"];
+    let model_path = props["model_path"].as_str();
+    let model = alias.or_else(|| {
+        model_path.and_then(|p| {
+            std::path::Path::new(p)
+                .file_name()?
+                .to_str()
+                .map(String::from)
+        })
+    });
+    let slots_busy = match &slots {
+        Some(slots) => Some(
+            slots
+                .iter()
+                .filter(|s| s["is_processing"].as_bool().unwrap_or(false))
+                .count() as f64,
+        ),
+        None => metric("requests_processing"),
+    };
+    Ok(Json(serde_json::json!({
+        "model": model,
+        "model_path": model_path,
+        "build": props["build_info"],
+        "context_size": settings["n_ctx"],
+        "n_gpu_layers": props
+            .get("n_gpu_layers")
+            .or_else(|| settings.get("n_gpu_layers"))
+            .cloned()
+            .or_else(|| configured_gpu_layers.map(Into::into)),
+        "total_slots": props["total_slots"],
+        "slots_busy": slots_busy,
+        "requests_deferred": metric("requests_deferred"),
+        "tokens_per_second": {
+            "prompt": metric("prompt_tokens_seconds"),
+            "generation": metric("predicted_tokens_seconds"),
+        },
+        "tokens_total": {
+            "prompt": metric("prompt_tokens_total"),
+            "generated": metric("tokens_predicted_total"),
+        },
+        "kv_cache_usage": metric("kv_cache_usage_ratio"),
+        "metrics_enabled": metrics.is_some(),
+    })))
+}
+
+// Prometheus text format, one `name value` sample per line; labels are not used by
+// llama-server's metrics.
+fn parse_metrics(text: &str) -> std::collections::HashMap<String, f64> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (name, value) = line.split_once(char::is_whitespace)?;
+            Some((name.to_string(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
@@ -0,0 +1,82 @@
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use std::sync::Arc;
+
+use crate::AppState;
+use crate::admin::constant_time_eq;
+
+// One shared login for the whole server, UI and API alike, on when BASIC_AUTH_USER and
+// BASIC_AUTH_PASS are both set. Meant for a single user exposing a homelab instance; browsers
+// prompt for it and keep sending it. It takes the Authorization header, so API clients send
+// Basic credentials and sign in to accounts with the session cookie rather than a bearer
+// token. /api/admin still accepts its ADMIN_TOKEN bearer without the basic login.
+
+#[derive(Clone)]
+pub struct BasicAuth {
+    user: String,
+    pass: String,
+}
+
+impl BasicAuth {
+    pub fn from_env() -> Option<Self> {
+        let user = crate::config::var("BASIC_AUTH_USER").ok()?;
+        let pass = crate::config::var("BASIC_AUTH_PASS").ok()?;
+        if user.is_empty() || pass.is_empty() {
+            return None;
+        }
+        Some(Self { user, pass })
+    }
+
+    fn accepts(&self, header: &str) -> bool {
+        let Some(encoded) = header.strip_prefix("Basic ") else {
+            return false;
+        };
+        let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(encoded.trim()) else {
+            return false;
+        };
+        let Some((user, pass)) = String::from_utf8_lossy(&decoded)
+            .split_once(':')
+            .map(|(u, p)| (u.to_string(), p.to_string()))
+        else {
+            return false;
+        };
+        // Both compared in full so a wrong user takes as long as a wrong password.
+        let user_ok = constant_time_eq(user.as_bytes(), self.user.as_bytes());
+        let pass_ok = constant_time_eq(pass.as_bytes(), self.pass.as_bytes());
+        user_ok & pass_ok
+    }
+}
+
+pub async fn require_basic_auth(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(auth) = &state.basic_auth else {
+        return next.run(req).await;
+    };
+    let presented = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let admin_bearer =
+        req.uri().path().starts_with("/api/admin/") && presented.starts_with("Bearer ");
+    if admin_bearer || auth.accepts(presented) {
+        return next.run(req).await;
+    }
+    (
+        StatusCode::UNAUTHORIZED,
+        [(
+            header::WWW_AUTHENTICATE,
+            "Basic realm=\"chat-llama\", charset=\"UTF-8\"",
+        )],
+        "authentication required",
+    )
+        .into_response()
+}
@@ -0,0 +1,339 @@
+use axum::{Json, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::search::{self, SearchProvider};
+use crate::throttle::ThrottleLimits;
+use crate::{AppState, LiveState};
+
+// ---------- Config file ----------
+
+// Settings can also live in CONFIG_FILE (default data/chat-llama.env) as KEY=VALUE lines using
+// the environment variable names. A key set in the file wins over the environment, so it can
+// be changed without a restart: SIGHUP, POST /api/admin/reload, or (with CONFIG_WATCH=1) an
+// edit to the file reloads it and rebuilds the settings-only parts of the state: upstream URLs
+// and models, tool toggles and tool backends, search, moderation, quotas and the like (see
+// AppState::reloaded). Stores, caches, MCP servers, loaded models and pending approvals carry
+// over, and chats already streaming finish with the settings they started with. JSON configs
+// such as HTTP_TOOLS_CONFIG and MODERATION_RULES are read again on reload too.
+
+static FILE_SETTINGS: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+// std::env::var for settings, with CONFIG_FILE taking precedence.
+pub fn var(name: &str) -> Result<String, std::env::VarError> {
+    if let Some(value) = FILE_SETTINGS.read().unwrap().get(name) {
+        return Ok(value.clone());
+    }
+    std::env::var(name)
+}
+
+fn config_file() -> PathBuf {
+    std::env::var("CONFIG_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("data/chat-llama.env"))
+}
+
+// Reads CONFIG_FILE into the settings `var` sees; a missing file means no file settings.
+pub fn load_file() -> anyhow::Result<()> {
+    let path = config_file();
+    let raw = match std::fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => anyhow::bail!("could not read {}: {err}", path.display()),
+    };
+    let mut settings = BTreeMap::new();
+    for (number, line) in raw.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            anyhow::bail!("{}:{}: expected KEY=VALUE", path.display(), number + 1);
+        };
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|q| value.strip_prefix(*q)?.strip_suffix(*q))
+            .unwrap_or(value);
+        settings.insert(key.trim().to_string(), value.to_string());
+    }
+    *FILE_SETTINGS.write().unwrap() = settings;
+    Ok(())
+}
+
+// Re-reads CONFIG_FILE and swaps in a state rebuilt from it. A file that fails to parse
+// leaves everything as it was.
+pub fn reload(live: &LiveState) -> anyhow::Result<()> {
+    load_file()?;
+    let next = live.current().reloaded();
+    live.replace(Arc::new(next));
+    log!("configuration reloaded");
+    Ok(())
+}
+
+// Reloads on SIGHUP and, with CONFIG_WATCH=1, when CONFIG_FILE changes.
+pub fn spawn_reload_triggers(live: LiveState) {
+    #[cfg(unix)]
+    {
+        let live = live.clone();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{SignalKind, signal};
+            let mut hangups = match signal(SignalKind::hangup()) {
+                Ok(hangups) => hangups,
+                Err(err) => {
+                    log!("cannot listen for SIGHUP: {err}");
+                    return;
+                }
+            };
+            while hangups.recv().await.is_some() {
+                if let Err(err) = reload(&live) {
+                    log!("reload failed: {err:?}");
+                }
+            }
+        });
+    }
+    if !crate::env_flag("CONFIG_WATCH") {
+        return;
+    }
+    tokio::spawn(async move {
+        let modified = || {
+            std::fs::metadata(config_file())
+                .and_then(|m| m.modified())
+                .ok()
+        };
+        let mut seen: Option<SystemTime> = modified();
+        loop {
+            tokio::time::sleep(WATCH_INTERVAL).await;
+            let now = modified();
+            if now == seen {
+                continue;
+            }
+            seen = now;
+            if let Err(err) = reload(&live) {
+                log!("reload failed: {err:?}");
+            }
+        }
+    });
+}
+
+pub async fn reload_handler(
+    State(live): State<LiveState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    reload(&live).map_err(|err| (StatusCode::BAD_REQUEST, format!("reload failed: {err}")))?;
+    Ok(Json(serde_json::json!({ "reloaded": true })))
+}
+
+// ---------- Runtime overrides ----------
+
+// Settings an admin can change while the server runs, through GET/PATCH /api/admin/config.
+// Overrides are saved to CONFIG_OVERRIDES (default data/config_overrides.json) and applied
+// over the environment at startup. A PATCH sets the keys it names; null drops an override.
+//   default_model    chat model when a request names none: a MODEL_SWAP_CONFIG alias, or
+//                    the model name sent upstream in place of LLAMA_MODEL
+//   disabled_tools   replaces DISABLED_TOOLS
+//   search_provider  replaces SEARCH_PROVIDER, e.g. "brave,searxng"
+//   scrape_rate      {burst, per_minute, delay_ms, max_wait_secs} replaces SCRAPE_HOST_*
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Overrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled_tools: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrape_rate: Option<ThrottleLimits>,
+}
+
+pub struct RuntimeConfig {
+    path: Option<PathBuf>,
+    overrides: RwLock<Overrides>,
+    // Built from the search_provider override.
+    search: RwLock<Option<Arc<dyn SearchProvider>>>,
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl RuntimeConfig {
+    pub fn from_env() -> Self {
+        let path = match var("CONFIG_OVERRIDES") {
+            Ok(p) if p.trim().is_empty() => None,
+            Ok(p) => Some(PathBuf::from(p)),
+            Err(_) => Some(PathBuf::from("data/config_overrides.json")),
+        };
+        let overrides: Overrides = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|raw| match serde_json::from_str(&raw) {
+                Ok(overrides) => Some(overrides),
+                Err(err) => {
+                    log!("failed to parse config overrides: {err:?}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        let search = overrides.search_provider.as_deref().and_then(|names| {
+            search::provider_from_names(names)
+                .map_err(|err| log!("ignoring search_provider override: {err}"))
+                .ok()
+                .map(Arc::from)
+        });
+        Self {
+            path,
+            overrides: RwLock::new(overrides),
+            search: RwLock::new(search),
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn overrides(&self) -> Overrides {
+        self.overrides.read().unwrap().clone()
+    }
+
+    pub fn default_model(&self) -> Option<String> {
+        self.overrides.read().unwrap().default_model.clone()
+    }
+
+    // None when DISABLED_TOOLS is not overridden.
+    pub fn tool_disabled(&self, name: &str) -> Option<bool> {
+        let overrides = self.overrides.read().unwrap();
+        let disabled = overrides.disabled_tools.as_ref()?;
+        Some(disabled.iter().any(|t| t == name))
+    }
+
+    pub fn search_provider(&self) -> Option<Arc<dyn SearchProvider>> {
+        self.search.read().unwrap().clone()
+    }
+
+    // The caller holds write_lock from reading the overrides until they are saved.
+    async fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec_pretty(&self.overrides())?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+}
+
+// ---------- Handlers ----------
+
+fn effective(state: &AppState) -> serde_json::Value {
+    let overrides = state.config.overrides();
+    let default_model = overrides
+        .default_model
+        .clone()
+        .or_else(|| match &state.model_swap {
+            Some(swap) => swap.resolve(None).ok(),
+            None => Some(state.llama_model.clone()),
+        });
+    let search_provider = overrides.search_provider.clone().unwrap_or_else(|| {
+        var("SEARCH_PROVIDER")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| "searxng".into())
+    });
+    serde_json::json!({
+        "effective": {
+            "default_model": default_model,
+            "disabled_tools": overrides.disabled_tools.as_ref().unwrap_or(&state.disabled_tools),
+            "search_provider": search_provider,
+            "scrape_rate": state.host_throttle.limits(),
+        },
+        "overrides": overrides,
+    })
+}
+
+pub async fn get_config_handler(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(effective(&state))
+}
+
+pub async fn patch_config_handler(
+    State(state): State<Arc<AppState>>,
+    Json(patch): Json<serde_json::Map<String, serde_json::Value>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let bad_request = |msg: String| (StatusCode::BAD_REQUEST, msg);
+
+    // Held until the result is saved, so concurrent patches cannot undo each other.
+    let _guard = state.config.write_lock.lock().await;
+    let mut overrides = state.config.overrides();
+    let mut search = None;
+    for (key, value) in &patch {
+        let value = (!value.is_null()).then(|| value.clone());
+        match key.as_str() {
+            "default_model" => {
+                let model: Option<String> = value
+                    .map(serde_json::from_value)
+                    .transpose()
+                    .map_err(|e| bad_request(format!("default_model: {e}")))?;
+                if let (Some(model), Some(swap)) = (&model, &state.model_swap) {
+                    swap.resolve(Some(model)).map_err(bad_request)?;
+                }
+                if model.as_ref().is_some_and(|m| m.trim().is_empty()) {
+                    return Err(bad_request("default_model must not be empty".into()));
+                }
+                overrides.default_model = model;
+            }
+            "disabled_tools" => {
+                overrides.disabled_tools = value
+                    .map(serde_json::from_value)
+                    .transpose()
+                    .map_err(|e| bad_request(format!("disabled_tools: {e}")))?;
+            }
+            "search_provider" => {
+                let names: Option<String> = value
+                    .map(serde_json::from_value)
+                    .transpose()
+                    .map_err(|e| bad_request(format!("search_provider: {e}")))?;
+                let provider = names
+                    .as_deref()
+                    .map(search::provider_from_names)
+                    .transpose()
+                    .map_err(bad_request)?;
+                search = Some(provider.map(Arc::from));
+                overrides.search_provider = names;
+            }
+            "scrape_rate" => {
+                let limits: Option<ThrottleLimits> = value
+                    .map(serde_json::from_value)
+                    .transpose()
+                    .map_err(|e| bad_request(format!("scrape_rate: {e}")))?;
+                if let Some(limits) = &limits {
+                    limits
+                        .validate()
+                        .map_err(|e| bad_request(format!("scrape_rate: {e}")))?;
+                }
+                overrides.scrape_rate = limits;
+            }
+            other => return Err(bad_request(format!("{other} cannot be changed at runtime"))),
+        }
+    }
+
+    // Everything validated; apply it all at once.
+    state
+        .host_throttle
+        .set_limits(overrides.scrape_rate.clone());
+    if let Some(search) = search {
+        *state.config.search.write().unwrap() = search;
+    }
+    *state.config.overrides.write().unwrap() = overrides;
+    state.config.persist().await.map_err(|err| {
+        log!("failed to save config overrides: {err:?}");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "changes applied but could not be saved (see server logs)".to_string(),
+        )
+    })?;
+    Ok(Json(effective(&state)))
+}
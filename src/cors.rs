@@ -0,0 +1,80 @@
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+// Cross-origin access to the API, for a UI served from somewhere else (a Vite dev server, a
+// separate domain). Off unless CORS_ALLOWED_ORIGINS is set; read at startup.
+//   CORS_ALLOWED_ORIGINS    comma-separated origins such as http://localhost:5173, or * for any
+//   CORS_ALLOWED_METHODS    default GET, POST, PUT, PATCH, DELETE
+//   CORS_ALLOWED_HEADERS    default content-type, authorization, x-request-id
+//   CORS_ALLOW_CREDENTIALS  send cookies and basic auth cross-origin; needs an explicit origin
+//                           list, and the session cookie (SameSite=Lax) only travels between
+//                           origins on the same site
+// X-Request-Id is exposed to the calling page. Preflight answers are cached for an hour.
+
+const DEFAULT_METHODS: &str = "GET,POST,PUT,PATCH,DELETE";
+const DEFAULT_HEADERS: &str = "content-type,authorization,x-request-id";
+
+fn list(name: &str, default: &str) -> Vec<String> {
+    crate::config::var(name)
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| default.to_string())
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+pub fn layer_from_env() -> Option<CorsLayer> {
+    let origins = list("CORS_ALLOWED_ORIGINS", "");
+    if origins.is_empty() {
+        return None;
+    }
+    let any_origin = origins.iter().any(|o| o == "*");
+    let mut credentials = crate::env_flag("CORS_ALLOW_CREDENTIALS");
+    if any_origin && credentials {
+        log!("CORS_ALLOW_CREDENTIALS ignored: it needs a list of origins rather than *");
+        credentials = false;
+    }
+    let allow_origin = if any_origin {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().filter_map(|o| {
+            let value = HeaderValue::from_str(o.trim_end_matches('/')).ok();
+            if value.is_none() {
+                log!("ignoring invalid CORS origin {o:?}");
+            }
+            value
+        }))
+    };
+    let methods: Vec<Method> = list("CORS_ALLOWED_METHODS", DEFAULT_METHODS)
+        .iter()
+        .filter_map(|m| {
+            let method = Method::from_bytes(m.to_ascii_uppercase().as_bytes()).ok();
+            if method.is_none() {
+                log!("ignoring invalid CORS method {m:?}");
+            }
+            method
+        })
+        .collect();
+    let headers: Vec<HeaderName> = list("CORS_ALLOWED_HEADERS", DEFAULT_HEADERS)
+        .iter()
+        .filter_map(|h| {
+            let header = HeaderName::from_bytes(h.to_ascii_lowercase().as_bytes()).ok();
+            if header.is_none() {
+                log!("ignoring invalid CORS header {h:?}");
+            }
+            header
+        })
+        .collect();
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .allow_credentials(credentials)
+            .expose_headers([crate::request_id::HEADER])
+            .max_age(Duration::from_secs(3600)),
+    )
+}
@@ -0,0 +1,223 @@
+use axum::{Json, extract::State, http::StatusCode};
+use futures_util::{StreamExt, stream};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{AppState, env_flag};
+
+// ---------- Embeddings config ----------
+
+#[derive(Clone, Debug)]
+pub struct EmbeddingConfig {
+    // Full endpoint URL, e.g. http://127.0.0.1:8080/v1/embeddings
+    pub url: String,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+    // Search results whose similarity to the question is below this are dropped.
+    pub min_similarity: Option<f64>,
+}
+
+impl EmbeddingConfig {
+    // EMBEDDINGS_URL points at any OpenAI-style embeddings endpoint.
+    // EMBEDDINGS_ENABLED=true without a URL reuses llama-server's /v1/embeddings
+    // (started with --embeddings).
+    pub fn from_env(llama_base_url: &str) -> Option<Self> {
+        let url = match crate::config::var("EMBEDDINGS_URL") {
+            Ok(url) if !url.trim().is_empty() => url.trim().to_string(),
+            _ if env_flag("EMBEDDINGS_ENABLED") => {
+                format!("{}/v1/embeddings", llama_base_url.trim_end_matches('/'))
+            }
+            _ => return None,
+        };
+
+        Some(Self {
+            url,
+            model: crate::config::var("EMBEDDINGS_MODEL").ok(),
+            api_key: crate::config::var("EMBEDDINGS_API_KEY").ok(),
+            min_similarity: crate::config::var("SEARCH_MIN_SIMILARITY")
+                .ok()
+                .and_then(|v| v.trim().parse().ok()),
+        })
+    }
+}
+
+// ---------- Embeddings call ----------
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+// One vector per input, in input order.
+pub async fn embed(
+    client: &Client,
+    config: &EmbeddingConfig,
+    inputs: &[String],
+) -> anyhow::Result<Vec<Vec<f32>>> {
+    if inputs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let body = EmbeddingRequest {
+        model: config.model.as_deref(),
+        input: inputs,
+    };
+
+    let resp = client
+        .post(&config.url)
+        .header("Content-Type", "application/json")
+        .bearer_auth(config.api_key.as_deref().unwrap_or("no-key"))
+        .json(&body)
+        .send()
+        .await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("embeddings backend error {}: {}", status, body);
+    }
+
+    let parsed: EmbeddingResponse = resp.json().await?;
+    let mut vectors = vec![Vec::new(); inputs.len()];
+    for item in parsed.data {
+        if let Some(slot) = vectors.get_mut(item.index) {
+            *slot = item.embedding;
+        }
+    }
+    if vectors.iter().any(Vec::is_empty) {
+        anyhow::bail!("embeddings backend returned fewer vectors than inputs");
+    }
+
+    Ok(vectors)
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f64, 0.0f64, 0.0f64);
+    for (x, y) in a.iter().zip(b) {
+        let (x, y) = (f64::from(*x), f64::from(*y));
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+// ---------- Embeddings endpoint ----------
+
+// POST /api/embeddings takes {"input": "text" | ["text", ...], "model": optional} and answers
+// in the OpenAI format. It uses the EMBEDDINGS_URL backend, or llama-server's /v1/embeddings
+// when none is configured. Inputs go upstream in batches of EMBEDDINGS_BATCH_SIZE (default 32),
+// two batches at a time; EMBEDDINGS_MODELS (comma-separated) limits the models a caller may pick.
+
+const MAX_INPUTS: usize = 2048;
+const BATCH_CONCURRENCY: usize = 2;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+pub struct EmbeddingsApiRequest {
+    input: EmbeddingInput,
+    #[serde(default)]
+    model: Option<String>,
+}
+
+pub async fn embeddings_handler(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<EmbeddingsApiRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let inputs = match req.input {
+        EmbeddingInput::One(text) => vec![text],
+        EmbeddingInput::Many(texts) => texts,
+    };
+    if inputs.is_empty() || inputs.len() > MAX_INPUTS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("input must hold between 1 and {MAX_INPUTS} texts"),
+        ));
+    }
+
+    let mut config = state.embeddings.clone().unwrap_or_else(|| EmbeddingConfig {
+        url: format!(
+            "{}/v1/embeddings",
+            state.llama_base_url.trim_end_matches('/')
+        ),
+        model: None,
+        api_key: None,
+        min_similarity: None,
+    });
+    if let Some(model) = req.model.filter(|m| !m.trim().is_empty()) {
+        let allowed: Vec<String> = crate::config::var("EMBEDDINGS_MODELS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|m| m.trim().to_string())
+            .filter(|m| !m.is_empty())
+            .collect();
+        if !allowed.is_empty() && !allowed.contains(&model) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("model {model} is not available"),
+            ));
+        }
+        config.model = Some(model);
+    }
+    let batch_size = crate::config::var("EMBEDDINGS_BATCH_SIZE")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(32);
+
+    let client = Client::new();
+    let mut batches = Vec::new();
+    for chunk in inputs.chunks(batch_size) {
+        batches.push(embed(&client, &config, chunk));
+    }
+    let results: Vec<anyhow::Result<Vec<Vec<f32>>>> = stream::iter(batches)
+        .buffered(BATCH_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut data = Vec::with_capacity(inputs.len());
+    for result in results {
+        let vectors = result.map_err(|err| {
+            log!("embeddings request failed: {err:?}");
+            (
+                StatusCode::BAD_GATEWAY,
+                "embeddings backend error (see server logs)".to_string(),
+            )
+        })?;
+        for embedding in vectors {
+            data.push(serde_json::json!({
+                "object": "embedding",
+                "index": data.len(),
+                "embedding": embedding,
+            }));
+        }
+    }
+    Ok(Json(serde_json::json!({
+        "object": "list",
+        "model": config.model,
+        "data": data,
+    })))
+}
@@ -0,0 +1,453 @@
+use axum::{
+    Json,
+    extract::{Multipart, State},
+    http::StatusCode,
+};
+use serde::Serialize;
+use std::{
+    io::{Cursor, Read},
+    sync::Arc,
+};
+
+use crate::AppState;
+
+// Scanned PDFs have little or no text layer; below this we fall back to OCR.
+const OCR_MIN_CHARS_PER_PAGE: usize = 25;
+
+// Most XML a .docx or .pptx may decompress to, all of its entries together, so a small zip
+// bomb cannot fill memory.
+const MAX_OFFICE_XML_BYTES: u64 = 64 * 1024 * 1024;
+
+// ---------- Document text extraction ----------
+
+// Extracted plain text plus optional page boundaries (byte ranges into `text`).
+#[derive(Debug, Default)]
+pub struct ExtractedText {
+    pub text: String,
+    pub pages: Vec<PageSpan>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PageSpan {
+    pub page: u32,
+    pub byte_start: usize,
+    pub byte_end: usize,
+}
+
+impl ExtractedText {
+    fn from_pages(pages: Vec<String>) -> Self {
+        let mut out = ExtractedText::default();
+        for (i, page) in pages.into_iter().enumerate() {
+            let cleaned = page.trim();
+            if cleaned.is_empty() {
+                continue;
+            }
+            if !out.text.is_empty() {
+                out.text.push_str("\n\n");
+            }
+            let byte_start = out.text.len();
+            out.text.push_str(cleaned);
+            out.pages.push(PageSpan {
+                page: i as u32 + 1,
+                byte_start,
+                byte_end: out.text.len(),
+            });
+        }
+        out
+    }
+
+    // Compact excerpt that keeps "[p. N]" markers so the model can cite pages.
+    pub fn excerpt(&self, max_chars: usize) -> String {
+        if self.pages.is_empty() {
+            return collapse_whitespace(&self.text)
+                .chars()
+                .take(max_chars)
+                .collect();
+        }
+
+        let mut out = String::new();
+        for span in &self.pages {
+            let page_text = collapse_whitespace(&self.text[span.byte_start..span.byte_end]);
+            if !out.is_empty() {
+                out.push(' ');
+            }
+            out.push_str(&format!("[p. {}] {}", span.page, page_text));
+            if out.chars().count() >= max_chars {
+                break;
+            }
+        }
+        out.chars().take(max_chars).collect()
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub fn is_pdf(content_type: Option<&str>, name: &str, bytes: &[u8]) -> bool {
+    content_type.is_some_and(|ct| ct.starts_with("application/pdf"))
+        || name.to_ascii_lowercase().ends_with(".pdf")
+        || bytes.starts_with(b"%PDF-")
+}
+
+pub async fn extract_pdf(bytes: Vec<u8>) -> anyhow::Result<ExtractedText> {
+    // pdf-extract is CPU-bound and may panic on malformed files; keep it off the runtime.
+    let pages =
+        tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem_by_pages(&bytes))
+            .await
+            .map_err(|e| anyhow::anyhow!("pdf extraction panicked: {e}"))?
+            .map_err(|e| anyhow::anyhow!("pdf extraction failed: {e}"))?;
+
+    Ok(ExtractedText::from_pages(pages))
+}
+
+// ---------- Office formats ----------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OfficeKind {
+    Word,
+    Spreadsheet,
+    Presentation,
+}
+
+pub fn office_kind(content_type: Option<&str>, name: &str) -> Option<OfficeKind> {
+    let ct = content_type.unwrap_or_default();
+    let ext = name
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if ct.contains("wordprocessingml") || ext == "docx" {
+        Some(OfficeKind::Word)
+    } else if ct.contains("spreadsheetml")
+        || ct.contains("opendocument.spreadsheet")
+        || ct == "application/vnd.ms-excel"
+        || matches!(ext.as_str(), "xlsx" | "xlsm" | "xlsb" | "xls" | "ods")
+    {
+        Some(OfficeKind::Spreadsheet)
+    } else if ct.contains("presentationml") || ext == "pptx" {
+        Some(OfficeKind::Presentation)
+    } else {
+        None
+    }
+}
+
+pub async fn extract_office(kind: OfficeKind, bytes: Vec<u8>) -> anyhow::Result<ExtractedText> {
+    tokio::task::spawn_blocking(move || match kind {
+        OfficeKind::Word => extract_docx(&bytes),
+        OfficeKind::Spreadsheet => extract_spreadsheet(bytes),
+        OfficeKind::Presentation => extract_pptx(&bytes),
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("office extraction panicked: {e}"))?
+}
+
+// Reads one entry, taking its size from `budget`. The declared size is checked first, and
+// the read itself stops at the budget in case the declaration lies.
+fn read_zip_entry(
+    archive: &mut zip::ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+    budget: &mut u64,
+) -> anyhow::Result<String> {
+    let too_large = || anyhow::anyhow!("document is too large once decompressed");
+    let mut entry = archive.by_name(name)?;
+    if entry.size() > *budget {
+        return Err(too_large());
+    }
+    let mut xml = Vec::new();
+    (&mut entry).take(*budget + 1).read_to_end(&mut xml)?;
+    if xml.len() as u64 > *budget {
+        return Err(too_large());
+    }
+    *budget -= xml.len() as u64;
+    Ok(String::from_utf8(xml)?)
+}
+
+// Collects the text of `<*:t>` runs, one line per `<*:p>` paragraph.
+// Works for both WordprocessingML (w:) and DrawingML (a:) markup.
+fn ooxml_paragraphs(xml: &str) -> anyhow::Result<String> {
+    use quick_xml::events::Event as XmlEvent;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut out = String::new();
+    let mut line = String::new();
+    let mut in_text = false;
+
+    loop {
+        match reader.read_event()? {
+            XmlEvent::Start(e) if e.local_name().as_ref() == b"t" => in_text = true,
+            XmlEvent::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => {
+                    let trimmed = line.trim();
+                    if !trimmed.is_empty() {
+                        out.push_str(trimmed);
+                        out.push('\n');
+                    }
+                    line.clear();
+                }
+                _ => {}
+            },
+            XmlEvent::Empty(e) => match e.local_name().as_ref() {
+                b"tab" => line.push('\t'),
+                b"br" | b"cr" => line.push('\n'),
+                _ => {}
+            },
+            XmlEvent::Text(t) if in_text => line.push_str(&t.unescape()?),
+            XmlEvent::Eof => break,
+            _ => {}
+        }
+    }
+    if !line.trim().is_empty() {
+        out.push_str(line.trim());
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+fn extract_docx(bytes: &[u8]) -> anyhow::Result<ExtractedText> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut budget = MAX_OFFICE_XML_BYTES;
+    let xml = read_zip_entry(&mut archive, "word/document.xml", &mut budget)?;
+    Ok(ExtractedText {
+        text: ooxml_paragraphs(&xml)?,
+        pages: Vec::new(),
+    })
+}
+
+// Each slide becomes a "page" so citations can point at slide numbers.
+fn extract_pptx(bytes: &[u8]) -> anyhow::Result<ExtractedText> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut slides: Vec<(u32, String)> = archive
+        .file_names()
+        .filter_map(|name| {
+            let num = name
+                .strip_prefix("ppt/slides/slide")?
+                .strip_suffix(".xml")?
+                .parse()
+                .ok()?;
+            Some((num, name.to_string()))
+        })
+        .collect();
+    slides.sort_by_key(|(num, _)| *num);
+
+    let mut budget = MAX_OFFICE_XML_BYTES;
+    let mut pages = Vec::new();
+    for (_, name) in slides {
+        let xml = read_zip_entry(&mut archive, &name, &mut budget)?;
+        pages.push(ooxml_paragraphs(&xml)?);
+    }
+
+    Ok(ExtractedText::from_pages(pages))
+}
+
+// Each sheet becomes a "page" headed by its name; cells are tab-separated.
+fn extract_spreadsheet(bytes: Vec<u8>) -> anyhow::Result<ExtractedText> {
+    use calamine::Reader;
+
+    let mut workbook = calamine::open_workbook_auto_from_rs(Cursor::new(bytes))?;
+    let mut pages = Vec::new();
+    for sheet in workbook.sheet_names() {
+        let range = workbook.worksheet_range(&sheet)?;
+        let mut text = format!("Sheet: {sheet}\n");
+        for row in range.rows() {
+            let cells: Vec<String> = row.iter().map(|c| c.to_string()).collect();
+            if cells.iter().all(|c| c.trim().is_empty()) {
+                continue;
+            }
+            text.push_str(&cells.join("\t"));
+            text.push('\n');
+        }
+        pages.push(text);
+    }
+
+    Ok(ExtractedText::from_pages(pages))
+}
+
+// ---------- OCR ----------
+
+#[derive(Clone, Debug)]
+pub struct OcrConfig {
+    // Endpoint receiving a multipart `file` (image or PDF) and returning text,
+    // either as text/plain or JSON with a `text` field.
+    pub url: String,
+    pub language: Option<String>,
+    pub api_key: Option<String>,
+}
+
+impl OcrConfig {
+    pub fn from_env() -> Option<Self> {
+        let url = crate::config::var("OCR_URL")
+            .ok()
+            .filter(|u| !u.trim().is_empty())?;
+        Some(Self {
+            url: url.trim().to_string(),
+            language: crate::config::var("OCR_LANGUAGE").ok(),
+            api_key: crate::config::var("OCR_API_KEY").ok(),
+        })
+    }
+}
+
+pub fn is_image(content_type: Option<&str>, name: &str) -> bool {
+    let ext = name
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    content_type.is_some_and(|ct| ct.starts_with("image/"))
+        || matches!(
+            ext.as_str(),
+            "png" | "jpg" | "jpeg" | "tif" | "tiff" | "bmp" | "webp" | "gif"
+        )
+}
+
+pub async fn ocr(
+    config: &OcrConfig,
+    bytes: Vec<u8>,
+    content_type: Option<&str>,
+    name: &str,
+) -> anyhow::Result<ExtractedText> {
+    let mut part = reqwest::multipart::Part::bytes(bytes).file_name(name.to_string());
+    if let Some(ct) = content_type {
+        part = part.mime_str(ct)?;
+    }
+    let mut form = reqwest::multipart::Form::new().part("file", part);
+    if let Some(lang) = &config.language {
+        form = form.text("language", lang.clone());
+    }
+
+    let mut req = reqwest::Client::new().post(&config.url).multipart(form);
+    if let Some(key) = &config.api_key {
+        req = req.bearer_auth(key);
+    }
+    let resp = req.send().await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("ocr backend error {}: {}", status, body);
+    }
+
+    let is_json = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains("json"));
+    let body = resp.text().await?;
+    let text = if is_json {
+        let json: serde_json::Value = serde_json::from_str(&body)?;
+        json.get("text")
+            .or_else(|| json.pointer("/data/stdout"))
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("ocr response has no text field"))?
+            .to_string()
+    } else {
+        body
+    };
+
+    // Tesseract separates pages with form feeds.
+    if text.contains('\u{c}') {
+        Ok(ExtractedText::from_pages(
+            text.split('\u{c}').map(str::to_string).collect(),
+        ))
+    } else {
+        Ok(ExtractedText {
+            text,
+            pages: Vec::new(),
+        })
+    }
+}
+
+// ---------- Dispatch ----------
+
+// Picks an extractor from the content type / file name / magic bytes.
+pub async fn extract_document(
+    bytes: Vec<u8>,
+    content_type: Option<&str>,
+    name: &str,
+    ocr_config: Option<&OcrConfig>,
+) -> anyhow::Result<ExtractedText> {
+    if is_pdf(content_type, name, &bytes) {
+        let Some(ocr_config) = ocr_config else {
+            return extract_pdf(bytes).await;
+        };
+        match extract_pdf(bytes.clone()).await {
+            Ok(extracted)
+                if extracted.text.chars().count()
+                    >= OCR_MIN_CHARS_PER_PAGE * extracted.pages.len().max(1) =>
+            {
+                return Ok(extracted);
+            }
+            Ok(_) => {}
+            Err(err) => log!("pdf text extraction failed, trying OCR: {err:?}"),
+        }
+        return ocr(ocr_config, bytes, Some("application/pdf"), name).await;
+    }
+    if is_image(content_type, name) {
+        let Some(ocr_config) = ocr_config else {
+            anyhow::bail!("image uploads require OCR_URL to be configured");
+        };
+        return ocr(ocr_config, bytes, content_type, name).await;
+    }
+    if let Some(kind) = office_kind(content_type, name) {
+        return extract_office(kind, bytes).await;
+    }
+
+    Ok(ExtractedText {
+        text: String::from_utf8_lossy(&bytes).into_owned(),
+        pages: Vec::new(),
+    })
+}
+
+// ---------- HTTP endpoint ----------
+
+#[derive(Serialize)]
+pub struct OcrResponse {
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pages: Option<u32>,
+}
+
+// Multipart `file` -> recognized text, for inlining an image or scan into a chat message.
+pub async fn ocr_handler(
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> Result<Json<OcrResponse>, (StatusCode, String)> {
+    let Some(ocr_config) = &state.ocr else {
+        return Err((StatusCode::NOT_IMPLEMENTED, "OCR is not configured".into()));
+    };
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("invalid multipart body: {e}"),
+        )
+    })? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let name = field.file_name().unwrap_or("upload").to_string();
+        let content_type = field.content_type().map(str::to_string);
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("failed to read file: {e}")))?;
+
+        let extracted = ocr(ocr_config, bytes.to_vec(), content_type.as_deref(), &name)
+            .await
+            .map_err(|err| {
+                log!("ocr failed for {name}: {err:?}");
+                (
+                    StatusCode::BAD_GATEWAY,
+                    "OCR failed (see server logs)".into(),
+                )
+            })?;
+        return Ok(Json(OcrResponse {
+            text: extracted.text,
+            pages: extracted.pages.last().map(|p| p.page),
+        }));
+    }
+
+    Err((StatusCode::BAD_REQUEST, "missing file field".into()))
+}
@@ -0,0 +1,734 @@
+use axum::{
+    Json,
+    extract::{Multipart, Path, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use crate::{AppState, SearchResult, extract, rerank};
+
+const CHUNK_CHARS: usize = 800;
+const CHUNK_OVERLAP_CHARS: usize = 100;
+pub const DEFAULT_COLLECTION: &str = "default";
+
+// ---------- Knowledge base types ----------
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KbDocument {
+    pub id: String,
+    pub name: String,
+    #[serde(default = "default_collection")]
+    pub collection: String,
+    pub text: String,
+    pub chunks: Vec<KbChunk>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pages: Vec<KbPage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct KbPage {
+    pub page: u32,
+    pub byte_start: usize,
+    pub byte_end: usize,
+}
+
+// Offsets are into `KbDocument::text`: `start`/`end` in chars (for the UI),
+// `byte_start`/`byte_end` in UTF-8 bytes (for slicing).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct KbChunk {
+    pub start: usize,
+    pub end: usize,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    // Page the chunk starts on, for paginated sources like PDFs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChunkCitation {
+    pub document_id: String,
+    pub document_name: String,
+    pub collection: String,
+    pub chunk_index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KbCollection {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KbStore {
+    collections: Vec<KbCollection>,
+    documents: Vec<KbDocument>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KbDocumentInfo {
+    id: String,
+    name: String,
+    collection: String,
+    chars: usize,
+    chunks: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pages: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KbCollectionInfo {
+    name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    description: String,
+    documents: usize,
+    chunks: usize,
+    chars: usize,
+}
+
+fn default_collection() -> String {
+    DEFAULT_COLLECTION.to_string()
+}
+
+impl KbDocument {
+    fn info(&self) -> KbDocumentInfo {
+        KbDocumentInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            collection: self.collection.clone(),
+            chars: self.text.chars().count(),
+            chunks: self.chunks.len(),
+            pages: self.pages.last().map(|p| p.page),
+        }
+    }
+
+    fn chunk_text(&self, chunk: &KbChunk) -> &str {
+        &self.text[chunk.byte_start..chunk.byte_end]
+    }
+}
+
+// ---------- Store ----------
+
+pub struct KnowledgeBase {
+    store: RwLock<KbStore>,
+    path: Option<PathBuf>,
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl KnowledgeBase {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut store: KbStore = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|raw| match serde_json::from_str(&raw) {
+                Ok(store) => Some(store),
+                Err(err) => {
+                    log!("failed to parse knowledge base file: {err:?}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        if !store
+            .collections
+            .iter()
+            .any(|c| c.name == DEFAULT_COLLECTION)
+        {
+            store.collections.insert(
+                0,
+                KbCollection {
+                    name: DEFAULT_COLLECTION.to_string(),
+                    description: String::new(),
+                },
+            );
+        }
+
+        Self {
+            store: RwLock::new(store),
+            path,
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let path = match crate::config::var("KB_PATH") {
+            Ok(p) if p.trim().is_empty() => None,
+            Ok(p) => Some(PathBuf::from(p)),
+            Err(_) => Some(PathBuf::from("data/kb.json")),
+        };
+        Self::load(path)
+    }
+
+    async fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let _guard = self.write_lock.lock().await;
+        let json = {
+            let store = self.store.read().unwrap();
+            serde_json::to_vec(&*store)?
+        };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    pub fn has_collection(&self, name: &str) -> bool {
+        self.store
+            .read()
+            .unwrap()
+            .collections
+            .iter()
+            .any(|c| c.name == name)
+    }
+
+    // Returns false if a collection with that name already exists.
+    pub async fn create_collection(
+        &self,
+        name: String,
+        description: String,
+    ) -> anyhow::Result<bool> {
+        {
+            let mut store = self.store.write().unwrap();
+            if store.collections.iter().any(|c| c.name == name) {
+                return Ok(false);
+            }
+            store.collections.push(KbCollection { name, description });
+        }
+        self.persist().await?;
+        Ok(true)
+    }
+
+    // Removes the collection together with all of its documents.
+    pub async fn delete_collection(&self, name: &str) -> anyhow::Result<bool> {
+        let removed = {
+            let mut store = self.store.write().unwrap();
+            let before = store.collections.len();
+            store.collections.retain(|c| c.name != name);
+            store.documents.retain(|d| d.collection != name);
+            store.collections.len() != before
+        };
+        if removed {
+            self.persist().await?;
+        }
+        Ok(removed)
+    }
+
+    pub fn collection_stats(&self) -> Vec<KbCollectionInfo> {
+        let store = self.store.read().unwrap();
+        store
+            .collections
+            .iter()
+            .map(|c| {
+                let docs = store.documents.iter().filter(|d| d.collection == c.name);
+                let (documents, chunks, chars) = docs.fold((0, 0, 0), |(n, ch, cs), d| {
+                    (n + 1, ch + d.chunks.len(), cs + d.text.chars().count())
+                });
+                KbCollectionInfo {
+                    name: c.name.clone(),
+                    description: c.description.clone(),
+                    documents,
+                    chunks,
+                    chars,
+                }
+            })
+            .collect()
+    }
+
+    pub async fn add_document(
+        &self,
+        collection: String,
+        name: String,
+        text: String,
+        pages: Vec<KbPage>,
+    ) -> anyhow::Result<KbDocumentInfo> {
+        let mut chunks = chunk_text(&text, CHUNK_CHARS, CHUNK_OVERLAP_CHARS);
+        for chunk in &mut chunks {
+            chunk.page = pages
+                .iter()
+                .find(|p| chunk.byte_start < p.byte_end)
+                .map(|p| p.page);
+        }
+        let doc = KbDocument {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            collection,
+            text,
+            chunks,
+            pages,
+        };
+        let info = doc.info();
+        self.store.write().unwrap().documents.push(doc);
+        self.persist().await?;
+        Ok(info)
+    }
+
+    pub async fn delete_document(&self, id: &str) -> anyhow::Result<bool> {
+        let removed = {
+            let mut store = self.store.write().unwrap();
+            let before = store.documents.len();
+            store.documents.retain(|d| d.id != id);
+            store.documents.len() != before
+        };
+        if removed {
+            self.persist().await?;
+        }
+        Ok(removed)
+    }
+
+    pub fn list(&self, collection: Option<&str>) -> Vec<KbDocumentInfo> {
+        self.store
+            .read()
+            .unwrap()
+            .documents
+            .iter()
+            .filter(|d| collection.is_none_or(|c| d.collection == c))
+            .map(KbDocument::info)
+            .collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<KbDocument> {
+        self.store
+            .read()
+            .unwrap()
+            .documents
+            .iter()
+            .find(|d| d.id == id)
+            .cloned()
+    }
+
+    // BM25 over the chunks of the given collections (all when empty);
+    // returns (citation, chunk text) best first.
+    pub fn search(
+        &self,
+        query: &str,
+        collections: &[String],
+        limit: usize,
+    ) -> Vec<(ChunkCitation, String)> {
+        let query_terms = tokenize(query);
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+
+        let store = self.store.read().unwrap();
+        let chunks: Vec<(&KbDocument, usize, Vec<String>)> = store
+            .documents
+            .iter()
+            .filter(|doc| collections.is_empty() || collections.contains(&doc.collection))
+            .flat_map(|doc| {
+                doc.chunks
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, c)| (doc, i, tokenize(doc.chunk_text(c))))
+            })
+            .collect();
+        if chunks.is_empty() {
+            return Vec::new();
+        }
+
+        let n = chunks.len() as f64;
+        let avg_len = chunks.iter().map(|(_, _, t)| t.len()).sum::<usize>() as f64 / n;
+        let mut doc_freq: HashMap<&str, usize> = HashMap::new();
+        for term in &query_terms {
+            let df = chunks
+                .iter()
+                .filter(|(_, _, tokens)| tokens.iter().any(|t| t == term))
+                .count();
+            doc_freq.insert(term.as_str(), df);
+        }
+
+        let (k1, b) = (1.2, 0.75);
+        let mut scored: Vec<(f64, &KbDocument, usize)> = chunks
+            .iter()
+            .filter_map(|(doc, idx, tokens)| {
+                let len = tokens.len() as f64;
+                let score: f64 = query_terms
+                    .iter()
+                    .map(|term| {
+                        let tf = tokens.iter().filter(|t| *t == term).count() as f64;
+                        if tf == 0.0 {
+                            return 0.0;
+                        }
+                        let df = doc_freq[term.as_str()] as f64;
+                        let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                        idf * tf * (k1 + 1.0) / (tf + k1 * (1.0 - b + b * len / avg_len.max(1.0)))
+                    })
+                    .sum();
+                (score > 0.0).then_some((score, *doc, *idx))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, doc, idx)| {
+                let chunk = doc.chunks[idx];
+                let citation = ChunkCitation {
+                    document_id: doc.id.clone(),
+                    document_name: doc.name.clone(),
+                    collection: doc.collection.clone(),
+                    chunk_index: idx,
+                    start: chunk.start,
+                    end: chunk.end,
+                    byte_start: chunk.byte_start,
+                    byte_end: chunk.byte_end,
+                    page: chunk.page,
+                };
+                (citation, doc.chunk_text(&chunk).to_string())
+            })
+            .collect()
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.chars().count() > 1)
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+// Splits into ~`size`-char windows overlapping by `overlap`, preferring to break on whitespace.
+fn chunk_text(text: &str, size: usize, overlap: usize) -> Vec<KbChunk> {
+    let mut bounds: Vec<usize> = text.char_indices().map(|(b, _)| b).collect();
+    bounds.push(text.len());
+    let total = bounds.len() - 1;
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < total {
+        let mut end = (start + size).min(total);
+        if end < total {
+            let min_end = start + size * 4 / 5;
+            if let Some(ws) = (min_end..end)
+                .rev()
+                .find(|&i| text[bounds[i]..].starts_with(char::is_whitespace))
+            {
+                end = ws;
+            }
+        }
+
+        chunks.push(KbChunk {
+            start,
+            end,
+            byte_start: bounds[start],
+            byte_end: bounds[end],
+            page: None,
+        });
+
+        if end == total {
+            break;
+        }
+        start = end.saturating_sub(overlap).max(start + 1);
+    }
+
+    chunks
+}
+
+// ---------- kb_search tool ----------
+
+pub async fn kb_search(
+    state: &AppState,
+    query: &str,
+    collections: &[String],
+    limit: usize,
+) -> Vec<SearchResult> {
+    let candidate_limit = if state.reranker.is_some() {
+        limit * 4
+    } else {
+        limit
+    };
+    let mut hits = state
+        .knowledge_base
+        .search(query, collections, candidate_limit);
+
+    if let Some(reranker) = &state.reranker
+        && hits.len() > 1
+    {
+        let documents: Vec<String> = hits.iter().map(|(_, text)| text.clone()).collect();
+        match rerank::rerank(&reqwest::Client::new(), reranker, query, &documents).await {
+            Ok(scored) if !scored.is_empty() => {
+                let mut slots: Vec<Option<_>> = hits.into_iter().map(Some).collect();
+                hits = scored
+                    .into_iter()
+                    .filter_map(|(idx, _)| slots.get_mut(idx).and_then(Option::take))
+                    .collect();
+            }
+            Ok(_) => {}
+            Err(err) => log!("rerank failed, keeping BM25 order: {err:?}"),
+        }
+    }
+
+    hits.into_iter()
+        .take(limit)
+        .map(|(citation, text)| SearchResult {
+            title: match citation.page {
+                Some(page) => format!("{} (p. {page})", citation.document_name),
+                None => citation.document_name.clone(),
+            },
+            snippet: text,
+            url: format!("/api/kb/documents/{}", citation.document_id),
+            published: None,
+            citation: Some(citation),
+            provider: None,
+        })
+        .collect()
+}
+
+// ---------- HTTP endpoints ----------
+
+#[derive(Deserialize)]
+pub struct AddDocumentRequest {
+    name: String,
+    text: String,
+    #[serde(default = "default_collection")]
+    collection: String,
+}
+
+#[derive(Deserialize)]
+pub struct ListDocumentsQuery {
+    collection: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCollectionRequest {
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+pub async fn add_document_handler(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<AddDocumentRequest>,
+) -> Result<Json<KbDocumentInfo>, (StatusCode, String)> {
+    let name = req.name.trim();
+    if name.is_empty() || req.text.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "name and text are required".into()));
+    }
+    if !state.knowledge_base.has_collection(&req.collection) {
+        return Err((StatusCode::NOT_FOUND, "collection not found".into()));
+    }
+
+    state
+        .knowledge_base
+        .add_document(req.collection, name.to_string(), req.text, Vec::new())
+        .await
+        .map(Json)
+        .map_err(|err| {
+            log!("kb add failed: {err:?}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to store document".into(),
+            )
+        })
+}
+
+// Multipart upload: `file` (required), optional `name` and `collection` fields.
+pub async fn upload_document_handler(
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> Result<Json<KbDocumentInfo>, (StatusCode, String)> {
+    let bad_request = |msg: String| (StatusCode::BAD_REQUEST, msg);
+
+    let mut file: Option<(String, Option<String>, Vec<u8>)> = None;
+    let mut name: Option<String> = None;
+    let mut collection = default_collection();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| bad_request(format!("invalid multipart body: {e}")))?
+    {
+        match field.name().unwrap_or_default() {
+            "file" => {
+                let file_name = field.file_name().unwrap_or("upload").to_string();
+                let content_type = field.content_type().map(str::to_string);
+                let bytes = field
+                    .bytes()
+                    .await
+                    .map_err(|e| bad_request(format!("failed to read file: {e}")))?;
+                file = Some((file_name, content_type, bytes.to_vec()));
+            }
+            "name" => name = field.text().await.ok().filter(|n| !n.trim().is_empty()),
+            "collection" => {
+                if let Ok(c) = field.text().await
+                    && !c.trim().is_empty()
+                {
+                    collection = c.trim().to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let Some((file_name, content_type, bytes)) = file else {
+        return Err(bad_request("missing file field".into()));
+    };
+    if !state.knowledge_base.has_collection(&collection) {
+        return Err((StatusCode::NOT_FOUND, "collection not found".into()));
+    }
+
+    let extracted = extract::extract_document(
+        bytes,
+        content_type.as_deref(),
+        &file_name,
+        state.ocr.as_ref(),
+    )
+    .await
+    .map_err(|err| {
+        log!("kb extraction failed for {file_name}: {err:?}");
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("could not extract text from {file_name}"),
+        )
+    })?;
+    if extracted.text.trim().is_empty() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("no text found in {file_name}"),
+        ));
+    }
+
+    let pages = extracted
+        .pages
+        .iter()
+        .map(|p| KbPage {
+            page: p.page,
+            byte_start: p.byte_start,
+            byte_end: p.byte_end,
+        })
+        .collect();
+
+    state
+        .knowledge_base
+        .add_document(collection, name.unwrap_or(file_name), extracted.text, pages)
+        .await
+        .map(Json)
+        .map_err(|err| {
+            log!("kb add failed: {err:?}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to store document".into(),
+            )
+        })
+}
+
+pub async fn list_documents_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ListDocumentsQuery>,
+) -> Json<Vec<KbDocumentInfo>> {
+    Json(state.knowledge_base.list(query.collection.as_deref()))
+}
+
+pub async fn get_document_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<KbDocument>, (StatusCode, String)> {
+    state
+        .knowledge_base
+        .get(&id)
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "document not found".into()))
+}
+
+pub async fn delete_document_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    match state.knowledge_base.delete_document(&id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "document not found".into())),
+        Err(err) => {
+            log!("kb delete failed: {err:?}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to delete document".into(),
+            ))
+        }
+    }
+}
+
+pub async fn list_collections_handler(
+    State(state): State<Arc<AppState>>,
+) -> Json<Vec<KbCollectionInfo>> {
+    Json(state.knowledge_base.collection_stats())
+}
+
+pub async fn get_collection_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<KbCollectionInfo>, (StatusCode, String)> {
+    state
+        .knowledge_base
+        .collection_stats()
+        .into_iter()
+        .find(|c| c.name == name)
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "collection not found".into()))
+}
+
+pub async fn create_collection_handler(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateCollectionRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "name is required".into()));
+    }
+
+    match state
+        .knowledge_base
+        .create_collection(name.to_string(), req.description)
+        .await
+    {
+        Ok(true) => Ok(StatusCode::CREATED),
+        Ok(false) => Err((StatusCode::CONFLICT, "collection already exists".into())),
+        Err(err) => {
+            log!("kb create collection failed: {err:?}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to create collection".into(),
+            ))
+        }
+    }
+}
+
+pub async fn delete_collection_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if name == DEFAULT_COLLECTION {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "the default collection cannot be deleted".into(),
+        ));
+    }
+
+    match state.knowledge_base.delete_collection(&name).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "collection not found".into())),
+        Err(err) => {
+            log!("kb delete collection failed: {err:?}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to delete collection".into(),
+            ))
+        }
+    }
+}
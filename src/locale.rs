@@ -0,0 +1,174 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Answer language per chat: the request's `language` (a BCP 47 tag such as "de" or "pt-BR"),
+// else the user's entry in LOCALES_CONFIG, else its default. With a language set, web search
+// asks for results in it, Wikipedia defaults to that edition, the model is told to answer in
+// it and the built-in system prompt is swapped for a translation when one is configured.
+// LOCALES_CONFIG (default data/locales.json):
+//   { "default": "en",
+//     "users": { "lena": "de" },
+//     "prompts": { "de": { "plain": "Du bist ein hilfreicher Assistent. ...",
+//                          "search": "...", "kb_search": "...", "memory": "..." } } }
+// Prompts are looked up by full tag, then by primary language ("pt-BR", then "pt"); any part
+// left out stays English. Without a language, search keeps SearXNG's "en".
+
+const PLAIN_PROMPT: &str = "You are a helpful AI assistant. Answer as clearly as possible using only your existing knowledge.";
+const SEARCH_PROMPT: &str = "You are a helpful AI assistant. You can call the web_search tool to fetch recent web information.\n\
+Use the tool whenever the user asks for factual data you are unsure about.\n\
+When citing information derived from tool results, refer to them as [n] where n is the result index.";
+const KB_SEARCH_HINT: &str = "You can call the kb_search tool to look up passages from the user's uploaded documents. \
+Prefer it for questions about those documents and cite passages as [n].";
+const MEMORY_HINT: &str = "You have long-term memory. Call remember to save lasting facts or preferences the user \
+shares, and recall to look up older ones.";
+
+#[derive(Deserialize, Default, Clone)]
+pub struct LocalizedPrompts {
+    #[serde(default)]
+    plain: Option<String>,
+    #[serde(default)]
+    search: Option<String>,
+    #[serde(default)]
+    kb_search: Option<String>,
+    #[serde(default)]
+    memory: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Locales {
+    #[serde(default)]
+    default: Option<String>,
+    #[serde(default)]
+    users: HashMap<String, String>,
+    #[serde(default)]
+    prompts: HashMap<String, LocalizedPrompts>,
+}
+
+// The pieces build_llama_messages puts together, in the chat's language.
+pub struct BuiltInPrompts {
+    pub plain: String,
+    pub search: String,
+    pub kb_search: String,
+    pub memory: String,
+    // Appended last, so it holds even after a persona or custom prompt.
+    pub answer_language: Option<String>,
+}
+
+impl Locales {
+    pub fn from_env() -> Self {
+        let path =
+            crate::config::var("LOCALES_CONFIG").unwrap_or_else(|_| "data/locales.json".into());
+        let Ok(raw) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match serde_json::from_str(&raw) {
+            Ok(locales) => locales,
+            Err(err) => {
+                log!("failed to parse {path}: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    // The chat's language, or None to leave everything as it was.
+    pub fn resolve(
+        &self,
+        requested: Option<&str>,
+        user: Option<&str>,
+    ) -> Result<Option<String>, String> {
+        if let Some(tag) = requested.map(str::trim).filter(|t| !t.is_empty()) {
+            return valid_tag(tag)
+                .map(Some)
+                .ok_or_else(|| format!("invalid language {tag:?}; use a tag such as \"de\""));
+        }
+        Ok(user
+            .and_then(|u| self.users.get(u))
+            .or(self.default.as_ref())
+            .and_then(|tag| valid_tag(tag)))
+    }
+
+    pub fn prompts(&self, language: Option<&str>) -> BuiltInPrompts {
+        let localized = language
+            .and_then(|tag| {
+                self.prompts
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(tag))
+                    .or_else(|| {
+                        self.prompts
+                            .iter()
+                            .find(|(key, _)| key.eq_ignore_ascii_case(primary(tag)))
+                    })
+            })
+            .map(|(_, prompts)| prompts.clone())
+            .unwrap_or_default();
+        let pick = |text: Option<String>, english: &str| text.unwrap_or_else(|| english.into());
+        BuiltInPrompts {
+            plain: pick(localized.plain, PLAIN_PROMPT),
+            search: pick(localized.search, SEARCH_PROMPT),
+            kb_search: pick(localized.kb_search, KB_SEARCH_HINT),
+            memory: pick(localized.memory, MEMORY_HINT),
+            answer_language: language.map(|tag| {
+                format!(
+                    "Always answer in {}, even when the question or the sources are in another language.",
+                    language_name(tag)
+                )
+            }),
+        }
+    }
+}
+
+// Letters, digits and hyphens only, since the tag ends up in URLs.
+fn valid_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim();
+    let valid = !tag.is_empty()
+        && tag.len() <= 35
+        && tag.split('-').all(|part| {
+            !part.is_empty() && part.len() <= 8 && part.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    valid.then(|| tag.to_string())
+}
+
+// "pt-BR" -> "pt".
+pub fn primary(tag: &str) -> &str {
+    tag.split('-').next().unwrap_or(tag)
+}
+
+// English name of a language for the answer instruction; unknown tags are used as they are.
+pub fn language_name(tag: &str) -> String {
+    let name = match primary(tag).to_ascii_lowercase().as_str() {
+        "ar" => "Arabic",
+        "cs" => "Czech",
+        "da" => "Danish",
+        "de" => "German",
+        "el" => "Greek",
+        "en" => "English",
+        "es" => "Spanish",
+        "fi" => "Finnish",
+        "fr" => "French",
+        "he" => "Hebrew",
+        "hi" => "Hindi",
+        "hu" => "Hungarian",
+        "id" => "Indonesian",
+        "it" => "Italian",
+        "ja" => "Japanese",
+        "ko" => "Korean",
+        "nl" => "Dutch",
+        "no" | "nb" => "Norwegian",
+        "pl" => "Polish",
+        "pt" => "Portuguese",
+        "ro" => "Romanian",
+        "ru" => "Russian",
+        "sv" => "Swedish",
+        "th" => "Thai",
+        "tr" => "Turkish",
+        "uk" => "Ukrainian",
+        "vi" => "Vietnamese",
+        "zh" => "Chinese",
+        _ => return tag.to_string(),
+    };
+    if tag.contains('-') {
+        format!("{name} ({tag})")
+    } else {
+        name.to_string()
+    }
+}
@@ -1,30 +1,400 @@
 use axum::{
-    Json, Router,
-    extract::State,
-    response::sse::{Event, KeepAlive, Sse},
-    routing::post,
+    Extension, Json, Router,
+    extract::{DefaultBodyLimit, State},
+    middleware,
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{delete, get, post, put},
 };
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+use std::{collections::VecDeque, convert::Infallible, net::SocketAddr, sync::Arc};
+use tower_http::compression::CompressionLayer;
 use tower_http::services::{ServeDir, ServeFile};
 
+// First, so its log! macro is visible in every module.
+#[macro_use]
+mod request_id;
+
+mod access_log;
+mod admin;
+mod audio;
+mod audit;
+mod backend;
+mod basic_auth;
+mod config;
+mod cors;
+mod embeddings;
+mod extract;
+mod kb;
+mod locale;
+mod mcp_server;
+mod memory;
+mod moderation;
+mod oidc;
+mod persona;
+mod pii;
+mod prompt;
+mod quota;
+mod rbac;
+mod readability;
+mod reasoning;
+mod render;
+mod rerank;
+mod robots;
+mod search;
+mod slots;
+mod ssrf;
+mod structured;
+mod swap;
+mod tables;
+mod throttle;
+mod tls;
+mod tools;
+mod usage;
+mod users;
+
+use access_log::AccessLog;
+use audio::{TtsConfig, WhisperConfig};
+use audit::AuditLog;
+use basic_auth::BasicAuth;
+use config::RuntimeConfig;
+use embeddings::EmbeddingConfig;
+use extract::OcrConfig;
+use kb::KnowledgeBase;
+use locale::{BuiltInPrompts, Locales};
+use memory::MemoryStore;
+use moderation::{Moderation, Stage};
+use oidc::OidcVerifier;
+use persona::{Persona, PersonaStore};
+use pii::PiiRedactor;
+use prompt::{CustomPromptPolicy, PromptVariables, SystemPromptMode};
+use quota::Quotas;
+use rbac::{Role, RolePolicy};
+use render::RenderConfig;
+use rerank::RerankConfig;
+use robots::RobotsPolicy;
+use search::{ExcerptConfig, SearchCache, SearchProvider, SearchQuery};
+use slots::SlotPinning;
+use ssrf::NetworkGuard;
+use swap::ModelSwap;
+use tables::TableStore;
+use throttle::HostThrottle;
+use tools::approval::ApprovalGate;
+use tools::budget::TokenBudgetConfig;
+use tools::client::ClientToolBroker;
+use tools::code::CodeExecConfig;
+use tools::condense::CondenseConfig;
+use tools::files::FileReadConfig;
+use tools::image::ImageGenConfig;
+use tools::mcp::McpRegistry;
+use tools::plugins::PluginRegistry;
+use tools::sanitize::InjectionGuard;
+use tools::shell::ShellConfig;
+use tools::sql::SqlToolConfig;
+use tools::webhook::WebhookRegistry;
+use tools::{Tool, ToolChoice, ToolContext, ToolLoopLimits, ToolTimeouts};
+use usage::{UsageLog, UsageTracker};
+use users::{Identity, UserStore};
+
 // ---------- App state ----------
 
 #[derive(Clone)]
 struct AppState {
     llama_base_url: String,
     llama_model: String,
+    reranker: Option<RerankConfig>,
+    embeddings: Option<EmbeddingConfig>,
+    knowledge_base: Arc<KnowledgeBase>,
+    tables: Arc<TableStore>,
+    memory: Arc<MemoryStore>,
+    // Shared assistant presets chats can pick by id or name (PERSONAS_PATH).
+    personas: Arc<PersonaStore>,
+    ocr: Option<OcrConfig>,
+    whisper: Option<WhisperConfig>,
+    tts: Option<TtsConfig>,
+    image_gen: Option<ImageGenConfig>,
+    code_exec: Option<CodeExecConfig>,
+    shell: Option<ShellConfig>,
+    sql: Option<SqlToolConfig>,
+    file_read: Option<FileReadConfig>,
+    // Tools discovered from MCP servers at startup.
+    mcp: Arc<McpRegistry>,
+    // WASM tool plugins loaded from PLUGIN_DIR.
+    plugins: Arc<PluginRegistry>,
+    // HTTP tools declared in HTTP_TOOLS_CONFIG.
+    webhooks: Arc<WebhookRegistry>,
+    // Backend behind web_search/news_search (SEARCH_PROVIDER).
+    search: Arc<dyn SearchProvider>,
+    search_cache: Arc<SearchCache>,
+    // Keeps the scraper off private and internal addresses.
+    network_guard: Arc<NetworkGuard>,
+    // Pages are only scraped where robots.txt allows it (SCRAPE_RESPECT_ROBOTS).
+    robots: Option<Arc<RobotsPolicy>>,
+    // Spaces out requests to the same host across all chats.
+    host_throttle: Arc<HostThrottle>,
+    // Headless-browser fallback for script-rendered pages (RENDER_SERVICE_URL).
+    renderer: Option<RenderConfig>,
+    // Page text added to web_search results, unless a request overrides it.
+    excerpts: ExcerptConfig,
+    // Rewrite the model's query with a quick completion before searching (SEARCH_QUERY_REWRITE).
+    rewrite_queries: bool,
+    // Tool names no chat may use (DISABLED_TOOLS).
+    disabled_tools: Vec<String>,
+    // Client-executed tool calls waiting for the browser's result.
+    client_tools: Arc<ClientToolBroker>,
+    // Tool calls that wait for the user's go-ahead (APPROVAL_REQUIRED_TOOLS).
+    approvals: Arc<ApprovalGate>,
+    tool_timeouts: ToolTimeouts,
+    tool_limits: ToolLoopLimits,
+    // Shrinks oversized tool results (TOOL_SUMMARY_THRESHOLD).
+    condense: Option<CondenseConfig>,
+    // Caps tool results to a share of the context window.
+    token_budget: Option<Arc<TokenBudgetConfig>>,
+    // Neutralizes and fences third-party text in tool results (INJECTION_GUARD).
+    injection_guard: Option<InjectionGuard>,
+    // Rule and endpoint checks on user messages and replies (MODERATION_RULES, MODERATION_URL).
+    moderation: Option<Arc<Moderation>>,
+    // Masks emails, phone and card numbers in what leaves the server or is stored (PII_REDACTION).
+    pii: Option<PiiRedactor>,
+    // Where `grammar_name` looks up GBNF files (GRAMMAR_DIR).
+    grammar_dir: std::path::PathBuf,
+    // Sampling seed for chats that do not set one (LLAMA_SEED).
+    default_seed: Option<i64>,
+    // Let llama-server reuse the KV cache for the unchanged start of a chat (LLAMA_CACHE_PROMPT).
+    cache_prompt: bool,
+    // Whether chats may bring their own system prompt (CUSTOM_SYSTEM_PROMPT).
+    custom_system_prompt: CustomPromptPolicy,
+    // Values for {{name}} placeholders in system prompts (PROMPT_VARIABLES).
+    prompt_variables: Arc<PromptVariables>,
+    // Per-user answer languages and translated built-in prompts (LOCALES_CONFIG).
+    locales: Arc<Locales>,
+    // Conversation -> llama-server slot assignments (LLAMA_SLOT_PINNING).
+    slot_pinning: Option<Arc<SlotPinning>>,
+    // Alias -> llama-server, started and stopped on demand (MODEL_SWAP_CONFIG).
+    model_swap: Option<Arc<ModelSwap>>,
+    // Tokens, tool calls and wall time per chat request (USAGE_LOG).
+    usage: Arc<UsageLog>,
+    // Daily and monthly allowances per user, enforced before a chat starts (QUOTAS_CONFIG).
+    quotas: Option<Arc<Quotas>>,
+    // Settings changed through /api/admin/config, layered over the environment.
+    config: Arc<RuntimeConfig>,
+    // Bearer token for /api/admin; the admin API is off without it (ADMIN_TOKEN).
+    admin_token: Option<String>,
+    // Accounts and their login sessions; required for /api when `accounts` is on (ACCOUNTS).
+    users: Arc<UserStore>,
+    accounts: bool,
+    // Accepts bearer JWTs from an identity provider as sign-in (OIDC_ISSUER).
+    oidc: Option<Arc<OidcVerifier>>,
+    // One login in front of the UI and API (BASIC_AUTH_USER, BASIC_AUTH_PASS).
+    basic_auth: Option<BasicAuth>,
+    // Tools each role may use, and API keys with their roles (ROLES_CONFIG).
+    roles: Arc<RolePolicy>,
+    // Append-only record of chats, tool calls and fetched pages (AUDIT_LOG).
+    audit: Arc<AuditLog>,
+    // A line per HTTP request, with private fields redacted (ACCESS_LOG).
+    access_log: Option<AccessLog>,
 }
 
 impl AppState {
+    // SEARCH_PROVIDER, unless an admin has switched it at runtime.
+    fn search_provider(&self) -> Arc<dyn SearchProvider> {
+        self.config
+            .search_provider()
+            .unwrap_or_else(|| self.search.clone())
+    }
+
     fn from_env() -> Self {
+        let llama_base_url = llama_base_url_from_env();
+        let reranker = RerankConfig::from_env(&llama_base_url);
+        let embeddings = EmbeddingConfig::from_env(&llama_base_url);
+        let pii = PiiRedactor::from_env();
+        let runtime_config = RuntimeConfig::from_env();
+        let host_throttle = HostThrottle::from_env();
+        if let Some(limits) = runtime_config.overrides().scrape_rate {
+            host_throttle.set_limits(Some(limits));
+        }
         Self {
-            llama_base_url: std::env::var("LLAMA_BASE_URL")
-                .unwrap_or_else(|_| "http://127.0.0.1:8080".to_string()),
-            llama_model: std::env::var("LLAMA_MODEL").unwrap_or_else(|_| "local-model".to_string()),
+            llama_base_url,
+            llama_model: llama_model_from_env(),
+            reranker,
+            embeddings,
+            knowledge_base: Arc::new(KnowledgeBase::from_env()),
+            tables: Arc::new(TableStore::default()),
+            memory: Arc::new(MemoryStore::from_env()),
+            personas: Arc::new(PersonaStore::from_env()),
+            ocr: OcrConfig::from_env(),
+            whisper: WhisperConfig::from_env(),
+            tts: TtsConfig::from_env(),
+            image_gen: ImageGenConfig::from_env(),
+            code_exec: CodeExecConfig::from_env(),
+            shell: ShellConfig::from_env(),
+            sql: SqlToolConfig::from_env(),
+            file_read: FileReadConfig::from_env(),
+            mcp: Arc::new(McpRegistry::default()),
+            plugins: Arc::new(PluginRegistry::from_env()),
+            webhooks: Arc::new(WebhookRegistry::from_env()),
+            search: search::provider_from_env().into(),
+            search_cache: Arc::new(SearchCache::from_env()),
+            network_guard: Arc::new(NetworkGuard::from_env()),
+            robots: RobotsPolicy::from_env().map(Arc::new),
+            host_throttle: Arc::new(host_throttle),
+            renderer: RenderConfig::from_env(),
+            excerpts: ExcerptConfig::from_env(),
+            rewrite_queries: env_flag("SEARCH_QUERY_REWRITE"),
+            disabled_tools: tools::disabled_tools_from_env(),
+            client_tools: Arc::new(tools::client::broker_from_env()),
+            approvals: Arc::new(ApprovalGate::from_env()),
+            tool_timeouts: ToolTimeouts::from_env(),
+            tool_limits: ToolLoopLimits::from_env(),
+            condense: CondenseConfig::from_env(),
+            token_budget: TokenBudgetConfig::from_env().map(Arc::new),
+            injection_guard: InjectionGuard::from_env(),
+            moderation: Moderation::from_env(pii.clone()).map(Arc::new),
+            pii,
+            grammar_dir: structured::grammar_dir_from_env(),
+            default_seed: default_seed_from_env(),
+            cache_prompt: cache_prompt_from_env(),
+            custom_system_prompt: CustomPromptPolicy::from_env(),
+            prompt_variables: Arc::new(PromptVariables::from_env()),
+            locales: Arc::new(Locales::from_env()),
+            slot_pinning: None,
+            model_swap: ModelSwap::from_env().map(Arc::new),
+            usage: Arc::new(UsageLog::from_env()),
+            quotas: Quotas::from_env().map(Arc::new),
+            config: Arc::new(runtime_config),
+            admin_token: config::var("ADMIN_TOKEN")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            users: Arc::new(UserStore::from_env()),
+            accounts: env_flag("ACCOUNTS"),
+            oidc: OidcVerifier::from_env().map(Arc::new),
+            basic_auth: BasicAuth::from_env(),
+            roles: Arc::new(RolePolicy::from_env()),
+            audit: Arc::new(AuditLog::from_env()),
+            access_log: AccessLog::from_env(),
         }
     }
+
+    // A copy with the plain settings read again after a config reload. Stores, caches, child
+    // processes, pending tool calls and the usage log carry over unchanged.
+    fn reloaded(&self) -> Self {
+        let llama_base_url = llama_base_url_from_env();
+        let pii = PiiRedactor::from_env();
+        Self {
+            llama_model: llama_model_from_env(),
+            reranker: RerankConfig::from_env(&llama_base_url),
+            embeddings: EmbeddingConfig::from_env(&llama_base_url),
+            ocr: OcrConfig::from_env(),
+            whisper: WhisperConfig::from_env(),
+            tts: TtsConfig::from_env(),
+            image_gen: ImageGenConfig::from_env(),
+            code_exec: CodeExecConfig::from_env(),
+            shell: ShellConfig::from_env(),
+            sql: SqlToolConfig::from_env(),
+            file_read: FileReadConfig::from_env(),
+            webhooks: Arc::new(WebhookRegistry::from_env()),
+            search: search::provider_from_env().into(),
+            renderer: RenderConfig::from_env(),
+            excerpts: ExcerptConfig::from_env(),
+            rewrite_queries: env_flag("SEARCH_QUERY_REWRITE"),
+            disabled_tools: tools::disabled_tools_from_env(),
+            tool_timeouts: ToolTimeouts::from_env(),
+            tool_limits: ToolLoopLimits::from_env(),
+            condense: CondenseConfig::from_env(),
+            token_budget: TokenBudgetConfig::from_env().map(Arc::new),
+            injection_guard: InjectionGuard::from_env(),
+            moderation: Moderation::from_env(pii.clone()).map(Arc::new),
+            pii,
+            grammar_dir: structured::grammar_dir_from_env(),
+            default_seed: default_seed_from_env(),
+            cache_prompt: cache_prompt_from_env(),
+            custom_system_prompt: CustomPromptPolicy::from_env(),
+            prompt_variables: Arc::new(PromptVariables::from_env()),
+            locales: Arc::new(Locales::from_env()),
+            quotas: Quotas::from_env().map(Arc::new),
+            accounts: env_flag("ACCOUNTS"),
+            oidc: OidcVerifier::from_env().map(Arc::new),
+            basic_auth: BasicAuth::from_env(),
+            roles: Arc::new(RolePolicy::from_env()),
+            access_log: AccessLog::from_env(),
+            llama_base_url,
+            ..self.clone()
+        }
+    }
+}
+
+fn llama_base_url_from_env() -> String {
+    config::var("LLAMA_BASE_URL").unwrap_or_else(|_| "http://127.0.0.1:8080".to_string())
+}
+
+fn llama_model_from_env() -> String {
+    config::var("LLAMA_MODEL").unwrap_or_else(|_| "local-model".to_string())
+}
+
+fn default_seed_from_env() -> Option<i64> {
+    config::var("LLAMA_SEED")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+fn cache_prompt_from_env() -> bool {
+    config::var("LLAMA_CACHE_PROMPT")
+        .map(|v| {
+            !matches!(
+                v.trim().to_ascii_lowercase().as_str(),
+                "0" | "false" | "no" | "off"
+            )
+        })
+        .unwrap_or(true)
+}
+
+// COMPRESSION=0 turns response compression off, e.g. behind a proxy that compresses. Read at
+// startup.
+fn compression_from_env() -> bool {
+    config::var("COMPRESSION")
+        .map(|v| {
+            !matches!(
+                v.trim().to_ascii_lowercase().as_str(),
+                "0" | "false" | "no" | "off"
+            )
+        })
+        .unwrap_or(true)
+}
+
+// Router state: the current AppState, replaced whole when the configuration is reloaded.
+// Handlers extract State<Arc<AppState>> and keep that snapshot for the rest of the request.
+#[derive(Clone)]
+struct LiveState(Arc<std::sync::RwLock<Arc<AppState>>>);
+
+impl LiveState {
+    fn current(&self) -> Arc<AppState> {
+        self.0.read().unwrap().clone()
+    }
+
+    fn replace(&self, state: Arc<AppState>) {
+        *self.0.write().unwrap() = state;
+    }
+}
+
+impl axum::extract::FromRef<LiveState> for Arc<AppState> {
+    fn from_ref(live: &LiveState) -> Self {
+        live.current()
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    config::var(name)
+        .map(|v| {
+            matches!(
+                v.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            )
+        })
+        .unwrap_or(false)
 }
 
 // ---------- Chat types ----------
@@ -32,14 +402,103 @@ impl AppState {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct ChatMessage {
     role: String, // "user" | "assistant" | "system"
-    content: String,
+    content: MessageContent,
+}
+
+// Plain string or OpenAI-style content parts (text + image_url), forwarded as-is.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct ImageUrl {
+    url: String, // data:image/...;base64,... or http(s) URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl MessageContent {
+    fn validate(&self) -> Result<(), String> {
+        let MessageContent::Parts(parts) = self else {
+            return Ok(());
+        };
+        for part in parts {
+            if let ContentPart::ImageUrl { image_url } = part {
+                let url = image_url.url.as_str();
+                let is_data = url.starts_with("data:image/") && url.contains(";base64,");
+                let is_http = url.starts_with("http://") || url.starts_with("https://");
+                if !is_data && !is_http {
+                    return Err("image_url must be a base64 data:image/ URL or http(s) URL".into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MessageContent {
+    fn text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    ContentPart::ImageUrl { .. } => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    fn map_text(&mut self, f: impl Fn(&str) -> String) {
+        match self {
+            MessageContent::Text(text) => *text = f(text),
+            MessageContent::Parts(parts) => {
+                for part in parts {
+                    if let ContentPart::Text { text } = part {
+                        *text = f(text);
+                    }
+                }
+            }
+        }
+    }
+
+    fn append_text(&mut self, extra: &str) {
+        match self {
+            MessageContent::Text(text) if text.trim().is_empty() => *text = extra.to_string(),
+            MessageContent::Text(text) => {
+                text.push_str("\n\n");
+                text.push_str(extra);
+            }
+            MessageContent::Parts(parts) => parts.push(ContentPart::Text {
+                text: extra.to_string(),
+            }),
+        }
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
 struct LlamaMessage {
     role: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    content: Option<String>,
+    content: Option<MessageContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_calls: Option<Vec<ToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -71,16 +530,16 @@ struct ToolCallBuilder {
 
 impl ToolCallBuilder {
     fn merge_delta(&mut self, delta: &serde_json::Value) {
-        if let Some(id) = delta.get("id").and_then(|v| v.as_str()) {
-            if self.id.is_none() {
-                self.id = Some(id.to_string());
-            }
+        if let Some(id) = delta.get("id").and_then(|v| v.as_str())
+            && self.id.is_none()
+        {
+            self.id = Some(id.to_string());
         }
         if let Some(function) = delta.get("function") {
-            if let Some(name) = function.get("name").and_then(|v| v.as_str()) {
-                if self.function_name.is_none() {
-                    self.function_name = Some(name.to_string());
-                }
+            if let Some(name) = function.get("name").and_then(|v| v.as_str())
+                && self.function_name.is_none()
+            {
+                self.function_name = Some(name.to_string());
             }
             if let Some(args) = function.get("arguments").and_then(|v| v.as_str()) {
                 self.arguments.push_str(args);
@@ -102,74 +561,338 @@ impl ToolCallBuilder {
     }
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Serialize, Clone)]
-struct Tool {
-    #[serde(rename = "type")]
-    tool_type: String,
-    function: ToolFunction,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Serialize, Clone)]
-struct ToolFunction {
-    name: String,
-    #[serde(default, skip_serializing_if = "String::is_empty")]
-    description: String,
-    parameters: serde_json::Value,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Serialize, Clone)]
-#[serde(untagged)]
-enum ToolChoice {
-    Simple(String),
-    Detailed(serde_json::Value),
-}
-
 #[derive(Debug, Deserialize)]
 struct ChatRequest {
-    message: String,
-    use_search: bool,
+    message: MessageContent,
+    // Collections kb_search may query; empty means all of them.
+    #[serde(default)]
+    kb_collections: Vec<String>,
+    // Uploaded table ids (or names) the query_table tool may read.
+    #[serde(default)]
+    tables: Vec<String>,
+    // Voice input; transcribed via whisper and appended to `message`.
+    #[serde(default)]
+    audio: Option<audio::AudioInput>,
+    // Stream synthesized speech for the reply as `audio` events.
+    #[serde(default)]
+    tts: bool,
+    // Tools to offer by name, e.g. ["web_search", "calculate"]; see GET /api/tools.
+    #[serde(default)]
+    tools: Vec<String>,
+    // Tools the browser executes itself; calls are sent back as `tool_request` events.
+    #[serde(default)]
+    client_tools: Vec<tools::client::ClientTool>,
+    // Per-chat override of how much page text web_search includes.
+    #[serde(default)]
+    search_excerpts: search::ExcerptOptions,
+    // Generation ends when the model writes any of these strings.
+    #[serde(default)]
+    stop: Vec<String>,
+    // Constrains the answer to JSON, optionally matching a schema; see structured.rs.
+    #[serde(default)]
+    response_format: Option<structured::ResponseFormat>,
+    // A GBNF grammar the answer must follow, inline or by file name.
+    #[serde(default)]
+    grammar: Option<String>,
+    #[serde(default)]
+    grammar_name: Option<String>,
+    // Token id or text -> bias from -100 to 100, or false to ban it outright. llama-server
+    // tokenizes text keys, so {"As an AI": false} suppresses that phrase's tokens.
+    #[serde(default)]
+    logit_bias: serde_json::Map<String, serde_json::Value>,
+    // Fixed sampling seed for reproducible answers; -1 asks for a random one.
+    #[serde(default)]
+    seed: Option<i64>,
+    // Extra llama-server sampling parameters (top_k, min_p, typical_p, repeat_penalty, ...)
+    // merged into the upstream request as-is.
+    #[serde(default)]
+    sampler_overrides: serde_json::Map<String, serde_json::Value>,
+    // Thinking effort and token budget for reasoning models; see reasoning.rs.
+    #[serde(default)]
+    reasoning: Option<reasoning::ReasoningOptions>,
+    // Client-chosen id that stays the same for every turn of a conversation.
+    #[serde(default)]
+    conversation_id: Option<String>,
+    // Model alias from MODEL_SWAP_CONFIG; see GET /api/models.
+    #[serde(default)]
+    model: Option<String>,
+    // End-user id recorded with this request's usage.
+    #[serde(default)]
+    user: Option<String>,
+    // Added to (or, with system_prompt_mode "replace", used instead of) the built-in system
+    // prompt; see prompt.rs.
+    #[serde(default)]
+    system_prompt: Option<String>,
+    #[serde(default)]
+    system_prompt_mode: SystemPromptMode,
+    // Persona id or name from /api/personas; fills in the prompt, tools and sampler settings.
+    #[serde(default)]
+    persona: Option<String>,
+    // Fill {{user_name}} and {{location}} in system prompts; see prompt.rs.
+    #[serde(default)]
+    user_name: Option<String>,
+    #[serde(default)]
+    location: Option<String>,
+    // Answer and search language, e.g. "de"; see locale.rs.
+    #[serde(default)]
+    language: Option<String>,
     history: Vec<ChatMessage>,
+    // The signed-in caller's role, which limits the tools on offer; see rbac.rs.
+    #[serde(skip)]
+    role: Option<Role>,
+    // The signed-in account, which owns the memories this chat sees.
+    #[serde(skip)]
+    account: Option<String>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct SearchResult {
     title: String,
     snippet: String,
     url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    published: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    citation: Option<kb::ChunkCitation>,
+    // Search provider that returned this result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provider: Option<String>,
 }
 
 // ---------- main ----------
 
+// Largest request bodies the server reads, in bytes; bigger ones get 413 before they are
+// buffered. Read at startup.
+//   MAX_CHAT_BODY_BYTES    /api/chat/stream, history and pasted images included (default 8 MiB)
+//   MAX_UPLOAD_BYTES       document, table, audio and image uploads (default 32 MiB)
+//   MAX_BODY_BYTES         every other endpoint (default 2 MiB)
+struct BodyLimits {
+    chat: usize,
+    upload: usize,
+    default: usize,
+}
+
+impl BodyLimits {
+    fn from_env() -> Self {
+        let bytes = |name: &str, default: usize| {
+            config::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|&n: &usize| n > 0)
+                .unwrap_or(default)
+        };
+        Self {
+            chat: bytes("MAX_CHAT_BODY_BYTES", 8 * 1024 * 1024),
+            upload: bytes("MAX_UPLOAD_BYTES", 32 * 1024 * 1024),
+            default: bytes("MAX_BODY_BYTES", 2 * 1024 * 1024),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let state = AppState::from_env();
+    config::load_file()?;
+    render::check_env()?;
+    let mut state = AppState::from_env();
+    state.mcp = Arc::new(McpRegistry::from_env().await);
+    state.slot_pinning = SlotPinning::from_env(&state.llama_base_url)
+        .await
+        .map(Arc::new);
+    if let Some(swap) = &state.model_swap {
+        swap.spawn_idle_unload();
+    }
+    if let Some(quotas) = &state.quotas {
+        quotas.warn_without_sign_in(&state);
+    }
 
-    // Serve ./dist (built Svelte app).
+    // Serve ./dist (built Svelte app), preferring .br/.gz copies built next to the files.
     // If file not found, serve index.html (SPA fallback).
-    let static_files = ServeDir::new("dist").not_found_service(ServeFile::new("dist/index.html"));
+    let static_files = ServeDir::new("dist")
+        .precompressed_br()
+        .precompressed_gzip()
+        .not_found_service(ServeFile::new("dist/index.html"));
+    let state = LiveState(Arc::new(std::sync::RwLock::new(Arc::new(state))));
+    config::spawn_reload_triggers(state.clone());
+    let limits = BodyLimits::from_env();
 
     let app = Router::new()
-        .route("/api/chat/stream", post(chat_stream_handler))
+        .route(
+            "/api/chat/stream",
+            post(chat_stream_handler).layer(DefaultBodyLimit::max(limits.chat)),
+        )
+        .route(
+            "/api/chat/:id/tool_result",
+            post(tools::client::tool_result_handler),
+        )
+        .route(
+            "/api/chat/:id/approval",
+            post(tools::approval::approval_handler),
+        )
+        .route(
+            "/api/kb/documents",
+            get(kb::list_documents_handler).post(kb::add_document_handler),
+        )
+        .route(
+            "/api/kb/documents/:id",
+            get(kb::get_document_handler).delete(kb::delete_document_handler),
+        )
+        .route(
+            "/api/kb/upload",
+            post(kb::upload_document_handler).layer(DefaultBodyLimit::max(limits.upload)),
+        )
+        .route(
+            "/api/kb/collections",
+            get(kb::list_collections_handler).post(kb::create_collection_handler),
+        )
+        .route(
+            "/api/kb/collections/:name",
+            get(kb::get_collection_handler).delete(kb::delete_collection_handler),
+        )
+        .route(
+            "/api/tables",
+            get(tables::list_tables_handler)
+                .post(tables::upload_table_handler)
+                .layer(DefaultBodyLimit::max(limits.upload)),
+        )
+        .route("/api/tables/:id", delete(tables::delete_table_handler))
+        .route("/api/tools", get(tools::list_tools_handler))
+        .route("/api/models", get(swap::list_models_handler))
+        .route("/api/usage", get(usage::usage_handler))
+        .route("/api/usage/quota", get(quota::quota_handler))
+        .route("/api/embeddings", post(embeddings::embeddings_handler))
+        .route("/api/rerank", post(rerank::rerank_handler))
+        .route("/api/tokenize", post(backend::tokenize_handler))
+        .route("/api/detokenize", post(backend::detokenize_handler))
+        .route("/api/backend/stats", get(backend::stats_handler))
+        .route("/mcp", post(mcp_server::mcp_handler))
+        .route("/api/memory", get(memory::list_memories_handler))
+        .route("/api/memory/:id", delete(memory::delete_memory_handler))
+        .route(
+            "/api/personas",
+            get(persona::list_personas_handler).post(persona::create_persona_handler),
+        )
+        .route(
+            "/api/personas/:id",
+            get(persona::get_persona_handler)
+                .put(persona::update_persona_handler)
+                .delete(persona::delete_persona_handler),
+        )
+        .route(
+            "/api/transcribe",
+            post(audio::transcribe_handler).layer(DefaultBodyLimit::max(limits.upload)),
+        )
+        .route("/api/tts", post(audio::tts_handler))
+        .route(
+            "/api/ocr",
+            post(extract::ocr_handler).layer(DefaultBodyLimit::max(limits.upload)),
+        )
+        .route("/api/auth/me", get(users::me_handler))
+        .route("/api/auth/settings", put(users::update_settings_handler))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            users::require_session,
+        ))
+        .route("/api/auth/register", post(users::register_handler))
+        .route("/api/auth/login", post(users::login_handler))
+        .route("/api/auth/logout", post(users::logout_handler))
+        .nest("/api/admin", admin::router(state.clone()))
+        .nest_service(
+            tools::image::IMAGE_ROUTE,
+            ServeDir::new(tools::image::image_dir_from_env()),
+        )
         .fallback_service(static_files)
-        .with_state(Arc::new(state));
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            basic_auth::require_basic_auth,
+        ))
+        // Routes with their own limit above override this one.
+        .layer(DefaultBodyLimit::max(limits.default));
+    // brotli or gzip, as the client accepts, for static files and API responses. The default
+    // predicate leaves out the SSE chat stream, images and tiny bodies.
+    let app = if compression_from_env() {
+        app.layer(CompressionLayer::new())
+    } else {
+        app
+    };
+    let app = app.layer(middleware::from_fn_with_state(
+        state.clone(),
+        access_log::log_requests,
+    ));
+    // Outside the auth layers so preflight requests, which carry no credentials, get through.
+    let app = match cors::layer_from_env() {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+    let app = app
+        .layer(middleware::from_fn(request_id::assign))
+        .with_state(state);
 
     let addr: SocketAddr = "0.0.0.0:3000".parse()?;
-    println!("Server running at http://{addr}");
-
+    let tls = tls::acceptor_from_env()?;
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    match tls {
+        Some(acceptor) => {
+            println!("Server running at https://{addr}");
+            tls::serve(listener, app, acceptor).await
+        }
+        None => {
+            println!("Server running at http://{addr}");
+            axum::serve(listener, app).await?;
+        }
+    }
 
     Ok(())
 }
 
 // ---------- Streaming chat endpoint (passes through real llama stream) ----------
 
+// Upstream fields the proxy sets itself; the dedicated ChatRequest fields cover the rest.
+const RESERVED_UPSTREAM_KEYS: &[&str] = &[
+    "model",
+    "messages",
+    "stream",
+    "tools",
+    "tool_choice",
+    "parallel_tool_calls",
+    "parse_tool_calls",
+    "stop",
+    "response_format",
+    "grammar",
+    "json_schema",
+    "logit_bias",
+    "seed",
+    "cache_prompt",
+    "id_slot",
+    "stream_options",
+];
+
 async fn chat_stream_handler(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<ChatRequest>,
+    identity: Option<Extension<Identity>>,
+    Json(mut req): Json<ChatRequest>,
+) -> axum::response::Response {
+    // Signed in, the account is the user and its settings fill in what the request leaves out.
+    if let Some(Extension(identity)) = identity {
+        let settings = state.users.settings(&identity.username).unwrap_or_default();
+        req.language = req.language.or(settings.language);
+        req.persona = req.persona.or(settings.persona);
+        req.user_name = req.user_name.or(settings.user_name);
+        req.location = req.location.or(settings.location);
+        req.account = Some(identity.username.clone());
+        req.user = Some(identity.username);
+        req.role = Some(identity.role);
+    }
+    if let Some(quotas) = &state.quotas
+        && let Err(exceeded) = quotas.check(&state, req.user.as_deref())
+    {
+        return exceeded.into_response();
+    }
+    stream_chat(state, req).await.into_response()
+}
+
+async fn stream_chat(
+    state: Arc<AppState>,
+    mut req: ChatRequest,
 ) -> Result<
     Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>>,
     (axum::http::StatusCode, String),
@@ -187,82 +910,504 @@ async fn chat_stream_handler(
         parallel_tool_calls: Option<bool>,
         #[serde(skip_serializing_if = "Option::is_none")]
         parse_tool_calls: Option<bool>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        stop: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        response_format: Option<serde_json::Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        grammar: Option<String>,
+        #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+        logit_bias: serde_json::Map<String, serde_json::Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seed: Option<i64>,
+        cache_prompt: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id_slot: Option<usize>,
+        stream_options: serde_json::Value,
+        #[serde(flatten)]
+        sampler_overrides: serde_json::Map<String, serde_json::Value>,
     }
 
-    let search_enabled = req.use_search;
-    let mut messages = build_llama_messages(&req, search_enabled);
-    let tools = if search_enabled {
-        Some(vec![web_search_tool_definition()])
-    } else {
-        None
+    if let Err(msg) = std::iter::once(&req.message)
+        .chain(req.history.iter().map(|m| &m.content))
+        .try_for_each(MessageContent::validate)
+    {
+        return Err((axum::http::StatusCode::BAD_REQUEST, msg));
+    }
+    let language = state
+        .locales
+        .resolve(req.language.as_deref(), req.user.as_deref())
+        .map_err(|msg| (axum::http::StatusCode::BAD_REQUEST, msg))?;
+    let persona = match &req.persona {
+        Some(key) => Some(state.personas.get(key).ok_or_else(|| {
+            (
+                axum::http::StatusCode::BAD_REQUEST,
+                format!("unknown persona: {key}"),
+            )
+        })?),
+        None => None,
+    };
+    if let Some(persona) = &persona {
+        if req.tools.is_empty() {
+            req.tools = persona.tools.clone();
+        }
+        for (key, value) in &persona.sampler_overrides {
+            req.sampler_overrides
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+    if req.system_prompt.is_some() {
+        state
+            .custom_system_prompt
+            .check(req.system_prompt_mode)
+            .map_err(|msg| (axum::http::StatusCode::BAD_REQUEST, msg))?;
+    }
+    if req.stop.iter().any(String::is_empty) {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            "stop sequences must not be empty".into(),
+        ));
+    }
+    if let Some(format) = &req.response_format {
+        format
+            .validate()
+            .map_err(|msg| (axum::http::StatusCode::BAD_REQUEST, msg))?;
+    }
+    for (token, bias) in &req.logit_bias {
+        let valid = match bias {
+            serde_json::Value::Bool(false) => true,
+            serde_json::Value::Number(n) => {
+                n.as_f64().is_some_and(|b| (-100.0..=100.0).contains(&b))
+            }
+            _ => false,
+        };
+        if !valid {
+            return Err((
+                axum::http::StatusCode::BAD_REQUEST,
+                format!("logit_bias for {token:?} must be a number from -100 to 100 or false"),
+            ));
+        }
+    }
+    if let Some(key) = req
+        .sampler_overrides
+        .keys()
+        .find(|key| RESERVED_UPSTREAM_KEYS.contains(&key.as_str()))
+    {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            format!("sampler_overrides cannot set {key}"),
+        ));
+    }
+    let grammar = match (&req.grammar, &req.grammar_name) {
+        (Some(_), Some(_)) => {
+            return Err((
+                axum::http::StatusCode::BAD_REQUEST,
+                "set either grammar or grammar_name, not both".into(),
+            ));
+        }
+        (Some(grammar), None) => structured::check_grammar(grammar).map(|_| Some(grammar.clone())),
+        (None, Some(name)) => structured::load_grammar(&state.grammar_dir, name)
+            .await
+            .map(Some),
+        (None, None) => Ok(None),
+    }
+    .map_err(|msg| (axum::http::StatusCode::BAD_REQUEST, msg))?;
+    // llama-server compiles both into a grammar and refuses to combine them.
+    if grammar.is_some() && req.response_format.as_ref().is_some_and(|f| f.is_json()) {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            "grammar cannot be combined with a JSON response_format".into(),
+        ));
+    }
+
+    let default_model = state.config.default_model();
+    let model_alias = match (&state.model_swap, &req.model) {
+        (Some(swap), requested) => Some(
+            swap.resolve(requested.as_deref().or(default_model.as_deref()))
+                .map_err(|msg| (axum::http::StatusCode::BAD_REQUEST, msg))?,
+        ),
+        (None, Some(_)) => {
+            return Err((
+                axum::http::StatusCode::BAD_REQUEST,
+                "model selection requires MODEL_SWAP_CONFIG to be configured".into(),
+            ));
+        }
+        (None, None) => None,
     };
+
+    let mut transcription = None;
+    if let Some(audio_input) = &req.audio {
+        let Some(whisper) = &state.whisper else {
+            return Err((
+                axum::http::StatusCode::BAD_REQUEST,
+                "audio input requires WHISPER_URL to be configured".into(),
+            ));
+        };
+        let text = audio::transcribe_input(whisper, audio_input)
+            .await
+            .map_err(|err| {
+                log!("transcription failed: {err:?}");
+                (
+                    axum::http::StatusCode::BAD_GATEWAY,
+                    "transcription failed (see server logs)".to_string(),
+                )
+            })?;
+        req.message.append_text(&text);
+        transcription = Some(text);
+    }
+
+    let mut input_verdict = None;
+    if let Some(moderation) = state.moderation.as_ref().filter(|m| m.covers(Stage::Input)) {
+        let verdict = moderation.check(Stage::Input, &req.message.text()).await;
+        if verdict.action == Some(moderation::Action::Redact) {
+            req.message
+                .map_text(|text| moderation.redact_text(Stage::Input, text));
+        }
+        input_verdict = Some(verdict);
+    }
+
+    let tts = match (req.tts, &state.tts) {
+        (false, _) => None,
+        (true, Some(tts)) => Some(tts.clone()),
+        (true, None) => {
+            return Err((
+                axum::http::StatusCode::BAD_REQUEST,
+                "tts requires TTS_URL to be configured".into(),
+            ));
+        }
+    };
+
+    let role_allows = |name: &str| {
+        req.role
+            .is_none_or(|role| state.roles.may_use_tool(&state, role, name))
+    };
+    let mut tool_defs: Vec<Tool> = Vec::new();
+    let mut chat_tables = Vec::new();
+    for id in &req.tables {
+        match state.tables.get(id) {
+            Some(table) => chat_tables.push(table),
+            None => {
+                return Err((
+                    axum::http::StatusCode::BAD_REQUEST,
+                    format!("unknown table: {id}"),
+                ));
+            }
+        }
+    }
+    if !chat_tables.is_empty() {
+        tool_defs.push(tables::query_table_tool_definition(&chat_tables));
+    }
+    for name in &req.tools {
+        if tool_defs.iter().any(|t| &t.function.name == name) {
+            continue;
+        }
+        let tool = tools::tool_definition(&state, name)
+            .map_err(|msg| (axum::http::StatusCode::BAD_REQUEST, msg))?;
+        if let Some(role) = req.role
+            && !role_allows(name)
+        {
+            return Err((
+                axum::http::StatusCode::FORBIDDEN,
+                format!("tool {name} is not available to the {} role", role.name()),
+            ));
+        }
+        tool_defs.push(tool);
+    }
+    for tool in &req.client_tools {
+        if tool_defs.iter().any(|t| t.function.name == tool.name) {
+            return Err((
+                axum::http::StatusCode::BAD_REQUEST,
+                format!("client tool {} clashes with a server tool", tool.name),
+            ));
+        }
+        tool_defs.push(tool.definition());
+    }
+    let extension_tools = state
+        .mcp
+        .definitions()
+        .chain(state.plugins.definitions())
+        .chain(state.webhooks.definitions())
+        .filter(|(tool, auto)| {
+            *auto
+                && !tools::is_disabled(&state, &tool.function.name)
+                && role_allows(&tool.function.name)
+        });
+    for (tool, _) in extension_tools {
+        if !tool_defs
+            .iter()
+            .any(|t| t.function.name == tool.function.name)
+        {
+            tool_defs.push(tool.clone());
+        }
+    }
+    let offered = |name: &str| tool_defs.iter().any(|t| t.function.name == name);
+    let memory_summary = (offered(memory::REMEMBER) || offered(memory::RECALL))
+        .then(|| state.memory.prompt_summary(req.account.as_deref()))
+        .flatten();
+    let mut prompt_vars = state.prompt_variables.for_request(
+        model_alias
+            .as_deref()
+            .or(default_model.as_deref())
+            .unwrap_or(&state.llama_model),
+        req.user_name.as_deref().or(req.user.as_deref()),
+        req.location.as_deref(),
+    );
+    if let Some(language) = &language {
+        prompt_vars.insert("language".into(), locale::language_name(language));
+    }
+    let mut messages = build_llama_messages(
+        &req,
+        persona.as_ref(),
+        &state.locales.prompts(language.as_deref()),
+        &prompt_vars,
+        &tool_defs,
+        memory_summary.as_deref(),
+    );
+    let tools = (!tool_defs.is_empty()).then_some(tool_defs);
+    if let Some(unknown) = req
+        .kb_collections
+        .iter()
+        .find(|c| !state.knowledge_base.has_collection(c))
+    {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            format!("unknown knowledge base collection: {unknown}"),
+        ));
+    }
+    // Identifies this chat stream for /api/chat/:id/tool_result.
+    let chat_id = uuid::Uuid::new_v4().to_string();
+    let audit_scope = audit::Scope {
+        request_id: request_id::current(),
+        user: req.user.clone(),
+        conversation_id: req.conversation_id.clone(),
+        chat_id: Some(chat_id.clone()),
+    };
+    state
+        .audit
+        .chat(
+            &audit_scope,
+            serde_json::json!({
+                "message": req.message.text(),
+                "model": model_alias
+                    .as_deref()
+                    .or(default_model.as_deref())
+                    .unwrap_or(&state.llama_model),
+                "persona": persona.as_ref().map(|p| &p.name),
+                "tools": tools.iter().flatten().map(|t| &t.function.name).collect::<Vec<_>>(),
+            }),
+        )
+        .await;
+    let tool_ctx = ToolContext {
+        kb_collections: req.kb_collections.clone(),
+        tables: chat_tables,
+        question: req.message.text(),
+        token_budget: None,
+        excerpts: state.excerpts.with_overrides(&req.search_excerpts),
+        language: language.clone(),
+        role: req.role,
+        account: req.account.clone(),
+        audit: audit_scope,
+    };
+
     let tool_choice = tools
         .as_ref()
         .map(|_| ToolChoice::Simple("auto".to_string()));
 
-    let llama_model = state.llama_model.clone();
+    let client_tool_names: Vec<String> = req.client_tools.iter().map(|t| t.name.clone()).collect();
+    let stop = req.stop.clone();
+    let response_format = req.response_format.clone();
+    let logit_bias = req.logit_bias.clone();
+    let seed = req.seed.or(state.default_seed);
+    let slot_lease = match (&state.slot_pinning, &req.conversation_id) {
+        (Some(pinning), Some(conversation)) => pinning.acquire(conversation),
+        _ => None,
+    };
+    let mut sampler_overrides = req.sampler_overrides.clone();
+    let mut thinking_budget = None;
+    if let Some(options) = &req.reasoning {
+        let mut kwargs = match sampler_overrides.remove("chat_template_kwargs") {
+            Some(serde_json::Value::Object(kwargs)) => kwargs,
+            None => serde_json::Map::new(),
+            Some(_) => {
+                return Err((
+                    axum::http::StatusCode::BAD_REQUEST,
+                    "chat_template_kwargs must be an object".into(),
+                ));
+            }
+        };
+        options.apply_template_kwargs(&mut kwargs);
+        if !kwargs.is_empty() {
+            sampler_overrides.insert("chat_template_kwargs".into(), kwargs.into());
+        }
+        if let Some(effort) = options.upstream_effort() {
+            sampler_overrides.insert("reasoning_effort".into(), effort.into());
+        }
+        thinking_budget = options.budget();
+    }
+
+    let tool_limits = state.tool_limits.clone();
+    let llama_model = default_model.unwrap_or_else(|| state.llama_model.clone());
     let llama_base_url = state.llama_base_url.clone();
     let client = reqwest::Client::new();
+    let output_moderation = state.moderation.clone().filter(|m| m.covers(Stage::Output));
+
+    let mut usage_tracker = UsageTracker::start(
+        &state.usage,
+        req.user.clone(),
+        req.conversation_id.clone(),
+        &llama_model,
+    );
 
     let event_stream = async_stream::stream! {
-        let mut sources: Vec<SearchResult> = Vec::new();
-        if let Ok(sources_json) = serde_json::to_string(&sources) {
-            yield Ok::<Event, Infallible>(Event::default().event("sources").data(sources_json));
-        }
-
-        loop {
-            let llama_req = LlamaStreamRequest {
-                model: llama_model.clone(),
-                messages: messages.clone(),
-                stream: true,
-                tools: tools.clone(),
-                tool_choice: tool_choice.clone(),
-                parallel_tool_calls: None,
-                parse_tool_calls: tools.as_ref().map(|_| true),
-            };
+        // Every way out, errors and moderation blocks included, ends with a usage event.
+        'chat: {
+            if let Some(text) = transcription {
+                let payload = serde_json::json!({ "text": text });
+                yield Ok::<Event, Infallible>(request_id::event("transcription", payload));
+            }
+            if let Some(verdict) = input_verdict {
+                if let Some(event) = verdict.event() {
+                    yield Ok(event);
+                }
+                if verdict.blocked() {
+                    break 'chat;
+                }
+            }
 
-            let url = format!("{}/v1/chat/completions", llama_base_url);
-            let resp = match client
-                .post(&url)
-                .header("Content-Type", "application/json")
-                .bearer_auth("no-key")
-                .json(&llama_req)
-                .send()
-                .await
-            {
-                Ok(resp) => match resp.error_for_status() {
-                    Ok(ok) => ok,
+            // Held until the stream ends so the model is not swapped out mid-chat.
+            let mut model_lease = None;
+            if let (Some(swap), Some(alias)) = (&state.model_swap, &model_alias) {
+                let loading = !swap.is_loaded(alias);
+                if loading {
+                    let payload = serde_json::json!({ "model": alias, "status": "loading" });
+                    yield Ok(request_id::event("model_swap", payload));
+                }
+                match swap.acquire(alias).await {
+                    Ok(lease) => model_lease = Some(lease),
                     Err(err) => {
-                        eprintln!("llama response error: {err:?}");
-                        let ev = Event::default()
-                            .event("error")
-                            .data("LLM error (see server logs)");
-                        yield Ok(ev);
-                        return;
+                        log!("could not load model {alias}: {err:?}");
+                        yield Ok(request_id::error_event(format!("could not load model {alias} (see server logs)")));
+                        break 'chat;
                     }
-                },
-                Err(err) => {
-                    eprintln!("llama stream send error: {err:?}");
-                    let ev = Event::default()
-                        .event("error")
-                        .data("LLM streaming error (see server logs)");
-                    yield Ok(ev);
-                    return;
                 }
+                if loading {
+                    let payload = serde_json::json!({ "model": alias, "status": "ready" });
+                    yield Ok(request_id::event("model_swap", payload));
+                }
+            }
+            let (llama_base_url, llama_model) = match &model_lease {
+                Some(lease) => (lease.base_url.clone(), lease.model.clone()),
+                None => (llama_base_url, llama_model),
             };
-
-            let mut byte_stream = resp.bytes_stream();
-            let mut buffer = String::new();
-            let mut tool_builders: Vec<ToolCallBuilder> = Vec::new();
-            let mut saw_tool_calls = false;
-
-            'stream_loop: while let Some(chunk_res) = byte_stream.next().await {
-                match chunk_res {
-                    Ok(chunk) => {
-                        buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-                        loop {
-                            if let Some(idx) = buffer.find("\n\n") {
+            usage_tracker.set_model(&llama_model);
+
+            let mut sources: Vec<SearchResult> = Vec::new();
+            yield Ok(request_id::event("sources", serde_json::json!({ "sources": sources })));
+
+            let mut tts_splitter = audio::SentenceSplitter::default();
+            let mut tts_jobs: VecDeque<(usize, tokio::task::JoinHandle<anyhow::Result<Vec<u8>>>)> = VecDeque::new();
+            let mut tts_next_index = 0;
+            let mut moderation_buffer = moderation::OutputBuffer::default();
+            // Set when a round ran over the thinking budget; the next round continues from it.
+            let mut thinking_prefill: Option<String> = None;
+            let mut pinned_slot = slot_lease.as_ref().map(slots::SlotLease::id);
+            let mut tool_rounds = 0;
+            let mut call_counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+
+            loop {
+                usage_tracker.end_round();
+                let mut round_messages = messages.clone();
+                let round_budget = thinking_budget.filter(|_| thinking_prefill.is_none());
+                if let Some(prefill) = thinking_prefill.take() {
+                    round_messages.push(LlamaMessage {
+                        role: "assistant".into(),
+                        content: Some(prefill.into()),
+                        tool_calls: None,
+                        name: None,
+                        tool_call_id: None,
+                    });
+                }
+                let mut llama_req = LlamaStreamRequest {
+                    model: llama_model.clone(),
+                    messages: round_messages,
+                    stream: true,
+                    tools: tools.clone(),
+                    tool_choice: tool_choice.clone(),
+                    parallel_tool_calls: tools.as_ref().map(|_| true),
+                    parse_tool_calls: tools.as_ref().map(|_| true),
+                    stop: stop.clone(),
+                    response_format: response_format.as_ref().map(structured::ResponseFormat::upstream),
+                    grammar: grammar.clone(),
+                    logit_bias: logit_bias.clone(),
+                    seed,
+                    cache_prompt: state.cache_prompt,
+                    id_slot: pinned_slot,
+                    stream_options: serde_json::json!({ "include_usage": true }),
+                    sampler_overrides: sampler_overrides.clone(),
+                };
+
+                let url = format!("{}/v1/chat/completions", llama_base_url);
+                let resp = loop {
+                    let sent = request_id::tag(client.post(&url))
+                        .header("Content-Type", "application/json")
+                        .bearer_auth("no-key")
+                        .json(&llama_req)
+                        .send()
+                        .await;
+                    // A pinned slot can stop existing when llama-server restarts with fewer slots;
+                    // retry once without the pin.
+                    if let (Ok(resp), Some(lease)) = (&sent, &slot_lease)
+                        && pinned_slot.is_some()
+                        && resp.status().is_client_error()
+                    {
+                        log!(
+                            "llama-server refused slot {}: {}; retrying unpinned",
+                            lease.id(),
+                            resp.status()
+                        );
+                        lease.forget();
+                        pinned_slot = None;
+                        llama_req.id_slot = None;
+                        continue;
+                    }
+                    break sent;
+                };
+                let resp = match resp {
+                    Ok(resp) => match resp.error_for_status() {
+                        Ok(ok) => ok,
+                        Err(err) => {
+                            log!("llama response error: {err:?}");
+                            let ev = request_id::error_event("LLM error (see server logs)");
+                            yield Ok(ev);
+                            break 'chat;
+                        }
+                    },
+                    Err(err) => {
+                        log!("llama stream send error: {err:?}");
+                        let ev = request_id::error_event("LLM streaming error (see server logs)");
+                        yield Ok(ev);
+                        break 'chat;
+                    }
+                };
+
+                let mut byte_stream = resp.bytes_stream();
+                let mut buffer = String::new();
+                let mut tool_builders: Vec<ToolCallBuilder> = Vec::new();
+                let mut saw_tool_calls = false;
+                // Text of this round; the last round's is the final answer.
+                let mut answer = String::new();
+                let mut think_splitter = reasoning::ThinkSplitter::default();
+                let mut thinking = String::new();
+                let mut thinking_deltas = 0;
+                let mut thinking_cut = false;
+
+                'stream_loop: while let Some(chunk_res) = byte_stream.next().await {
+                    match chunk_res {
+                        Ok(chunk) => {
+                            buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+                            while let Some(idx) = buffer.find("\n\n") {
                                 let event_block = buffer[..idx].to_string();
                                 buffer = buffer[idx + 2..].to_string();
 
@@ -279,324 +1424,961 @@ async fn chat_stream_handler(
                                         break 'stream_loop;
                                     }
 
-                                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&data_str) {
-                                        if let Some(choice) = json["choices"].get(0) {
-                                            if let Some(delta) = choice.get("delta") {
-                                                if let Some(tool_calls) = delta.get("tool_calls").and_then(|v| v.as_array()) {
-                                                    saw_tool_calls = true;
-                                                    for tc in tool_calls {
-                                                        let index = tc.get("index").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
-                                                        if index >= tool_builders.len() {
-                                                            tool_builders.resize_with(index + 1, ToolCallBuilder::default);
-                                                        }
-                                                        tool_builders[index].merge_delta(tc);
-                                                    }
-                                                    continue;
-                                                }
+                                    let Ok(json) = serde_json::from_str::<serde_json::Value>(&data_str) else {
+                                        continue;
+                                    };
+                                    usage_tracker.observe_chunk(&json);
+                                    let Some(delta) = json["choices"].get(0).and_then(|c| c.get("delta")) else {
+                                        continue;
+                                    };
+
+                                    if let Some(tool_calls) = delta.get("tool_calls").and_then(|v| v.as_array()) {
+                                        saw_tool_calls = true;
+                                        for tc in tool_calls {
+                                            let index = tc.get("index").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+                                            if index >= tool_builders.len() {
+                                                tool_builders.resize_with(index + 1, ToolCallBuilder::default);
+                                            }
+                                            tool_builders[index].merge_delta(tc);
+                                            // Lets the UI show arguments (e.g. a search query) as they are written.
+                                            if let Some(args) = tc["function"]["arguments"].as_str()
+                                                && !args.is_empty()
+                                            {
+                                                let builder = &tool_builders[index];
+                                                let payload = serde_json::json!({
+                                                    "index": index,
+                                                    "tool_call_id": builder.id,
+                                                    "name": builder.function_name,
+                                                    "delta": args,
+                                                });
+                                                yield Ok(request_id::event("tool_args_delta", payload));
+                                            }
+                                        }
+                                        continue;
+                                    }
 
-                                                if !saw_tool_calls {
-                                                    if let Some(delta_text) = delta
-                                                        .get("content")
-                                                        .and_then(|c| c.as_str())
-                                                    {
-                                                        if !delta_text.is_empty() {
-                                                            let out_json = serde_json::json!({
-                                                                "choices": [{
-                                                                    "delta": { "content": delta_text }
-                                                                }]
-                                                            });
-                                                            yield Ok(Event::default().data(out_json.to_string()));
-                                                        }
+                                    if let Some(thought) = delta.get("reasoning_content").and_then(|c| c.as_str())
+                                        && !thought.is_empty()
+                                    {
+                                        let payload = reasoning::event_payload(thought);
+                                        yield Ok(request_id::event("reasoning", payload));
+                                        thinking.push_str(thought);
+                                        thinking_deltas += 1;
+                                        if round_budget.is_some_and(|budget| thinking_deltas > budget) {
+                                            thinking_cut = true;
+                                            break 'stream_loop;
+                                        }
+                                    }
+
+                                    if !saw_tool_calls
+                                        && let Some(delta_text) = delta.get("content").and_then(|c| c.as_str())
+                                        && !delta_text.is_empty()
+                                    {
+                                        let (thought, delta_text) = think_splitter.push(delta_text);
+                                        if !thought.is_empty() {
+                                            let payload = reasoning::event_payload(&thought);
+                                            yield Ok(request_id::event("reasoning", payload));
+                                            thinking.push_str(&thought);
+                                            thinking_deltas += 1;
+                                            if round_budget.is_some_and(|budget| thinking_deltas > budget) {
+                                                thinking_cut = true;
+                                                break 'stream_loop;
+                                            }
+                                        }
+                                        if delta_text.is_empty() {
+                                            continue;
+                                        }
+                                        // With output moderation, text is released a checked sentence at a time.
+                                        let released = match &output_moderation {
+                                            Some(_) => moderation_buffer.push(&delta_text),
+                                            None => Some(delta_text),
+                                        };
+                                        let Some(mut delta_text) = released else {
+                                            continue;
+                                        };
+                                        if let Some(moderation) = &output_moderation {
+                                            let verdict = moderation.check(Stage::Output, &delta_text).await;
+                                            if let Some(event) = verdict.event() {
+                                                yield Ok(event);
+                                            }
+                                            if verdict.blocked() {
+                                                break 'chat;
+                                            }
+                                            delta_text = verdict.text;
+                                        }
+                                        let delta_text = delta_text.as_str();
+                                        let out_json = serde_json::json!({
+                                            "choices": [{
+                                                "delta": { "content": delta_text }
+                                            }]
+                                        });
+                                        yield Ok(request_id::data_event(out_json));
+                                        answer.push_str(delta_text);
+
+                                        if let Some(tts) = &tts {
+                                            for sentence in tts_splitter.push(delta_text) {
+                                                let (client, tts) = (client.clone(), tts.clone());
+                                                let job = tokio::spawn(async move {
+                                                    audio::synthesize(&client, &tts, &sentence, None).await
+                                                });
+                                                tts_jobs.push_back((tts_next_index, job));
+                                                tts_next_index += 1;
+                                            }
+                                            // Emit finished chunks in order without waiting on pending ones.
+                                            while tts_jobs.front().is_some_and(|(_, job)| job.is_finished()) {
+                                                let (index, job) = tts_jobs.pop_front().unwrap();
+                                                match job.await {
+                                                    Ok(Ok(bytes)) => {
+                                                        let payload = audio::audio_event_payload(tts, index, &bytes);
+                                                        yield Ok(request_id::event("audio", payload));
                                                     }
+                                                    Ok(Err(err)) => log!("tts chunk failed: {err:?}"),
+                                                    Err(err) => log!("tts task failed: {err:?}"),
                                                 }
                                             }
                                         }
                                     }
                                 }
-                            } else {
-                                break;
                             }
                         }
-                    }
-                    Err(err) => {
-                        eprintln!("llama chunk error: {err:?}");
-                        let ev = Event::default()
-                            .event("error")
-                            .data("stream error (see server logs)");
-                        yield Ok(ev);
-                        return;
+                        Err(err) => {
+                            log!("llama chunk error: {err:?}");
+                            let ev = request_id::error_event("stream error (see server logs)");
+                            yield Ok(ev);
+                            break 'chat;
+                        }
                     }
                 }
-            }
 
-            if saw_tool_calls {
-                let mut built_calls = Vec::new();
-                for builder in tool_builders {
-                    if let Some(call) = builder.build() {
-                        built_calls.push(call);
-                    }
+                if thinking_cut {
+                    let payload = serde_json::json!({ "truncated": true, "budget": round_budget });
+                    yield Ok(request_id::event("reasoning", payload));
+                    thinking_prefill = Some(reasoning::closing_prefill(&thinking));
+                    continue;
                 }
 
-                if built_calls.is_empty() {
-                    eprintln!("Tool call indicated but nothing was built");
-                    break;
+                // Flush text still held back by the think splitter or the moderation buffer.
+                let (thought, mut rest) = think_splitter.finish();
+                if !thought.is_empty() {
+                    let payload = reasoning::event_payload(&thought);
+                    yield Ok(request_id::event("reasoning", payload));
+                }
+                if let Some(moderation) = &output_moderation {
+                    moderation_buffer.push(&rest);
+                    rest = moderation_buffer.finish().unwrap_or_default();
+                    if !rest.is_empty() {
+                        let verdict = moderation.check(Stage::Output, &rest).await;
+                        if let Some(event) = verdict.event() {
+                            yield Ok(event);
+                        }
+                        if verdict.blocked() {
+                            break 'chat;
+                        }
+                        rest = verdict.text;
+                    }
+                }
+                if !rest.is_empty() {
+                    let out_json = serde_json::json!({
+                        "choices": [{
+                            "delta": { "content": rest }
+                        }]
+                    });
+                    yield Ok(request_id::data_event(out_json));
+                    answer.push_str(&rest);
+                    if let Some(tts) = &tts {
+                        for sentence in tts_splitter.push(&rest) {
+                            let (client, tts) = (client.clone(), tts.clone());
+                            let job = tokio::spawn(async move {
+                                audio::synthesize(&client, &tts, &sentence, None).await
+                            });
+                            tts_jobs.push_back((tts_next_index, job));
+                            tts_next_index += 1;
+                        }
+                    }
                 }
 
-                messages.push(LlamaMessage {
-                    role: "assistant".into(),
-                    content: None,
-                    tool_calls: Some(built_calls.clone()),
-                    name: None,
-                    tool_call_id: None,
-                });
-
-                for call in built_calls {
-                    match handle_tool_call(&call).await {
-                        Ok((tool_content, maybe_sources)) => {
-                            if let Some(new_sources) = maybe_sources {
-                                sources = new_sources;
-                                if let Ok(json) = serde_json::to_string(&sources) {
-                                    yield Ok(Event::default().event("sources").data(json));
-                                }
-                            }
+                if saw_tool_calls {
+                    let mut built_calls = Vec::new();
+                    for builder in tool_builders {
+                        if let Some(call) = builder.build() {
+                            built_calls.push(call);
+                        }
+                    }
 
-                            messages.push(LlamaMessage {
-                                role: "tool".into(),
-                                content: Some(tool_content),
-                                tool_calls: None,
-                                name: Some(call.function.name.clone()),
-                                tool_call_id: Some(call.id.clone()),
-                            });
+                    if built_calls.is_empty() {
+                        log!("Tool call indicated but nothing was built");
+                        break;
+                    }
+
+                    tool_rounds += 1;
+                    if tool_rounds > tool_limits.max_iterations {
+                        log!("tool loop stopped after {} rounds", tool_limits.max_iterations);
+                        let message = format!(
+                            "stopped after {} rounds of tool calls without an answer",
+                            tool_limits.max_iterations
+                        );
+                        yield Ok(request_id::error_event(message));
+                        break 'chat;
+                    }
+                    let mut repeated = None;
+                    for call in &built_calls {
+                        let count = call_counts.entry(tools::call_signature(call)).or_insert(0);
+                        *count += 1;
+                        if *count > tool_limits.max_repeats {
+                            repeated = Some(call.function.name.clone());
                         }
-                        Err(err) => {
-                            eprintln!("Tool execution failed: {err:?}");
-                            let error_payload = serde_json::json!({
-                                "error": format!("tool {name} failed: {err}", name = call.function.name)
-                            });
-                            messages.push(LlamaMessage {
-                                role: "tool".into(),
-                                content: Some(error_payload.to_string()),
-                                tool_calls: None,
-                                name: Some(call.function.name.clone()),
-                                tool_call_id: Some(call.id.clone()),
-                            });
+                    }
+                    if let Some(name) = repeated {
+                        log!("tool loop stopped: {name} repeated with identical arguments");
+                        let message = format!("stopped: the model kept calling {name} with the same arguments");
+                        yield Ok(request_id::error_event(message));
+                        break 'chat;
+                    }
+
+                    usage_tracker.add_tool_calls(built_calls.len());
+                    messages.push(LlamaMessage {
+                        role: "assistant".into(),
+                        content: None,
+                        tool_calls: Some(built_calls.clone()),
+                        name: None,
+                        tool_call_id: None,
+                    });
+
+                    // Calls run concurrently; approval prompts and client tool requests they
+                    // raise meanwhile arrive on `tool_events`.
+                    let mut turn_ctx = tool_ctx.clone();
+                    if let Some(budget) = &state.token_budget {
+                        turn_ctx.token_budget = Some(budget.per_result_tokens(&state, built_calls.len()).await);
+                    }
+                    let (events_tx, mut tool_events) = tokio::sync::mpsc::unbounded_channel();
+                    let jobs = built_calls.iter().map(|call| {
+                        let offered = tools.iter().flatten().any(|t| t.function.name == call.function.name);
+                        run_tool_call(&state, &turn_ctx, call, &chat_id, &client_tool_names, offered, &events_tx)
+                    });
+                    let mut all_jobs = std::pin::pin!(futures_util::future::join_all(jobs));
+                    let results = loop {
+                        let event = tokio::select! {
+                            results = &mut all_jobs => break results,
+                            Some(event) = tool_events.recv() => event,
+                        };
+                        yield Ok(event);
+                    };
+                    while let Ok(event) = tool_events.try_recv() {
+                        yield Ok(event);
+                    }
+
+                    for (call, result) in built_calls.iter().zip(results) {
+                        let error = result.as_ref().err().map(|err| err.to_string());
+                        state.audit.tool_call(&tool_ctx.audit, call, error).await;
+                        match result {
+                            Ok(output) => {
+                                let sources_changed = output.sources.is_some() || !output.extra_sources.is_empty();
+                                if let Some(new_sources) = output.sources {
+                                    sources = new_sources;
+                                }
+                                sources.extend(output.extra_sources);
+                                if sources_changed {
+                                    yield Ok(request_id::event("sources", serde_json::json!({ "sources": sources })));
+                                }
+                                for (event, payload) in output.events {
+                                    yield Ok(request_id::event(event, payload));
+                                }
+
+                                messages.push(LlamaMessage {
+                                    role: "tool".into(),
+                                    content: Some(output.content.into()),
+                                    tool_calls: None,
+                                    name: Some(call.function.name.clone()),
+                                    tool_call_id: Some(call.id.clone()),
+                                });
+                            }
+                            Err(err) => {
+                                log!("Tool execution failed: {err:?}");
+                                let mut error_payload = serde_json::json!({
+                                    "error": format!("tool {name} failed: {err}", name = call.function.name)
+                                });
+                                if let Some(timeout) = err.downcast_ref::<tools::ToolTimedOut>() {
+                                    error_payload["timed_out"] = true.into();
+                                    error_payload["timeout_secs"] = timeout.secs.into();
+                                    error_payload["hint"] = "Try a narrower request or answer without this tool.".into();
+                                }
+                                messages.push(LlamaMessage {
+                                    role: "tool".into(),
+                                    content: Some(error_payload.to_string().into()),
+                                    tool_calls: None,
+                                    name: Some(call.function.name.clone()),
+                                    tool_call_id: Some(call.id.clone()),
+                                });
+                            }
                         }
                     }
+
+                    continue;
+                } else {
+                    if let Some(format) = response_format.as_ref().filter(|f| f.is_json()) {
+                        let payload = structured::finalize(&state, format, &answer).await;
+                        yield Ok(request_id::event("structured_output", payload));
+                    }
+                    break;
                 }
+            }
 
-                continue;
-            } else {
-                break;
+            if let Some(tts) = &tts {
+                if let Some(rest) = tts_splitter.finish() {
+                    let (client, tts) = (client.clone(), tts.clone());
+                    let job = tokio::spawn(async move { audio::synthesize(&client, &tts, &rest, None).await });
+                    tts_jobs.push_back((tts_next_index, job));
+                }
+                while let Some((index, job)) = tts_jobs.pop_front() {
+                    match job.await {
+                        Ok(Ok(bytes)) => {
+                            let payload = audio::audio_event_payload(tts, index, &bytes);
+                            yield Ok(request_id::event("audio", payload));
+                        }
+                        Ok(Err(err)) => log!("tts chunk failed: {err:?}"),
+                        Err(err) => log!("tts task failed: {err:?}"),
+                    }
+                }
             }
         }
+
+        let payload = usage_tracker.event_payload();
+        yield Ok(request_id::event("usage", payload));
     };
 
-    Ok(Sse::new(event_stream).keep_alive(KeepAlive::default()))
+    let request_id = request_id::current().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    Ok(Sse::new(request_id::scope_events(request_id, event_stream))
+        .keep_alive(KeepAlive::default()))
 }
 
-#[derive(Deserialize)]
-struct SearxngSearchResponse {
-    results: Vec<SearxngResult>,
-}
+// Runs one call from a turn. Events that need the user (approval prompts, client tool
+// requests) go through `events` because the calls of a turn run at the same time.
+async fn run_tool_call(
+    state: &AppState,
+    ctx: &ToolContext,
+    call: &ToolCall,
+    chat_id: &str,
+    client_tools: &[String],
+    offered: bool,
+    events: &tokio::sync::mpsc::UnboundedSender<Event>,
+) -> anyhow::Result<tools::ToolOutput> {
+    if !offered {
+        anyhow::bail!("not enabled for this chat");
+    }
+    let request = serde_json::json!({
+        "chat_id": chat_id,
+        "tool_call_id": call.id,
+        "name": call.function.name,
+        "arguments": call.function.arguments,
+    });
+    if state.approvals.requires_approval(&call.function.name) {
+        let pending = state.approvals.register(chat_id, &call.id);
+        let mut payload = request.clone();
+        payload["timeout_secs"] = state.approvals.timeout().as_secs().into();
+        let _ = events.send(request_id::event("approval_required", payload));
+        match pending.wait().await {
+            Some(true) => {}
+            Some(false) => anyhow::bail!("the user denied this tool call"),
+            None => anyhow::bail!("the user did not approve this tool call in time"),
+        }
+    }
 
-#[derive(Deserialize)]
-struct SearxngResult {
-    title: Option<String>,
-    url: Option<String>,
-    content: Option<String>,
+    // Lifecycle events let the UI show what is running ("Searching the web for ...").
+    let arguments = serde_json::from_str::<serde_json::Value>(&call.function.arguments)
+        .unwrap_or_else(|_| call.function.arguments.clone().into());
+    let started = serde_json::json!({
+        "tool_call_id": call.id,
+        "name": call.function.name,
+        "arguments": arguments,
+    });
+    let _ = events.send(request_id::event("tool_call_started", started));
+    let start = std::time::Instant::now();
+
+    let result = if client_tools.contains(&call.function.name) {
+        let pending = state.client_tools.register(chat_id, &call.id);
+        let _ = events.send(request_id::event("tool_request", request));
+        tools::client::wait_for_result(&state.client_tools, pending, &call.function.name).await
+    } else {
+        tools::handle_tool_call(state, ctx, call).await
+    };
+
+    let mut finished = serde_json::json!({
+        "tool_call_id": call.id,
+        "name": call.function.name,
+        "duration_ms": start.elapsed().as_millis() as u64,
+        "success": result.is_ok(),
+    });
+    match &result {
+        Ok(output) => finished["result_chars"] = output.content.chars().count().into(),
+        Err(err) => finished["error"] = err.to_string().into(),
+    }
+    let _ = events.send(request_id::event("tool_call_finished", finished));
+    let mut output = match (result, &state.condense) {
+        (Ok(output), Some(config)) => {
+            tools::condense::condense(state, config, &ctx.question, &call.function.name, output)
+                .await
+        }
+        (result, _) => result?,
+    };
+    if let Some(guard) = &state.injection_guard {
+        output.content = guard.wrap(&call.function.name, output.content);
+    }
+    Ok(output)
 }
 
 use reqwest::Client;
 
-async fn web_search(query: &str) -> anyhow::Result<Vec<SearchResult>> {
-    let base_url = std::env::var("SEARCH_BASE_URL")
-        .unwrap_or_else(|_| "http://127.0.0.1:4434".into());
-    let base_url = base_url.trim_end_matches('/').to_owned();
-
-    // Client used for SearXNG API
+// `extra_queries` are other phrasings of the same question; every query runs concurrently
+// and the merged results are deduplicated before reranking and scraping. The user's message in
+// `ctx` judges relevance when embeddings are configured.
+async fn web_search(
+    state: &AppState,
+    ctx: &ToolContext,
+    search: &SearchQuery<'_>,
+    extra_queries: &[String],
+) -> anyhow::Result<Vec<SearchResult>> {
+    // Client used for the search API
     let search_client = Client::builder()
-        .user_agent("Mozilla/5.0 (X11; Linux x86_64) \
-                     AppleWebKit/537.36 (KHTML, like Gecko) \
-                     Chrome/123.0.0.0 Safari/537.36")
-        .build()?;
-
-    // Client for scraping result pages — no cookies, no referer
-    let scrape_client = Client::builder()
-        .user_agent("Mozilla/5.0 (X11; Linux x86_64) \
+        .user_agent(
+            "Mozilla/5.0 (X11; Linux x86_64) \
                      AppleWebKit/537.36 (KHTML, like Gecko) \
-                     Chrome/123.0.0.0 Safari/537.36")
-        // We don't add a cookie store, but we ALSO don't set any cookies
-        // (reqwest does not send cookies unless told to).
+                     Chrome/123.0.0.0 Safari/537.36",
+        )
         .build()?;
 
-    let resp = search_client
-        .get(format!("{base_url}/search"))
-        .query(&[
-            ("q", query),
-            ("format", "json"),
-            ("language", "en"),
-        ])
-        .header("Accept", "application/json")
-        .send()
-        .await?;
-
-    let status = resp.status();
-    if !status.is_success() {
-        let body = resp.text().await.unwrap_or_default();
-        anyhow::bail!("search backend error {}: {}", status, body);
+    let cache_key = SearchCache::key(search, extra_queries, &ctx.excerpts);
+    if let Some(results) = state.search_cache.get(&cache_key) {
+        return Ok(results);
     }
 
-    let parsed: SearxngSearchResponse = resp.json().await?;
+    let rewritten;
+    let search = if state.rewrite_queries {
+        rewritten = search::rewrite_query(state, search.query).await;
+        &SearchQuery {
+            query: &rewritten,
+            category: search.category,
+            time_range: search.time_range,
+            site: search.site,
+            language: search.language,
+        }
+    } else {
+        search
+    };
 
-    let mut results: Vec<SearchResult> = parsed
-        .results
-        .into_iter()
-        .filter_map(|r| {
-            let url = r.url?;
-            let title = r.title.unwrap_or_else(|| url.clone());
-            let snippet = r.content.unwrap_or_default();
-            Some(SearchResult { title, snippet, url })
+    let scrape_client = scrape_client(&state.network_guard)?;
+    let provider = state.search_provider();
+
+    let mut queries: Vec<String> = extra_queries.to_vec();
+    let variants = config::var("SEARCH_QUERY_VARIANTS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0usize)
+        .min(3);
+    if queries.is_empty() && variants > 0 {
+        queries = search::query_variations(state, search.query, variants).await;
+    }
+    let variant_searches: Vec<SearchQuery<'_>> = queries
+        .iter()
+        .map(|query| SearchQuery {
+            query,
+            category: search.category,
+            time_range: search.time_range,
+            site: search.site,
+            language: search.language,
         })
-        .take(5)
         .collect();
-
-    for res in results.iter_mut().take(2) {
-        if let Some(excerpt) = fetch_page_excerpt(&scrape_client, &res.url).await {
-            if res.snippet.is_empty() {
-                res.snippet = excerpt;
+    let responses = futures_util::future::join_all(
+        std::iter::once(search)
+            .chain(&variant_searches)
+            .map(|q| provider.search(&search_client, q)),
+    )
+    .await;
+    let candidates = merge_search_responses(responses)?;
+    let max_per_domain = config::var("SEARCH_MAX_PER_DOMAIN")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(2);
+    let candidates = search::dedupe(candidates, max_per_domain);
+
+    let candidates = match &state.embeddings {
+        Some(config) => {
+            let question = if ctx.question.trim().is_empty() {
+                search.query
             } else {
-                res.snippet = format!("{} • Page excerpt: {}", res.snippet, excerpt);
-            }
+                &ctx.question
+            };
+            filter_by_similarity(&search_client, config, question, candidates).await
+        }
+        None => candidates,
+    };
+
+    let mut results = match &state.reranker {
+        Some(reranker) => {
+            rerank_search_results(&search_client, reranker, search.query, candidates).await
+        }
+        None => candidates,
+    };
+    results.truncate(5);
+
+    // Excerpts are fetched concurrently (SCRAPE_CONCURRENCY, default 4) and whatever has not
+    // arrived by SCRAPE_DEADLINE_SECS (default 8) is left out.
+    let env_usize = |name: &str, default: usize| {
+        config::var(name)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(default)
+    };
+    let concurrency = env_usize("SCRAPE_CONCURRENCY", 4).max(1);
+    let deadline = tokio::time::Instant::now()
+        + std::time::Duration::from_secs(env_usize("SCRAPE_DEADLINE_SECS", 8) as u64);
+    let mut fetches = Vec::new();
+    for res in results.iter().take(ctx.excerpts.pages) {
+        let fetch = fetch_page_excerpt(
+            state,
+            ctx,
+            &scrape_client,
+            &res.url,
+            ctx.excerpts.chars_per_page,
+        );
+        fetches.push(async move {
+            tokio::time::timeout_at(deadline, fetch)
+                .await
+                .ok()
+                .flatten()
+        });
+    }
+    let excerpts: Vec<Option<String>> = futures_util::stream::iter(fetches)
+        .buffered(concurrency)
+        .collect()
+        .await;
+    for (res, excerpt) in results.iter_mut().zip(excerpts) {
+        let Some(excerpt) = excerpt else {
+            continue;
+        };
+        if res.snippet.is_empty() {
+            res.snippet = excerpt;
+        } else {
+            res.snippet = format!("{} • Page excerpt: {}", res.snippet, excerpt);
         }
     }
 
+    if let Some(total_chars) = ctx.excerpts.total_chars {
+        search::fit_snippets(&mut results, total_chars);
+    }
+
+    state.search_cache.insert(cache_key, results.clone()).await;
     Ok(results)
 }
 
-async fn fetch_page_excerpt(client: &Client, url: &str) -> Option<String> {
-    use scraper::{Html, Selector};
+// Interleaves the result lists so each query's best hits come first. Fails only when every
+// query failed.
+fn merge_search_responses(
+    responses: Vec<anyhow::Result<Vec<SearchResult>>>,
+) -> anyhow::Result<Vec<SearchResult>> {
+    let mut lists = Vec::new();
+    let mut first_err = None;
+    for response in responses {
+        match response {
+            Ok(results) => lists.push(results.into_iter()),
+            Err(err) if first_err.is_none() => first_err = Some(err),
+            Err(err) => log!("search query failed: {err:?}"),
+        }
+    }
+    if lists.is_empty() {
+        return Err(first_err.unwrap_or_else(|| anyhow::anyhow!("no search queries")));
+    }
+    if let Some(err) = first_err {
+        log!("search query failed: {err:?}");
+    }
+    let mut merged = Vec::new();
+    loop {
+        let before = merged.len();
+        merged.extend(lists.iter_mut().filter_map(Iterator::next));
+        if merged.len() == before {
+            return Ok(merged);
+        }
+    }
+}
+
+// Reorders results by reranker relevance; keeps the provider's order if the reranker fails.
+async fn rerank_search_results(
+    client: &Client,
+    reranker: &RerankConfig,
+    query: &str,
+    results: Vec<SearchResult>,
+) -> Vec<SearchResult> {
+    let documents: Vec<String> = results
+        .iter()
+        .map(|r| format!("{}\n{}", r.title, r.snippet))
+        .collect();
+
+    match rerank::rerank(client, reranker, query, &documents).await {
+        Ok(scored) if !scored.is_empty() => {
+            let mut slots: Vec<Option<SearchResult>> = results.into_iter().map(Some).collect();
+            scored
+                .into_iter()
+                .filter_map(|(idx, _)| slots.get_mut(idx).and_then(Option::take))
+                .collect()
+        }
+        Ok(_) => results,
+        Err(err) => {
+            log!("rerank failed, keeping search order: {err:?}");
+            results
+        }
+    }
+}
+
+// Sorts results by embedding similarity to the question and drops those under
+// SEARCH_MIN_SIMILARITY; keeps the provider's order if the embeddings call fails.
+async fn filter_by_similarity(
+    client: &Client,
+    config: &EmbeddingConfig,
+    question: &str,
+    results: Vec<SearchResult>,
+) -> Vec<SearchResult> {
+    if results.is_empty() {
+        return results;
+    }
+    let inputs: Vec<String> = std::iter::once(question.to_string())
+        .chain(
+            results
+                .iter()
+                .map(|r| format!("{}\n{}", r.title, r.snippet)),
+        )
+        .collect();
+
+    match embeddings::embed(client, config, &inputs).await {
+        Ok(vectors) => {
+            let mut scored: Vec<(f64, SearchResult)> = results
+                .into_iter()
+                .zip(&vectors[1..])
+                .map(|(result, vector)| {
+                    (embeddings::cosine_similarity(&vectors[0], vector), result)
+                })
+                .filter(|(score, _)| config.min_similarity.is_none_or(|min| *score >= min))
+                .collect();
+            scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+            scored.into_iter().map(|(_, result)| result).collect()
+        }
+        Err(err) => {
+            log!("embedding similarity failed, keeping search order: {err:?}");
+            results
+        }
+    }
+}
+
+// Client for scraping pages — no cookies, no referer
+fn scrape_client(guard: &Arc<NetworkGuard>) -> reqwest::Result<Client> {
+    Client::builder()
+        .user_agent(
+            "Mozilla/5.0 (X11; Linux x86_64) \
+                     AppleWebKit/537.36 (KHTML, like Gecko) \
+                     Chrome/123.0.0.0 Safari/537.36",
+        )
+        // We don't add a cookie store, but we ALSO don't set any cookies
+        // (reqwest does not send cookies unless told to).
+        .dns_resolver(Arc::clone(guard))
+        .redirect(guard.redirect_policy())
+        .build()
+}
+
+struct FetchedPage {
+    title: Option<String>,
+    byline: Option<String>,
+    text: String,
+}
+
+async fn fetch_page_excerpt(
+    state: &AppState,
+    ctx: &ToolContext,
+    client: &Client,
+    url: &str,
+    max_chars: usize,
+) -> Option<String> {
+    fetch_page(state, ctx, client, url, max_chars)
+        .await
+        .map(|page| page.text)
+}
+
+async fn fetch_page(
+    state: &AppState,
+    ctx: &ToolContext,
+    client: &Client,
+    url: &str,
+    max_chars: usize,
+) -> Option<FetchedPage> {
+    let parsed = match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            log!("not fetching {url}: {err}");
+            return None;
+        }
+    };
+    if let Err(reason) = state.network_guard.check_url(&parsed) {
+        log!("not fetching {url}: {reason}");
+        return None;
+    }
+    if let Some(robots) = &state.robots
+        && !robots.allows(client, url).await
+    {
+        log!("robots.txt disallows fetching {url}");
+        return None;
+    }
+    if !state
+        .host_throttle
+        .acquire(parsed.host_str().unwrap_or_default())
+        .await
+    {
+        log!("not fetching {url}: too many recent requests to that host");
+        return None;
+    }
 
     // Normal GET — reqwest won't send cookies unless explicitly configured
     let resp = client
         .get(url)
-        .header("Accept", "text/html,*/*")
+        .header("Accept", "text/html,application/pdf;q=0.9,*/*")
         // IMPORTANT: we intentionally do NOT set Referer
         .send()
-        .await
-        .ok()?;
+        .await;
+    let status = resp.as_ref().ok().map(|r| r.status().as_u16());
+    state.audit.fetch(&ctx.audit, url, status).await;
+    let resp = resp.ok()?;
 
     if !resp.status().is_success() {
         return None;
     }
 
-    let body = resp.text().await.ok()?;
-    let document = Html::parse_document(&body);
-    let body_sel = Selector::parse("body").ok()?;
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let is_pdf = extract::is_pdf(content_type.as_deref(), url, &[]);
+    if !is_pdf && !is_page_content_type(content_type.as_deref()) {
+        log!(
+            "not scraping {url}: unsupported content type {}",
+            content_type.unwrap_or_default()
+        );
+        return None;
+    }
+    let max_bytes = scrape_max_bytes();
+    if resp
+        .content_length()
+        .is_some_and(|len| len > max_bytes as u64)
+    {
+        log!("not scraping {url}: larger than {max_bytes} bytes");
+        return None;
+    }
+    let (bytes, truncated) = match read_capped(resp, max_bytes).await {
+        Ok(read) => read,
+        Err(err) => {
+            log!("reading {url} failed: {err}");
+            return None;
+        }
+    };
 
-    let mut text = String::new();
-    for node in document.select(&body_sel) {
-        text.push_str(&node.text().collect::<String>());
+    if is_pdf {
+        // A cut-off PDF cannot be parsed.
+        if truncated {
+            log!("not scraping {url}: larger than {max_bytes} bytes");
+            return None;
+        }
+        let extracted = match extract::extract_pdf(bytes).await {
+            Ok(extracted) => extracted,
+            Err(err) => {
+                log!("pdf excerpt failed for {url}: {err:?}");
+                return None;
+            }
+        };
+        let excerpt = extracted.excerpt(max_chars);
+        return (!excerpt.is_empty()).then_some(FetchedPage {
+            title: None,
+            byline: None,
+            text: excerpt,
+        });
     }
 
-    let cleaned = text.split_whitespace().collect::<Vec<_>>().join(" ");
-    if cleaned.is_empty() {
+    // HTML is still useful when cut off, so a long page is parsed up to the cap.
+    if truncated {
+        log!("{url} exceeds {max_bytes} bytes; using the first part");
+    }
+    let body = decode_page(&bytes, content_type.as_deref(), url);
+    let mut article = readability::extract(&body, url);
+    if let Some(renderer) = &state.renderer
+        && article.text.chars().count() < renderer.min_chars
+        && state
+            .host_throttle
+            .acquire(parsed.host_str().unwrap_or_default())
+            .await
+    {
+        match render::render(renderer, url, max_bytes).await {
+            Ok(html) => {
+                let rendered = readability::extract(&html, url);
+                if rendered.text.chars().count() > article.text.chars().count() {
+                    article = rendered;
+                }
+            }
+            Err(err) => log!("rendering {url} failed: {err:?}"),
+        }
+    }
+    if article.text.is_empty() {
         return None;
     }
 
-    Some(cleaned.chars().take(4000).collect())
+    Some(FetchedPage {
+        title: article.title,
+        byline: article.byline,
+        text: article.text.chars().take(max_chars).collect(),
+    })
 }
 
-
-fn web_search_tool_definition() -> Tool {
-    Tool {
-        tool_type: "function".into(),
-        function: ToolFunction {
-            name: "web_search".into(),
-            description: "Searches the web and returns the top results.".into(),
-            parameters: serde_json::json!({
-                "type": "object",
-                "properties": {
-                    "query": {
-                        "type": "string",
-                        "description": "Short search query describing what you need to know"
-                    },
-                    "max_results": {
-                        "type": "integer",
-                        "minimum": 1,
-                        "maximum": 5,
-                        "description": "Optional maximum number of results to return (default 5)"
-                    }
-                },
-                "required": ["query"]
-            }),
-        },
-    }
+// Pages without a Content-Type are tried; anything that is clearly not a document is not.
+fn is_page_content_type(content_type: Option<&str>) -> bool {
+    let Some(content_type) = content_type else {
+        return true;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    matches!(
+        mime.as_str(),
+        "" | "text/html" | "application/xhtml+xml" | "text/plain" | "text/xml" | "application/xml"
+    )
 }
 
-#[derive(Deserialize)]
-struct WebSearchToolArgs {
-    query: String,
-    #[serde(default)]
-    max_results: Option<usize>,
+// SCRAPE_MAX_BYTES (default 5 MB) caps how much of any page is downloaded.
+fn scrape_max_bytes() -> usize {
+    config::var("SCRAPE_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(5 * 1024 * 1024)
 }
 
-async fn handle_tool_call(call: &ToolCall) -> anyhow::Result<(String, Option<Vec<SearchResult>>)> {
-    match call.function.name.as_str() {
-        "web_search" => {
-            let args: WebSearchToolArgs = serde_json::from_str(&call.function.arguments)
-                .map_err(|e| anyhow::anyhow!("invalid search args: {e}"))?;
-            let trimmed_query = args.query.trim();
-            if trimmed_query.is_empty() {
-                anyhow::bail!("search query missing");
-            }
-            let mut results = web_search(trimmed_query).await?;
-            let limit = args.max_results.unwrap_or(5).clamp(1, 7);
-            if results.len() > limit {
-                results.truncate(limit);
-            }
-            let payload = format_search_results_for_tool(&results, trimmed_query);
-            Ok((payload, Some(results)))
-        }
-        other => {
-            anyhow::bail!("unknown tool call: {other}");
+// Reads the body up to `max_bytes`; the flag is set when there was more.
+async fn read_capped(
+    mut resp: reqwest::Response,
+    max_bytes: usize,
+) -> reqwest::Result<(Vec<u8>, bool)> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        if bytes.len() + chunk.len() > max_bytes {
+            bytes.extend_from_slice(&chunk[..max_bytes - bytes.len()]);
+            return Ok((bytes, true));
         }
+        bytes.extend_from_slice(&chunk);
     }
+    Ok((bytes, false))
 }
 
-fn format_search_results_for_tool(results: &[SearchResult], query: &str) -> String {
-    let entries: Vec<_> = results
-        .iter()
-        .enumerate()
-        .map(|(i, r)| {
-            serde_json::json!({
-                "id": i + 1,
-                "title": r.title,
-                "snippet": r.snippet,
-                "url": r.url,
-            })
-        })
-        .collect();
+// Picks the encoding like a browser would: byte order mark, then the Content-Type charset,
+// then a <meta> declaration near the top, then UTF-8 if the bytes are valid UTF-8, and
+// finally a statistical guess (helped by the URL's top-level domain).
+fn decode_page(bytes: &[u8], content_type: Option<&str>, url: &str) -> String {
+    if let Some((encoding, bom_len)) = encoding_rs::Encoding::for_bom(bytes) {
+        return encoding
+            .decode_without_bom_handling(&bytes[bom_len..])
+            .0
+            .into_owned();
+    }
+    let declared = content_type
+        .and_then(charset_param)
+        .or_else(|| meta_charset(bytes))
+        .and_then(|label| encoding_rs::Encoding::for_label(label.trim().as_bytes()));
+    let encoding = match declared {
+        Some(encoding) => encoding,
+        None if std::str::from_utf8(bytes).is_ok() => encoding_rs::UTF_8,
+        None => {
+            let tld = reqwest::Url::parse(url).ok().and_then(|u| {
+                u.host_str()
+                    .and_then(|h| h.rsplit('.').next())
+                    .map(str::to_ascii_lowercase)
+            });
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(bytes, true);
+            detector.guess(tld.as_deref().map(str::as_bytes), true)
+        }
+    };
+    encoding.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+fn charset_param(content_type: &str) -> Option<String> {
+    content_type
+        .split(';')
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches(['"', '\'']).to_string())
+}
 
-    serde_json::json!({
-        "query": query,
-        "results": entries,
-        "instructions": "Use the snippets and cite sources like [id] in your response."
+// <meta charset="..."> or <meta http-equiv="Content-Type" content="...; charset=...">
+// within the first few kilobytes.
+fn meta_charset(bytes: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]).to_ascii_lowercase();
+    head.split("<meta").skip(1).find_map(|tag| {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let value = &tag[tag.find("charset=")? + "charset=".len()..];
+        let value = value.trim_start_matches(['"', '\'']);
+        let end = value
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "-_:.".contains(c)))
+            .unwrap_or(value.len());
+        (end > 0).then(|| value[..end].to_string())
     })
-    .to_string()
 }
 
 // ---------- Non-streaming call to llama-server ----------
 
-fn build_llama_messages(req: &ChatRequest, search_enabled: bool) -> Vec<LlamaMessage> {
+// Assembly order. Everything before the new message must come out byte-identical from one
+// turn to the next so llama-server can reuse the KV cache for that prefix (cache_prompt):
+//   1. the system prompt, whose text depends only on which tools are offered, the persona
+//      and the prompt variables (of which only {{date}} and {{weekday}} change, once a day);
+//   2. the history, exactly as the client sent it;
+//   3. the new user message, followed by per-turn context such as the memory summary;
+//   4. within a turn, the assistant's tool calls and the tool results.
+// Tool definitions are rendered by the chat template near the system prompt, so they keep the
+// order in which the chat offers them. Anything that changes between turns belongs in step 3.
+fn build_llama_messages(
+    req: &ChatRequest,
+    persona: Option<&Persona>,
+    prompts: &BuiltInPrompts,
+    prompt_vars: &std::collections::BTreeMap<String, String>,
+    tools: &[Tool],
+    memory_summary: Option<&str>,
+) -> Vec<LlamaMessage> {
     let mut messages = Vec::<LlamaMessage>::new();
+    let offered = |name: &str| tools.iter().any(|t| t.function.name == name);
 
-    let system_prompt = if search_enabled {
-        "You are a helpful AI assistant. You can call the web_search tool to fetch recent web information.\n\
-Use the tool whenever the user asks for factual data you are unsure about.\n\
-When citing information derived from tool results, refer to them as [n] where n is the result index."
+    let mut system_prompt = if offered("web_search") {
+        prompts.search.clone()
     } else {
-        "You are a helpful AI assistant. Answer as clearly as possible using only your existing knowledge."
+        prompts.plain.clone()
     };
+    if offered("kb_search") {
+        system_prompt.push('\n');
+        system_prompt.push_str(&prompts.kb_search);
+    }
+    if offered(memory::REMEMBER) || offered(memory::RECALL) {
+        system_prompt.push('\n');
+        system_prompt.push_str(&prompts.memory);
+    }
+    if let Some(persona) = persona {
+        system_prompt = prompt::apply_custom(
+            system_prompt,
+            Some(&persona.system_prompt),
+            persona.system_prompt_mode,
+        );
+    }
+    // Only the operator's and the persona's text is a template, not the caller's.
+    let mut system_prompt = prompt::apply_custom(
+        prompt::render(&system_prompt, prompt_vars),
+        req.system_prompt.as_deref(),
+        req.system_prompt_mode,
+    );
+    if let Some(instruction) = &prompts.answer_language {
+        system_prompt.push_str("\n\n");
+        system_prompt.push_str(&prompt::render(instruction, prompt_vars));
+    }
 
     messages.push(LlamaMessage {
         role: "system".into(),
@@ -616,9 +2398,17 @@ When citing information derived from tool results, refer to them as [n] where n
         });
     }
 
+    // Saved memories change as the chat goes on, so they follow the new message rather than
+    // sitting in the system prompt.
+    let mut message = req.message.clone();
+    if let Some(summary) = memory_summary {
+        message.append_text(&format!(
+            "[From your long-term memory, not written by the user]\nWhat you remember about the user:\n{summary}"
+        ));
+    }
     messages.push(LlamaMessage {
         role: "user".into(),
-        content: Some(req.message.clone()),
+        content: Some(message),
         tool_calls: None,
         name: None,
         tool_call_id: None,
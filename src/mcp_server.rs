@@ -0,0 +1,146 @@
+use axum::{
+    Extension, Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+use crate::rbac::Role;
+use crate::tools::{self, Tool, ToolContext};
+use crate::users::Identity;
+use crate::{AppState, ToolCall, ToolCallFunctionCall};
+
+// Streamable HTTP MCP endpoint exposing the search/scrape/knowledge-base stack to other
+// agent frontends. Replies are always plain JSON, which the transport allows; there are
+// no server-initiated messages, so no SSE stream or session is needed. Signed-in callers only
+// see and call the tools their role allows, and their calls are audited under their name.
+
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+fn exposed_tools(state: &AppState, role: Option<Role>) -> Vec<Tool> {
+    [
+        "web_search",
+        tools::NEWS_SEARCH,
+        tools::open_url::TOOL_NAME,
+        "kb_search",
+    ]
+    .into_iter()
+    .filter(|name| role.is_none_or(|role| state.roles.may_use_tool(state, role, name)))
+    .filter_map(|name| tools::tool_definition(state, name).ok())
+    .collect()
+}
+
+fn rpc_result(id: &serde_json::Value, result: serde_json::Value) -> Response {
+    Json(serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result })).into_response()
+}
+
+fn rpc_error(id: &serde_json::Value, code: i64, message: &str) -> Response {
+    Json(serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    }))
+    .into_response()
+}
+
+pub async fn mcp_handler(
+    State(state): State<Arc<AppState>>,
+    identity: Option<Extension<Identity>>,
+    body: String,
+) -> Response {
+    let identity = identity.map(|Extension(identity)| identity);
+    let role = identity.as_ref().map(|identity| identity.role);
+    let Ok(message) = serde_json::from_str::<serde_json::Value>(&body) else {
+        return rpc_error(&serde_json::Value::Null, -32700, "parse error");
+    };
+    let method = message["method"].as_str().unwrap_or_default();
+    // Notifications and responses need no reply.
+    let Some(id) = message.get("id").filter(|_| !method.is_empty()) else {
+        return StatusCode::ACCEPTED.into_response();
+    };
+    let params = &message["params"];
+
+    match method {
+        "initialize" => {
+            // Echo the client's version when given; we only use features common to all.
+            let version = params["protocolVersion"]
+                .as_str()
+                .unwrap_or(PROTOCOL_VERSION);
+            rpc_result(
+                id,
+                serde_json::json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": {} },
+                    "serverInfo": {
+                        "name": "chat-llama",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }),
+            )
+        }
+        "ping" => rpc_result(id, serde_json::json!({})),
+        "tools/list" => {
+            let tools: Vec<_> = exposed_tools(&state, role)
+                .into_iter()
+                .map(|t| {
+                    serde_json::json!({
+                        "name": t.function.name,
+                        "description": t.function.description,
+                        "inputSchema": t.function.parameters,
+                    })
+                })
+                .collect();
+            rpc_result(id, serde_json::json!({ "tools": tools }))
+        }
+        "tools/call" => {
+            let Some(name) = params["name"].as_str() else {
+                return rpc_error(id, -32602, "missing tool name");
+            };
+            if !exposed_tools(&state, role)
+                .iter()
+                .any(|t| t.function.name == name)
+            {
+                return rpc_error(id, -32602, &format!("unknown tool: {name}"));
+            }
+            let call = ToolCall {
+                id: "mcp".into(),
+                call_type: "function".into(),
+                function: ToolCallFunctionCall {
+                    name: name.to_string(),
+                    arguments: params
+                        .get("arguments")
+                        .cloned()
+                        .unwrap_or_else(|| serde_json::json!({}))
+                        .to_string(),
+                },
+            };
+            let ctx = ToolContext {
+                excerpts: state.excerpts.clone(),
+                role,
+                account: identity.as_ref().map(|identity| identity.username.clone()),
+                audit: crate::audit::Scope {
+                    request_id: crate::request_id::current(),
+                    user: identity.map(|identity| identity.username),
+                    ..Default::default()
+                },
+                ..ToolContext::default()
+            };
+            // Tool failures are results the caller's model should see, not protocol errors.
+            let (text, is_error) = match tools::handle_tool_call(&state, &ctx, &call).await {
+                Ok(output) => (output.content, false),
+                Err(err) => (err.to_string(), true),
+            };
+            let error = is_error.then(|| text.clone());
+            state.audit.tool_call(&ctx.audit, &call, error).await;
+            rpc_result(
+                id,
+                serde_json::json!({
+                    "content": [{ "type": "text", "text": text }],
+                    "isError": is_error,
+                }),
+            )
+        }
+        other => rpc_error(id, -32601, &format!("method not found: {other}")),
+    }
+}
@@ -0,0 +1,355 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use crate::AppState;
+use crate::tools::{Tool, ToolOutput};
+use crate::users::Identity;
+
+pub const REMEMBER: &str = "remember";
+pub const RECALL: &str = "recall";
+
+const MAX_MEMORY_CHARS: usize = 500;
+
+// ---------- Store ----------
+
+// With sign-in on, each memory belongs to the account that saved it and nobody else sees,
+// recalls or deletes it; MEMORY_MAX_ENTRIES applies per account. Without sign-in there is no
+// owner and everyone shares the memories.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Memory {
+    pub id: String,
+    pub text: String,
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct MemoryFile {
+    memories: Vec<Memory>,
+}
+
+pub struct MemoryStore {
+    memories: RwLock<Vec<Memory>>,
+    path: Option<PathBuf>,
+    write_lock: tokio::sync::Mutex<()>,
+    max_entries: usize,
+    prompt_chars: usize,
+}
+
+impl MemoryStore {
+    pub fn load(path: Option<PathBuf>, max_entries: usize, prompt_chars: usize) -> Self {
+        let file: MemoryFile = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|raw| match serde_json::from_str(&raw) {
+                Ok(file) => Some(file),
+                Err(err) => {
+                    log!("failed to parse memory file: {err:?}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            memories: RwLock::new(file.memories),
+            path,
+            write_lock: tokio::sync::Mutex::new(()),
+            max_entries,
+            prompt_chars,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let path = match crate::config::var("MEMORY_PATH") {
+            Ok(p) if p.trim().is_empty() => None,
+            Ok(p) => Some(PathBuf::from(p)),
+            Err(_) => Some(PathBuf::from("data/memory.json")),
+        };
+        let env_usize = |name: &str, default: usize| {
+            crate::config::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        Self::load(
+            path,
+            env_usize("MEMORY_MAX_ENTRIES", 500),
+            env_usize("MEMORY_PROMPT_CHARS", 2000),
+        )
+    }
+
+    async fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let _guard = self.write_lock.lock().await;
+        let json = {
+            let memories = self.memories.read().unwrap();
+            serde_json::to_vec(&serde_json::json!({ "memories": &*memories }))?
+        };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    // Stores a fact unless the owner has an identical one; the owner's oldest entries go once
+    // the cap is hit.
+    pub async fn remember(
+        &self,
+        owner: Option<&str>,
+        text: &str,
+    ) -> anyhow::Result<(Memory, bool)> {
+        let (memory, created) = {
+            let mut memories = self.memories.write().unwrap();
+            if let Some(existing) = memories
+                .iter()
+                .find(|m| m.owner.as_deref() == owner && m.text.eq_ignore_ascii_case(text))
+            {
+                (existing.clone(), false)
+            } else {
+                let memory = Memory {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    text: text.to_string(),
+                    created_at: chrono::Utc::now()
+                        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                    owner: owner.map(String::from),
+                };
+                memories.push(memory.clone());
+                let mut excess = memories
+                    .iter()
+                    .filter(|m| m.owner.as_deref() == owner)
+                    .count()
+                    .saturating_sub(self.max_entries);
+                memories.retain(|m| {
+                    let drop = excess > 0 && m.owner.as_deref() == owner;
+                    excess -= drop as usize;
+                    !drop
+                });
+                (memory, true)
+            }
+        };
+        if created {
+            self.persist().await?;
+        }
+        Ok((memory, created))
+    }
+
+    pub async fn delete(&self, owner: Option<&str>, id: &str) -> anyhow::Result<bool> {
+        let removed = {
+            let mut memories = self.memories.write().unwrap();
+            let before = memories.len();
+            memories.retain(|m| m.id != id || m.owner.as_deref() != owner);
+            memories.len() != before
+        };
+        if removed {
+            self.persist().await?;
+        }
+        Ok(removed)
+    }
+
+    pub fn list(&self, owner: Option<&str>) -> Vec<Memory> {
+        self.memories
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|m| m.owner.as_deref() == owner)
+            .cloned()
+            .collect()
+    }
+
+    // Ranks memories by how many query words they contain, newest first on ties.
+    // An empty query returns the most recent memories.
+    pub fn recall(&self, owner: Option<&str>, query: &str, limit: usize) -> Vec<Memory> {
+        let terms = words(query);
+        let memories = self.memories.read().unwrap();
+        let mut scored: Vec<(usize, usize, &Memory)> = memories
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.owner.as_deref() == owner)
+            .map(|(i, m)| {
+                let text = words(&m.text);
+                let score = terms.iter().filter(|t| text.contains(t)).count();
+                (score, i, m)
+            })
+            .filter(|(score, _, _)| terms.is_empty() || *score > 0)
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, _, m)| m.clone())
+            .collect()
+    }
+
+    // Most recent memories that fit the prompt budget, oldest of those first.
+    pub fn prompt_summary(&self, owner: Option<&str>) -> Option<String> {
+        let memories = self.memories.read().unwrap();
+        let mut lines = Vec::new();
+        let mut used = 0;
+        for memory in memories
+            .iter()
+            .rev()
+            .filter(|m| m.owner.as_deref() == owner)
+        {
+            used += memory.text.chars().count() + 3;
+            if used > self.prompt_chars {
+                break;
+            }
+            lines.push(format!("- {}", memory.text));
+        }
+        if lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(lines.join("\n"))
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 2)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// ---------- remember / recall tools ----------
+
+pub fn remember_tool_definition() -> Tool {
+    Tool::function(
+        REMEMBER,
+        "Saves a short fact about the user (a preference, detail, or ongoing project) to long-term \
+memory so it is available in future conversations. Only save things the user would want \
+remembered; write each fact as one self-contained sentence.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "fact": {
+                    "type": "string",
+                    "description": "The fact to remember, e.g. \"The user prefers metric units.\""
+                }
+            },
+            "required": ["fact"]
+        }),
+    )
+}
+
+pub fn recall_tool_definition() -> Tool {
+    Tool::function(
+        RECALL,
+        "Searches long-term memory for facts saved in earlier conversations.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "Keywords to look for; empty returns the most recent memories"
+                },
+                "max_results": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 20,
+                    "description": "Optional maximum number of memories to return (default 10)"
+                }
+            }
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct RememberArgs {
+    fact: String,
+}
+
+#[derive(Deserialize)]
+struct RecallArgs {
+    #[serde(default)]
+    query: String,
+    #[serde(default)]
+    max_results: Option<usize>,
+}
+
+pub async fn run_remember(
+    store: &MemoryStore,
+    owner: Option<&str>,
+    arguments: &str,
+) -> anyhow::Result<ToolOutput> {
+    let args: RememberArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid remember args: {e}"))?;
+    let fact = args.fact.split_whitespace().collect::<Vec<_>>().join(" ");
+    if fact.is_empty() {
+        anyhow::bail!("fact missing");
+    }
+    if fact.chars().count() > MAX_MEMORY_CHARS {
+        anyhow::bail!("fact is too long; keep it under {MAX_MEMORY_CHARS} characters");
+    }
+    let (memory, created) = store.remember(owner, &fact).await?;
+    Ok(ToolOutput::text(
+        serde_json::json!({
+            "status": if created { "saved" } else { "already remembered" },
+            "memory": memory.text,
+        })
+        .to_string(),
+    ))
+}
+
+pub fn run_recall(
+    store: &MemoryStore,
+    owner: Option<&str>,
+    arguments: &str,
+) -> anyhow::Result<ToolOutput> {
+    let args: RecallArgs =
+        serde_json::from_str(arguments).map_err(|e| anyhow::anyhow!("invalid recall args: {e}"))?;
+    let limit = args.max_results.unwrap_or(10).clamp(1, 20);
+    let memories = store.recall(owner, args.query.trim(), limit);
+    let entries: Vec<_> = memories
+        .iter()
+        .map(|m| serde_json::json!({ "fact": m.text, "saved_at": m.created_at }))
+        .collect();
+    Ok(ToolOutput::text(
+        serde_json::json!({ "query": args.query.trim(), "memories": entries }).to_string(),
+    ))
+}
+
+// ---------- HTTP handlers ----------
+
+fn owner(identity: Option<Extension<Identity>>) -> Option<String> {
+    identity.map(|Extension(identity)| identity.username)
+}
+
+pub async fn list_memories_handler(
+    State(state): State<Arc<AppState>>,
+    identity: Option<Extension<Identity>>,
+) -> Json<Vec<Memory>> {
+    Json(state.memory.list(owner(identity).as_deref()))
+}
+
+pub async fn delete_memory_handler(
+    State(state): State<Arc<AppState>>,
+    identity: Option<Extension<Identity>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    match state.memory.delete(owner(identity).as_deref(), &id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "memory not found".into())),
+        Err(err) => {
+            log!("memory delete failed: {err:?}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to delete memory".into(),
+            ))
+        }
+    }
+}
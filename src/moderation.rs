@@ -0,0 +1,371 @@
+use axum::response::sse::Event;
+use regex::Regex;
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::pii::PiiRedactor;
+
+// Moderation of the user's message before generation and of the model's reply while it
+// streams. Rules live in MODERATION_RULES (default data/moderation.json) as a JSON array:
+//   [{ "keywords": ["foo", "bar baz"], "action": "block", "category": "profanity" },
+//    { "pattern": "\\b\\d{3}-\\d{2}-\\d{4}\\b", "action": "redact", "stages": ["output"] }]
+// `keywords` match case-insensitively on word boundaries, `pattern` is a regex. Actions are
+// block (stop and refuse), redact (replace the match) and flag (report only); a rule covers
+// both stages unless `stages` says otherwise.
+//
+// MODERATION_URL additionally sends text to an OpenAI-compatible moderation endpoint
+// (POST {"input": ...} answered with {"results": [{"flagged": ..., "categories": {...}}]}).
+// Flagged text gets MODERATION_URL_ACTION, block (default) or flag; MODERATION_URL_STAGES
+// limits the stages it checks. Endpoint failures are logged and let the text through. With
+// PII_REDACTION on, personal data is masked before text goes to the endpoint.
+//
+// The reply is checked a sentence at a time and only released once checked, so redacted or
+// blocked text never reaches the client. Every triggered check emits a `moderation` event.
+
+const REDACTED: &str = "[redacted]";
+// Unpunctuated output (code, lists) is released at a word break past this length.
+const MAX_HOLD_CHARS: usize = 400;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Flag,
+    Redact,
+    Block,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Flag => "flag",
+            Action::Redact => "redact",
+            Action::Block => "block",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Input,
+    Output,
+}
+
+impl Stage {
+    fn as_str(self) -> &'static str {
+        match self {
+            Stage::Input => "input",
+            Stage::Output => "output",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RuleConfig {
+    #[serde(default)]
+    keywords: Vec<String>,
+    #[serde(default)]
+    pattern: Option<String>,
+    action: Action,
+    #[serde(default)]
+    stages: Option<Vec<Stage>>,
+    #[serde(default)]
+    category: Option<String>,
+}
+
+struct Rule {
+    regex: Regex,
+    action: Action,
+    stages: Vec<Stage>,
+    category: String,
+}
+
+struct Endpoint {
+    url: String,
+    api_key: Option<String>,
+    model: Option<String>,
+    action: Action,
+    stages: Vec<Stage>,
+    pii: Option<PiiRedactor>,
+}
+
+pub struct Moderation {
+    rules: Vec<Rule>,
+    endpoint: Option<Endpoint>,
+}
+
+const BOTH_STAGES: [Stage; 2] = [Stage::Input, Stage::Output];
+
+fn parse_stages(list: &str) -> Vec<Stage> {
+    list.split(',')
+        .filter_map(|s| match s.trim().to_ascii_lowercase().as_str() {
+            "input" => Some(Stage::Input),
+            "output" => Some(Stage::Output),
+            "" => None,
+            other => {
+                log!("ignoring unknown moderation stage {other:?}");
+                None
+            }
+        })
+        .collect()
+}
+
+impl Moderation {
+    pub fn from_env(pii: Option<PiiRedactor>) -> Option<Self> {
+        let moderation = Self {
+            rules: load_rules(),
+            endpoint: endpoint_from_env(pii),
+        };
+        (!moderation.rules.is_empty() || moderation.endpoint.is_some()).then_some(moderation)
+    }
+
+    pub fn covers(&self, stage: Stage) -> bool {
+        self.rules.iter().any(|r| r.stages.contains(&stage))
+            || self
+                .endpoint
+                .as_ref()
+                .is_some_and(|e| e.stages.contains(&stage))
+    }
+
+    pub async fn check(&self, stage: Stage, text: &str) -> Verdict {
+        let mut verdict = Verdict {
+            stage,
+            action: None,
+            categories: Vec::new(),
+            text: text.to_string(),
+        };
+        for rule in self.rules.iter().filter(|r| r.stages.contains(&stage)) {
+            if !rule.regex.is_match(&verdict.text) {
+                continue;
+            }
+            verdict.raise(rule.action, &rule.category);
+            if rule.action == Action::Redact {
+                verdict.text = rule.regex.replace_all(&verdict.text, REDACTED).into_owned();
+            }
+        }
+        if let Some(endpoint) = self.endpoint.as_ref().filter(|e| e.stages.contains(&stage))
+            && !text.trim().is_empty()
+        {
+            match query_endpoint(endpoint, text).await {
+                Ok(Some(categories)) => {
+                    for category in &categories {
+                        verdict.raise(endpoint.action, category);
+                    }
+                    if categories.is_empty() {
+                        verdict.raise(endpoint.action, "flagged");
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => log!("moderation endpoint failed: {err:?}"),
+            }
+        }
+        verdict
+    }
+
+    // Just the redaction rules, for rewriting the text parts of a message one by one.
+    pub fn redact_text(&self, stage: Stage, text: &str) -> String {
+        let mut out = text.to_string();
+        for rule in &self.rules {
+            if rule.action == Action::Redact && rule.stages.contains(&stage) {
+                out = rule.regex.replace_all(&out, REDACTED).into_owned();
+            }
+        }
+        out
+    }
+}
+
+fn load_rules() -> Vec<Rule> {
+    let path =
+        crate::config::var("MODERATION_RULES").unwrap_or_else(|_| "data/moderation.json".into());
+    let Ok(raw) = std::fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+    let configs: Vec<RuleConfig> = match serde_json::from_str(&raw) {
+        Ok(configs) => configs,
+        Err(err) => {
+            log!("failed to parse {path}: {err}");
+            return Vec::new();
+        }
+    };
+
+    let mut rules = Vec::new();
+    for (index, config) in configs.into_iter().enumerate() {
+        let source = match (&config.pattern, config.keywords.is_empty()) {
+            (Some(pattern), true) => pattern.clone(),
+            (None, false) => {
+                let words: Vec<String> = config
+                    .keywords
+                    .iter()
+                    .map(|k| regex::escape(k.trim()))
+                    .collect();
+                format!(r"(?i)\b(?:{})\b", words.join("|"))
+            }
+            _ => {
+                log!("skipping moderation rule {index}: set either keywords or pattern");
+                continue;
+            }
+        };
+        let regex = match Regex::new(&source) {
+            Ok(regex) => regex,
+            Err(err) => {
+                log!("skipping moderation rule {index}: {err}");
+                continue;
+            }
+        };
+        rules.push(Rule {
+            regex,
+            action: config.action,
+            stages: config.stages.unwrap_or_else(|| BOTH_STAGES.to_vec()),
+            category: config.category.unwrap_or_else(|| format!("rule {index}")),
+        });
+    }
+    rules
+}
+
+fn endpoint_from_env(pii: Option<PiiRedactor>) -> Option<Endpoint> {
+    let url = crate::config::var("MODERATION_URL")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())?;
+    let action = match crate::config::var("MODERATION_URL_ACTION")
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "" | "block" => Action::Block,
+        "flag" => Action::Flag,
+        other => {
+            // The endpoint does not say which span was objectionable, so there is nothing to redact.
+            log!("unsupported MODERATION_URL_ACTION {other:?}; using block");
+            Action::Block
+        }
+    };
+    let stages = match crate::config::var("MODERATION_URL_STAGES") {
+        Ok(list) if !list.trim().is_empty() => parse_stages(&list),
+        _ => BOTH_STAGES.to_vec(),
+    };
+    Some(Endpoint {
+        url,
+        api_key: crate::config::var("MODERATION_API_KEY")
+            .ok()
+            .filter(|v| !v.trim().is_empty()),
+        model: crate::config::var("MODERATION_MODEL")
+            .ok()
+            .filter(|v| !v.trim().is_empty()),
+        action,
+        stages,
+        pii,
+    })
+}
+
+// The flagged categories, or None when the text passed.
+async fn query_endpoint(endpoint: &Endpoint, text: &str) -> anyhow::Result<Option<Vec<String>>> {
+    let input = match &endpoint.pii {
+        Some(pii) => pii.redact(text),
+        None => text.to_string(),
+    };
+    let mut body = serde_json::json!({ "input": input });
+    if let Some(model) = &endpoint.model {
+        body["model"] = model.clone().into();
+    }
+    let mut request = reqwest::Client::new()
+        .post(&endpoint.url)
+        .timeout(Duration::from_secs(15))
+        .json(&body);
+    if let Some(key) = &endpoint.api_key {
+        request = request.bearer_auth(key);
+    }
+    let resp: serde_json::Value = request.send().await?.error_for_status()?.json().await?;
+    let Some(results) = resp["results"].as_array() else {
+        anyhow::bail!("moderation response has no results");
+    };
+    if !results.iter().any(|r| r["flagged"].as_bool() == Some(true)) {
+        return Ok(None);
+    }
+    let mut categories: Vec<String> = results
+        .iter()
+        .filter_map(|r| r["categories"].as_object())
+        .flatten()
+        .filter(|(_, flagged)| flagged.as_bool() == Some(true))
+        .map(|(name, _)| name.clone())
+        .collect();
+    categories.dedup();
+    Ok(Some(categories))
+}
+
+pub struct Verdict {
+    stage: Stage,
+    // The most severe action triggered, if any.
+    pub action: Option<Action>,
+    pub categories: Vec<String>,
+    // The checked text after redaction.
+    pub text: String,
+}
+
+impl Verdict {
+    fn raise(&mut self, action: Action, category: &str) {
+        self.action = self.action.max(Some(action));
+        if !self.categories.iter().any(|c| c == category) {
+            self.categories.push(category.to_string());
+        }
+    }
+
+    pub fn blocked(&self) -> bool {
+        self.action == Some(Action::Block)
+    }
+
+    pub fn event(&self) -> Option<Event> {
+        let action = self.action?;
+        let mut payload = serde_json::json!({
+            "stage": self.stage.as_str(),
+            "action": action.as_str(),
+            "categories": self.categories,
+        });
+        if action == Action::Block {
+            payload["message"] = match self.stage {
+                Stage::Input => "The message was blocked by content moderation.",
+                Stage::Output => "The reply was stopped by content moderation.",
+            }
+            .into();
+        }
+        Some(crate::request_id::event("moderation", payload))
+    }
+}
+
+// Holds back streamed output until a sentence is complete so it can be checked as a whole.
+#[derive(Default)]
+pub struct OutputBuffer {
+    pending: String,
+}
+
+impl OutputBuffer {
+    pub fn push(&mut self, delta: &str) -> Option<String> {
+        self.pending.push_str(delta);
+        let boundary = self
+            .pending
+            .char_indices()
+            .rev()
+            .find(|(_, c)| matches!(c, '.' | '!' | '?' | '\n'))
+            .map(|(i, c)| i + c.len_utf8());
+        let cut = match boundary {
+            Some(cut) => cut,
+            None if self.pending.len() > MAX_HOLD_CHARS => self
+                .pending
+                .char_indices()
+                .rev()
+                .find(|(_, c)| c.is_whitespace())
+                .map(|(i, _)| i)?,
+            None => return None,
+        };
+        if cut == 0 {
+            return None;
+        }
+        let rest = self.pending.split_off(cut);
+        Some(std::mem::replace(&mut self.pending, rest))
+    }
+
+    pub fn finish(&mut self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| std::mem::take(&mut self.pending))
+    }
+}
@@ -0,0 +1,152 @@
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, jwk::JwkSet};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::rbac::Role;
+
+// Bearer JWTs from an external identity provider (Authentik, Keycloak, ...), on when
+// OIDC_ISSUER is set. Like ACCOUNTS, this makes /api require a signed-in user; with both on,
+// either a session or a valid token will do.
+//   OIDC_ISSUER          must match the token's `iss`, e.g. https://auth.example.com/application/o/chat/
+//   OIDC_JWKS_URL        signing keys; found through {issuer}/.well-known/openid-configuration
+//                        when unset
+//   OIDC_AUDIENCE        required `aud` (usually the client id); unchecked when unset
+//   OIDC_USERNAME_CLAIM  claim used as the user name (default preferred_username, then sub)
+//   OIDC_ROLE_CLAIM      string or list claim holding role names (default roles); see rbac.rs
+// The user name is prefixed with `oidc:` (oidc:alice), which local account names cannot
+// contain, so a token never acts as, or saves settings over, the account of the same name.
+// Quotas and the admin API refer to these users by the prefixed name.
+// Keys are cached and fetched again when a token names a key id the cache does not know,
+// at most once a minute. Only asymmetric algorithms (RS*, PS*, ES*, EdDSA) are accepted.
+
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct OidcVerifier {
+    issuer: String,
+    jwks_url: Option<String>,
+    audience: Option<String>,
+    username_claim: String,
+    role_claim: String,
+    keys: RwLock<Option<(JwkSet, Instant)>>,
+    client: reqwest::Client,
+}
+
+impl OidcVerifier {
+    pub fn from_env() -> Option<Self> {
+        let non_empty = |name: &str| {
+            crate::config::var(name)
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        Some(Self {
+            issuer: non_empty("OIDC_ISSUER")?,
+            jwks_url: non_empty("OIDC_JWKS_URL"),
+            audience: non_empty("OIDC_AUDIENCE"),
+            username_claim: non_empty("OIDC_USERNAME_CLAIM")
+                .unwrap_or_else(|| "preferred_username".into()),
+            role_claim: non_empty("OIDC_ROLE_CLAIM").unwrap_or_else(|| "roles".into()),
+            keys: RwLock::new(None),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        })
+    }
+
+    async fn jwks_url(&self) -> anyhow::Result<String> {
+        if let Some(url) = &self.jwks_url {
+            return Ok(url.clone());
+        }
+        let discovery = format!(
+            "{}/.well-known/openid-configuration",
+            self.issuer.trim_end_matches('/')
+        );
+        let config: serde_json::Value = self
+            .client
+            .get(&discovery)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        config["jwks_uri"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow::anyhow!("{discovery} has no jwks_uri"))
+    }
+
+    // The cached key set, fetched first if there is none or `refresh` asks for a newer one.
+    async fn keys(&self, refresh: bool) -> anyhow::Result<JwkSet> {
+        if let Some((keys, fetched)) = &*self.keys.read().unwrap()
+            && (!refresh || fetched.elapsed() < MIN_REFRESH_INTERVAL)
+        {
+            return Ok(keys.clone());
+        }
+        let keys: JwkSet = self
+            .client
+            .get(self.jwks_url().await?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        *self.keys.write().unwrap() = Some((keys.clone(), Instant::now()));
+        Ok(keys)
+    }
+
+    // The user name and role carried by a valid token.
+    pub async fn verify(&self, token: &str) -> anyhow::Result<(String, Role)> {
+        let header = jsonwebtoken::decode_header(token)?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            anyhow::bail!("{:?} tokens are not accepted", header.alg);
+        }
+        let mut keys = self.keys(false).await?;
+        let find = |keys: &JwkSet| match &header.kid {
+            Some(kid) => keys.find(kid).cloned(),
+            None => keys.keys.first().cloned(),
+        };
+        let jwk = match find(&keys) {
+            Some(jwk) => jwk,
+            None => {
+                keys = self.keys(true).await?;
+                find(&keys).ok_or_else(|| anyhow::anyhow!("unknown signing key"))?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.issuer]);
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        let claims = jsonwebtoken::decode::<serde_json::Map<String, serde_json::Value>>(
+            token,
+            &DecodingKey::from_jwk(&jwk)?,
+            &validation,
+        )?
+        .claims;
+        let username = [self.username_claim.as_str(), "sub"]
+            .iter()
+            .find_map(|claim| claims.get(*claim)?.as_str())
+            .filter(|name| !name.is_empty())
+            .map(|name| format!("oidc:{name}"))
+            .ok_or_else(|| anyhow::anyhow!("token has no {} claim", self.username_claim))?;
+        let role = match claims.get(&self.role_claim) {
+            Some(serde_json::Value::String(name)) => Role::parse(name),
+            Some(serde_json::Value::Array(names)) => {
+                names.iter().filter_map(|n| Role::parse(n.as_str()?)).max()
+            }
+            _ => None,
+        };
+        Ok((username, role.unwrap_or_default()))
+    }
+}
+
+// Tokens look like header.payload.signature; session tokens have no dots.
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
@@ -0,0 +1,282 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use crate::AppState;
+use crate::prompt::SystemPromptMode;
+
+// Named assistant presets shared by everyone using the server, kept in PERSONAS_PATH (default
+// data/personas.json; empty keeps them in memory only) and managed through /api/personas.
+// A chat picks one with `persona` (id or name) and gets:
+//   system_prompt     added to the built-in prompt, or used instead of it with
+//                     system_prompt_mode "replace"; a request's own system_prompt comes after
+//   tools             offered when the request lists no tools of its own
+//   sampler_overrides llama-server parameters (temperature, top_p, ...) the request's own
+//                     sampler_overrides take precedence over
+
+// ---------- Store ----------
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Persona {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub system_prompt: String,
+    #[serde(default)]
+    pub system_prompt_mode: SystemPromptMode,
+    #[serde(default)]
+    pub tools: Vec<String>,
+    #[serde(default)]
+    pub sampler_overrides: serde_json::Map<String, serde_json::Value>,
+    pub updated_at: String,
+}
+
+// Body of POST /api/personas and PUT /api/personas/:id.
+#[derive(Deserialize)]
+pub struct PersonaInput {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    system_prompt: String,
+    #[serde(default)]
+    system_prompt_mode: SystemPromptMode,
+    #[serde(default)]
+    tools: Vec<String>,
+    #[serde(default)]
+    sampler_overrides: serde_json::Map<String, serde_json::Value>,
+}
+
+impl PersonaInput {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name is required".into());
+        }
+        if let Some(key) = self
+            .sampler_overrides
+            .keys()
+            .find(|key| crate::RESERVED_UPSTREAM_KEYS.contains(&key.as_str()))
+        {
+            return Err(format!("sampler_overrides cannot set {key}"));
+        }
+        Ok(())
+    }
+
+    fn into_persona(self, id: String) -> Persona {
+        Persona {
+            id,
+            name: self.name.trim().to_string(),
+            description: self.description,
+            system_prompt: self.system_prompt,
+            system_prompt_mode: self.system_prompt_mode,
+            tools: self.tools,
+            sampler_overrides: self.sampler_overrides,
+            updated_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct PersonaFile {
+    personas: Vec<Persona>,
+}
+
+pub struct PersonaStore {
+    personas: RwLock<Vec<Persona>>,
+    path: Option<PathBuf>,
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+pub enum SaveError {
+    NameTaken,
+    NotFound,
+    Io(anyhow::Error),
+}
+
+impl PersonaStore {
+    pub fn from_env() -> Self {
+        let path = match crate::config::var("PERSONAS_PATH") {
+            Ok(p) if p.trim().is_empty() => None,
+            Ok(p) => Some(PathBuf::from(p)),
+            Err(_) => Some(PathBuf::from("data/personas.json")),
+        };
+        let file: PersonaFile = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|raw| match serde_json::from_str(&raw) {
+                Ok(file) => Some(file),
+                Err(err) => {
+                    log!("failed to parse personas file: {err:?}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            personas: RwLock::new(file.personas),
+            path,
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    async fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let _guard = self.write_lock.lock().await;
+        let json = {
+            let personas = self.personas.read().unwrap();
+            serde_json::to_vec_pretty(&serde_json::json!({ "personas": &*personas }))?
+        };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<Persona> {
+        self.personas.read().unwrap().clone()
+    }
+
+    // Looks a persona up by id, or failing that by name (ignoring case).
+    pub fn get(&self, key: &str) -> Option<Persona> {
+        let personas = self.personas.read().unwrap();
+        personas
+            .iter()
+            .find(|p| p.id == key)
+            .or_else(|| personas.iter().find(|p| p.name.eq_ignore_ascii_case(key)))
+            .cloned()
+    }
+
+    // Inserts a new persona, or replaces the one with `id`.
+    async fn save(&self, id: Option<&str>, input: PersonaInput) -> Result<Persona, SaveError> {
+        let persona = {
+            let mut personas = self.personas.write().unwrap();
+            if personas.iter().any(|p| {
+                Some(p.id.as_str()) != id && p.name.eq_ignore_ascii_case(input.name.trim())
+            }) {
+                return Err(SaveError::NameTaken);
+            }
+            match id {
+                Some(id) => {
+                    let slot = personas
+                        .iter_mut()
+                        .find(|p| p.id == id)
+                        .ok_or(SaveError::NotFound)?;
+                    *slot = input.into_persona(id.to_string());
+                    slot.clone()
+                }
+                None => {
+                    let persona = input.into_persona(uuid::Uuid::new_v4().to_string());
+                    personas.push(persona.clone());
+                    persona
+                }
+            }
+        };
+        self.persist().await.map_err(SaveError::Io)?;
+        Ok(persona)
+    }
+
+    async fn delete(&self, id: &str) -> anyhow::Result<bool> {
+        let removed = {
+            let mut personas = self.personas.write().unwrap();
+            let before = personas.len();
+            personas.retain(|p| p.id != id);
+            personas.len() != before
+        };
+        if removed {
+            self.persist().await?;
+        }
+        Ok(removed)
+    }
+}
+
+// ---------- HTTP handlers ----------
+
+fn save_error(err: SaveError) -> (StatusCode, String) {
+    match err {
+        SaveError::NameTaken => (
+            StatusCode::CONFLICT,
+            "a persona with that name already exists".into(),
+        ),
+        SaveError::NotFound => (StatusCode::NOT_FOUND, "persona not found".into()),
+        SaveError::Io(err) => {
+            log!("persona save failed: {err:?}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to save persona".into(),
+            )
+        }
+    }
+}
+
+pub async fn list_personas_handler(State(state): State<Arc<AppState>>) -> Json<Vec<Persona>> {
+    Json(state.personas.list())
+}
+
+pub async fn get_persona_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Persona>, (StatusCode, String)> {
+    state
+        .personas
+        .get(&id)
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "persona not found".into()))
+}
+
+pub async fn create_persona_handler(
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<PersonaInput>,
+) -> Result<(StatusCode, Json<Persona>), (StatusCode, String)> {
+    input
+        .validate()
+        .map_err(|msg| (StatusCode::BAD_REQUEST, msg))?;
+    let persona = state.personas.save(None, input).await.map_err(save_error)?;
+    Ok((StatusCode::CREATED, Json(persona)))
+}
+
+pub async fn update_persona_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(input): Json<PersonaInput>,
+) -> Result<Json<Persona>, (StatusCode, String)> {
+    input
+        .validate()
+        .map_err(|msg| (StatusCode::BAD_REQUEST, msg))?;
+    state
+        .personas
+        .save(Some(&id), input)
+        .await
+        .map(Json)
+        .map_err(save_error)
+}
+
+pub async fn delete_persona_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    match state.personas.delete(&id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "persona not found".into())),
+        Err(err) => {
+            log!("persona delete failed: {err:?}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to delete persona".into(),
+            ))
+        }
+    }
+}
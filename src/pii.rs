@@ -0,0 +1,140 @@
+use regex::{Captures, Regex};
+
+// Opt-in masking of personal data for privacy-sensitive deployments (PII_REDACTION=1).
+// Email addresses, phone numbers and credit-card numbers are replaced with placeholders in
+// tool arguments sent to third-party services, in text sent to the moderation endpoint and
+// in facts saved to memory. PII_REDACTION_KINDS (comma-separated email, phone, card) limits
+// what is masked.
+
+#[derive(Clone)]
+pub struct PiiRedactor {
+    // Checked in order, so card numbers are not mistaken for phone numbers.
+    patterns: Vec<(Kind, Regex)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Email,
+    Card,
+    Phone,
+}
+
+impl Kind {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "email" => Some(Kind::Email),
+            "card" | "credit_card" => Some(Kind::Card),
+            "phone" => Some(Kind::Phone),
+            _ => None,
+        }
+    }
+
+    fn placeholder(self) -> &'static str {
+        match self {
+            Kind::Email => "[email]",
+            Kind::Card => "[card number]",
+            Kind::Phone => "[phone]",
+        }
+    }
+
+    fn pattern(self) -> &'static str {
+        match self {
+            Kind::Email => r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}\b",
+            Kind::Card => r"\b\d(?:[ -]?\d){12,18}\b",
+            // Dots are left out as separators so IP and version numbers are not caught.
+            Kind::Phone => {
+                r"(?:\+|\b)(?:\d{1,3}[ -]?)?(?:\(\d{1,4}\)[ -]?)?\d{2,4}(?:[ -]?\d{2,4}){2,4}\b"
+            }
+        }
+    }
+
+    // Second look at a match the regex alone cannot judge.
+    fn accepts(self, found: &str) -> bool {
+        let digits: Vec<u32> = found.chars().filter_map(|c| c.to_digit(10)).collect();
+        match self {
+            Kind::Email => true,
+            Kind::Card => luhn_valid(&digits),
+            // Eight digits or fewer is more likely a date or an amount.
+            Kind::Phone => (9..=15).contains(&digits.len()),
+        }
+    }
+}
+
+impl PiiRedactor {
+    pub fn from_env() -> Option<Self> {
+        if !crate::env_flag("PII_REDACTION") {
+            return None;
+        }
+        let kinds: Vec<Kind> = match crate::config::var("PII_REDACTION_KINDS") {
+            Ok(list) if !list.trim().is_empty() => list
+                .split(',')
+                .map(|k| k.trim().to_ascii_lowercase())
+                .filter(|k| !k.is_empty())
+                .filter_map(|k| {
+                    let kind = Kind::parse(&k);
+                    if kind.is_none() {
+                        log!("ignoring unknown PII_REDACTION_KINDS entry {k:?}");
+                    }
+                    kind
+                })
+                .collect(),
+            _ => vec![Kind::Email, Kind::Card, Kind::Phone],
+        };
+        let patterns = [Kind::Email, Kind::Card, Kind::Phone]
+            .into_iter()
+            .filter(|kind| kinds.contains(kind))
+            .map(|kind| (kind, Regex::new(kind.pattern()).expect("valid PII pattern")))
+            .collect();
+        Some(Self { patterns })
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for (kind, regex) in &self.patterns {
+            out = regex
+                .replace_all(&out, |caps: &Captures| {
+                    let found = &caps[0];
+                    if kind.accepts(found) {
+                        kind.placeholder().to_string()
+                    } else {
+                        found.to_string()
+                    }
+                })
+                .into_owned();
+        }
+        out
+    }
+
+    // Masks the string values of a JSON document such as tool call arguments; numbers and
+    // keys are left alone. Anything that is not JSON is redacted as plain text.
+    pub fn redact_json(&self, raw: &str) -> String {
+        let Ok(mut value) = serde_json::from_str::<serde_json::Value>(raw) else {
+            return self.redact(raw);
+        };
+        self.redact_value(&mut value);
+        value.to_string()
+    }
+
+    fn redact_value(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(text) => *text = self.redact(text),
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|v| self.redact_value(v)),
+            serde_json::Value::Object(map) => map.values_mut().for_each(|v| self.redact_value(v)),
+            _ => {}
+        }
+    }
+}
+
+fn luhn_valid(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match i % 2 {
+            0 => d,
+            _ if d * 2 > 9 => d * 2 - 9,
+            _ => d * 2,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// A chat may bring its own `system_prompt`, which is refused unless CUSTOM_SYSTEM_PROMPT
+// allows it:
+//   off (default)  requests with system_prompt get a 400
+//   append         the text is added after the built-in prompt
+//   replace        as append, and system_prompt_mode: "replace" may drop the built-in
+//                  prompt (including its tool hints) entirely
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum CustomPromptPolicy {
+    Off,
+    Append,
+    Replace,
+}
+
+impl CustomPromptPolicy {
+    pub fn from_env() -> Self {
+        match crate::config::var("CUSTOM_SYSTEM_PROMPT")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "off" | "0" | "false" | "no" => Self::Off,
+            "append" => Self::Append,
+            "replace" | "on" | "1" | "true" | "yes" => Self::Replace,
+            other => {
+                log!("unknown CUSTOM_SYSTEM_PROMPT {other:?}; custom prompts stay off");
+                Self::Off
+            }
+        }
+    }
+
+    pub fn check(self, mode: SystemPromptMode) -> Result<(), String> {
+        let needed = match mode {
+            SystemPromptMode::Append => Self::Append,
+            SystemPromptMode::Replace => Self::Replace,
+        };
+        if self >= needed {
+            return Ok(());
+        }
+        Err(match self {
+            Self::Off => "custom system prompts are disabled (CUSTOM_SYSTEM_PROMPT)".into(),
+            _ => "this server only allows appending to the system prompt".into(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SystemPromptMode {
+    #[default]
+    Append,
+    Replace,
+}
+
+// The built-in prompt with the request's own text applied.
+pub fn apply_custom(built_in: String, custom: Option<&str>, mode: SystemPromptMode) -> String {
+    match (custom.map(str::trim).filter(|c| !c.is_empty()), mode) {
+        (None, _) => built_in,
+        (Some(custom), SystemPromptMode::Replace) => custom.to_string(),
+        (Some(custom), SystemPromptMode::Append) => format!("{built_in}\n\n{custom}"),
+    }
+}
+
+// ---------- Template variables ----------
+
+// The built-in and persona system prompts may use {{name}} placeholders, filled in for each
+// request; a request's own system_prompt is used as written. There is no time of day: the
+// system prompt has to stay the same from turn to turn for the prompt cache.
+//   {{date}} {{weekday}}         server local date (2026-03-14) and day name
+//   {{model}}                    model alias or name the chat runs on
+//   {{user_name}} {{location}}   the request's user_name (or user) and location
+// plus any defined in PROMPT_VARIABLES (default data/prompt_variables.json), a flat object
+// such as {"company": "Acme", "location": "Berlin"}. A configured user_name or location is
+// the fallback when a request has none. Unknown placeholders are left as written.
+
+pub struct PromptVariables(BTreeMap<String, String>);
+
+impl PromptVariables {
+    pub fn from_env() -> Self {
+        let path = crate::config::var("PROMPT_VARIABLES")
+            .unwrap_or_else(|_| "data/prompt_variables.json".into());
+        let vars = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|raw| match serde_json::from_str(&raw) {
+                Ok(vars) => Some(vars),
+                Err(err) => {
+                    log!("failed to parse {path}: {err}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self(vars)
+    }
+
+    // The configured variables with this request's values laid over them.
+    pub fn for_request(
+        &self,
+        model: &str,
+        user_name: Option<&str>,
+        location: Option<&str>,
+    ) -> BTreeMap<String, String> {
+        let now = chrono::Local::now();
+        let mut vars = self.0.clone();
+        vars.insert("date".into(), now.format("%Y-%m-%d").to_string());
+        vars.insert("weekday".into(), now.format("%A").to_string());
+        vars.insert("model".into(), model.to_string());
+        for (name, value) in [("user_name", user_name), ("location", location)] {
+            if let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) {
+                vars.insert(name.into(), value.to_string());
+            }
+        }
+        vars
+    }
+}
+
+// Replaces each {{name}} (spaces inside the braces allowed) that has a value in `vars`.
+pub fn render(template: &str, vars: &BTreeMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after
+            .find("}}")
+            .and_then(|end| Some((vars.get(after[..end].trim())?, end)));
+        match value {
+            Some((value, end)) => {
+                out.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
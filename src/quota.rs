@@ -0,0 +1,239 @@
+use axum::{
+    Extension, Json,
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::AppState;
+use crate::rbac::Role;
+use crate::users::Identity;
+
+// Daily and monthly allowances per user, checked against the usage log before a chat starts.
+// QUOTAS_CONFIG (default data/quotas.json):
+//   { "default": { "daily_tokens": 200000, "monthly_requests": 3000, "monthly_cost": 5.0 },
+//     "users": { "alice": { "daily_tokens": 1000000 }, "admin": {} } }
+// A user listed under `users` gets that entry instead of the default, so `{}` means no limits.
+// Chats without a `user` share the default allowance. Without sign-in (accounts, OIDC or API
+// keys) a chat's `user` is whatever the client sends, so there are no per-user allowances:
+// `users` is ignored and all chats together draw on the one default allowance. Periods are
+// calendar days and months in UTC; cost is in the MODEL_PRICING currency. A chat in progress is counted once it ends,
+// so the last one before a limit may run over it.
+
+#[derive(Deserialize, Default, Clone)]
+struct Limits {
+    #[serde(default)]
+    daily_tokens: Option<u64>,
+    #[serde(default)]
+    daily_requests: Option<u64>,
+    #[serde(default)]
+    daily_cost: Option<f64>,
+    #[serde(default)]
+    monthly_tokens: Option<u64>,
+    #[serde(default)]
+    monthly_requests: Option<u64>,
+    #[serde(default)]
+    monthly_cost: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct Quotas {
+    #[serde(default)]
+    default: Limits,
+    #[serde(default)]
+    users: HashMap<String, Limits>,
+}
+
+#[derive(Clone, Copy)]
+enum Period {
+    Day,
+    Month,
+}
+
+impl Period {
+    // Prefix of the usage log's RFC 3339 timestamps that falls in the current period.
+    fn prefix(self, now: DateTime<Utc>) -> String {
+        match self {
+            Period::Day => now.format("%Y-%m-%d").to_string(),
+            Period::Month => now.format("%Y-%m").to_string(),
+        }
+    }
+
+    fn resets_at(self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = now.date_naive();
+        let next = match self {
+            Period::Day => today + Duration::days(1),
+            Period::Month if today.month() == 12 => {
+                NaiveDate::from_ymd_opt(today.year() + 1, 1, 1).expect("valid date")
+            }
+            Period::Month => {
+                NaiveDate::from_ymd_opt(today.year(), today.month() + 1, 1).expect("valid date")
+            }
+        };
+        next.and_hms_opt(0, 0, 0).expect("valid time").and_utc()
+    }
+}
+
+// One limit with what has been used against it this period.
+struct Standing {
+    name: &'static str,
+    limit: f64,
+    used: f64,
+    resets_at: DateTime<Utc>,
+}
+
+impl Standing {
+    fn exceeded(&self) -> bool {
+        self.used >= self.limit
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "quota": self.name,
+            "limit": self.limit,
+            "used": self.used,
+            "remaining": (self.limit - self.used).max(0.0),
+            "resets_at": self.resets_at.to_rfc3339(),
+        })
+    }
+}
+
+impl Quotas {
+    pub fn from_env() -> Option<Self> {
+        let path =
+            crate::config::var("QUOTAS_CONFIG").unwrap_or_else(|_| "data/quotas.json".into());
+        let raw = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&raw) {
+            Ok(quotas) => Some(quotas),
+            Err(err) => {
+                log!("failed to parse {path}: {err}");
+                None
+            }
+        }
+    }
+
+    // Run at startup, when per-user entries cannot apply.
+    pub fn warn_without_sign_in(&self, state: &AppState) {
+        if !self.users.is_empty() && !crate::users::sign_in_enabled(state) {
+            log!(
+                "QUOTAS_CONFIG users are ignored without sign-in; all chats share the default quota"
+            );
+        }
+    }
+
+    fn standings(&self, state: &AppState, user: Option<&str>) -> Vec<Standing> {
+        // Unverified names would each get a fresh allowance, so without sign-in nobody has one.
+        let user = crate::users::sign_in_enabled(state).then_some(user);
+        let limits = user
+            .flatten()
+            .and_then(|u| self.users.get(u))
+            .unwrap_or(&self.default);
+        let now = Utc::now();
+        let mut standings = Vec::new();
+        for (period, tokens, requests, cost, names) in [
+            (
+                Period::Day,
+                limits.daily_tokens,
+                limits.daily_requests,
+                limits.daily_cost,
+                ["daily_tokens", "daily_requests", "daily_cost"],
+            ),
+            (
+                Period::Month,
+                limits.monthly_tokens,
+                limits.monthly_requests,
+                limits.monthly_cost,
+                ["monthly_tokens", "monthly_requests", "monthly_cost"],
+            ),
+        ] {
+            let limits = [tokens.map(|t| t as f64), requests.map(|r| r as f64), cost];
+            if limits.iter().all(Option::is_none) {
+                continue;
+            }
+            let used = state.usage.period_totals(user, &period.prefix(now));
+            let used = [used.tokens as f64, used.requests as f64, used.cost];
+            for ((name, limit), used) in names.into_iter().zip(limits).zip(used) {
+                if let Some(limit) = limit {
+                    standings.push(Standing {
+                        name,
+                        limit,
+                        used,
+                        resets_at: period.resets_at(now),
+                    });
+                }
+            }
+        }
+        standings
+    }
+
+    // Err with the first exhausted allowance, checked before a generation starts.
+    pub fn check(&self, state: &AppState, user: Option<&str>) -> Result<(), QuotaExceeded> {
+        match self
+            .standings(state, user)
+            .into_iter()
+            .find(Standing::exceeded)
+        {
+            Some(standing) => Err(QuotaExceeded(standing)),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct QuotaExceeded(Standing);
+
+impl IntoResponse for QuotaExceeded {
+    fn into_response(self) -> Response {
+        let standing = self.0;
+        let retry_after = (standing.resets_at - Utc::now()).num_seconds().max(1);
+        let mut body = standing.to_json();
+        body["error"] = "quota_exceeded".into();
+        body["message"] = format!(
+            "{} quota of {} used up; it resets at {}",
+            standing.name,
+            standing.limit,
+            standing.resets_at.to_rfc3339()
+        )
+        .into();
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(body),
+        )
+            .into_response()
+    }
+}
+
+// ---------- Handler ----------
+
+#[derive(Deserialize)]
+pub struct QuotaQuery {
+    #[serde(default)]
+    user: Option<String>,
+}
+
+// Remaining allowance for a user (or the shared default without one).
+pub async fn quota_handler(
+    State(state): State<Arc<AppState>>,
+    identity: Option<Extension<Identity>>,
+    Query(mut query): Query<QuotaQuery>,
+) -> Json<serde_json::Value> {
+    if let Some(Extension(identity)) = identity
+        && identity.role != Role::Admin
+    {
+        query.user = Some(identity.username);
+    }
+    let standings = state
+        .quotas
+        .as_ref()
+        .map(|q| q.standings(&state, query.user.as_deref()))
+        .unwrap_or_default();
+    Json(serde_json::json!({
+        "user": query.user,
+        "exceeded": standings.iter().any(Standing::exceeded),
+        "quotas": standings.iter().map(Standing::to_json).collect::<Vec<_>>(),
+    }))
+}
@@ -0,0 +1,165 @@
+use axum::http::Method;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::AppState;
+use crate::admin::constant_time_eq;
+use crate::memory;
+use crate::tools::{code, files, shell, sql};
+
+// Roles for signed-in callers (accounts, OIDC tokens and API keys):
+//   admin     everything, including /api/admin, every tool and everyone's usage
+//   user      chats with the tools allowed below; sees only their own usage
+//   readonly  chats and reads, but cannot change anything: no uploads, no edits to the
+//             knowledge base, tables, personas or memory, and only tools allowed below
+// The first account registered is an admin and later ones are users; admins change roles
+// with PUT /api/admin/users/:username. OIDC users get the highest role named in the
+// OIDC_ROLE_CLAIM claim (default "roles"), else user. Without sign-in there are no roles and
+// nothing here applies.
+// ROLES_CONFIG (default data/roles.json):
+//   { "tools": { "user": ["general", "memory", "extension"], "readonly": ["general"] },
+//     "api_keys": [ { "name": "grafana", "key": "long-random-string", "role": "readonly" } ] }
+// Tool lists name tools or categories: "system" (shell, run_code, read_file, sql_query),
+// "memory" (remember, recall), "extension" (MCP, plugin and webhook tools) and "general"
+// (the rest). The lists above are the defaults. API keys are sent as
+// `Authorization: Bearer <key>` and act as a caller named after the key.
+
+#[derive(
+    Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Readonly,
+    // Accounts made before roles existed are users.
+    #[default]
+    User,
+    Admin,
+}
+
+impl Role {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "admin" => Some(Self::Admin),
+            "user" => Some(Self::User),
+            "readonly" | "read-only" | "read_only" => Some(Self::Readonly),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::User => "user",
+            Self::Readonly => "readonly",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiKey {
+    name: String,
+    key: String,
+    role: Role,
+}
+
+#[derive(Deserialize)]
+pub struct RolePolicy {
+    #[serde(default = "default_tools")]
+    tools: HashMap<Role, Vec<String>>,
+    #[serde(default)]
+    api_keys: Vec<ApiKey>,
+}
+
+fn default_tools() -> HashMap<Role, Vec<String>> {
+    HashMap::from([
+        (
+            Role::User,
+            vec!["general".into(), "memory".into(), "extension".into()],
+        ),
+        (Role::Readonly, vec!["general".into()]),
+    ])
+}
+
+impl Default for RolePolicy {
+    fn default() -> Self {
+        Self {
+            tools: default_tools(),
+            api_keys: Vec::new(),
+        }
+    }
+}
+
+impl RolePolicy {
+    pub fn from_env() -> Self {
+        let path = crate::config::var("ROLES_CONFIG").unwrap_or_else(|_| "data/roles.json".into());
+        let Ok(raw) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<Self>(&raw) {
+            Ok(mut policy) => {
+                policy.api_keys.retain(|k| !k.key.trim().is_empty());
+                policy
+            }
+            Err(err) => {
+                log!("failed to parse {path}: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn has_api_keys(&self) -> bool {
+        !self.api_keys.is_empty()
+    }
+
+    // (name, role) of the API key matching `token`.
+    pub fn api_key(&self, token: &str) -> Option<(String, Role)> {
+        self.api_keys
+            .iter()
+            .find(|k| constant_time_eq(k.key.as_bytes(), token.as_bytes()))
+            .map(|k| (k.name.clone(), k.role))
+    }
+
+    pub fn may_use_tool(&self, state: &AppState, role: Role, tool: &str) -> bool {
+        if role == Role::Admin {
+            return true;
+        }
+        let category = tool_category(state, tool);
+        self.tools
+            .get(&role)
+            .is_some_and(|allowed| allowed.iter().any(|a| a == tool || a == category))
+    }
+}
+
+pub fn tool_category(state: &AppState, tool: &str) -> &'static str {
+    match tool {
+        shell::TOOL_NAME | code::TOOL_NAME | files::TOOL_NAME | sql::TOOL_NAME => "system",
+        memory::REMEMBER | memory::RECALL => "memory",
+        _ if state.mcp.tool_definition(tool).is_some()
+            || state.plugins.tool_definition(tool).is_some()
+            || state.webhooks.tool_definition(tool).is_some() =>
+        {
+            "extension"
+        }
+        _ => "general",
+    }
+}
+
+// Writes a readonly caller may still make: chatting, stateless conversions and their own
+// settings.
+const READONLY_WRITES: &[&str] = &[
+    "/api/chat/",
+    "/api/tokenize",
+    "/api/detokenize",
+    "/api/embeddings",
+    "/api/rerank",
+    "/api/tts",
+    "/api/transcribe",
+    "/api/ocr",
+    "/api/auth/settings",
+    "/mcp",
+];
+
+pub fn readonly_allows(method: &Method, path: &str) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+        || READONLY_WRITES.iter().any(|p| path.starts_with(p))
+}
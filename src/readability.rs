@@ -0,0 +1,525 @@
+use reqwest::Url;
+use scraper::{ElementRef, Html, Node, Selector};
+use std::collections::HashMap;
+
+// Readability-style main content extraction: paragraphs score their parent and grandparent
+// containers, the best container (adjusted for link density and class/id hints) wins, and
+// only it is rendered, as Markdown. Nav menus, cookie banners, footers and the like are skipped.
+
+pub struct Article {
+    pub title: Option<String>,
+    pub byline: Option<String>,
+    pub text: String,
+}
+
+// Never part of the content.
+const SKIP_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "nav", "header", "footer", "aside", "form",
+    "iframe", "svg", "canvas", "button", "select", "input", "textarea", "dialog", "menu",
+];
+
+// class/id fragments of page chrome, unless the same element also looks like content.
+const UNLIKELY_HINTS: &[&str] = &[
+    "cookie",
+    "consent",
+    "banner",
+    "navbar",
+    "menu",
+    "breadcrumb",
+    "footer",
+    "sidebar",
+    "comment",
+    "share",
+    "social",
+    "promo",
+    "advert",
+    "sponsor",
+    "related",
+    "popup",
+    "modal",
+    "newsletter",
+    "subscribe",
+    "signup",
+    "masthead",
+    "skip-link",
+    "disqus",
+    "pagination",
+];
+const CONTENT_HINTS: &[&str] = &[
+    "article", "content", "main", "post", "entry", "story", "body", "text", "blog",
+];
+
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "dl",
+    "dt",
+    "dd",
+    "pre",
+    "blockquote",
+    "table",
+    "tr",
+    "figure",
+    "figcaption",
+    "address",
+    "hr",
+];
+
+// `url` is the page's address, used to make relative links absolute.
+pub fn extract(html: &str, url: &str) -> Article {
+    let document = Html::parse_document(html);
+    let title = meta_content(&document, r#"meta[property="og:title"]"#)
+        .or_else(|| first_text(&document, "title"))
+        .or_else(|| first_text(&document, "h1"));
+    let byline = meta_content(&document, r#"meta[name="author"]"#)
+        .or_else(|| first_text(&document, r#"[rel="author"], [itemprop="author"], .byline"#))
+        .filter(|b| b.chars().count() <= 100);
+
+    let base = Url::parse(url).ok();
+    let text = match best_candidate(&document) {
+        Some(roots) => roots
+            .into_iter()
+            .map(|root| render_block(root, base.as_ref()))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        None => first_element(&document, "body")
+            .map(|body| render_blocks(body, base.as_ref()))
+            .unwrap_or_default(),
+    };
+
+    Article {
+        title,
+        byline,
+        text: tidy(&text),
+    }
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("static selector")
+}
+
+fn first_element<'a>(document: &'a Html, css: &str) -> Option<ElementRef<'a>> {
+    document.select(&selector(css)).next()
+}
+
+fn first_text(document: &Html, css: &str) -> Option<String> {
+    first_element(document, css)
+        .map(|el| collapse(&el.text().collect::<String>()))
+        .filter(|t| !t.is_empty())
+}
+
+fn meta_content(document: &Html, css: &str) -> Option<String> {
+    first_element(document, css)
+        .and_then(|el| el.value().attr("content"))
+        .map(collapse)
+        .filter(|t| !t.is_empty())
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// ---------- Candidate scoring ----------
+
+fn is_skipped(el: ElementRef<'_>) -> bool {
+    let value = el.value();
+    if SKIP_TAGS.contains(&value.name()) {
+        return true;
+    }
+    if value.attr("hidden").is_some() || value.attr("aria-hidden") == Some("true") {
+        return true;
+    }
+    let hints = format!(
+        "{} {}",
+        value.attr("class").unwrap_or_default(),
+        value.id().unwrap_or_default()
+    )
+    .to_ascii_lowercase();
+    UNLIKELY_HINTS.iter().any(|h| hints.contains(h))
+        && !CONTENT_HINTS.iter().any(|h| hints.contains(h))
+        && !matches!(value.name(), "article" | "main" | "body")
+}
+
+fn inside_skipped(el: ElementRef<'_>) -> bool {
+    std::iter::once(el)
+        .chain(el.ancestors().filter_map(ElementRef::wrap))
+        .any(is_skipped)
+}
+
+fn class_weight(el: ElementRef<'_>) -> f64 {
+    let value = el.value();
+    let hints = format!(
+        "{} {}",
+        value.attr("class").unwrap_or_default(),
+        value.id().unwrap_or_default()
+    )
+    .to_ascii_lowercase();
+    let mut weight = 0.0;
+    if CONTENT_HINTS.iter().any(|h| hints.contains(h)) {
+        weight += 25.0;
+    }
+    if UNLIKELY_HINTS.iter().any(|h| hints.contains(h)) {
+        weight -= 25.0;
+    }
+    weight
+}
+
+fn tag_weight(name: &str) -> f64 {
+    match name {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "address" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    }
+}
+
+fn link_density(el: ElementRef<'_>) -> f64 {
+    let total = el.text().map(str::len).sum::<usize>();
+    if total == 0 {
+        return 0.0;
+    }
+    let links: usize = el
+        .select(&selector("a"))
+        .map(|a| a.text().map(str::len).sum::<usize>())
+        .sum();
+    links as f64 / total as f64
+}
+
+// The winning container followed by any siblings that score close to it, in document order.
+fn best_candidate(document: &Html) -> Option<Vec<ElementRef<'_>>> {
+    let mut scores = HashMap::new();
+    for para in document.select(&selector("p, pre, td, blockquote")) {
+        if inside_skipped(para) {
+            continue;
+        }
+        let text = collapse(&para.text().collect::<String>());
+        let len = text.chars().count();
+        if len < 25 {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (len as f64 / 100.0).min(3.0);
+        let parents = para.ancestors().filter_map(ElementRef::wrap).take(2);
+        for (depth, parent) in parents.enumerate() {
+            let entry = scores
+                .entry(parent.id())
+                .or_insert_with(|| tag_weight(parent.value().name()) + class_weight(parent));
+            *entry += if depth == 0 { score } else { score / 2.0 };
+        }
+    }
+
+    let mut ranked: Vec<(ElementRef<'_>, f64)> = scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let el = ElementRef::wrap(document.tree.get(id)?)?;
+            Some((el, score * (1.0 - link_density(el))))
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let (best, best_score) = *ranked.first()?;
+    if best_score <= 0.0 {
+        return None;
+    }
+
+    let Some(parent) = best.parent().and_then(ElementRef::wrap) else {
+        return Some(vec![best]);
+    };
+    let threshold = (best_score * 0.2).max(10.0);
+    let roots = parent
+        .children()
+        .filter_map(ElementRef::wrap)
+        .filter(|sibling| {
+            sibling.id() == best.id()
+                || ranked
+                    .iter()
+                    .any(|(el, score)| el.id() == sibling.id() && *score >= threshold)
+        })
+        .collect();
+    Some(roots)
+}
+
+// ---------- Markdown rendering ----------
+
+// Headings, lists, links, emphasis, code and tables survive as Markdown so the model can
+// quote them; everything else becomes paragraphs separated by blank lines.
+fn render_blocks(el: ElementRef<'_>, base: Option<&Url>) -> String {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut inline = String::new();
+    for child in el.children() {
+        match child.value() {
+            Node::Text(text) => inline.push_str(&collapse_inline(text)),
+            Node::Element(_) => {
+                let Some(child) = ElementRef::wrap(child) else {
+                    continue;
+                };
+                if is_skipped(child) {
+                    continue;
+                }
+                if BLOCK_TAGS.contains(&child.value().name()) {
+                    flush_inline(&mut inline, &mut blocks);
+                    let block = render_block(child, base);
+                    if !block.trim().is_empty() {
+                        blocks.push(block);
+                    }
+                } else {
+                    inline.push_str(&render_inline(child, base));
+                }
+            }
+            _ => {}
+        }
+    }
+    flush_inline(&mut inline, &mut blocks);
+    blocks.join("\n\n")
+}
+
+fn flush_inline(inline: &mut String, blocks: &mut Vec<String>) {
+    let lines: Vec<&str> = inline
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    if !lines.is_empty() {
+        blocks.push(lines.join("\n"));
+    }
+    inline.clear();
+}
+
+fn render_block(el: ElementRef<'_>, base: Option<&Url>) -> String {
+    let name = el.value().name();
+    match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = name[1..].parse().unwrap_or(1);
+            let text = collapse(&render_inline(el, base));
+            if text.is_empty() {
+                String::new()
+            } else {
+                format!("{} {text}", "#".repeat(level))
+            }
+        }
+        "ul" | "ol" => render_list(el, base),
+        "li" => prefix_lines(&render_blocks(el, base), "- ", "  "),
+        "pre" => {
+            let code = el.text().collect::<String>();
+            let lang = std::iter::once(el)
+                .chain(el.select(&selector("code")))
+                .filter_map(|e| e.value().attr("class"))
+                .flat_map(str::split_whitespace)
+                .find_map(|c| c.strip_prefix("language-").or(c.strip_prefix("lang-")))
+                .unwrap_or_default();
+            format!("```{lang}\n{}\n```", code.trim_matches('\n'))
+        }
+        "blockquote" => {
+            let inner = render_blocks(el, base);
+            inner
+                .lines()
+                .map(|l| {
+                    if l.is_empty() {
+                        ">".to_string()
+                    } else {
+                        format!("> {l}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        "table" => render_table(el, base),
+        "hr" => "---".to_string(),
+        _ => render_blocks(el, base),
+    }
+}
+
+fn render_list(el: ElementRef<'_>, base: Option<&Url>) -> String {
+    let ordered = el.value().name() == "ol";
+    let mut number: usize = el
+        .value()
+        .attr("start")
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(1);
+    let mut items = Vec::new();
+    for item in el.children().filter_map(ElementRef::wrap) {
+        if is_skipped(item) {
+            continue;
+        }
+        let body = render_blocks(item, base);
+        if body.trim().is_empty() {
+            continue;
+        }
+        let marker = if ordered {
+            format!("{number}. ")
+        } else {
+            "- ".to_string()
+        };
+        number += 1;
+        let indent = " ".repeat(marker.len());
+        items.push(prefix_lines(&body, &marker, &indent));
+    }
+    items.join("\n")
+}
+
+// `first` before the first line, `rest` before the others (blank lines stay blank).
+fn prefix_lines(text: &str, first: &str, rest: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| match (i, line.is_empty()) {
+            (0, _) => format!("{first}{line}"),
+            (_, true) => String::new(),
+            _ => format!("{rest}{line}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Layout tables (a single column) are rendered as ordinary content.
+fn render_table(el: ElementRef<'_>, base: Option<&Url>) -> String {
+    let rows: Vec<Vec<String>> = el
+        .select(&selector("tr"))
+        .map(|tr| {
+            tr.children()
+                .filter_map(ElementRef::wrap)
+                .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                .map(|cell| collapse(&render_inline(cell, base)).replace('|', "\\|"))
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect();
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if columns < 2 {
+        return render_blocks(el, base);
+    }
+    let mut lines = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let cells: Vec<&str> = (0..columns)
+            .map(|c| row.get(c).map_or("", String::as_str))
+            .collect();
+        lines.push(format!("| {} |", cells.join(" | ")));
+        if i == 0 {
+            lines.push(format!("|{}", " --- |".repeat(columns)));
+        }
+    }
+    lines.join("\n")
+}
+
+fn render_inline(el: ElementRef<'_>, base: Option<&Url>) -> String {
+    let mut inner = String::new();
+    for child in el.children() {
+        match child.value() {
+            Node::Text(text) => inner.push_str(&collapse_inline(text)),
+            Node::Element(_) => {
+                let Some(child) = ElementRef::wrap(child) else {
+                    continue;
+                };
+                if is_skipped(child) {
+                    continue;
+                }
+                match child.value().name() {
+                    "br" => inner.push('\n'),
+                    name if BLOCK_TAGS.contains(&name) => {
+                        inner.push(' ');
+                        inner.push_str(&render_inline(child, base));
+                        inner.push(' ');
+                    }
+                    _ => inner.push_str(&render_inline(child, base)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let value = el.value();
+    let wrap = |marker: &str| {
+        let text = inner.trim();
+        if text.is_empty() {
+            inner.clone()
+        } else {
+            // Keep the surrounding spaces outside the markers.
+            let lead = if inner.starts_with(' ') { " " } else { "" };
+            let trail = if inner.ends_with(' ') { " " } else { "" };
+            format!("{lead}{marker}{text}{marker}{trail}")
+        }
+    };
+    match value.name() {
+        "a" => match value.attr("href").and_then(|href| resolve_link(base, href)) {
+            Some(url) if !inner.trim().is_empty() => {
+                let text = collapse(&inner);
+                let lead = if inner.starts_with(' ') { " " } else { "" };
+                let trail = if inner.ends_with(' ') { " " } else { "" };
+                format!("{lead}[{text}]({url}){trail}")
+            }
+            _ => inner,
+        },
+        "strong" | "b" => wrap("**"),
+        "em" | "i" => wrap("_"),
+        "code" | "kbd" | "samp" => {
+            let code = el.text().collect::<String>();
+            if code.trim().is_empty() {
+                code
+            } else {
+                format!("`{}`", code.trim())
+            }
+        }
+        "img" => String::new(),
+        _ => inner,
+    }
+}
+
+// Absolute http(s) links only; in-page anchors and javascript: links are dropped.
+fn resolve_link(base: Option<&Url>, href: &str) -> Option<String> {
+    let href = href.trim();
+    if href.is_empty() || href.starts_with('#') {
+        return None;
+    }
+    let url = match base {
+        Some(base) => base.join(href).ok()?,
+        None => Url::parse(href).ok()?,
+    };
+    matches!(url.scheme(), "http" | "https").then(|| url.to_string())
+}
+
+fn collapse_inline(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                out.push(' ');
+            }
+            in_space = true;
+        } else {
+            out.push(c);
+            in_space = false;
+        }
+    }
+    out
+}
+
+// Trims trailing spaces and keeps at most one blank line between blocks.
+fn tidy(text: &str) -> String {
+    let mut out = String::new();
+    let mut blank = false;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            blank = true;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank { "\n\n" } else { "\n" });
+        }
+        out.push_str(line);
+        blank = false;
+    }
+    out
+}
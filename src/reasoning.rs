@@ -0,0 +1,140 @@
+use serde::Deserialize;
+
+// Reasoning models either send their chain of thought as `reasoning_content` deltas (llama-server
+// with --reasoning-format) or inline as <think>...</think> in the content. Both are sent to the
+// client as `reasoning` events so the answer itself stays clean.
+
+const OPEN_TAG: &str = "<think>";
+const CLOSE_TAG: &str = "</think>";
+
+// Splits streamed content into reasoning and answer text. Tags may arrive split across deltas,
+// so a trailing fragment that could start one is held back until the next delta.
+#[derive(Default)]
+pub struct ThinkSplitter {
+    in_think: bool,
+    pending: String,
+}
+
+impl ThinkSplitter {
+    // Returns (reasoning, content) found so far in this delta.
+    pub fn push(&mut self, delta: &str) -> (String, String) {
+        self.pending.push_str(delta);
+        let mut reasoning = String::new();
+        let mut content = String::new();
+        loop {
+            let tag = if self.in_think { CLOSE_TAG } else { OPEN_TAG };
+            let out = if self.in_think {
+                &mut reasoning
+            } else {
+                &mut content
+            };
+            if let Some(pos) = self.pending.find(tag) {
+                out.push_str(&self.pending[..pos]);
+                self.pending.drain(..pos + tag.len());
+                self.in_think = !self.in_think;
+                continue;
+            }
+            let keep = partial_tag_len(&self.pending, tag);
+            let release = self.pending.len() - keep;
+            out.push_str(&self.pending[..release]);
+            self.pending.drain(..release);
+            break;
+        }
+        (reasoning, content)
+    }
+
+    pub fn finish(&mut self) -> (String, String) {
+        let rest = std::mem::take(&mut self.pending);
+        if std::mem::take(&mut self.in_think) {
+            (rest, String::new())
+        } else {
+            (String::new(), rest)
+        }
+    }
+}
+
+// Length of the longest suffix of `text` that is a proper prefix of `tag`.
+fn partial_tag_len(text: &str, tag: &str) -> usize {
+    (1..tag.len())
+        .rev()
+        .find(|&n| text.ends_with(&tag[..n]))
+        .unwrap_or(0)
+}
+
+pub fn event_payload(delta: &str) -> serde_json::Value {
+    serde_json::json!({ "delta": delta })
+}
+
+// ---------- Effort and budget ----------
+
+// `reasoning: {effort, max_tokens}` on a chat. The effort is passed upstream as
+// `reasoning_effort` and as chat template arguments (enable_thinking is false for "none"),
+// which covers templates that read either. Because many templates ignore both, the proxy also
+// enforces a thinking budget: max_tokens, or 1024 for low and 4096 for medium effort. Once the
+// reasoning passes it (counted in streamed deltas, about one token each) generation restarts
+// with the reasoning so far closed off in an assistant prefill, so the model goes straight to
+// its answer.
+
+const LOW_EFFORT_BUDGET: usize = 1024;
+const MEDIUM_EFFORT_BUDGET: usize = 4096;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Effort {
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl Effort {
+    fn as_str(self) -> &'static str {
+        match self {
+            Effort::None => "none",
+            Effort::Low => "low",
+            Effort::Medium => "medium",
+            Effort::High => "high",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ReasoningOptions {
+    #[serde(default)]
+    pub effort: Option<Effort>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+}
+
+impl ReasoningOptions {
+    pub fn budget(&self) -> Option<usize> {
+        if self.max_tokens.is_some() {
+            return self.max_tokens;
+        }
+        match self.effort? {
+            Effort::None => Some(0),
+            Effort::Low => Some(LOW_EFFORT_BUDGET),
+            Effort::Medium => Some(MEDIUM_EFFORT_BUDGET),
+            Effort::High => None,
+        }
+    }
+
+    pub fn upstream_effort(&self) -> Option<&'static str> {
+        self.effort.map(Effort::as_str)
+    }
+
+    // Merged into any chat_template_kwargs the request already carries.
+    pub fn apply_template_kwargs(&self, kwargs: &mut serde_json::Map<String, serde_json::Value>) {
+        if let Some(effort) = self.effort {
+            kwargs.insert("reasoning_effort".into(), effort.as_str().into());
+        }
+        if self.budget() == Some(0) {
+            kwargs.insert("enable_thinking".into(), false.into());
+        }
+    }
+}
+
+// Assistant prefill that ends the thinking section after the budget ran out.
+pub fn closing_prefill(thinking: &str) -> String {
+    format!("{OPEN_TAG}\n{}\n{CLOSE_TAG}\n\n", thinking.trim())
+}
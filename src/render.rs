@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use crate::read_capped;
+
+// Optional headless-browser fallback for pages that are an empty shell without JavaScript.
+// RENDER_SERVICE_URL points at a browserless-style endpoint (e.g. http://browserless:3000/content)
+// that takes a POST with {"url": ...} and answers with the rendered HTML. It is only used when
+// the static fetch yields fewer than RENDER_MIN_CHARS characters of content (default 200).
+//
+// The scraper's network checks only see the URL handed over: redirects, scripts and frames
+// inside the browser are not checked, so the render service must run on a network with no route
+// to loopback, private or link-local addresses (cloud metadata included). Setting
+// RENDER_SERVICE_ISOLATED=1 states that it does; without it the server refuses to start with
+// RENDER_SERVICE_URL set (unless SCRAPE_ALLOW_PRIVATE_NETWORKS already allows those addresses).
+//
+// RENDER_API_KEY is sent as a bearer token, or as the `token` query parameter with
+// RENDER_API_KEY_IN=query (browserless); a key in the URL may show up in access logs.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyIn {
+    Bearer,
+    Query,
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderConfig {
+    pub url: String,
+    pub api_key: Option<String>,
+    pub key_in: KeyIn,
+    pub min_chars: usize,
+    pub timeout: Duration,
+}
+
+fn service_url() -> Option<String> {
+    crate::config::var("RENDER_SERVICE_URL")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn isolated() -> bool {
+    crate::env_flag("RENDER_SERVICE_ISOLATED") || crate::env_flag("SCRAPE_ALLOW_PRIVATE_NETWORKS")
+}
+
+// Run at startup.
+pub fn check_env() -> anyhow::Result<()> {
+    if service_url().is_some() && !isolated() {
+        anyhow::bail!(
+            "RENDER_SERVICE_URL is set but RENDER_SERVICE_ISOLATED is not: the headless browser \
+             can reach internal addresses the scraper refuses, so run the render service on an \
+             isolated network and set RENDER_SERVICE_ISOLATED=1"
+        );
+    }
+    Ok(())
+}
+
+impl RenderConfig {
+    pub fn from_env() -> Option<Self> {
+        let url = service_url()?;
+        if !isolated() {
+            log!("rendering disabled: RENDER_SERVICE_URL needs RENDER_SERVICE_ISOLATED=1");
+            return None;
+        }
+        let key_in = match crate::config::var("RENDER_API_KEY_IN") {
+            Ok(v) if v.trim().eq_ignore_ascii_case("query") => KeyIn::Query,
+            Ok(v) if !v.trim().is_empty() && !v.trim().eq_ignore_ascii_case("bearer") => {
+                log!("unknown RENDER_API_KEY_IN {v:?}; sending the key as a bearer token");
+                KeyIn::Bearer
+            }
+            _ => KeyIn::Bearer,
+        };
+        let env_parse = |name: &str| {
+            crate::config::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+        };
+        Some(Self {
+            url,
+            api_key: crate::config::var("RENDER_API_KEY")
+                .ok()
+                .filter(|v| !v.trim().is_empty()),
+            key_in,
+            min_chars: env_parse("RENDER_MIN_CHARS").unwrap_or(200) as usize,
+            timeout: Duration::from_secs(env_parse("RENDER_TIMEOUT_SECS").unwrap_or(30)),
+        })
+    }
+}
+
+pub async fn render(config: &RenderConfig, url: &str, max_bytes: usize) -> anyhow::Result<String> {
+    let mut request = reqwest::Client::new()
+        .post(&config.url)
+        .timeout(config.timeout)
+        .json(&serde_json::json!({ "url": url }));
+    if let Some(key) = &config.api_key {
+        request = match config.key_in {
+            KeyIn::Bearer => request.bearer_auth(key),
+            KeyIn::Query => request.query(&[("token", key)]),
+        };
+    }
+    let resp = request.send().await?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("render service error {status}: {body}");
+    }
+    // Browsers serialize the rendered DOM as UTF-8.
+    let (bytes, _) = read_capped(resp, max_bytes).await?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
@@ -0,0 +1,105 @@
+use axum::{
+    extract::Request,
+    http::{HeaderValue, header::HeaderName},
+    middleware::Next,
+    response::{Response, sse::Event},
+};
+use futures_util::{Stream, StreamExt};
+use std::convert::Infallible;
+
+// Every request gets an id: the caller's X-Request-Id when it is a sensible token (letters,
+// digits, `-`, `_`, `.`, up to 128 characters), otherwise a new UUID. It is sent back in the
+// X-Request-Id response header, prefixed to the server's log lines while the request is
+// handled and passed on to llama-server, so one id follows a failed chat from the browser
+// through the logs. A chat stream carries it as `request_id` in the JSON data of every event,
+// starting with a `request` event, and always ends with a `usage` event.
+
+pub const HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// The id of the request being handled on this task, if any.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(String::clone).ok()
+}
+
+// An eprintln! that names the request it belongs to.
+macro_rules! log {
+    ($($arg:tt)*) => {
+        match $crate::request_id::current() {
+            Some(id) => eprintln!("[{id}] {}", format_args!($($arg)*)),
+            None => eprintln!($($arg)*),
+        }
+    };
+}
+
+fn accepted(id: &str) -> bool {
+    (1..=128).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+pub async fn assign(req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(&HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| accepted(id))
+        .map(String::from)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(req)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(HEADER, value);
+    }
+    response
+}
+
+// Adds the current request's id to an upstream request.
+pub fn tag(builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match current() {
+        Some(id) => builder.header(HEADER.as_str(), id),
+        None => builder,
+    }
+}
+
+// A chat stream event with the request id added to its JSON payload.
+pub fn event(name: &str, payload: serde_json::Value) -> Event {
+    Event::default().event(name).data(tagged(payload))
+}
+
+// The same for an unnamed event (the content deltas).
+pub fn data_event(payload: serde_json::Value) -> Event {
+    Event::default().data(tagged(payload))
+}
+
+fn tagged(mut payload: serde_json::Value) -> String {
+    if let Some(fields) = payload.as_object_mut()
+        && let Some(id) = current()
+    {
+        fields.insert("request_id".into(), id.into());
+    }
+    payload.to_string()
+}
+
+// An `error` event: {"message": ..., "request_id": ...}.
+pub fn error_event(message: impl Into<String>) -> Event {
+    event("error", serde_json::json!({ "message": message.into() }))
+}
+
+// A chat's events, led by a `request` event naming the request id, with the id in scope while
+// the stream is polled (the handler has returned by then).
+pub fn scope_events<S>(id: String, events: S) -> impl Stream<Item = Result<Event, Infallible>>
+where
+    S: Stream<Item = Result<Event, Infallible>> + Send + 'static,
+{
+    let first = Event::default()
+        .event("request")
+        .data(serde_json::json!({ "request_id": id }).to_string());
+    let mut events = Box::pin(events);
+    futures_util::stream::once(async move { Ok(first) }).chain(futures_util::stream::poll_fn(
+        move |cx| REQUEST_ID.sync_scope(id.clone(), || events.as_mut().poll_next(cx)),
+    ))
+}
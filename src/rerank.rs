@@ -0,0 +1,169 @@
+use axum::{Json, extract::State, http::StatusCode};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{AppState, env_flag};
+
+// ---------- Reranker config ----------
+
+#[derive(Clone, Debug)]
+pub struct RerankConfig {
+    // Full endpoint URL, e.g. http://127.0.0.1:8080/v1/rerank
+    pub url: String,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+}
+
+impl RerankConfig {
+    // RERANK_URL points at any Jina/Cohere-style rerank endpoint.
+    // RERANK_ENABLED=true without a URL reuses llama-server's /v1/rerank.
+    pub fn from_env(llama_base_url: &str) -> Option<Self> {
+        let url = match crate::config::var("RERANK_URL") {
+            Ok(url) if !url.trim().is_empty() => url.trim().to_string(),
+            _ if env_flag("RERANK_ENABLED") => {
+                format!("{}/v1/rerank", llama_base_url.trim_end_matches('/'))
+            }
+            _ => return None,
+        };
+
+        Some(Self {
+            url,
+            model: crate::config::var("RERANK_MODEL").ok(),
+            api_key: crate::config::var("RERANK_API_KEY").ok(),
+        })
+    }
+}
+
+// ---------- Rerank call ----------
+
+#[derive(Serialize)]
+struct RerankRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    query: &'a str,
+    documents: &'a [String],
+    top_n: usize,
+}
+
+#[derive(Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankResult>,
+}
+
+#[derive(Deserialize)]
+struct RerankResult {
+    index: usize,
+    #[serde(alias = "score")]
+    relevance_score: f64,
+}
+
+// Returns (document index, score) pairs, best first.
+pub async fn rerank(
+    client: &Client,
+    config: &RerankConfig,
+    query: &str,
+    documents: &[String],
+) -> anyhow::Result<Vec<(usize, f64)>> {
+    if documents.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let body = RerankRequest {
+        model: config.model.as_deref(),
+        query,
+        documents,
+        top_n: documents.len(),
+    };
+
+    let resp = client
+        .post(&config.url)
+        .header("Content-Type", "application/json")
+        .bearer_auth(config.api_key.as_deref().unwrap_or("no-key"))
+        .json(&body)
+        .send()
+        .await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("rerank backend error {}: {}", status, body);
+    }
+
+    let parsed: RerankResponse = resp.json().await?;
+    let mut scored: Vec<(usize, f64)> = parsed
+        .results
+        .into_iter()
+        .filter(|r| r.index < documents.len())
+        .map(|r| (r.index, r.relevance_score))
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    Ok(scored)
+}
+
+// ---------- Rerank endpoint ----------
+
+// POST /api/rerank takes {"query": ..., "documents": [...], "top_n": optional,
+// "return_documents": optional} and returns the documents' scores, best first. It uses the
+// same backend as the search pipeline (RERANK_URL), or llama-server's /v1/rerank when none is
+// configured.
+
+const MAX_DOCUMENTS: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct RerankApiRequest {
+    query: String,
+    documents: Vec<String>,
+    #[serde(default)]
+    top_n: Option<usize>,
+    #[serde(default)]
+    return_documents: bool,
+}
+
+pub async fn rerank_handler(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<RerankApiRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if req.query.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "query is empty".into()));
+    }
+    if req.documents.is_empty() || req.documents.len() > MAX_DOCUMENTS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("documents must hold between 1 and {MAX_DOCUMENTS} texts"),
+        ));
+    }
+    let config = state.reranker.clone().unwrap_or_else(|| RerankConfig {
+        url: format!("{}/v1/rerank", state.llama_base_url.trim_end_matches('/')),
+        model: None,
+        api_key: None,
+    });
+
+    let mut scored = rerank(&Client::new(), &config, &req.query, &req.documents)
+        .await
+        .map_err(|err| {
+            log!("rerank request failed: {err:?}");
+            (
+                StatusCode::BAD_GATEWAY,
+                "rerank backend error (see server logs)".to_string(),
+            )
+        })?;
+    if let Some(top_n) = req.top_n {
+        scored.truncate(top_n);
+    }
+    let results: Vec<_> = scored
+        .into_iter()
+        .map(|(index, score)| {
+            let mut result = serde_json::json!({ "index": index, "relevance_score": score });
+            if req.return_documents {
+                result["document"] = req.documents[index].clone().into();
+            }
+            result
+        })
+        .collect();
+    Ok(Json(serde_json::json!({
+        "model": config.model,
+        "results": results,
+    })))
+}
@@ -0,0 +1,186 @@
+use reqwest::{Client, StatusCode, Url};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::env_flag;
+
+// With SCRAPE_RESPECT_ROBOTS on, pages are only fetched when the site's robots.txt allows it
+// for ROBOTS_USER_AGENT (default "chat-llama", falling back to the `*` group). Each site's
+// rules are cached for ROBOTS_CACHE_SECS (default 3600). A missing or unreachable robots.txt
+// allows everything.
+
+const MAX_ROBOTS_BYTES: usize = 512 * 1024;
+
+pub struct RobotsPolicy {
+    user_agent: String,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (Instant, Rules)>>,
+}
+
+#[derive(Clone)]
+struct Rules {
+    // (path pattern, allowed)
+    rules: Vec<(String, bool)>,
+}
+
+impl RobotsPolicy {
+    pub fn from_env() -> Option<Self> {
+        if !env_flag("SCRAPE_RESPECT_ROBOTS") {
+            return None;
+        }
+        Some(Self {
+            user_agent: crate::config::var("ROBOTS_USER_AGENT")
+                .ok()
+                .map(|v| v.trim().to_ascii_lowercase())
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| "chat-llama".to_string()),
+            ttl: Duration::from_secs(
+                crate::config::var("ROBOTS_CACHE_SECS")
+                    .ok()
+                    .and_then(|v| v.trim().parse().ok())
+                    .unwrap_or(3600),
+            ),
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub async fn allows(&self, client: &Client, url: &str) -> bool {
+        let Ok(url) = Url::parse(url) else {
+            return false;
+        };
+        let origin = url.origin().ascii_serialization();
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(&origin)
+            .filter(|(fetched, _)| fetched.elapsed() < self.ttl)
+            .map(|(_, rules)| rules.clone());
+        let rules = match cached {
+            Some(rules) => rules,
+            None => {
+                let rules = self.fetch_rules(client, &origin).await;
+                self.cache
+                    .lock()
+                    .unwrap()
+                    .insert(origin, (Instant::now(), rules.clone()));
+                rules
+            }
+        };
+        let path = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        rules.allows(&path)
+    }
+
+    async fn fetch_rules(&self, client: &Client, origin: &str) -> Rules {
+        let resp = client
+            .get(format!("{origin}/robots.txt"))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await;
+        let body = match resp {
+            Ok(resp) if resp.status() == StatusCode::OK => resp.text().await.unwrap_or_default(),
+            Ok(_) => String::new(),
+            Err(err) => {
+                log!("could not fetch robots.txt for {origin}: {err}");
+                String::new()
+            }
+        };
+        let body: String = body.chars().take(MAX_ROBOTS_BYTES).collect();
+        Rules::parse(&body, &self.user_agent)
+    }
+}
+
+// ---------- Parsing and matching ----------
+
+impl Rules {
+    // Rules of the groups naming our agent, or of the `*` groups when none does.
+    fn parse(body: &str, user_agent: &str) -> Self {
+        let mut groups: Vec<(Vec<String>, Rules)> = Vec::new();
+        let mut in_rules = false;
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match field.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // Consecutive user-agent lines share a group; one after rules starts a new one.
+                    if in_rules || groups.is_empty() {
+                        groups.push((Vec::new(), Rules { rules: Vec::new() }));
+                        in_rules = false;
+                    }
+                    if let Some((agents, _)) = groups.last_mut() {
+                        agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                field @ ("allow" | "disallow") => {
+                    in_rules = true;
+                    // An empty Disallow allows everything.
+                    if let Some((_, group)) = groups.last_mut()
+                        && !value.is_empty()
+                    {
+                        group.rules.push((value.to_string(), field == "allow"));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let names_us = |agents: &Vec<String>| {
+            agents
+                .iter()
+                .any(|a| a != "*" && user_agent.contains(a.as_str()))
+        };
+        let rules = if groups.iter().any(|(agents, _)| names_us(agents)) {
+            groups
+                .into_iter()
+                .filter(|(agents, _)| names_us(agents))
+                .flat_map(|(_, group)| group.rules)
+                .collect()
+        } else {
+            groups
+                .into_iter()
+                .filter(|(agents, _)| agents.iter().any(|a| a == "*"))
+                .flat_map(|(_, group)| group.rules)
+                .collect()
+        };
+        Self { rules }
+    }
+
+    // The longest matching pattern wins; on a tie Allow does.
+    fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(pattern, _)| pattern_matches(pattern, path))
+            .max_by_key(|(pattern, allowed)| (pattern.len(), *allowed))
+            .is_none_or(|(_, allowed)| *allowed)
+    }
+}
+
+// Prefix match with `*` wildcards and an optional `$` end anchor.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
@@ -0,0 +1,957 @@
+use futures_util::future::BoxFuture;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::{AppState, SearchResult};
+
+// ---------- Provider trait ----------
+
+pub struct SearchQuery<'a> {
+    pub query: &'a str,
+    // One of CATEGORIES. Providers without topic categories treat all but "news" as general.
+    pub category: &'a str,
+    // One of TIME_RANGES: only pages from the last day/week/month/year.
+    pub time_range: Option<&'a str>,
+    // Bare domain (see normalize_site) to restrict results to.
+    pub site: Option<&'a str>,
+    // BCP 47 tag of the chat's language, when it has one.
+    pub language: Option<&'a str>,
+}
+
+impl SearchQuery<'_> {
+    // Query text with a `site:` operator, which SearXNG engines, Brave and DuckDuckGo honour.
+    fn text_with_site(&self) -> String {
+        match self.site {
+            Some(site) => format!("{} site:{site}", self.query),
+            None => self.query.to_string(),
+        }
+    }
+}
+
+// Accepts "docs.rs", "site:docs.rs" or "https://www.reddit.com/r/rust" and returns the
+// host ("docs.rs", "reddit.com"); None if it is not a plausible domain.
+pub fn normalize_site(input: &str) -> Option<String> {
+    let site = input.trim().trim_start_matches("site:");
+    let site = site.split_once("://").map_or(site, |(_, rest)| rest);
+    let host = site.split(['/', '?', '#']).next()?.to_ascii_lowercase();
+    let host = host.trim_start_matches("www.").trim_end_matches('.');
+    let valid = host.contains('.')
+        && host.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    valid.then(|| host.to_string())
+}
+
+// Categories the model may pick in web_search (SearXNG names).
+pub const CATEGORIES: &[&str] = &["general", "news", "it", "science"];
+
+pub const TIME_RANGES: &[&str] = &["day", "week", "month", "year"];
+
+pub trait SearchProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Raw results in the provider's order; reranking and page excerpts happen in web_search.
+    fn search<'a>(
+        &'a self,
+        client: &'a Client,
+        query: &'a SearchQuery<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<SearchResult>>>;
+}
+
+// SEARCH_PROVIDER lists backends to try in order, e.g. "searxng,duckduckgo": searxng
+// (default), brave, duckduckgo or google. Misconfigured entries are skipped.
+pub fn provider_from_env() -> Box<dyn SearchProvider> {
+    let names = crate::config::var("SEARCH_PROVIDER").unwrap_or_default();
+    let mut providers = Vec::new();
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        match provider_by_name(name) {
+            Ok(provider) => providers.push(provider),
+            Err(err) => log!("skipping search provider: {err}"),
+        }
+    }
+    if providers.is_empty() {
+        providers.push(Box::new(Searxng::from_env()));
+    }
+    let names: Vec<_> = providers.iter().map(|p| p.name()).collect();
+    log!("web search providers: {}", names.join(", "));
+    Box::new(FallbackChain { providers })
+}
+
+// Strict variant for settings changed at runtime: every name must resolve.
+pub fn provider_from_names(names: &str) -> Result<Box<dyn SearchProvider>, String> {
+    let providers = names
+        .split(',')
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(provider_by_name)
+        .collect::<Result<Vec<_>, _>>()?;
+    if providers.is_empty() {
+        return Err("no search provider given".into());
+    }
+    Ok(Box::new(FallbackChain { providers }))
+}
+
+fn provider_by_name(name: &str) -> Result<Box<dyn SearchProvider>, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "" | "searxng" => Ok(Box::new(Searxng::from_env())),
+        "brave" => Brave::from_env()
+            .map(|p| Box::new(p) as Box<dyn SearchProvider>)
+            .ok_or_else(|| "brave search requires BRAVE_API_KEY".to_string()),
+        "duckduckgo" | "ddg" => Ok(Box::new(DuckDuckGo)),
+        "google" => Google::from_env()
+            .map(|p| Box::new(p) as Box<dyn SearchProvider>)
+            .ok_or_else(|| "google search requires GOOGLE_API_KEY and GOOGLE_CSE_ID".to_string()),
+        other => Err(format!("unknown SEARCH_PROVIDER {other:?}")),
+    }
+}
+
+// ---------- Query rewriting ----------
+
+// With SEARCH_QUERY_REWRITE on, the model's query goes through a quick completion that turns
+// a conversational question into search keywords before it reaches the provider. Any failure
+// keeps the original query.
+pub async fn rewrite_query(state: &AppState, query: &str) -> String {
+    match request_rewrite(state, query).await {
+        Ok(rewritten) => rewritten,
+        Err(err) => {
+            log!("search query rewrite failed: {err:?}");
+            query.to_string()
+        }
+    }
+}
+
+async fn request_rewrite(state: &AppState, query: &str) -> anyhow::Result<String> {
+    let today = chrono::Local::now().format("%Y-%m-%d");
+    let prompt = format!(
+        "Rewrite the following request as a web search query. Today is {today}. \
+Drop greetings and conversational filler, expand acronyms that are ambiguous, and add the \
+year when the request is about recent or upcoming events. Keep names, versions and quoted \
+phrases intact. Reply with the query only, on one line.\n\nRequest: {query}"
+    );
+    let reply = quick_completion(state, &prompt, 64).await?;
+    clean_query_line(&reply).ok_or_else(|| anyhow::anyhow!("unusable rewrite {reply:?}"))
+}
+
+// Alternative phrasings searched alongside the model's query when it gives none itself
+// (SEARCH_QUERY_VARIANTS, default 0).
+pub async fn query_variations(state: &AppState, query: &str, count: usize) -> Vec<String> {
+    let prompt = format!(
+        "Write {count} different web search queries that could find the answer to the \
+following request, using other wording or angles than the request itself. Reply with one \
+query per line and nothing else.\n\nRequest: {query}"
+    );
+    match quick_completion(state, &prompt, 32 * count as u32 + 32).await {
+        Ok(reply) => reply
+            .lines()
+            .map(|line| {
+                line.trim_start_matches(|c: char| c.is_ascii_digit() || "-*.) ".contains(c))
+            })
+            .filter_map(clean_query_line)
+            .filter(|variant| !variant.eq_ignore_ascii_case(query))
+            .take(count)
+            .collect(),
+        Err(err) => {
+            log!("search query variations failed: {err:?}");
+            Vec::new()
+        }
+    }
+}
+
+async fn quick_completion(
+    state: &AppState,
+    prompt: &str,
+    max_tokens: u32,
+) -> anyhow::Result<String> {
+    let body = serde_json::json!({
+        "model": state.llama_model,
+        "messages": [{ "role": "user", "content": prompt }],
+        "stream": false,
+        "temperature": 0.0,
+        "max_tokens": max_tokens,
+    });
+    let resp: serde_json::Value = crate::request_id::tag(
+        Client::new().post(format!("{}/v1/chat/completions", state.llama_base_url)),
+    )
+    .bearer_auth("no-key")
+    .timeout(Duration::from_secs(15))
+    .json(&body)
+    .send()
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    Ok(resp["choices"][0]["message"]["content"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string())
+}
+
+fn clean_query_line(text: &str) -> Option<String> {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?
+        .trim_matches(|c| c == '"' || c == '\'' || c == '`')
+        .trim();
+    (!line.is_empty() && line.chars().count() <= 300).then(|| line.to_string())
+}
+
+// ---------- Page excerpts ----------
+
+// How much page text web_search adds to its results: the top SEARCH_EXCERPT_PAGES results
+// (default 2) get up to SEARCH_EXCERPT_CHARS of their page (default 4000), and
+// SEARCH_SNIPPET_BUDGET optionally caps all snippets together. A chat request can override
+// each value through `search_excerpts`.
+#[derive(Clone, Debug)]
+pub struct ExcerptConfig {
+    pub pages: usize,
+    pub chars_per_page: usize,
+    pub total_chars: Option<usize>,
+}
+
+const MAX_EXCERPT_PAGES: usize = 10;
+const MAX_EXCERPT_CHARS: usize = 50_000;
+
+impl Default for ExcerptConfig {
+    fn default() -> Self {
+        Self {
+            pages: 2,
+            chars_per_page: 4000,
+            total_chars: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ExcerptOptions {
+    #[serde(default)]
+    pub pages: Option<usize>,
+    #[serde(default)]
+    pub chars_per_page: Option<usize>,
+    #[serde(default)]
+    pub total_chars: Option<usize>,
+}
+
+impl ExcerptConfig {
+    pub fn from_env() -> Self {
+        let env_usize = |name: &str| {
+            crate::config::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse::<usize>().ok())
+        };
+        let defaults = Self::default();
+        Self {
+            pages: env_usize("SEARCH_EXCERPT_PAGES").unwrap_or(defaults.pages),
+            chars_per_page: env_usize("SEARCH_EXCERPT_CHARS").unwrap_or(defaults.chars_per_page),
+            total_chars: env_usize("SEARCH_SNIPPET_BUDGET").filter(|&n| n > 0),
+        }
+    }
+
+    pub fn with_overrides(&self, options: &ExcerptOptions) -> Self {
+        Self {
+            pages: options.pages.unwrap_or(self.pages).min(MAX_EXCERPT_PAGES),
+            chars_per_page: options
+                .chars_per_page
+                .unwrap_or(self.chars_per_page)
+                .min(MAX_EXCERPT_CHARS),
+            total_chars: options.total_chars.filter(|&n| n > 0).or(self.total_chars),
+        }
+    }
+}
+
+// Shortens every snippet by the same proportion until all of them fit `total_chars`.
+pub fn fit_snippets(results: &mut [SearchResult], total_chars: usize) {
+    let total: usize = results.iter().map(|r| r.snippet.chars().count()).sum();
+    if total <= total_chars {
+        return;
+    }
+    for result in results.iter_mut() {
+        let len = result.snippet.chars().count();
+        let keep = len * total_chars / total;
+        if keep < len {
+            let cut: String = result.snippet.chars().take(keep).collect();
+            result.snippet = format!("{}…", cut.trim_end());
+        }
+    }
+}
+
+// ---------- Result cache ----------
+
+// Finished web_search results (after reranking and page excerpts) keyed by the normalized
+// query. SEARCH_CACHE_TTL_SECS (default 900, 0 disables), SEARCH_CACHE_SIZE entries (default
+// 256, least recently used evicted first) and optionally SEARCH_CACHE_PATH to keep the cache
+// across restarts.
+pub struct SearchCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    ttl_secs: u64,
+    capacity: usize,
+    path: Option<PathBuf>,
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CacheEntry {
+    results: Vec<SearchResult>,
+    stored_at: u64,
+    #[serde(default)]
+    last_used: u64,
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl SearchCache {
+    pub fn from_env() -> Self {
+        let env_u64 = |name: &str, default: u64| {
+            crate::config::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        let path = non_empty(crate::config::var("SEARCH_CACHE_PATH").ok()).map(PathBuf::from);
+        let entries = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|raw| match serde_json::from_str(&raw) {
+                Ok(entries) => Some(entries),
+                Err(err) => {
+                    log!("failed to parse search cache: {err:?}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            entries: Mutex::new(entries),
+            ttl_secs: env_u64("SEARCH_CACHE_TTL_SECS", 900),
+            capacity: env_u64("SEARCH_CACHE_SIZE", 256) as usize,
+            path,
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn key(
+        query: &SearchQuery<'_>,
+        extra_queries: &[String],
+        excerpts: &ExcerptConfig,
+    ) -> String {
+        let text = std::iter::once(query.query)
+            .chain(extra_queries.iter().map(String::as_str))
+            .map(|q| {
+                q.split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .to_lowercase()
+            })
+            .collect::<Vec<_>>()
+            .join(" ; ");
+        format!(
+            "{text}|{}|{}|{}|{}|{}x{}/{}",
+            query.category,
+            query.time_range.unwrap_or_default(),
+            query.site.unwrap_or_default(),
+            query.language.unwrap_or_default(),
+            excerpts.pages,
+            excerpts.chars_per_page,
+            excerpts.total_chars.unwrap_or_default()
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<SearchResult>> {
+        if self.ttl_secs == 0 {
+            return None;
+        }
+        let now = unix_now();
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(key)?;
+        if now.saturating_sub(entry.stored_at) > self.ttl_secs {
+            entries.remove(key);
+            return None;
+        }
+        entry.last_used = now;
+        Some(entry.results.clone())
+    }
+
+    pub async fn insert(&self, key: String, results: Vec<SearchResult>) {
+        if self.ttl_secs == 0 || self.capacity == 0 || results.is_empty() {
+            return;
+        }
+        let now = unix_now();
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.retain(|_, e| now.saturating_sub(e.stored_at) <= self.ttl_secs);
+            entries.insert(
+                key,
+                CacheEntry {
+                    results,
+                    stored_at: now,
+                    last_used: now,
+                },
+            );
+            while entries.len() > self.capacity {
+                let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, e)| e.last_used)
+                    .map(|(k, _)| k.clone())
+                else {
+                    break;
+                };
+                entries.remove(&oldest);
+            }
+        }
+        if let Err(err) = self.persist().await {
+            log!("failed to save search cache: {err:?}");
+        }
+    }
+
+    async fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let _guard = self.write_lock.lock().await;
+        let json = serde_json::to_vec(&*self.entries.lock().unwrap())?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+}
+
+// ---------- Deduplication ----------
+
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid", "ref", "ref_src", "igshid", "amp",
+];
+
+// Drops results that point at the same page (AMP/mobile variants, tracking parameters,
+// http vs https) and keeps at most `max_per_domain` results per site, preserving order.
+pub fn dedupe(results: Vec<SearchResult>, max_per_domain: usize) -> Vec<SearchResult> {
+    let mut seen = std::collections::HashSet::new();
+    let mut per_domain: HashMap<String, usize> = HashMap::new();
+    results
+        .into_iter()
+        .filter(|r| {
+            let Some((host, key)) = normalize_url(&r.url) else {
+                return seen.insert(r.url.clone());
+            };
+            if !seen.insert(key) {
+                return false;
+            }
+            let count = per_domain.entry(host).or_insert(0);
+            *count += 1;
+            *count <= max_per_domain
+        })
+        .collect()
+}
+
+// Returns (site, comparable URL) for http(s) URLs.
+fn normalize_url(raw: &str) -> Option<(String, String)> {
+    let url = reqwest::Url::parse(raw).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let mut host = url.host_str()?.to_ascii_lowercase();
+    for prefix in ["www.", "m.", "mobile.", "amp."] {
+        if let Some(rest) = host.strip_prefix(prefix) {
+            host = rest.to_string();
+        }
+    }
+    let mut path = url.path().trim_end_matches('/').to_string();
+    for suffix in ["/amp", ".amp"] {
+        if let Some(rest) = path.strip_suffix(suffix) {
+            path = rest.to_string();
+        }
+    }
+    let mut query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(k, _)| !k.starts_with("utm_") && !TRACKING_PARAMS.contains(&k.as_ref()))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    query.sort();
+    let query: Vec<String> = query.iter().map(|(k, v)| format!("{k}={v}")).collect();
+    let key = format!("{host}{path}?{}", query.join("&"));
+    Some((host, key))
+}
+
+// ---------- Fallback chain ----------
+
+// Tries each provider until one returns results; records which one did on every result.
+pub struct FallbackChain {
+    providers: Vec<Box<dyn SearchProvider>>,
+}
+
+impl SearchProvider for FallbackChain {
+    fn name(&self) -> &'static str {
+        self.providers.first().map_or("none", |p| p.name())
+    }
+
+    fn search<'a>(
+        &'a self,
+        client: &'a Client,
+        query: &'a SearchQuery<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<SearchResult>>> {
+        Box::pin(async move {
+            let mut last_error = None;
+            for provider in &self.providers {
+                match provider.search(client, query).await {
+                    Ok(mut results) if !results.is_empty() => {
+                        for result in &mut results {
+                            result.provider = Some(provider.name().to_string());
+                        }
+                        return Ok(results);
+                    }
+                    Ok(_) => log!(
+                        "{} returned no results for {:?}",
+                        provider.name(),
+                        query.query
+                    ),
+                    Err(err) => {
+                        log!("{} search failed: {err:?}", provider.name());
+                        last_error = Some(err);
+                    }
+                }
+            }
+            // Every provider answering with nothing is a valid (empty) answer.
+            match last_error {
+                Some(err) if self.providers.len() == 1 => Err(err),
+                Some(err) => Err(err.context("all search providers failed or returned nothing")),
+                None => Ok(Vec::new()),
+            }
+        })
+    }
+}
+
+async fn check_status(
+    resp: reqwest::Response,
+    provider: &str,
+) -> anyhow::Result<reqwest::Response> {
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("{provider} error {status}: {body}");
+    }
+    Ok(resp)
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|v| !v.trim().is_empty())
+}
+
+// ---------- SearXNG ----------
+
+// SEARXNG_ENGINES restricts which engines run (comma-separated SearXNG engine names),
+// SEARXNG_CATEGORIES replaces "general" for ordinary searches, and SEARXNG_SAFESEARCH sets
+// the filter level (0 off, 1 moderate, 2 strict).
+pub struct Searxng {
+    base_url: String,
+    engines: Option<String>,
+    general_categories: String,
+    safesearch: Option<u8>,
+}
+
+#[derive(Deserialize)]
+struct SearxngSearchResponse {
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Deserialize)]
+struct SearxngResult {
+    title: Option<String>,
+    url: Option<String>,
+    content: Option<String>,
+    #[serde(default, rename = "publishedDate")]
+    published_date: Option<String>,
+}
+
+impl Searxng {
+    fn from_env() -> Self {
+        let base_url = crate::config::var("SEARCH_BASE_URL")
+            .unwrap_or_else(|_| "http://127.0.0.1:4434".into());
+        let list = |name: &str| {
+            non_empty(crate::config::var(name).ok()).map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+        };
+        let safesearch = crate::config::var("SEARXNG_SAFESEARCH")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|level| *level <= 2);
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            engines: list("SEARXNG_ENGINES"),
+            general_categories: list("SEARXNG_CATEGORIES").unwrap_or_else(|| "general".into()),
+            safesearch,
+        }
+    }
+}
+
+impl SearchProvider for Searxng {
+    fn name(&self) -> &'static str {
+        "searxng"
+    }
+
+    fn search<'a>(
+        &'a self,
+        client: &'a Client,
+        query: &'a SearchQuery<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<SearchResult>>> {
+        Box::pin(async move {
+            let categories = match query.category {
+                "general" => self.general_categories.as_str(),
+                other => other,
+            };
+            let mut params = vec![
+                ("q", query.text_with_site()),
+                ("format", "json".into()),
+                ("language", query.language.unwrap_or("en").to_string()),
+                ("categories", categories.to_string()),
+            ];
+            if let Some(engines) = &self.engines {
+                params.push(("engines", engines.clone()));
+            }
+            if let Some(range) = query.time_range {
+                params.push(("time_range", range.to_string()));
+            }
+            if let Some(level) = self.safesearch {
+                params.push(("safesearch", level.to_string()));
+            }
+            let resp = client
+                .get(format!("{}/search", self.base_url))
+                .query(&params)
+                .header("Accept", "application/json")
+                .send()
+                .await?;
+            let parsed: SearxngSearchResponse =
+                check_status(resp, "search backend").await?.json().await?;
+
+            Ok(parsed
+                .results
+                .into_iter()
+                .filter_map(|r| {
+                    let url = r.url?;
+                    Some(SearchResult {
+                        title: r.title.unwrap_or_else(|| url.clone()),
+                        snippet: r.content.unwrap_or_default(),
+                        url,
+                        published: non_empty(r.published_date),
+                        citation: None,
+                        provider: None,
+                    })
+                })
+                .collect())
+        })
+    }
+}
+
+// ---------- Brave Search API ----------
+
+pub struct Brave {
+    api_key: String,
+}
+
+#[derive(Deserialize)]
+struct BraveWebResponse {
+    #[serde(default)]
+    web: Option<BraveResults>,
+}
+
+#[derive(Deserialize)]
+struct BraveResults {
+    #[serde(default)]
+    results: Vec<BraveResult>,
+}
+
+#[derive(Deserialize)]
+struct BraveResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    page_age: Option<String>,
+    #[serde(default)]
+    age: Option<String>,
+}
+
+impl Brave {
+    fn from_env() -> Option<Self> {
+        let api_key = non_empty(crate::config::var("BRAVE_API_KEY").ok())?;
+        Some(Self { api_key })
+    }
+}
+
+impl SearchProvider for Brave {
+    fn name(&self) -> &'static str {
+        "brave"
+    }
+
+    fn search<'a>(
+        &'a self,
+        client: &'a Client,
+        query: &'a SearchQuery<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<SearchResult>>> {
+        Box::pin(async move {
+            let news = query.category == "news";
+            let endpoint = if news { "news" } else { "web" };
+            let freshness = query.time_range.map(|r| format!("p{}", range_letter(r)));
+            let text = query.text_with_site();
+            let search_lang = query
+                .language
+                .map(|l| crate::locale::primary(l).to_lowercase());
+            let mut params = vec![("q", text.as_str()), ("count", "10")];
+            if let Some(freshness) = &freshness {
+                params.push(("freshness", freshness));
+            }
+            if let Some(lang) = &search_lang {
+                params.push(("search_lang", lang));
+            }
+            let resp = client
+                .get(format!(
+                    "https://api.search.brave.com/res/v1/{endpoint}/search"
+                ))
+                .query(&params)
+                .header("Accept", "application/json")
+                .header("X-Subscription-Token", &self.api_key)
+                .send()
+                .await?;
+            let resp = check_status(resp, "brave").await?;
+            // News results sit at the top level; web results under `web`.
+            let results = if news {
+                resp.json::<BraveResults>().await?.results
+            } else {
+                resp.json::<BraveWebResponse>()
+                    .await?
+                    .web
+                    .map(|w| w.results)
+                    .unwrap_or_default()
+            };
+
+            Ok(results
+                .into_iter()
+                .map(|r| SearchResult {
+                    title: r.title,
+                    snippet: strip_tags(&r.description.unwrap_or_default()),
+                    url: r.url,
+                    published: non_empty(r.page_age).or(non_empty(r.age)),
+                    citation: None,
+                    provider: None,
+                })
+                .collect())
+        })
+    }
+}
+
+// ---------- DuckDuckGo (HTML endpoint, no key) ----------
+
+pub struct DuckDuckGo;
+
+impl SearchProvider for DuckDuckGo {
+    fn name(&self) -> &'static str {
+        "duckduckgo"
+    }
+
+    fn search<'a>(
+        &'a self,
+        client: &'a Client,
+        query: &'a SearchQuery<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<SearchResult>>> {
+        Box::pin(async move {
+            let resp = client
+                .post("https://html.duckduckgo.com/html/")
+                .form(&[
+                    ("q", query.text_with_site().as_str()),
+                    ("df", query.time_range.map_or("", range_letter)),
+                ])
+                .send()
+                .await?;
+            let html = check_status(resp, "duckduckgo").await?.text().await?;
+            Ok(parse_duckduckgo_html(&html))
+        })
+    }
+}
+
+fn parse_duckduckgo_html(html: &str) -> Vec<SearchResult> {
+    use scraper::{Html, Selector};
+
+    let document = Html::parse_document(html);
+    let result_sel = Selector::parse(".result").unwrap();
+    let link_sel = Selector::parse("a.result__a").unwrap();
+    let snippet_sel = Selector::parse(".result__snippet").unwrap();
+
+    document
+        .select(&result_sel)
+        .filter_map(|result| {
+            let link = result.select(&link_sel).next()?;
+            let url = duckduckgo_target(link.value().attr("href")?)?;
+            let title = link.text().collect::<String>().trim().to_string();
+            let snippet = result
+                .select(&snippet_sel)
+                .next()
+                .map(|s| s.text().collect::<String>().trim().to_string())
+                .unwrap_or_default();
+            Some(SearchResult {
+                title: if title.is_empty() { url.clone() } else { title },
+                snippet,
+                url,
+                published: None,
+                citation: None,
+                provider: None,
+            })
+        })
+        .collect()
+}
+
+// Result links go through a redirect (`//duckduckgo.com/l/?uddg=<url>`); ads use `y.js`.
+fn duckduckgo_target(href: &str) -> Option<String> {
+    let absolute = if href.starts_with("//") {
+        format!("https:{href}")
+    } else {
+        href.to_string()
+    };
+    let url = reqwest::Url::parse(&absolute).ok()?;
+    if url.path() == "/y.js" {
+        return None;
+    }
+    if url.path() == "/l/" {
+        return url
+            .query_pairs()
+            .find(|(k, _)| k == "uddg")
+            .map(|(_, v)| v.into_owned());
+    }
+    matches!(url.scheme(), "http" | "https").then_some(absolute)
+}
+
+// ---------- Google Programmable Search ----------
+
+pub struct Google {
+    api_key: String,
+    cse_id: String,
+}
+
+#[derive(Deserialize)]
+struct GoogleResponse {
+    #[serde(default)]
+    items: Vec<GoogleItem>,
+}
+
+#[derive(Deserialize)]
+struct GoogleItem {
+    title: String,
+    link: String,
+    #[serde(default)]
+    snippet: Option<String>,
+    #[serde(default)]
+    pagemap: Option<serde_json::Value>,
+}
+
+impl Google {
+    fn from_env() -> Option<Self> {
+        Some(Self {
+            api_key: non_empty(crate::config::var("GOOGLE_API_KEY").ok())?,
+            cse_id: non_empty(crate::config::var("GOOGLE_CSE_ID").ok())?,
+        })
+    }
+}
+
+impl SearchProvider for Google {
+    fn name(&self) -> &'static str {
+        "google"
+    }
+
+    fn search<'a>(
+        &'a self,
+        client: &'a Client,
+        query: &'a SearchQuery<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<SearchResult>>> {
+        Box::pin(async move {
+            let mut params = vec![
+                ("key", self.api_key.as_str()),
+                ("cx", self.cse_id.as_str()),
+                ("q", query.query),
+                ("num", "10"),
+            ];
+            if query.category == "news" {
+                params.push(("sort", "date"));
+            }
+            if let Some(site) = query.site {
+                params.push(("siteSearch", site));
+            }
+            let date_restrict = query.time_range.map(|r| format!("{}1", range_letter(r)));
+            if let Some(restrict) = &date_restrict {
+                params.push(("dateRestrict", restrict));
+            }
+            let lang_restrict = query
+                .language
+                .map(|l| format!("lang_{}", crate::locale::primary(l).to_lowercase()));
+            if let Some(lang) = &lang_restrict {
+                params.push(("lr", lang));
+            }
+            let resp = client
+                .get("https://www.googleapis.com/customsearch/v1")
+                .query(&params)
+                .send()
+                .await?;
+            let parsed: GoogleResponse = check_status(resp, "google").await?.json().await?;
+
+            Ok(parsed
+                .items
+                .into_iter()
+                .map(|item| {
+                    let published = item
+                        .pagemap
+                        .as_ref()
+                        .and_then(|p| p["metatags"][0]["article:published_time"].as_str())
+                        .map(str::to_string);
+                    SearchResult {
+                        title: item.title,
+                        snippet: item.snippet.unwrap_or_default(),
+                        url: item.link,
+                        published,
+                        citation: None,
+                        provider: None,
+                    }
+                })
+                .collect())
+        })
+    }
+}
+
+// "day" -> "d" etc., the base of each provider's recency codes.
+fn range_letter(range: &str) -> &'static str {
+    match range {
+        "day" => "d",
+        "week" => "w",
+        "month" => "m",
+        _ => "y",
+    }
+}
+
+// Brave highlights matches with <strong> tags.
+fn strip_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,150 @@
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Pins a conversation to one llama-server slot (`id_slot`) so every turn lands where its KV
+// cache already is. Enabled with LLAMA_SLOT_PINNING=1 for chats that send a conversation_id.
+// The slot count comes from LLAMA_SLOTS or llama-server's /props. A new conversation takes a
+// free slot, or evicts the one idle the longest; a turn whose slot is busy with another
+// request is left to llama-server's own slot choice rather than queued behind it.
+
+pub struct SlotPinning {
+    slots: Mutex<Vec<Slot>>,
+}
+
+#[derive(Clone)]
+struct Slot {
+    conversation: Option<String>,
+    busy: bool,
+    last_used: Instant,
+}
+
+impl SlotPinning {
+    pub async fn from_env(llama_base_url: &str) -> Option<Self> {
+        if !crate::env_flag("LLAMA_SLOT_PINNING") {
+            return None;
+        }
+        let configured = crate::config::var("LLAMA_SLOTS")
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok());
+        let count = match configured {
+            Some(count) => count,
+            None => match total_slots(llama_base_url).await {
+                Ok(count) => count,
+                Err(err) => {
+                    log!("slot pinning disabled: could not read slot count: {err:?}");
+                    return None;
+                }
+            },
+        };
+        if count == 0 {
+            return None;
+        }
+        let slot = Slot {
+            conversation: None,
+            busy: false,
+            last_used: Instant::now(),
+        };
+        Some(Self {
+            slots: Mutex::new(vec![slot; count]),
+        })
+    }
+
+    // The slot for this conversation's next request, or None to let llama-server choose.
+    pub fn acquire(self: &Arc<Self>, conversation: &str) -> Option<SlotLease> {
+        let mut slots = self.slots.lock().unwrap();
+        let index = match slots
+            .iter()
+            .position(|s| s.conversation.as_deref() == Some(conversation))
+        {
+            Some(index) if slots[index].busy => return None,
+            Some(index) => index,
+            None => {
+                let index = slots
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| !s.busy)
+                    .min_by_key(|(_, s)| (s.conversation.is_some(), s.last_used))
+                    .map(|(i, _)| i)?;
+                slots[index].conversation = Some(conversation.to_string());
+                index
+            }
+        };
+        slots[index].busy = true;
+        Some(SlotLease {
+            pinning: Arc::clone(self),
+            index,
+        })
+    }
+
+    pub fn assignments(&self) -> Vec<SlotAssignment> {
+        self.slots
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(id, slot)| SlotAssignment {
+                id,
+                conversation: slot.conversation.clone(),
+                in_use: slot.busy,
+                idle_secs: (!slot.busy).then(|| slot.last_used.elapsed().as_secs()),
+            })
+            .collect()
+    }
+
+    fn release(&self, index: usize) {
+        let mut slots = self.slots.lock().unwrap();
+        slots[index].busy = false;
+        slots[index].last_used = Instant::now();
+    }
+
+    fn forget(&self, index: usize) {
+        self.slots.lock().unwrap()[index].conversation = None;
+    }
+}
+
+#[derive(Serialize)]
+pub struct SlotAssignment {
+    pub id: usize,
+    pub conversation: Option<String>,
+    pub in_use: bool,
+    pub idle_secs: Option<u64>,
+}
+
+// Marks the slot busy until the chat stream holding it ends.
+pub struct SlotLease {
+    pinning: Arc<SlotPinning>,
+    index: usize,
+}
+
+impl SlotLease {
+    pub fn id(&self) -> usize {
+        self.index
+    }
+
+    // Drops the pin after llama-server refused the slot, e.g. because it now runs fewer.
+    pub fn forget(&self) {
+        self.pinning.forget(self.index);
+    }
+}
+
+impl Drop for SlotLease {
+    fn drop(&mut self) {
+        self.pinning.release(self.index);
+    }
+}
+
+async fn total_slots(llama_base_url: &str) -> anyhow::Result<usize> {
+    let props: serde_json::Value = reqwest::Client::new()
+        .get(format!("{llama_base_url}/props"))
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    props["total_slots"]
+        .as_u64()
+        .map(|n| n as usize)
+        .ok_or_else(|| anyhow::anyhow!("/props has no total_slots"))
+}
@@ -0,0 +1,233 @@
+use reqwest::Url;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::{Attempt, Policy};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use crate::env_flag;
+
+// Keeps the page scraper away from internal services: only http(s) URLs are fetched, and
+// hosts resolving to loopback, private, link-local and other non-public addresses are refused,
+// both for the first request and for every redirect. SCRAPE_ALLOW_PRIVATE_NETWORKS turns the
+// check off; SCRAPE_ALLOWED_NETWORKS (comma-separated IPs or CIDRs) exempts specific ranges.
+//
+// SCRAPE_ALLOWED_DOMAINS and SCRAPE_DENIED_DOMAINS are comma-separated glob patterns such as
+// `*.example.com` or `docs.*`; a pattern without `*` also covers its subdomains. When an
+// allowlist is set only matching hosts are contacted, and the denylist always wins.
+
+const MAX_REDIRECTS: usize = 10;
+
+#[derive(Clone)]
+pub struct NetworkGuard {
+    allow_private: bool,
+    allowed: Vec<(IpAddr, u8)>,
+    allowed_domains: Vec<String>,
+    denied_domains: Vec<String>,
+}
+
+fn domain_patterns(name: &str) -> Vec<String> {
+    crate::config::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|p| p.trim().trim_end_matches('.').to_ascii_lowercase())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+impl NetworkGuard {
+    pub fn from_env() -> Self {
+        let allowed = crate::config::var("SCRAPE_ALLOWED_NETWORKS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| {
+                let parsed = parse_network(s);
+                if parsed.is_none() {
+                    log!("ignoring invalid network {s:?} in SCRAPE_ALLOWED_NETWORKS");
+                }
+                parsed
+            })
+            .collect();
+        Self {
+            allow_private: env_flag("SCRAPE_ALLOW_PRIVATE_NETWORKS"),
+            allowed,
+            allowed_domains: domain_patterns("SCRAPE_ALLOWED_DOMAINS"),
+            denied_domains: domain_patterns("SCRAPE_DENIED_DOMAINS"),
+        }
+    }
+
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        self.allow_private
+            || is_public(ip)
+            || self
+                .allowed
+                .iter()
+                .any(|(net, prefix)| in_network(ip, *net, *prefix))
+    }
+
+    pub fn permits_domain(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let matches = |patterns: &[String]| patterns.iter().any(|p| domain_matches(p, &host));
+        !matches(&self.denied_domains)
+            && (self.allowed_domains.is_empty() || matches(&self.allowed_domains))
+    }
+
+    // Scheme, domain list and literal-IP check; resolved addresses of hostnames are checked
+    // by the resolver.
+    pub fn check_url(&self, url: &Url) -> Result<(), String> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("{} URLs are not fetched", url.scheme()));
+        }
+        let Some(host) = url.host_str() else {
+            return Err("URL has no host".into());
+        };
+        if !self.permits_domain(host) {
+            return Err(format!("{host} is not an allowed domain"));
+        }
+        let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse() else {
+            return Ok(());
+        };
+        if self.permits(ip) {
+            Ok(())
+        } else {
+            Err(format!("{ip} is not a public address"))
+        }
+    }
+
+    pub fn redirect_policy(self: &Arc<Self>) -> Policy {
+        let guard = Arc::clone(self);
+        Policy::custom(move |attempt: Attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match guard.check_url(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(reason) => attempt.error(format!("redirect blocked: {reason}")),
+            }
+        })
+    }
+}
+
+// Resolves hostnames for the scrape client and refuses the lookup when any address is not
+// permitted, so a public name pointing at an internal address cannot slip through.
+impl Resolve for NetworkGuard {
+    fn resolve(&self, name: Name) -> Resolving {
+        let guard = self.clone();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if let Some(blocked) = addrs.iter().find(|addr| !guard.permits(addr.ip())) {
+                return Err(format!(
+                    "{} resolves to {}, which is not a public address",
+                    name.as_str(),
+                    blocked.ip()
+                )
+                .into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+// ---------- Domain patterns ----------
+
+fn domain_matches(pattern: &str, host: &str) -> bool {
+    if !pattern.contains('*') {
+        return host == pattern || host.ends_with(&format!(".{pattern}"));
+    }
+    glob_matches(pattern, host)
+}
+
+// `*` matches any run of characters, including dots.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.is_empty()
+}
+
+// ---------- Address ranges ----------
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public_v4(v4);
+            }
+            let segments = ip.segments();
+            // NAT64 (64:ff9b::/96) embeds an IPv4 address.
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [a, b] = segments[6].to_be_bytes();
+                let [c, d] = segments[7].to_be_bytes();
+                return is_public_v4(Ipv4Addr::new(a, b, c, d));
+            }
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || in_network(
+                    IpAddr::V6(ip),
+                    IpAddr::V6(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0)),
+                    7,
+                )
+                || in_network(
+                    IpAddr::V6(ip),
+                    IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0)),
+                    10,
+                ))
+        }
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let carrier_nat = in_network(IpAddr::V4(ip), IpAddr::V4(Ipv4Addr::new(100, 64, 0, 0)), 10);
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || ip.octets()[0] == 0
+        || ip.octets()[0] >= 240
+        || carrier_nat)
+}
+
+fn parse_network(text: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match text.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix.parse::<u8>().ok()?)),
+        None => (text, None),
+    };
+    let ip: IpAddr = addr.parse().ok()?;
+    let max = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max);
+    (prefix <= max).then_some((ip, prefix))
+}
+
+fn in_network(ip: IpAddr, net: IpAddr, prefix: u8) -> bool {
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,431 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::AppState;
+
+// Structured output. A chat's `response_format` is forwarded to llama-server, which turns the
+// schema into a grammar, e.g. {"type": "json_schema", "schema": {...}} or OpenAI's
+// {"type": "json_schema", "json_schema": {"name": ..., "schema": {...}}}. Constrained output
+// can still come back unusable (cut off at max tokens, wrapped in a code fence), so once the
+// answer is complete it is parsed, repaired if needed and checked against the schema. When
+// local fixes are not enough the model gets one non-streaming attempt at correcting it. The
+// outcome is sent as a `structured_output` event.
+
+const REPAIR_MAX_TOKENS: u32 = 2048;
+const MAX_ERRORS: usize = 20;
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    JsonObject {
+        #[serde(default)]
+        schema: Option<Value>,
+    },
+    JsonSchema {
+        #[serde(default)]
+        schema: Option<Value>,
+        #[serde(default)]
+        json_schema: Option<Value>,
+    },
+}
+
+impl ResponseFormat {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ResponseFormat::JsonSchema { .. } if self.schema().is_none() => {
+                Err("response_format json_schema needs a schema object".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        !matches!(self, ResponseFormat::Text)
+    }
+
+    fn schema(&self) -> Option<&Value> {
+        let schema = match self {
+            ResponseFormat::Text => None,
+            ResponseFormat::JsonObject { schema } => schema.as_ref(),
+            ResponseFormat::JsonSchema {
+                schema,
+                json_schema,
+            } => schema
+                .as_ref()
+                .or_else(|| json_schema.as_ref().and_then(|w| w.get("schema"))),
+        };
+        schema.filter(|s| s.is_object())
+    }
+
+    // The shape llama-server reads.
+    pub fn upstream(&self) -> Value {
+        match (self, self.schema()) {
+            (ResponseFormat::Text, _) => serde_json::json!({ "type": "text" }),
+            (ResponseFormat::JsonObject { .. }, None) => {
+                serde_json::json!({ "type": "json_object" })
+            }
+            (ResponseFormat::JsonObject { .. }, Some(schema)) => {
+                serde_json::json!({ "type": "json_object", "schema": schema })
+            }
+            (ResponseFormat::JsonSchema { .. }, schema) => serde_json::json!({
+                "type": "json_schema",
+                "json_schema": { "schema": schema.cloned().unwrap_or_else(|| serde_json::json!({})) },
+            }),
+        }
+    }
+}
+
+// Payload of the `structured_output` event for a finished answer.
+pub async fn finalize(state: &AppState, format: &ResponseFormat, answer: &str) -> Value {
+    let schema = format.schema();
+    let check = |value: Value| {
+        let errors = match schema {
+            Some(schema) => validate(schema, &value),
+            None => Vec::new(),
+        };
+        (value, errors)
+    };
+
+    let mut repaired = false;
+    let mut outcome = match serde_json::from_str::<Value>(answer.trim()) {
+        Ok(value) => Some(check(value)),
+        Err(_) => {
+            repaired = true;
+            repair(answer).map(check)
+        }
+    };
+
+    if !outcome
+        .as_ref()
+        .is_some_and(|(_, errors)| errors.is_empty())
+    {
+        let errors = match &outcome {
+            Some((_, errors)) => errors.clone(),
+            None => vec!["not valid JSON".to_string()],
+        };
+        match repair_with_model(state, format, answer, &errors).await {
+            Ok(fixed) => {
+                if let Some(result) = serde_json::from_str(fixed.trim())
+                    .ok()
+                    .or_else(|| repair(&fixed))
+                    .map(check)
+                    && (outcome.is_none() || result.1.is_empty())
+                {
+                    repaired = true;
+                    outcome = Some(result);
+                }
+            }
+            Err(err) => log!("structured output repair failed: {err:?}"),
+        }
+    }
+
+    match outcome {
+        Some((value, errors)) => serde_json::json!({
+            "valid": errors.is_empty(),
+            "repaired": repaired,
+            "value": value,
+            "errors": errors,
+        }),
+        None => serde_json::json!({
+            "valid": false,
+            "repaired": false,
+            "value": null,
+            "errors": ["the answer is not valid JSON"],
+        }),
+    }
+}
+
+async fn repair_with_model(
+    state: &AppState,
+    format: &ResponseFormat,
+    answer: &str,
+    errors: &[String],
+) -> anyhow::Result<String> {
+    let schema = format
+        .schema()
+        .map(|s| format!("\n\nJSON schema:\n{s}"))
+        .unwrap_or_default();
+    let prompt = format!(
+        "The text below was supposed to be a single JSON document but has problems: {}.{schema}\n\n\
+Rewrite it as valid JSON that keeps its content. Reply with the JSON only.\n\nText:\n{answer}",
+        errors.join("; ")
+    );
+    let body = serde_json::json!({
+        "model": state.llama_model,
+        "messages": [{ "role": "user", "content": prompt }],
+        "stream": false,
+        "temperature": 0.0,
+        "max_tokens": REPAIR_MAX_TOKENS,
+        "response_format": format.upstream(),
+    });
+    let resp: Value = crate::request_id::tag(
+        reqwest::Client::new().post(format!("{}/v1/chat/completions", state.llama_base_url)),
+    )
+    .bearer_auth("no-key")
+    .timeout(Duration::from_secs(60))
+    .json(&body)
+    .send()
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    Ok(resp["choices"][0]["message"]["content"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string())
+}
+
+// ---------- Grammars ----------
+
+// GBNF grammars (llama.cpp's own format) constrain sampling token by token. A chat passes one
+// inline as `grammar` or names a file with `grammar_name`, which is read from
+// GRAMMAR_DIR/<name>.gbnf (default data/grammars).
+
+const MAX_GRAMMAR_BYTES: usize = 64 * 1024;
+
+pub fn grammar_dir_from_env() -> PathBuf {
+    crate::config::var("GRAMMAR_DIR")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("data/grammars"))
+}
+
+pub fn check_grammar(grammar: &str) -> Result<(), String> {
+    if grammar.trim().is_empty() {
+        return Err("grammar is empty".into());
+    }
+    if grammar.len() > MAX_GRAMMAR_BYTES {
+        return Err(format!("grammar is larger than {MAX_GRAMMAR_BYTES} bytes"));
+    }
+    Ok(())
+}
+
+pub async fn load_grammar(dir: &Path, name: &str) -> Result<String, String> {
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return Err(format!("invalid grammar name: {name:?}"));
+    }
+    let grammar = tokio::fs::read_to_string(dir.join(format!("{name}.gbnf")))
+        .await
+        .map_err(|_| format!("unknown grammar: {name}"))?;
+    check_grammar(&grammar)?;
+    Ok(grammar)
+}
+
+// ---------- Local repair ----------
+
+// Fixes the usual defects of model-written JSON: surrounding prose or code fences, trailing
+// commas, and a document cut off before its strings and brackets were closed.
+fn repair(text: &str) -> Option<Value> {
+    let start = text.find(['{', '['])?;
+    let mut out = String::with_capacity(text.len());
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in text[start..].chars() {
+        if in_string {
+            out.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                drop_trailing_comma(&mut out);
+                closers.pop();
+            }
+            _ => {}
+        }
+        out.push(c);
+        if closers.is_empty() {
+            // Anything after the document is prose.
+            break;
+        }
+    }
+
+    if in_string {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+    }
+    let trimmed = out.trim_end().len();
+    out.truncate(trimmed);
+    if out.ends_with(':') {
+        out.push_str("null");
+    }
+    drop_trailing_comma(&mut out);
+    while let Some(closer) = closers.pop() {
+        out.push(closer);
+    }
+    serde_json::from_str(&out).ok()
+}
+
+fn drop_trailing_comma(out: &mut String) {
+    let trimmed = out.trim_end();
+    if trimmed.ends_with(',') {
+        out.truncate(trimmed.len() - 1);
+    }
+}
+
+// ---------- Schema validation ----------
+
+// Covers the JSON Schema keywords grammar-based generation supports: type, enum, const,
+// properties, required, additionalProperties, items, min/maxItems, min/maxLength,
+// minimum/maximum and anyOf/oneOf/allOf.
+fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    check_value(schema, value, "$", &mut errors);
+    errors.truncate(MAX_ERRORS);
+    errors
+}
+
+fn check_value(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if let Some(types) = schema.get("type") {
+        let allowed: Vec<&str> = match types {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| has_type(value, t)) {
+            errors.push(format!("{path}: expected {}", allowed.join(" or ")));
+            return;
+        }
+    }
+    if let Some(options) = schema.get("enum").and_then(Value::as_array)
+        && !options.contains(value)
+    {
+        errors.push(format!("{path}: not one of the allowed values"));
+    }
+    if let Some(expected) = schema.get("const")
+        && expected != value
+    {
+        errors.push(format!("{path}: expected {expected}"));
+    }
+
+    for key in ["anyOf", "oneOf"] {
+        if let Some(options) = schema.get(key).and_then(Value::as_array)
+            && !options.iter().any(|option| {
+                let mut sub = Vec::new();
+                check_value(option, value, path, &mut sub);
+                sub.is_empty()
+            })
+        {
+            errors.push(format!("{path}: matches none of the {key} options"));
+        }
+    }
+    if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+        for option in all {
+            check_value(option, value, path, errors);
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for name in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                if !map.contains_key(name) {
+                    errors.push(format!("{path}: missing required property {name:?}"));
+                }
+            }
+            for (name, item) in map {
+                let child = format!("{path}.{name}");
+                match properties.and_then(|p| p.get(name)) {
+                    Some(sub) => check_value(sub, item, &child, errors),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            errors.push(format!("{path}: unexpected property {name:?}"))
+                        }
+                        Some(sub @ Value::Object(_)) => check_value(sub, item, &child, errors),
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            let bound = |key: &str| schema.get(key).and_then(Value::as_u64);
+            if let Some(min) = bound("minItems")
+                && (items.len() as u64) < min
+            {
+                errors.push(format!("{path}: fewer than {min} items"));
+            }
+            if let Some(max) = bound("maxItems")
+                && items.len() as u64 > max
+            {
+                errors.push(format!("{path}: more than {max} items"));
+            }
+            if let Some(sub) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check_value(sub, item, &format!("{path}[{i}]"), errors);
+                }
+            }
+        }
+        Value::String(text) => {
+            let len = text.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64)
+                && len < min
+            {
+                errors.push(format!("{path}: shorter than {min} characters"));
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64)
+                && len > max
+            {
+                errors.push(format!("{path}: longer than {max} characters"));
+            }
+        }
+        Value::Number(number) => {
+            let n = number.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64)
+                && n < min
+            {
+                errors.push(format!("{path}: below the minimum of {min}"));
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64)
+                && n > max
+            {
+                errors.push(format!("{path}: above the maximum of {max}"));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        _ => true,
+    }
+}
@@ -0,0 +1,417 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::process::Child;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+use crate::AppState;
+
+// Swaps models on one GPU on demand, llama-swap style. MODEL_SWAP_CONFIG (default
+// data/models.json) maps the aliases a chat may ask for in `model` to a llama-server:
+//   { "models": {
+//       "qwen-14b": { "cmd": ["llama-server", "-m", "/models/qwen-14b.gguf", "--port", "8081"],
+//                     "url": "http://127.0.0.1:8081" },
+//       "big-box": { "url": "http://10.0.0.5:8080", "model": "llama-70b",
+//                    "load_url": "http://10.0.0.5:8080/models/load",
+//                    "unload_url": "http://10.0.0.5:8080/models/unload" } },
+//     "default": "qwen-14b", "load_timeout_secs": 300, "idle_unload_secs": 900 }
+// A model with `cmd` is started as a child process; otherwise `load_url` and `unload_url`
+// (if set) are POSTed {"model": ...} to have a management API do it. Only one model is loaded
+// at a time. Chats for the loaded model run side by side; a chat for another model waits for
+// them to finish, and chats arriving during the swap queue up behind it in order. Helper calls
+// outside a chat (summaries, tokenize, embeddings) still go to LLAMA_BASE_URL.
+
+const DEFAULT_LOAD_TIMEOUT_SECS: u64 = 300;
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize)]
+struct SwapConfig {
+    models: BTreeMap<String, ModelConfig>,
+    #[serde(default)]
+    default: Option<String>,
+    #[serde(default)]
+    load_timeout_secs: Option<u64>,
+    #[serde(default)]
+    idle_unload_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ModelConfig {
+    url: String,
+    // Model name sent upstream; defaults to the alias.
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    cmd: Vec<String>,
+    #[serde(default)]
+    load_url: Option<String>,
+    #[serde(default)]
+    unload_url: Option<String>,
+}
+
+impl ModelConfig {
+    fn upstream_name<'a>(&'a self, alias: &'a str) -> &'a str {
+        self.model.as_deref().unwrap_or(alias)
+    }
+}
+
+pub struct ModelSwap {
+    models: BTreeMap<String, ModelConfig>,
+    default: Option<String>,
+    load_timeout: Duration,
+    idle_unload: Option<Duration>,
+    active: Arc<RwLock<Active>>,
+    last_used: Mutex<Instant>,
+}
+
+#[derive(Default)]
+struct Active {
+    alias: Option<String>,
+    // Behind a mutex so a chat holding only a read lock can notice it has exited.
+    child: Mutex<Option<Child>>,
+}
+
+impl Active {
+    fn serves(&self, alias: &str) -> bool {
+        if self.alias.as_deref() != Some(alias) {
+            return false;
+        }
+        match self.child.lock().unwrap().as_mut() {
+            Some(child) => matches!(child.try_wait(), Ok(None)),
+            None => true,
+        }
+    }
+}
+
+impl ModelSwap {
+    pub fn from_env() -> Option<Self> {
+        let path =
+            crate::config::var("MODEL_SWAP_CONFIG").unwrap_or_else(|_| "data/models.json".into());
+        let raw = std::fs::read_to_string(&path).ok()?;
+        let config: SwapConfig = match serde_json::from_str(&raw) {
+            Ok(config) => config,
+            Err(err) => {
+                log!("failed to parse {path}: {err}");
+                return None;
+            }
+        };
+        if config.models.is_empty() {
+            return None;
+        }
+        let mut default = config.default;
+        if let Some(alias) = &default
+            && !config.models.contains_key(alias)
+        {
+            log!("{path}: default model {alias:?} is not listed; ignoring it");
+            default = None;
+        }
+        if default.is_none() && config.models.len() == 1 {
+            default = config.models.keys().next().cloned();
+        }
+        Some(Self {
+            models: config.models,
+            default,
+            load_timeout: Duration::from_secs(
+                config
+                    .load_timeout_secs
+                    .unwrap_or(DEFAULT_LOAD_TIMEOUT_SECS),
+            ),
+            idle_unload: config
+                .idle_unload_secs
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            active: Arc::default(),
+            last_used: Mutex::new(Instant::now()),
+        })
+    }
+
+    // The alias a chat runs on: the one it asked for, or the default.
+    pub fn resolve(&self, requested: Option<&str>) -> Result<String, String> {
+        match requested {
+            Some(alias) if self.models.contains_key(alias) => Ok(alias.to_string()),
+            Some(alias) => Err(format!("unknown model: {alias}")),
+            None => self
+                .default
+                .clone()
+                .ok_or_else(|| "model is required: no default model is configured".to_string()),
+        }
+    }
+
+    // False while another model is loaded or a swap is under way, i.e. when acquire() will
+    // have to wait.
+    pub fn is_loaded(&self, alias: &str) -> bool {
+        self.active
+            .try_read()
+            .is_ok_and(|active| active.serves(alias))
+    }
+
+    // Waits until `alias` is loaded and keeps it loaded for as long as the lease is held.
+    pub async fn acquire(self: &Arc<Self>, alias: &str) -> anyhow::Result<ModelLease> {
+        let config = self
+            .models
+            .get(alias)
+            .ok_or_else(|| anyhow::anyhow!("unknown model: {alias}"))?;
+        let active = self.active.clone().read_owned().await;
+        if active.serves(alias) {
+            return Ok(self.lease(active, alias, config));
+        }
+        drop(active);
+
+        let mut active = self.active.clone().write_owned().await;
+        // Another queued chat may have loaded it already.
+        if !active.serves(alias) {
+            self.unload(&mut active).await;
+            self.load(&mut active, alias, config).await?;
+        }
+        Ok(self.lease(OwnedRwLockWriteGuard::downgrade(active), alias, config))
+    }
+
+    fn lease(
+        self: &Arc<Self>,
+        guard: OwnedRwLockReadGuard<Active>,
+        alias: &str,
+        config: &ModelConfig,
+    ) -> ModelLease {
+        *self.last_used.lock().unwrap() = Instant::now();
+        ModelLease {
+            _guard: guard,
+            swap: Arc::clone(self),
+            base_url: config.url.trim_end_matches('/').to_string(),
+            model: config.upstream_name(alias).to_string(),
+        }
+    }
+
+    async fn load(
+        &self,
+        active: &mut Active,
+        alias: &str,
+        config: &ModelConfig,
+    ) -> anyhow::Result<()> {
+        log!("loading model {alias}");
+        let started = Instant::now();
+        let client = reqwest::Client::new();
+        if let Some((program, args)) = config.cmd.split_first() {
+            let child = tokio::process::Command::new(program)
+                .args(args)
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::inherit())
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|err| anyhow::anyhow!("could not start {program}: {err}"))?;
+            *active.child.get_mut().unwrap() = Some(child);
+        } else if let Some(load_url) = &config.load_url {
+            client
+                .post(load_url)
+                .timeout(self.load_timeout)
+                .json(&serde_json::json!({ "model": config.upstream_name(alias) }))
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        active.alias = Some(alias.to_string());
+
+        let health = format!("{}/health", config.url.trim_end_matches('/'));
+        loop {
+            if let Some(child) = active.child.get_mut().unwrap()
+                && let Some(status) = child.try_wait()?
+            {
+                active.alias = None;
+                active.child.get_mut().unwrap().take();
+                anyhow::bail!("llama-server for {alias} exited while loading ({status})");
+            }
+            let ready = client
+                .get(&health)
+                .timeout(Duration::from_secs(5))
+                .send()
+                .await
+                .is_ok_and(|resp| resp.status().is_success());
+            if ready {
+                log!(
+                    "model {alias} ready in {:.1}s",
+                    started.elapsed().as_secs_f64()
+                );
+                return Ok(());
+            }
+            if started.elapsed() > self.load_timeout {
+                self.unload(active).await;
+                anyhow::bail!(
+                    "{alias} was not ready after {}s",
+                    self.load_timeout.as_secs()
+                );
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    async fn unload(&self, active: &mut Active) {
+        let Some(alias) = active.alias.take() else {
+            return;
+        };
+        log!("unloading model {alias}");
+        if let Some(mut child) = active.child.get_mut().unwrap().take() {
+            if let Err(err) = child.kill().await {
+                log!("could not stop llama-server for {alias}: {err}");
+            }
+            return;
+        }
+        let Some(config) = self.models.get(&alias) else {
+            return;
+        };
+        if let Some(unload_url) = &config.unload_url {
+            let sent = reqwest::Client::new()
+                .post(unload_url)
+                .timeout(self.load_timeout)
+                .json(&serde_json::json!({ "model": config.upstream_name(&alias) }))
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(err) = sent {
+                log!("unloading {alias} failed: {err:?}");
+            }
+        }
+    }
+
+    // Frees the GPU after no chat has used the loaded model for idle_unload_secs.
+    pub fn spawn_idle_unload(self: &Arc<Self>) {
+        let Some(idle) = self.idle_unload else {
+            return;
+        };
+        let swap = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(IDLE_CHECK_INTERVAL).await;
+                if swap.last_used.lock().unwrap().elapsed() < idle {
+                    continue;
+                }
+                // Fails while any chat holds a lease.
+                if let Ok(mut active) = swap.active.clone().try_write_owned() {
+                    swap.unload(&mut active).await;
+                }
+            }
+        });
+    }
+
+    // The loaded model's alias, server URL and --n-gpu-layers setting, without loading anything.
+    pub fn loaded_backend(&self) -> Option<(String, String, Option<i64>)> {
+        let active = self.active.try_read().ok()?;
+        let alias = active.alias.clone()?;
+        let config = self.models.get(&alias)?;
+        let gpu_layers = config
+            .cmd
+            .windows(2)
+            .find(|pair| matches!(pair[0].as_str(), "-ngl" | "--gpu-layers" | "--n-gpu-layers"))
+            .and_then(|pair| pair[1].parse().ok());
+        Some((
+            alias,
+            config.url.trim_end_matches('/').to_string(),
+            gpu_layers,
+        ))
+    }
+
+    fn status(&self) -> serde_json::Value {
+        let (loaded, swapping) = match self.active.try_read() {
+            Ok(active) => (active.alias.clone(), false),
+            Err(_) => (None, true),
+        };
+        let models: Vec<_> = self
+            .models
+            .keys()
+            .map(|alias| {
+                serde_json::json!({
+                    "id": alias,
+                    "loaded": loaded.as_deref() == Some(alias.as_str()),
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "models": models,
+            "default": self.default,
+            "loaded": loaded,
+            // A swap is running or waiting for chats to finish.
+            "busy": swapping,
+        })
+    }
+}
+
+// Keeps the model loaded until the chat holding it ends.
+pub struct ModelLease {
+    _guard: OwnedRwLockReadGuard<Active>,
+    swap: Arc<ModelSwap>,
+    pub base_url: String,
+    pub model: String,
+}
+
+impl Drop for ModelLease {
+    fn drop(&mut self) {
+        *self.swap.last_used.lock().unwrap() = Instant::now();
+    }
+}
+
+// ---------- Handlers ----------
+
+// Models a chat can pick; without MODEL_SWAP_CONFIG that is only the default model.
+pub async fn list_models_handler(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let default_model = state.config.default_model();
+    match &state.model_swap {
+        Some(swap) => {
+            let mut status = swap.status();
+            if let Some(alias) = default_model {
+                status["default"] = alias.into();
+            }
+            Json(status)
+        }
+        None => {
+            let model = default_model.unwrap_or_else(|| state.llama_model.clone());
+            Json(serde_json::json!({
+                "models": [{ "id": model, "loaded": true }],
+                "default": model,
+                "loaded": model,
+                "busy": false,
+            }))
+        }
+    }
+}
+
+// Admin: load a model ahead of the first chat that needs it.
+pub async fn load_model_handler(
+    State(state): State<Arc<AppState>>,
+    Path(alias): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let swap = swap_or_404(&state)?;
+    swap.resolve(Some(&alias))
+        .map_err(|msg| (StatusCode::NOT_FOUND, msg))?;
+    let lease = swap.acquire(&alias).await.map_err(|err| {
+        log!("loading {alias} failed: {err:?}");
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("could not load {alias}: {err}"),
+        )
+    })?;
+    drop(lease);
+    Ok(Json(swap.status()))
+}
+
+// Admin: unload the current model once running chats are done with it.
+pub async fn unload_model_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let swap = swap_or_404(&state)?;
+    let mut active = swap.active.clone().write_owned().await;
+    swap.unload(&mut active).await;
+    drop(active);
+    Ok(Json(swap.status()))
+}
+
+fn swap_or_404(state: &AppState) -> Result<&Arc<ModelSwap>, (StatusCode, String)> {
+    state.model_swap.as_ref().ok_or((
+        StatusCode::NOT_FOUND,
+        "model swapping is not configured (MODEL_SWAP_CONFIG)".to_string(),
+    ))
+}
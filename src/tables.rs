@@ -0,0 +1,561 @@
+use axum::{
+    Json,
+    extract::{Multipart, Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::AppState;
+use crate::tools::{Tool, ToolFunction};
+
+const MAX_TABLE_ROWS: usize = 500_000;
+const MAX_RESULT_ROWS: usize = 100;
+
+// ---------- In-memory table store ----------
+
+pub struct Table {
+    pub id: String,
+    pub name: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableInfo {
+    id: String,
+    name: String,
+    columns: Vec<String>,
+    rows: usize,
+}
+
+impl Table {
+    fn info(&self) -> TableInfo {
+        TableInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            columns: self.columns.clone(),
+            rows: self.rows.len(),
+        }
+    }
+
+    fn column_index(&self, name: &str) -> anyhow::Result<usize> {
+        self.columns
+            .iter()
+            .position(|c| c == name)
+            .or_else(|| {
+                self.columns
+                    .iter()
+                    .position(|c| c.eq_ignore_ascii_case(name))
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown column {name:?}; available: {}",
+                    self.columns.join(", ")
+                )
+            })
+    }
+}
+
+#[derive(Default)]
+pub struct TableStore {
+    tables: RwLock<Vec<Arc<Table>>>,
+}
+
+impl TableStore {
+    pub fn insert_csv(&self, name: String, bytes: &[u8]) -> anyhow::Result<TableInfo> {
+        let delimiter = if name.to_ascii_lowercase().ends_with(".tsv") {
+            b'\t'
+        } else {
+            b','
+        };
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(bytes);
+
+        let columns: Vec<String> = reader
+            .headers()?
+            .iter()
+            .enumerate()
+            .map(|(i, h)| {
+                let h = h.trim();
+                if h.is_empty() {
+                    format!("column_{}", i + 1)
+                } else {
+                    h.to_string()
+                }
+            })
+            .collect();
+
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            let mut row: Vec<String> = record.iter().map(|v| v.trim().to_string()).collect();
+            row.resize(columns.len(), String::new());
+            rows.push(row);
+            if rows.len() > MAX_TABLE_ROWS {
+                anyhow::bail!("table has more than {MAX_TABLE_ROWS} rows");
+            }
+        }
+
+        let table = Table {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            columns,
+            rows,
+        };
+        let info = table.info();
+        self.tables.write().unwrap().push(Arc::new(table));
+        Ok(info)
+    }
+
+    pub fn get(&self, id_or_name: &str) -> Option<Arc<Table>> {
+        let tables = self.tables.read().unwrap();
+        tables
+            .iter()
+            .find(|t| t.id == id_or_name)
+            .or_else(|| tables.iter().find(|t| t.name == id_or_name))
+            .cloned()
+    }
+
+    pub fn list(&self) -> Vec<TableInfo> {
+        self.tables
+            .read()
+            .unwrap()
+            .iter()
+            .map(|t| t.info())
+            .collect()
+    }
+
+    pub fn delete(&self, id: &str) -> bool {
+        let mut tables = self.tables.write().unwrap();
+        let before = tables.len();
+        tables.retain(|t| t.id != id);
+        tables.len() != before
+    }
+}
+
+// ---------- query_table tool ----------
+
+pub fn query_table_tool_definition(tables: &[Arc<Table>]) -> Tool {
+    let available = tables
+        .iter()
+        .map(|t| format!("{} (columns: {})", t.name, t.columns.join(", ")))
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    Tool {
+        tool_type: "function".into(),
+        function: ToolFunction {
+            name: "query_table".into(),
+            description: format!(
+                "Runs filter/group/aggregate queries over the user's uploaded tables and returns JSON rows. \
+Use operation \"describe\" first to see column types and sample rows. Available tables: {available}"
+            ),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "table": { "type": "string", "description": "Table name" },
+                    "operation": {
+                        "type": "string",
+                        "enum": ["describe", "query"],
+                        "description": "describe returns schema and sample rows; query runs the query (default)"
+                    },
+                    "filters": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "column": { "type": "string" },
+                                "op": { "type": "string", "enum": ["eq", "ne", "gt", "gte", "lt", "lte", "contains"] },
+                                "value": { "type": ["string", "number"] }
+                            },
+                            "required": ["column", "op", "value"]
+                        }
+                    },
+                    "group_by": { "type": "array", "items": { "type": "string" } },
+                    "aggregations": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "column": { "type": "string", "description": "Column to aggregate; omit for count" },
+                                "op": { "type": "string", "enum": ["count", "sum", "mean", "min", "max", "distinct"] }
+                            },
+                            "required": ["op"]
+                        }
+                    },
+                    "columns": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Columns to return when not aggregating (default all)"
+                    },
+                    "sort_by": { "type": "string", "description": "Column or aggregate name (e.g. sum_price) to sort by" },
+                    "descending": { "type": "boolean" },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_RESULT_ROWS }
+                },
+                "required": ["table"]
+            }),
+        },
+    }
+}
+
+#[derive(Deserialize)]
+pub struct QueryTableArgs {
+    pub table: String,
+    #[serde(default)]
+    operation: Option<String>,
+    #[serde(default)]
+    filters: Vec<Filter>,
+    #[serde(default)]
+    group_by: Vec<String>,
+    #[serde(default)]
+    aggregations: Vec<Aggregation>,
+    #[serde(default)]
+    columns: Vec<String>,
+    #[serde(default)]
+    sort_by: Option<String>,
+    #[serde(default)]
+    descending: bool,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct Filter {
+    column: String,
+    op: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Aggregation {
+    #[serde(default)]
+    column: Option<String>,
+    op: String,
+}
+
+fn parse_number(value: &str) -> Option<f64> {
+    let cleaned: String = value
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ',' | '$' | '€' | '£' | '%' | ' '))
+        .collect();
+    cleaned.parse().ok().filter(|v: &f64| v.is_finite())
+}
+
+fn compare_values(a: &str, b: &str) -> Ordering {
+    match (parse_number(a), parse_number(b)) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        _ => a.to_lowercase().cmp(&b.to_lowercase()),
+    }
+}
+
+fn json_number(v: f64) -> serde_json::Value {
+    if v.fract() == 0.0 && v.abs() < 1e15 {
+        serde_json::json!(v as i64)
+    } else {
+        serde_json::json!((v * 1e6).round() / 1e6)
+    }
+}
+
+fn describe(table: &Table) -> serde_json::Value {
+    let columns: Vec<_> = table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let values: Vec<&str> = table
+                .rows
+                .iter()
+                .map(|r| r[i].as_str())
+                .filter(|v| !v.is_empty())
+                .collect();
+            let numbers: Vec<f64> = values.iter().filter_map(|v| parse_number(v)).collect();
+            let numeric = !values.is_empty() && numbers.len() == values.len();
+            let mut col = serde_json::json!({
+                "name": name,
+                "type": if numeric { "number" } else { "text" },
+                "non_empty": values.len(),
+            });
+            if numeric {
+                let min = numbers.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                let mean = numbers.iter().sum::<f64>() / numbers.len() as f64;
+                col["min"] = json_number(min);
+                col["max"] = json_number(max);
+                col["mean"] = json_number(mean);
+            } else {
+                let mut distinct: Vec<&str> = values.clone();
+                distinct.sort_unstable();
+                distinct.dedup();
+                col["distinct"] = serde_json::json!(distinct.len());
+            }
+            col
+        })
+        .collect();
+
+    let sample: Vec<_> = table
+        .rows
+        .iter()
+        .take(5)
+        .map(|r| row_object(&table.columns, r, None))
+        .collect();
+
+    serde_json::json!({
+        "table": table.name,
+        "row_count": table.rows.len(),
+        "columns": columns,
+        "sample_rows": sample,
+    })
+}
+
+fn row_object(columns: &[String], row: &[String], only: Option<&[usize]>) -> serde_json::Value {
+    let mut obj = serde_json::Map::new();
+    let indices: Vec<usize> = match only {
+        Some(idx) => idx.to_vec(),
+        None => (0..columns.len()).collect(),
+    };
+    for i in indices {
+        let value = match parse_number(&row[i]) {
+            Some(n) if !row[i].is_empty() => json_number(n),
+            _ => serde_json::Value::String(row[i].clone()),
+        };
+        obj.insert(columns[i].clone(), value);
+    }
+    serde_json::Value::Object(obj)
+}
+
+fn filter_matches(cell: &str, op: &str, value: &str) -> anyhow::Result<bool> {
+    Ok(match op {
+        "eq" => compare_values(cell, value) == Ordering::Equal,
+        "ne" => compare_values(cell, value) != Ordering::Equal,
+        "gt" => compare_values(cell, value) == Ordering::Greater,
+        "gte" => compare_values(cell, value) != Ordering::Less,
+        "lt" => compare_values(cell, value) == Ordering::Less,
+        "lte" => compare_values(cell, value) != Ordering::Greater,
+        "contains" => cell.to_lowercase().contains(&value.to_lowercase()),
+        other => anyhow::bail!("unknown filter op {other:?}"),
+    })
+}
+
+fn aggregate(op: &str, values: &[&str]) -> anyhow::Result<serde_json::Value> {
+    let numbers = || values.iter().filter_map(|v| parse_number(v));
+    Ok(match op {
+        "count" => serde_json::json!(values.iter().filter(|v| !v.is_empty()).count()),
+        "distinct" => {
+            let mut distinct: Vec<&str> = values.to_vec();
+            distinct.sort_unstable();
+            distinct.dedup();
+            serde_json::json!(distinct.len())
+        }
+        "sum" => json_number(numbers().sum()),
+        "mean" => {
+            let n: Vec<f64> = numbers().collect();
+            if n.is_empty() {
+                serde_json::Value::Null
+            } else {
+                json_number(n.iter().sum::<f64>() / n.len() as f64)
+            }
+        }
+        "min" => numbers()
+            .reduce(f64::min)
+            .map(json_number)
+            .unwrap_or(serde_json::Value::Null),
+        "max" => numbers()
+            .reduce(f64::max)
+            .map(json_number)
+            .unwrap_or(serde_json::Value::Null),
+        other => anyhow::bail!("unknown aggregation op {other:?}"),
+    })
+}
+
+fn sort_key(v: &serde_json::Value) -> String {
+    match v {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub fn run_query(table: &Table, args: &QueryTableArgs) -> anyhow::Result<serde_json::Value> {
+    if args.operation.as_deref() == Some("describe") {
+        return Ok(describe(table));
+    }
+
+    let filters: Vec<(usize, &str, String)> = args
+        .filters
+        .iter()
+        .map(|f| {
+            let value = match &f.value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            Ok((table.column_index(&f.column)?, f.op.as_str(), value))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    let mut matched: Vec<&Vec<String>> = Vec::new();
+    for row in &table.rows {
+        let mut keep = true;
+        for (idx, op, value) in &filters {
+            if !filter_matches(&row[*idx], op, value)? {
+                keep = false;
+                break;
+            }
+        }
+        if keep {
+            matched.push(row);
+        }
+    }
+
+    let limit = args.limit.unwrap_or(20).clamp(1, MAX_RESULT_ROWS);
+
+    let mut out_rows: Vec<serde_json::Value> =
+        if args.aggregations.is_empty() && args.group_by.is_empty() {
+            let only: Option<Vec<usize>> = if args.columns.is_empty() {
+                None
+            } else {
+                Some(
+                    args.columns
+                        .iter()
+                        .map(|c| table.column_index(c))
+                        .collect::<anyhow::Result<_>>()?,
+                )
+            };
+            matched
+                .iter()
+                .map(|r| row_object(&table.columns, r, only.as_deref()))
+                .collect()
+        } else {
+            let group_idx: Vec<usize> = args
+                .group_by
+                .iter()
+                .map(|c| table.column_index(c))
+                .collect::<anyhow::Result<_>>()?;
+            let aggs: Vec<(Option<usize>, &str)> = if args.aggregations.is_empty() {
+                vec![(None, "count")]
+            } else {
+                args.aggregations
+                    .iter()
+                    .map(|a| {
+                        let idx = a
+                            .column
+                            .as_deref()
+                            .map(|c| table.column_index(c))
+                            .transpose()?;
+                        Ok((idx, a.op.as_str()))
+                    })
+                    .collect::<anyhow::Result<_>>()?
+            };
+
+            let mut groups: Vec<(Vec<&str>, Vec<&Vec<String>>)> = Vec::new();
+            let mut positions: HashMap<Vec<&str>, usize> = HashMap::new();
+            for row in &matched {
+                let key: Vec<&str> = group_idx.iter().map(|&i| row[i].as_str()).collect();
+                let pos = *positions.entry(key.clone()).or_insert_with(|| {
+                    groups.push((key, Vec::new()));
+                    groups.len() - 1
+                });
+                groups[pos].1.push(row);
+            }
+
+            groups
+                .into_iter()
+                .map(|(key, rows)| {
+                    let mut obj = serde_json::Map::new();
+                    for (&i, value) in group_idx.iter().zip(key) {
+                        obj.insert(table.columns[i].clone(), value.into());
+                    }
+                    for (col, op) in &aggs {
+                        let (name, values): (String, Vec<&str>) = match col {
+                            Some(i) => (
+                                format!("{op}_{}", table.columns[*i]),
+                                rows.iter().map(|r| r[*i].as_str()).collect(),
+                            ),
+                            None => ((*op).to_string(), rows.iter().map(|_| "1").collect()),
+                        };
+                        obj.insert(name, aggregate(op, &values)?);
+                    }
+                    Ok(serde_json::Value::Object(obj))
+                })
+                .collect::<anyhow::Result<_>>()?
+        };
+
+    if let Some(sort_by) = &args.sort_by {
+        out_rows.sort_by(|a, b| {
+            let av = a.get(sort_by).map(sort_key).unwrap_or_default();
+            let bv = b.get(sort_by).map(sort_key).unwrap_or_default();
+            compare_values(&av, &bv)
+        });
+        if args.descending {
+            out_rows.reverse();
+        }
+    }
+
+    let total = out_rows.len();
+    out_rows.truncate(limit);
+
+    Ok(serde_json::json!({
+        "table": table.name,
+        "matched_rows": matched.len(),
+        "result_rows": total,
+        "truncated": total > limit,
+        "rows": out_rows,
+    }))
+}
+
+// ---------- HTTP endpoints ----------
+
+// Multipart upload with a `file` field holding CSV/TSV data.
+pub async fn upload_table_handler(
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> Result<Json<TableInfo>, (StatusCode, String)> {
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("invalid multipart body: {e}"),
+        )
+    })? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let name = field.file_name().unwrap_or("table.csv").to_string();
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("failed to read file: {e}")))?;
+        return state
+            .tables
+            .insert_csv(name, &bytes)
+            .map(Json)
+            .map_err(|e| {
+                (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("invalid CSV: {e}"),
+                )
+            });
+    }
+
+    Err((StatusCode::BAD_REQUEST, "missing file field".into()))
+}
+
+pub async fn list_tables_handler(State(state): State<Arc<AppState>>) -> Json<Vec<TableInfo>> {
+    Json(state.tables.list())
+}
+
+pub async fn delete_table_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> StatusCode {
+    if state.tables.delete(&id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+// Per-host politeness for the scraper, shared by every chat: a token bucket allowing
+// SCRAPE_HOST_BURST requests (default 4) refilled at SCRAPE_HOST_RATE per minute (default 30),
+// plus at least SCRAPE_HOST_DELAY_MS (default 500) between two requests to the same host.
+// A fetch that would have to wait longer than SCRAPE_HOST_MAX_WAIT_SECS (default 10) is
+// skipped instead. The admin config API can replace these limits at runtime.
+
+const PRUNE_ABOVE: usize = 1024;
+
+pub struct HostThrottle {
+    limits: RwLock<ThrottleLimits>,
+    env_limits: ThrottleLimits,
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThrottleLimits {
+    pub burst: f64,
+    pub per_minute: f64,
+    pub delay_ms: u64,
+    pub max_wait_secs: u64,
+}
+
+impl ThrottleLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.burst < 1.0 {
+            return Err("burst must be at least 1".into());
+        }
+        if self.per_minute <= 0.0 {
+            return Err("per_minute must be positive".into());
+        }
+        Ok(())
+    }
+}
+
+struct HostState {
+    tokens: f64,
+    refilled_at: Instant,
+    last_start: Instant,
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    crate::config::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+impl HostThrottle {
+    pub fn from_env() -> Self {
+        let limits = ThrottleLimits {
+            burst: env_parse("SCRAPE_HOST_BURST")
+                .filter(|b: &f64| *b >= 1.0)
+                .unwrap_or(4.0),
+            per_minute: env_parse("SCRAPE_HOST_RATE")
+                .filter(|r: &f64| *r > 0.0)
+                .unwrap_or(30.0),
+            delay_ms: env_parse("SCRAPE_HOST_DELAY_MS").unwrap_or(500),
+            max_wait_secs: env_parse("SCRAPE_HOST_MAX_WAIT_SECS").unwrap_or(10),
+        };
+        Self {
+            limits: RwLock::new(limits.clone()),
+            env_limits: limits,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn limits(&self) -> ThrottleLimits {
+        self.limits.read().unwrap().clone()
+    }
+
+    // None goes back to the SCRAPE_HOST_* settings.
+    pub fn set_limits(&self, limits: Option<ThrottleLimits>) {
+        *self.limits.write().unwrap() = limits.unwrap_or_else(|| self.env_limits.clone());
+    }
+
+    // Waits for this host's turn; false when that would take longer than the maximum wait.
+    pub async fn acquire(&self, host: &str) -> bool {
+        let Some(start_at) = self.reserve(host) else {
+            return false;
+        };
+        tokio::time::sleep_until(start_at.into()).await;
+        true
+    }
+
+    fn reserve(&self, host: &str) -> Option<Instant> {
+        let limits = self.limits();
+        let burst = limits.burst;
+        let per_sec = limits.per_minute / 60.0;
+        let min_delay = Duration::from_millis(limits.delay_ms);
+        let max_wait = Duration::from_secs(limits.max_wait_secs);
+        let now = Instant::now();
+        let mut hosts = self.hosts.lock().unwrap();
+        if hosts.len() > PRUNE_ABOVE {
+            hosts.retain(|_, s| {
+                now.saturating_duration_since(s.last_start) < Duration::from_secs(600)
+            });
+        }
+        let Some(state) = hosts.get_mut(host) else {
+            hosts.insert(
+                host.to_string(),
+                HostState {
+                    tokens: burst - 1.0,
+                    refilled_at: now,
+                    last_start: now,
+                },
+            );
+            return Some(now);
+        };
+
+        let elapsed = now
+            .saturating_duration_since(state.refilled_at)
+            .as_secs_f64();
+        let tokens = (state.tokens + elapsed * per_sec).min(burst);
+        // Tokens below one are owed by requests already waiting.
+        let token_wait = Duration::from_secs_f64(((1.0 - tokens) / per_sec).max(0.0));
+        let start_at = (now + token_wait).max(state.last_start + min_delay);
+        if start_at.saturating_duration_since(now) > max_wait {
+            return None;
+        }
+        state.tokens = tokens - 1.0;
+        state.refilled_at = now;
+        state.last_start = start_at;
+        Some(start_at)
+    }
+}
@@ -0,0 +1,78 @@
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::{
+    self,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+
+// HTTPS, on when TLS_CERT and TLS_KEY name PEM files (the certificate chain and its private
+// key). Browsers then negotiate HTTP/2 through ALPN, so any number of chat streams share one
+// connection instead of running into HTTP/1.1's six per host. Without TLS the server still
+// speaks HTTP/2 to clients that start with it (h2c), which reverse proxies such as Caddy and
+// Envoy can be set to use upstream. Read at startup.
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn acceptor_from_env() -> anyhow::Result<Option<TlsAcceptor>> {
+    let var = |name: &str| {
+        crate::config::var(name)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+    };
+    let (cert, key) = match (var("TLS_CERT"), var("TLS_KEY")) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) => return Ok(None),
+        _ => anyhow::bail!("TLS_CERT and TLS_KEY must be set together"),
+    };
+    let certs = CertificateDer::pem_file_iter(&cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| anyhow::anyhow!("reading {cert}: {err}"))?;
+    let key = PrivateKeyDer::from_pem_file(&key)
+        .map_err(|err| anyhow::anyhow!("reading {key}: {err}"))?;
+    let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+// axum::serve with a TLS handshake in front of every connection.
+pub async fn serve(listener: TcpListener, app: Router, acceptor: TlsAcceptor) -> ! {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                log!("accept failed: {err}");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let (acceptor, app) = (acceptor.clone(), app.clone());
+        tokio::spawn(async move {
+            let tls = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(tls)) => tls,
+                Ok(Err(err)) => {
+                    log!("TLS handshake with {peer} failed: {err}");
+                    return;
+                }
+                Err(_) => return,
+            };
+            let service = TowerToHyperService::new(app);
+            if let Err(err) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(tls), service)
+                .await
+            {
+                log!("connection from {peer} ended with an error: {err}");
+            }
+        });
+    }
+}
@@ -0,0 +1,78 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::pending::{PendingReply, ReplyBroker};
+use crate::AppState;
+
+// Tools listed in APPROVAL_REQUIRED_TOOLS (comma-separated, `*` for every tool) only run
+// once the user agrees: the stream emits `approval_required` and waits for a POST to
+// /api/chat/:id/approval. No answer within APPROVAL_TIMEOUT_SECS counts as a denial.
+
+pub struct ApprovalGate {
+    tools: Vec<String>,
+    broker: Arc<ReplyBroker<bool>>,
+}
+
+impl ApprovalGate {
+    pub fn from_env() -> Self {
+        let tools = crate::config::var("APPROVAL_REQUIRED_TOOLS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+        let timeout = crate::config::var("APPROVAL_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(120);
+        Self {
+            tools,
+            broker: Arc::new(ReplyBroker::new(Duration::from_secs(timeout))),
+        }
+    }
+
+    pub fn requires_approval(&self, name: &str) -> bool {
+        self.tools.iter().any(|t| t == "*" || t == name)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.broker.timeout()
+    }
+
+    pub fn register(&self, chat_id: &str, call_id: &str) -> PendingReply<bool> {
+        self.broker.register(chat_id, call_id)
+    }
+}
+
+// ---------- HTTP handler ----------
+
+#[derive(Deserialize)]
+pub struct ApprovalBody {
+    tool_call_id: String,
+    approved: bool,
+}
+
+pub async fn approval_handler(
+    State(state): State<Arc<AppState>>,
+    Path(chat_id): Path<String>,
+    Json(body): Json<ApprovalBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if state
+        .approvals
+        .broker
+        .resolve(&chat_id, &body.tool_call_id, body.approved)
+    {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            "no tool call awaiting approval with that id".into(),
+        ))
+    }
+}
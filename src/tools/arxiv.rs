@@ -0,0 +1,233 @@
+use serde::Deserialize;
+
+use super::{Tool, ToolOutput};
+use crate::SearchResult;
+
+pub const TOOL_NAME: &str = "arxiv_search";
+
+fn api_url() -> String {
+    crate::config::var("ARXIV_API_URL")
+        .unwrap_or_else(|_| "https://export.arxiv.org/api/query".into())
+}
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Searches arXiv for research papers and returns titles, authors, abstracts, and PDF \
+links. Use it for questions about scientific research, ML papers, or specific preprints.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "Keywords, or arXiv query syntax like \"ti:transformer AND au:vaswani\""
+                },
+                "max_results": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 10,
+                    "description": "Optional maximum number of papers to return (default 5)"
+                },
+                "sort_by": {
+                    "type": "string",
+                    "enum": ["relevance", "submitted"],
+                    "description": "relevance (default) or newest submissions first"
+                }
+            },
+            "required": ["query"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct ArxivArgs {
+    query: String,
+    #[serde(default)]
+    max_results: Option<usize>,
+    #[serde(default)]
+    sort_by: Option<String>,
+}
+
+#[derive(Default, Debug)]
+struct Paper {
+    id: String,
+    title: String,
+    summary: String,
+    authors: Vec<String>,
+    published: String,
+    pdf_url: Option<String>,
+    category: Option<String>,
+}
+
+// Plain keywords become `all:a AND all:b`; queries already using field prefixes pass through.
+fn build_search_query(query: &str) -> String {
+    const FIELDS: [&str; 9] = [
+        "ti:", "au:", "abs:", "co:", "jr:", "cat:", "rn:", "id:", "all:",
+    ];
+    if FIELDS.iter().any(|f| query.contains(f)) {
+        return query.to_string();
+    }
+    query
+        .split_whitespace()
+        .map(|w| format!("all:{w}"))
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+pub async fn run(arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: ArxivArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid arxiv_search args: {e}"))?;
+    let query = args.query.trim();
+    if query.is_empty() {
+        anyhow::bail!("search query missing");
+    }
+    let limit = args.max_results.unwrap_or(5).clamp(1, 10).to_string();
+    let sort_by = match args.sort_by.as_deref() {
+        Some("submitted") => "submittedDate",
+        _ => "relevance",
+    };
+
+    let resp = reqwest::Client::new()
+        .get(api_url())
+        .query(&[
+            ("search_query", build_search_query(query).as_str()),
+            ("start", "0"),
+            ("max_results", limit.as_str()),
+            ("sortBy", sort_by),
+            ("sortOrder", "descending"),
+        ])
+        .send()
+        .await?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("arxiv backend error {}: {}", status, body);
+    }
+    let papers = parse_feed(&resp.text().await?)?;
+
+    let entries: Vec<_> = papers
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            serde_json::json!({
+                "id": i + 1,
+                "arxiv_id": p.id.rsplit("/abs/").next().unwrap_or(&p.id),
+                "title": p.title,
+                "authors": p.authors,
+                "published": p.published,
+                "category": p.category,
+                "abstract": p.summary,
+                "url": p.id,
+                "pdf_url": p.pdf_url,
+            })
+        })
+        .collect();
+    let content = serde_json::json!({
+        "query": query,
+        "results": entries,
+        "instructions": "Cite papers like [id] and mention authors and year when relevant."
+    })
+    .to_string();
+
+    let sources = papers
+        .into_iter()
+        .map(|p| {
+            let authors = match p.authors.len() {
+                0 => String::new(),
+                1..=3 => p.authors.join(", "),
+                _ => format!("{} et al.", p.authors[0]),
+            };
+            let year = p.published.get(..4).unwrap_or_default();
+            SearchResult {
+                title: p.title,
+                snippet: format!("{authors} ({year}). {}", p.summary),
+                url: p.id,
+                published: Some(p.published),
+                citation: None,
+                provider: None,
+            }
+        })
+        .collect();
+
+    let mut output = ToolOutput::text(content);
+    output.sources = Some(sources);
+    Ok(output)
+}
+
+// ---------- Atom feed parsing ----------
+
+fn parse_feed(xml: &str) -> anyhow::Result<Vec<Paper>> {
+    use quick_xml::events::Event as XmlEvent;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut papers = Vec::new();
+    let mut current: Option<Paper> = None;
+    let mut in_field = false;
+    let mut text = String::new();
+
+    loop {
+        match reader.read_event()? {
+            XmlEvent::Start(e) => match e.local_name().as_ref() {
+                b"entry" => current = Some(Paper::default()),
+                _ if current.is_some() => {
+                    in_field = true;
+                    text.clear();
+                }
+                _ => {}
+            },
+            XmlEvent::Text(t) if in_field => text.push_str(&t.unescape()?),
+            XmlEvent::End(e) => {
+                let Some(paper) = current.as_mut() else {
+                    continue;
+                };
+                match e.local_name().as_ref() {
+                    b"entry" => {
+                        papers.extend(current.take());
+                        continue;
+                    }
+                    b"id" => paper.id = text.trim().to_string(),
+                    b"title" => paper.title = collapse_whitespace(&text),
+                    b"summary" => paper.summary = collapse_whitespace(&text),
+                    b"published" => paper.published = text.trim().to_string(),
+                    b"name" => paper.authors.push(text.trim().to_string()),
+                    _ => {}
+                }
+                in_field = false;
+                text.clear();
+            }
+            XmlEvent::Empty(e) => {
+                let Some(paper) = current.as_mut() else {
+                    continue;
+                };
+                let attr = |key: &[u8]| {
+                    e.attributes()
+                        .flatten()
+                        .find(|a| a.key.local_name().as_ref() == key)
+                        .and_then(|a| a.unescape_value().ok())
+                        .map(|v| v.into_owned())
+                };
+                match e.local_name().as_ref() {
+                    b"link" if attr(b"title").as_deref() == Some("pdf") => {
+                        paper.pdf_url = attr(b"href");
+                    }
+                    b"primary_category" => paper.category = attr(b"term"),
+                    _ => {}
+                }
+            }
+            XmlEvent::Eof => break,
+            _ => {}
+        }
+    }
+
+    // arXiv reports query errors as a single entry titled "Error".
+    if let [only] = papers.as_slice()
+        && only.title == "Error"
+    {
+        anyhow::bail!("arxiv query error: {}", only.summary);
+    }
+    Ok(papers)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
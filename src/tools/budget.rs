@@ -0,0 +1,138 @@
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+use crate::AppState;
+
+// Keeps tool results within a share of the model's context window, counted with the
+// llama-server tokenizer. TOOL_RESULT_CONTEXT_FRACTION caps a single result and
+// TOOL_TURN_CONTEXT_FRACTION (default 0.5) all results of one turn together. Disabled
+// unless the first is set. The window comes from CONTEXT_TOKENS or llama-server's /props.
+
+const TRIM_ROUNDS: usize = 4;
+
+pub struct TokenBudgetConfig {
+    result_fraction: f64,
+    turn_fraction: f64,
+    context_tokens: OnceCell<usize>,
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    crate::config::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+impl TokenBudgetConfig {
+    pub fn from_env() -> Option<Self> {
+        let result_fraction: f64 =
+            env_parse("TOOL_RESULT_CONTEXT_FRACTION").filter(|f: &f64| *f > 0.0 && *f <= 1.0)?;
+        let context_tokens = OnceCell::new();
+        if let Some(tokens) = env_parse("CONTEXT_TOKENS") {
+            let _ = context_tokens.set(tokens);
+        }
+        Some(Self {
+            result_fraction,
+            turn_fraction: env_parse("TOOL_TURN_CONTEXT_FRACTION")
+                .filter(|f: &f64| *f > 0.0 && *f <= 1.0)
+                .unwrap_or(0.5),
+            context_tokens,
+        })
+    }
+
+    // Token allowance for each of `calls` results produced in one turn.
+    pub async fn per_result_tokens(&self, state: &AppState, calls: usize) -> usize {
+        let context = self.context_tokens(state).await as f64;
+        let per_result = context * self.result_fraction;
+        let per_turn_share = context * self.turn_fraction / calls.max(1) as f64;
+        per_result.min(per_turn_share) as usize
+    }
+
+    pub async fn context_tokens(&self, state: &AppState) -> usize {
+        if let Some(tokens) = self.context_tokens.get() {
+            return *tokens;
+        }
+        match fetch_context_tokens(state).await {
+            Ok(tokens) => *self.context_tokens.get_or_init(|| async { tokens }).await,
+            Err(err) => {
+                log!("could not read context size from llama-server: {err:?}");
+                4096
+            }
+        }
+    }
+}
+
+pub async fn fetch_context_tokens(state: &AppState) -> anyhow::Result<usize> {
+    let props: serde_json::Value = reqwest::Client::new()
+        .get(format!("{}/props", state.llama_base_url))
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    props["default_generation_settings"]["n_ctx"]
+        .as_u64()
+        .map(|n| n as usize)
+        .ok_or_else(|| anyhow::anyhow!("/props has no n_ctx"))
+}
+
+// Falls back to about four characters per token when /tokenize is unavailable.
+pub async fn count_tokens(state: &AppState, text: &str) -> usize {
+    let resp = crate::request_id::tag(
+        reqwest::Client::new().post(format!("{}/tokenize", state.llama_base_url)),
+    )
+    .timeout(Duration::from_secs(10))
+    .json(&serde_json::json!({ "content": text }))
+    .send()
+    .await
+    .and_then(|r| r.error_for_status());
+    let tokens = match resp {
+        Ok(resp) => resp.json::<serde_json::Value>().await.ok(),
+        Err(_) => None,
+    };
+    tokens
+        .and_then(|v| v["tokens"].as_array().map(Vec::len))
+        .unwrap_or_else(|| text.chars().count().div_ceil(4))
+}
+
+// Shortens every piece in proportion to its length until `render` fits the budget.
+pub async fn fit_pieces(
+    state: &AppState,
+    budget: usize,
+    pieces: &mut [String],
+    render: impl Fn(&[String]) -> String,
+) -> String {
+    let mut rendered = render(pieces);
+    for _ in 0..TRIM_ROUNDS {
+        let tokens = count_tokens(state, &rendered).await;
+        if tokens <= budget {
+            break;
+        }
+        // Slightly under the exact ratio since the surrounding JSON does not shrink.
+        let ratio = budget as f64 / tokens as f64 * 0.9;
+        for piece in pieces.iter_mut() {
+            let keep = (piece.chars().count() as f64 * ratio) as usize;
+            *piece = shorten(piece, keep);
+        }
+        rendered = render(pieces);
+    }
+    rendered
+}
+
+pub async fn fit_text(state: &AppState, budget: usize, text: String) -> String {
+    let mut pieces = [text];
+    fit_pieces(state, budget, &mut pieces, |p| p[0].clone()).await
+}
+
+// Cuts at a word boundary where possible and marks the cut.
+fn shorten(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_chars).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(pos) if pos > cut.len() / 2 => &cut[..pos],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end())
+}
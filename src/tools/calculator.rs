@@ -0,0 +1,338 @@
+use serde::Deserialize;
+
+use super::{Tool, ToolOutput};
+
+pub const TOOL_NAME: &str = "calculate";
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Evaluates a math expression exactly. Supports + - * / ^, parentheses, percentages \
+(\"15% of 80\", \"200 * 7.5%\"), constants pi and e, and functions sqrt, cbrt, abs, exp, ln, \
+log10, log2, sin, cos, tan, asin, acos, atan, round, floor, ceil, min, max. Use it for any \
+arithmetic instead of computing in your head.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "expression": {
+                    "type": "string",
+                    "description": "Expression to evaluate, e.g. \"(1299 - 999) / 999 * 100\""
+                }
+            },
+            "required": ["expression"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct CalculateArgs {
+    expression: String,
+}
+
+pub fn run(arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: CalculateArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid calculate args: {e}"))?;
+    let expression = args.expression.trim();
+    if expression.is_empty() {
+        anyhow::bail!("expression missing");
+    }
+    if expression.len() > 1000 {
+        anyhow::bail!("expression too long");
+    }
+
+    let value = evaluate(expression).map_err(|e| anyhow::anyhow!("cannot evaluate: {e}"))?;
+    if !value.is_finite() {
+        anyhow::bail!("result is not a finite number");
+    }
+    Ok(ToolOutput::text(
+        serde_json::json!({
+            "expression": expression,
+            "result": format_number(value),
+        })
+        .to_string(),
+    ))
+}
+
+// Drops float noise like 0.30000000000000004 while keeping large integers exact.
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        return format!("{}", value as i64);
+    }
+    let rounded: f64 = format!("{value:.12}").parse().unwrap_or(value);
+    format!("{rounded}")
+}
+
+// ---------- Expression parser ----------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = input.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '0'..='9' | '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                // Scientific notation: 1.5e3, 2E-4
+                if i < chars.len()
+                    && matches!(chars[i], 'e' | 'E')
+                    && chars
+                        .get(i + 1)
+                        .is_some_and(|n| n.is_ascii_digit() || matches!(n, '+' | '-'))
+                {
+                    i += 2;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = text
+                    .parse()
+                    .map_err(|_| format!("invalid number {text:?}"))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphabetic() => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let ident: String = chars[start..i].iter().collect();
+                tokens.push(Token::Ident(ident.to_ascii_lowercase()));
+            }
+            '+' | '-' | '*' | '/' | '^' | '%' => {
+                tokens.push(Token::Op(c));
+                i += 1;
+            }
+            '×' => {
+                tokens.push(Token::Op('*'));
+                i += 1;
+            }
+            '÷' => {
+                tokens.push(Token::Op('/'));
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            other => return Err(format!("unexpected character {other:?}")),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+const MAX_DEPTH: usize = 64;
+
+fn evaluate(input: &str) -> Result<f64, String> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.expr()?;
+    if let Some(token) = parser.peek() {
+        return Err(format!("unexpected {token:?}"));
+    }
+    Ok(value)
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<f64, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("expression nested too deeply".into());
+        }
+        let mut value = self.term()?;
+        loop {
+            if self.eat(&Token::Op('+')) {
+                value += self.term()?;
+            } else if self.eat(&Token::Op('-')) {
+                value -= self.term()?;
+            } else {
+                break;
+            }
+        }
+        self.depth -= 1;
+        Ok(value)
+    }
+
+    // term := unary (('*' | '/' | "of" | "mod") unary)*
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat(&Token::Op('*')) || self.eat(&Token::Ident("of".into())) {
+                value *= self.unary()?;
+            } else if self.eat(&Token::Op('/')) {
+                let rhs = self.unary()?;
+                if rhs == 0.0 {
+                    return Err("division by zero".into());
+                }
+                value /= rhs;
+            } else if self.eat(&Token::Ident("mod".into())) {
+                let rhs = self.unary()?;
+                if rhs == 0.0 {
+                    return Err("modulo by zero".into());
+                }
+                value = value.rem_euclid(rhs);
+            } else {
+                break;
+            }
+        }
+        Ok(value)
+    }
+
+    // unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.eat(&Token::Op('-')) {
+            return Ok(-self.unary()?);
+        }
+        if self.eat(&Token::Op('+')) {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    // power := postfix ('^' unary)?   (right-associative)
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.postfix()?;
+        if self.eat(&Token::Op('^')) {
+            let exponent = self.unary()?;
+            return Ok(base.powf(exponent));
+        }
+        Ok(base)
+    }
+
+    // postfix := primary '%'*
+    fn postfix(&mut self) -> Result<f64, String> {
+        let mut value = self.primary()?;
+        while self.eat(&Token::Op('%')) {
+            value /= 100.0;
+        }
+        Ok(value)
+    }
+
+    fn primary(&mut self) -> Result<f64, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(n),
+            Some(Token::LParen) => {
+                let value = self.expr()?;
+                if !self.eat(&Token::RParen) {
+                    return Err("missing closing parenthesis".into());
+                }
+                Ok(value)
+            }
+            Some(Token::Ident(name)) => {
+                if self.eat(&Token::LParen) {
+                    let mut args = Vec::new();
+                    if !self.eat(&Token::RParen) {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.eat(&Token::RParen) {
+                                break;
+                            }
+                            if !self.eat(&Token::Comma) {
+                                return Err(format!("expected ',' or ')' in {name}()"));
+                            }
+                        }
+                    }
+                    return call_function(&name, &args);
+                }
+                match name.as_str() {
+                    "pi" | "π" => Ok(std::f64::consts::PI),
+                    "e" => Ok(std::f64::consts::E),
+                    "tau" => Ok(std::f64::consts::TAU),
+                    other => Err(format!("unknown identifier {other:?}")),
+                }
+            }
+            Some(token) => Err(format!("unexpected {token:?}")),
+            None => Err("unexpected end of expression".into()),
+        }
+    }
+}
+
+fn call_function(name: &str, args: &[f64]) -> Result<f64, String> {
+    let unary = |f: fn(f64) -> f64| match args {
+        [x] => Ok(f(*x)),
+        _ => Err(format!("{name}() takes one argument")),
+    };
+    match name {
+        "sqrt" => unary(f64::sqrt),
+        "cbrt" => unary(f64::cbrt),
+        "abs" => unary(f64::abs),
+        "exp" => unary(f64::exp),
+        "ln" => unary(f64::ln),
+        "log" | "log10" => unary(f64::log10),
+        "log2" => unary(f64::log2),
+        "sin" => unary(f64::sin),
+        "cos" => unary(f64::cos),
+        "tan" => unary(f64::tan),
+        "asin" => unary(f64::asin),
+        "acos" => unary(f64::acos),
+        "atan" => unary(f64::atan),
+        "floor" => unary(f64::floor),
+        "ceil" => unary(f64::ceil),
+        "round" => match args {
+            [x] => Ok(x.round()),
+            [x, digits] => {
+                let factor = 10f64.powi(*digits as i32);
+                Ok((x * factor).round() / factor)
+            }
+            _ => Err("round() takes one or two arguments".into()),
+        },
+        "min" | "max" if !args.is_empty() => {
+            let pick = if name == "min" { f64::min } else { f64::max };
+            Ok(args.iter().copied().reduce(pick).unwrap_or_default())
+        }
+        "min" | "max" => Err(format!("{name}() needs at least one argument")),
+        other => Err(format!("unknown function {other}()")),
+    }
+}
@@ -0,0 +1,99 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::pending::{PendingReply, ReplyBroker};
+use super::{Tool, ToolOutput};
+use crate::AppState;
+
+// Tools the browser runs itself (geolocation, clipboard, ...). The request declares them in
+// `client_tools`; when the model calls one the stream emits a `tool_request` event with the
+// chat id and waits until the client POSTs the result to /api/chat/:id/tool_result.
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClientTool {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "empty_schema")]
+    pub parameters: serde_json::Value,
+}
+
+fn empty_schema() -> serde_json::Value {
+    serde_json::json!({ "type": "object", "properties": {} })
+}
+
+impl ClientTool {
+    pub fn definition(&self) -> Tool {
+        Tool::function(&self.name, &self.description, self.parameters.clone())
+    }
+}
+
+pub type ClientToolBroker = ReplyBroker<Result<String, String>>;
+
+pub fn broker_from_env() -> ClientToolBroker {
+    ReplyBroker::new(Duration::from_secs(
+        crate::config::var("CLIENT_TOOL_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(120),
+    ))
+}
+
+pub async fn wait_for_result(
+    broker: &ClientToolBroker,
+    pending: PendingReply<Result<String, String>>,
+    name: &str,
+) -> anyhow::Result<ToolOutput> {
+    match pending.wait().await {
+        Some(Ok(content)) => Ok(ToolOutput::text(content)),
+        Some(Err(message)) => anyhow::bail!("{message}"),
+        None => anyhow::bail!(
+            "client did not return a result for {name} within {}s",
+            broker.timeout().as_secs()
+        ),
+    }
+}
+
+// ---------- HTTP handler ----------
+
+#[derive(Deserialize)]
+pub struct ToolResultBody {
+    tool_call_id: String,
+    // Any JSON; strings are passed to the model as-is.
+    #[serde(default)]
+    result: serde_json::Value,
+    // Set instead of `result` when the browser could not run the tool.
+    #[serde(default)]
+    error: Option<String>,
+}
+
+pub async fn tool_result_handler(
+    State(state): State<Arc<AppState>>,
+    Path(chat_id): Path<String>,
+    Json(body): Json<ToolResultBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let result = match body.error {
+        Some(error) => Err(error),
+        None => Ok(match body.result {
+            serde_json::Value::String(text) => text,
+            other => other.to_string(),
+        }),
+    };
+    if state
+        .client_tools
+        .resolve(&chat_id, &body.tool_call_id, result)
+    {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            "no pending tool call with that id".into(),
+        ))
+    }
+}
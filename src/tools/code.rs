@@ -0,0 +1,371 @@
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{Tool, ToolOutput};
+
+pub const TOOL_NAME: &str = "run_code";
+
+// ---------- Config ----------
+
+#[derive(Clone, Debug)]
+pub enum CodeBackend {
+    // Runs interpreters on this host under rlimits. This limits resources but is
+    // not isolation: code can still read files and use the network.
+    Local { python: String, node: String },
+    // Piston-compatible sandbox service (`POST /api/v2/execute`).
+    Piston { url: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct CodeExecConfig {
+    pub backend: CodeBackend,
+    pub timeout: Duration,
+    pub memory_mb: u64,
+    pub max_output_chars: usize,
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    crate::config::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+impl CodeExecConfig {
+    // Disabled unless CODE_EXEC_BACKEND is set.
+    pub fn from_env() -> Option<Self> {
+        let backend = match crate::config::var("CODE_EXEC_BACKEND")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "off" | "none" => return None,
+            "local" => CodeBackend::Local {
+                python: crate::config::var("CODE_EXEC_PYTHON").unwrap_or_else(|_| "python3".into()),
+                node: crate::config::var("CODE_EXEC_NODE").unwrap_or_else(|_| "node".into()),
+            },
+            "piston" => {
+                let Some(url) = crate::config::var("CODE_EXEC_URL")
+                    .ok()
+                    .filter(|u| !u.trim().is_empty())
+                else {
+                    log!("code execution disabled: CODE_EXEC_BACKEND=piston needs CODE_EXEC_URL");
+                    return None;
+                };
+                CodeBackend::Piston {
+                    url: url.trim().trim_end_matches('/').to_string(),
+                }
+            }
+            other => {
+                log!("code execution disabled: unknown CODE_EXEC_BACKEND {other:?}");
+                return None;
+            }
+        };
+
+        Some(Self {
+            backend,
+            timeout: Duration::from_secs(env_parse("CODE_EXEC_TIMEOUT_SECS").unwrap_or(10)),
+            memory_mb: env_parse("CODE_EXEC_MEMORY_MB").unwrap_or(512),
+            max_output_chars: env_parse("CODE_EXEC_MAX_OUTPUT").unwrap_or(8000),
+        })
+    }
+}
+
+// ---------- Tool ----------
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Runs a short Python or JavaScript program and returns its stdout and stderr. Use it for \
+calculations, data processing, and checking code. Print the values you need; there is no \
+network access guarantee, no user input, and a short time limit.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "language": {
+                    "type": "string",
+                    "enum": ["python", "javascript"],
+                    "description": "Programming language of the snippet"
+                },
+                "code": {
+                    "type": "string",
+                    "description": "Complete program to run"
+                }
+            },
+            "required": ["language", "code"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct RunCodeArgs {
+    language: String,
+    code: String,
+}
+
+#[derive(Default)]
+pub(super) struct RunResult {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: Option<i64>,
+    pub timed_out: bool,
+    pub truncated: bool,
+}
+
+impl RunResult {
+    fn cap_output(&mut self, max_chars: usize) {
+        for stream in [&mut self.stdout, &mut self.stderr] {
+            if stream.chars().count() > max_chars {
+                *stream = stream.chars().take(max_chars).collect();
+                self.truncated = true;
+            }
+        }
+    }
+}
+
+pub async fn run(config: &CodeExecConfig, arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: RunCodeArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid run_code args: {e}"))?;
+    let language = match args.language.trim().to_ascii_lowercase().as_str() {
+        "python" | "python3" | "py" => "python",
+        "javascript" | "js" | "node" => "javascript",
+        other => anyhow::bail!("unsupported language {other:?}; use python or javascript"),
+    };
+    if args.code.trim().is_empty() {
+        anyhow::bail!("code missing");
+    }
+
+    let mut result = match &config.backend {
+        CodeBackend::Local { python, node } => {
+            let interpreter = if language == "python" { python } else { node };
+            run_local(config, interpreter, language, &args.code).await?
+        }
+        CodeBackend::Piston { url } => run_piston(config, url, language, &args.code).await?,
+    };
+    result.cap_output(config.max_output_chars);
+
+    Ok(ToolOutput::text(
+        serde_json::json!({
+            "language": language,
+            "exit_code": result.exit_code,
+            "timed_out": result.timed_out,
+            "stdout": result.stdout,
+            "stderr": result.stderr,
+            "truncated": result.truncated,
+        })
+        .to_string(),
+    ))
+}
+
+// ---------- Local subprocess backend ----------
+
+// Keeps the first `cap` bytes and drains the rest so the child never blocks on a full pipe.
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, cap: usize) -> (Vec<u8>, bool) {
+    let mut buf = Vec::new();
+    let _ = (&mut reader).take(cap as u64).read_to_end(&mut buf).await;
+    let extra = tokio::io::copy(&mut reader, &mut tokio::io::sink())
+        .await
+        .unwrap_or(0);
+    (buf, extra > 0)
+}
+
+async fn run_local(
+    config: &CodeExecConfig,
+    interpreter: &str,
+    language: &str,
+    code: &str,
+) -> anyhow::Result<RunResult> {
+    let dir = std::env::temp_dir().join(format!("chat-llama-run-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&dir).await?;
+    let file = dir.join(if language == "python" {
+        "main.py"
+    } else {
+        "main.js"
+    });
+    tokio::fs::write(&file, code).await?;
+
+    let mut cmd = tokio::process::Command::new(interpreter);
+    if language == "python" {
+        // Isolated mode: ignore PYTHON* env vars and the user site-packages.
+        cmd.arg("-I");
+    }
+    cmd.arg(&file)
+        .current_dir(&dir)
+        .env_clear()
+        .env("PATH", std::env::var("PATH").unwrap_or_default())
+        .env("HOME", &dir)
+        .env("LANG", "C.UTF-8")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+
+    let outcome = run_limited(
+        cmd,
+        config.timeout,
+        config.memory_mb,
+        config.max_output_chars,
+    )
+    .await;
+
+    if let Err(err) = tokio::fs::remove_dir_all(&dir).await {
+        log!("failed to clean up {}: {err}", dir.display());
+    }
+    outcome.map_err(|e| anyhow::anyhow!("failed to run {interpreter}: {e}"))
+}
+
+// Runs a prepared command (stdio piped) under rlimits in its own process group, killing
+// the group on timeout. Shared with the shell tool.
+pub(super) async fn run_limited(
+    mut cmd: tokio::process::Command,
+    timeout: Duration,
+    memory_mb: u64,
+    max_output_chars: usize,
+) -> anyhow::Result<RunResult> {
+    #[cfg(unix)]
+    apply_rlimits(&mut cmd, timeout, memory_mb);
+
+    let mut child = cmd.spawn()?;
+    let pid = child.id();
+    let cap = max_output_chars * 4;
+    let stdout = tokio::spawn(read_capped(child.stdout.take().expect("piped stdout"), cap));
+    let stderr = tokio::spawn(read_capped(child.stderr.take().expect("piped stderr"), cap));
+
+    let status = tokio::time::timeout(timeout, child.wait()).await;
+    // Kill the whole process group, including anything the snippet left running,
+    // so the output pipes close.
+    #[cfg(unix)]
+    if let Some(pid) = pid {
+        unsafe {
+            libc::kill(-(pid as i32), libc::SIGKILL);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+    let _ = child.kill().await;
+
+    let (stdout, out_truncated) = stdout.await?;
+    let (stderr, err_truncated) = stderr.await?;
+    let mut stderr = String::from_utf8_lossy(&stderr).into_owned();
+    let (exit_code, timed_out) = match status {
+        Ok(status) => (status?.code().map(i64::from), false),
+        Err(_) => {
+            if !stderr.is_empty() {
+                stderr.push('\n');
+            }
+            stderr.push_str(&format!("killed after {}s time limit", timeout.as_secs()));
+            (None, true)
+        }
+    };
+    let mut result = RunResult {
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr,
+        exit_code,
+        timed_out,
+        truncated: out_truncated || err_truncated,
+    };
+    result.cap_output(max_output_chars);
+    Ok(result)
+}
+
+#[cfg(unix)]
+fn apply_rlimits(cmd: &mut tokio::process::Command, timeout: Duration, memory_mb: u64) {
+    let cpu_secs = timeout.as_secs() + 1;
+    let data_bytes = memory_mb * 1024 * 1024;
+    // SAFETY: only async-signal-safe libc calls run between fork and exec.
+    unsafe {
+        cmd.pre_exec(move || {
+            let set = |resource, limit: u64| {
+                let rlim = libc::rlimit {
+                    rlim_cur: limit as libc::rlim_t,
+                    rlim_max: limit as libc::rlim_t,
+                };
+                if libc::setrlimit(resource, &rlim) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            };
+            // Own process group so a timeout can kill every descendant.
+            libc::setpgid(0, 0);
+            set(libc::RLIMIT_CPU, cpu_secs)?;
+            // RLIMIT_DATA rather than RLIMIT_AS: V8 reserves far more address space than it uses.
+            set(libc::RLIMIT_DATA, data_bytes)?;
+            set(libc::RLIMIT_FSIZE, 16 * 1024 * 1024)?;
+            set(libc::RLIMIT_NOFILE, 64)?;
+            set(libc::RLIMIT_CORE, 0)?;
+            Ok(())
+        });
+    }
+}
+
+// ---------- Piston backend ----------
+
+#[derive(Deserialize)]
+struct PistonResponse {
+    #[serde(default)]
+    run: Option<PistonStage>,
+    #[serde(default)]
+    compile: Option<PistonStage>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PistonStage {
+    #[serde(default)]
+    stdout: String,
+    #[serde(default)]
+    stderr: String,
+    #[serde(default)]
+    code: Option<i64>,
+    #[serde(default)]
+    signal: Option<String>,
+}
+
+async fn run_piston(
+    config: &CodeExecConfig,
+    url: &str,
+    language: &str,
+    code: &str,
+) -> anyhow::Result<RunResult> {
+    let file_name = if language == "python" {
+        "main.py"
+    } else {
+        "main.js"
+    };
+    let body = serde_json::json!({
+        "language": language,
+        "version": "*",
+        "files": [{ "name": file_name, "content": code }],
+        "run_timeout": config.timeout.as_millis() as u64,
+        "run_memory_limit": config.memory_mb * 1024 * 1024,
+    });
+
+    let resp = reqwest::Client::new()
+        .post(format!("{url}/api/v2/execute"))
+        .timeout(config.timeout + Duration::from_secs(10))
+        .json(&body)
+        .send()
+        .await?;
+    let status = resp.status();
+    let parsed: PistonResponse = resp.json().await?;
+    if let Some(message) = parsed.message {
+        anyhow::bail!("sandbox error {}: {}", status, message);
+    }
+
+    // Compile errors (for languages that have a compile stage) take precedence.
+    let stage = match parsed.compile {
+        Some(compile) if compile.code.is_some_and(|c| c != 0) => compile,
+        _ => parsed
+            .run
+            .ok_or_else(|| anyhow::anyhow!("sandbox returned no run result"))?,
+    };
+    Ok(RunResult {
+        timed_out: stage.signal.as_deref() == Some("SIGKILL"),
+        stdout: stage.stdout,
+        stderr: stage.stderr,
+        exit_code: stage.code,
+        truncated: false,
+    })
+}
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use super::ToolOutput;
+use crate::AppState;
+
+// Optional stage that shrinks oversized tool results before they enter the context, for
+// small-context models. Enabled by TOOL_SUMMARY_THRESHOLD (characters); results longer than
+// that are condensed to about TOOL_SUMMARY_TARGET characters (default half the threshold).
+// TOOL_SUMMARY_MODE=llm (default) asks the model for a summary focused on the user's
+// question and falls back to extractive trimming on failure; `extractive` never calls it.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SummaryMode {
+    Llm,
+    Extractive,
+}
+
+#[derive(Clone, Debug)]
+pub struct CondenseConfig {
+    pub threshold_chars: usize,
+    pub target_chars: usize,
+    pub mode: SummaryMode,
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    crate::config::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+impl CondenseConfig {
+    pub fn from_env() -> Option<Self> {
+        let threshold_chars: usize = env_parse("TOOL_SUMMARY_THRESHOLD").filter(|&n| n > 0)?;
+        let mode = match crate::config::var("TOOL_SUMMARY_MODE")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "llm" => SummaryMode::Llm,
+            "extractive" => SummaryMode::Extractive,
+            other => {
+                log!("unknown TOOL_SUMMARY_MODE {other:?}; using extractive");
+                SummaryMode::Extractive
+            }
+        };
+        Some(Self {
+            threshold_chars,
+            target_chars: env_parse("TOOL_SUMMARY_TARGET").unwrap_or(threshold_chars / 2),
+            mode,
+        })
+    }
+}
+
+pub async fn condense(
+    state: &AppState,
+    config: &CondenseConfig,
+    question: &str,
+    tool_name: &str,
+    mut output: ToolOutput,
+) -> ToolOutput {
+    if output.content.chars().count() <= config.threshold_chars {
+        return output;
+    }
+    if config.mode == SummaryMode::Llm {
+        match summarize_with_llm(state, config, question, tool_name, &output.content).await {
+            Ok(summary) => {
+                output.content = summary;
+                return output;
+            }
+            Err(err) => log!("tool output summary failed for {tool_name}: {err:?}"),
+        }
+    }
+    output.content = extractive(&output.content, question, config.target_chars);
+    output
+}
+
+async fn summarize_with_llm(
+    state: &AppState,
+    config: &CondenseConfig,
+    question: &str,
+    tool_name: &str,
+    content: &str,
+) -> anyhow::Result<String> {
+    let prompt = format!(
+        "Condense the following output of the {tool_name} tool to at most {} characters. \
+Keep every fact, number, date, URL and result id that could help answer the user's question, \
+and drop everything else. Reply with the condensed text only.\n\n\
+User's question: {question}\n\nTool output:\n{content}",
+        config.target_chars
+    );
+    let body = serde_json::json!({
+        "model": state.llama_model,
+        "messages": [{ "role": "user", "content": prompt }],
+        "stream": false,
+        "temperature": 0.2,
+        // Roughly three characters per token, with headroom.
+        "max_tokens": config.target_chars / 3 + 64,
+    });
+    let resp: serde_json::Value = crate::request_id::tag(
+        reqwest::Client::new().post(format!("{}/v1/chat/completions", state.llama_base_url)),
+    )
+    .bearer_auth("no-key")
+    .timeout(Duration::from_secs(60))
+    .json(&body)
+    .send()
+    .await?
+    .error_for_status()?
+    .json()
+    .await?;
+    let summary = resp["choices"][0]["message"]["content"]
+        .as_str()
+        .map(str::trim)
+        .unwrap_or_default();
+    if summary.is_empty() {
+        anyhow::bail!("empty summary");
+    }
+    Ok(summary.to_string())
+}
+
+// ---------- Extractive trimming ----------
+
+// JSON results keep their structure: each long string value is trimmed in proportion to its
+// share of the text. Anything else is trimmed as plain text.
+fn extractive(content: &str, question: &str, budget: usize) -> String {
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(content) else {
+        return extract_sentences(content, question, budget);
+    };
+    let total = string_chars(&value).max(1);
+    let overshoot = content.chars().count().saturating_sub(budget);
+    trim_strings(&mut value, question, overshoot, total);
+    value.to_string()
+}
+
+fn string_chars(value: &serde_json::Value) -> usize {
+    match value {
+        serde_json::Value::String(s) => s.chars().count(),
+        serde_json::Value::Array(items) => items.iter().map(string_chars).sum(),
+        serde_json::Value::Object(map) => map.values().map(string_chars).sum(),
+        _ => 0,
+    }
+}
+
+fn trim_strings(value: &mut serde_json::Value, question: &str, overshoot: usize, total: usize) {
+    match value {
+        serde_json::Value::String(s) => {
+            let len = s.chars().count();
+            // Short strings (titles, URLs, dates) are worth more than the few characters saved.
+            if len > 200 {
+                let cut = overshoot * len / total;
+                *s = extract_sentences(s, question, len.saturating_sub(cut).max(100));
+            }
+        }
+        serde_json::Value::Array(items) => items
+            .iter_mut()
+            .for_each(|v| trim_strings(v, question, overshoot, total)),
+        serde_json::Value::Object(map) => map
+            .values_mut()
+            .for_each(|v| trim_strings(v, question, overshoot, total)),
+        _ => {}
+    }
+}
+
+// Keeps the sentences sharing the most words with the question, in their original order.
+fn extract_sentences(text: &str, question: &str, budget: usize) -> String {
+    if text.chars().count() <= budget {
+        return text.to_string();
+    }
+    let terms = words(question);
+    let sentences: Vec<&str> = text
+        .split_inclusive(['.', '!', '?', '\n'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    let mut ranked: Vec<(usize, usize)> = sentences
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let sentence = words(s);
+            (terms.iter().filter(|t| sentence.contains(t)).count(), i)
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let mut keep = vec![false; sentences.len()];
+    let mut used = 0;
+    for (_, i) in ranked {
+        let len = sentences[i].chars().count() + 1;
+        if used + len > budget {
+            continue;
+        }
+        used += len;
+        keep[i] = true;
+    }
+    let kept: Vec<&str> = sentences
+        .iter()
+        .zip(&keep)
+        .filter(|(_, keep)| **keep)
+        .map(|(s, _)| *s)
+        .collect();
+    if kept.is_empty() {
+        return text.chars().take(budget).collect();
+    }
+    kept.join(" ")
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 2)
+        .map(str::to_lowercase)
+        .collect()
+}
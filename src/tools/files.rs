@@ -0,0 +1,192 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use super::{Tool, ToolOutput};
+
+pub const TOOL_NAME: &str = "read_file";
+
+// ---------- Config ----------
+
+#[derive(Clone, Debug)]
+pub struct FileReadConfig {
+    // Canonicalized; nothing outside it can be read, including through symlinks.
+    pub root: PathBuf,
+    // Files larger than this are refused outright.
+    pub max_file_bytes: u64,
+    // Cap on the text returned to the model per call.
+    pub max_output_chars: usize,
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    crate::config::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+impl FileReadConfig {
+    // Disabled unless READ_FILE_ROOT points at an existing directory.
+    pub fn from_env() -> Option<Self> {
+        let root = crate::config::var("READ_FILE_ROOT")
+            .ok()
+            .filter(|r| !r.trim().is_empty())?;
+        let root = match Path::new(root.trim()).canonicalize() {
+            Ok(root) if root.is_dir() => root,
+            Ok(root) => {
+                log!("read_file disabled: {} is not a directory", root.display());
+                return None;
+            }
+            Err(err) => {
+                log!("read_file disabled: cannot resolve READ_FILE_ROOT {root:?}: {err}");
+                return None;
+            }
+        };
+        Some(Self {
+            root,
+            max_file_bytes: env_parse("READ_FILE_MAX_FILE_BYTES").unwrap_or(50 * 1024 * 1024),
+            max_output_chars: env_parse("READ_FILE_MAX_OUTPUT").unwrap_or(20_000),
+        })
+    }
+
+    // Resolves a user path against the root and rejects anything that escapes it.
+    fn resolve(&self, path: &str) -> anyhow::Result<PathBuf> {
+        let relative = path.trim().trim_start_matches('/');
+        let joined = self.root.join(relative);
+        let resolved = joined
+            .canonicalize()
+            .map_err(|e| anyhow::anyhow!("cannot open {path:?}: {e}"))?;
+        if !resolved.starts_with(&self.root) {
+            anyhow::bail!("{path:?} is outside the readable directory");
+        }
+        Ok(resolved)
+    }
+
+    fn display_path(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        format!("/{}", relative.display())
+    }
+}
+
+// ---------- Tool ----------
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Reads a text file (such as a log or config file) from the user's shared directory, or \
+lists a directory. Paths are relative to that directory; \"/\" lists its top level. Use \
+start_line to page through long files, or a negative start_line to read the end of a log.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "File or directory path, e.g. \"logs/app.log\""
+                },
+                "start_line": {
+                    "type": "integer",
+                    "description": "Optional 1-based first line to return; negative counts from the end (-200 = last 200 lines)"
+                },
+                "max_lines": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 2000,
+                    "description": "Optional maximum number of lines to return (default 400)"
+                }
+            },
+            "required": ["path"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct ReadFileArgs {
+    path: String,
+    #[serde(default)]
+    start_line: Option<i64>,
+    #[serde(default)]
+    max_lines: Option<usize>,
+}
+
+pub async fn run(config: &FileReadConfig, arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: ReadFileArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid read_file args: {e}"))?;
+    let path = config.resolve(&args.path)?;
+    let metadata = tokio::fs::metadata(&path).await?;
+
+    if metadata.is_dir() {
+        return list_dir(config, &path).await;
+    }
+    if metadata.len() > config.max_file_bytes {
+        anyhow::bail!(
+            "{} is {} bytes, over the {} byte limit",
+            config.display_path(&path),
+            metadata.len(),
+            config.max_file_bytes
+        );
+    }
+
+    let bytes = tokio::fs::read(&path).await?;
+    if bytes.iter().take(8192).any(|&b| b == 0) {
+        anyhow::bail!("{} looks like a binary file", config.display_path(&path));
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    let lines: Vec<&str> = text.lines().collect();
+    let total_lines = lines.len();
+    let max_lines = args.max_lines.unwrap_or(400).clamp(1, 2000);
+    let start = match args.start_line {
+        Some(n) if n < 0 => total_lines.saturating_sub(n.unsigned_abs() as usize),
+        Some(n) => (n.max(1) as usize - 1).min(total_lines),
+        None => 0,
+    };
+
+    let mut content = String::new();
+    let mut chars = 0;
+    let mut end = start;
+    for line in lines.iter().skip(start).take(max_lines) {
+        chars += line.chars().count() + 1;
+        if chars > config.max_output_chars {
+            break;
+        }
+        content.push_str(line);
+        content.push('\n');
+        end += 1;
+    }
+
+    Ok(ToolOutput::text(
+        serde_json::json!({
+            "path": config.display_path(&path),
+            "size_bytes": metadata.len(),
+            "total_lines": total_lines,
+            "first_line": start + 1,
+            "last_line": end,
+            "truncated": end < total_lines,
+            "content": content,
+        })
+        .to_string(),
+    ))
+}
+
+async fn list_dir(config: &FileReadConfig, path: &Path) -> anyhow::Result<ToolOutput> {
+    let mut entries = Vec::new();
+    let mut dir = tokio::fs::read_dir(path).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let metadata = entry.metadata().await.ok();
+        let is_dir = metadata.as_ref().is_some_and(|m| m.is_dir());
+        entries.push(serde_json::json!({
+            "name": entry.file_name().to_string_lossy(),
+            "type": if is_dir { "directory" } else { "file" },
+            "size_bytes": metadata.filter(|m| m.is_file()).map(|m| m.len()),
+        }));
+    }
+    entries.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    let truncated = entries.len() > 500;
+    entries.truncate(500);
+
+    Ok(ToolOutput::text(
+        serde_json::json!({
+            "path": config.display_path(path),
+            "entries": entries,
+            "truncated": truncated,
+        })
+        .to_string(),
+    ))
+}
@@ -0,0 +1,240 @@
+use serde::Deserialize;
+
+use super::{Tool, ToolOutput};
+use crate::SearchResult;
+
+pub const TOOL_NAME: &str = "github_search";
+
+fn api_url() -> String {
+    crate::config::var("GITHUB_API_URL")
+        .unwrap_or_else(|_| "https://api.github.com".into())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+// Optional; raises rate limits and is required by GitHub for code search.
+fn token() -> Option<String> {
+    crate::config::var("GITHUB_TOKEN")
+        .ok()
+        .filter(|t| !t.trim().is_empty())
+}
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Searches GitHub repositories, code, or issues/pull requests. Supports GitHub search \
+qualifiers in the query, e.g. \"tokio select language:rust\" or \"repo:owner/name is:issue is:open\".",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "GitHub search query, optionally with qualifiers"
+                },
+                "kind": {
+                    "type": "string",
+                    "enum": ["repositories", "code", "issues"],
+                    "description": "What to search (default repositories)"
+                },
+                "max_results": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 10,
+                    "description": "Optional maximum number of results to return (default 5)"
+                }
+            },
+            "required": ["query"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct GithubArgs {
+    query: String,
+    #[serde(default)]
+    kind: Option<String>,
+    #[serde(default)]
+    max_results: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    total_count: u64,
+    #[serde(default)]
+    items: Vec<serde_json::Value>,
+}
+
+pub async fn run(arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: GithubArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid github_search args: {e}"))?;
+    let query = args.query.trim();
+    if query.is_empty() {
+        anyhow::bail!("search query missing");
+    }
+    let kind = args.kind.as_deref().unwrap_or("repositories");
+    if !matches!(kind, "repositories" | "code" | "issues") {
+        anyhow::bail!("unknown search kind {kind:?}");
+    }
+    let token = token();
+    if kind == "code" && token.is_none() {
+        anyhow::bail!("GitHub code search requires GITHUB_TOKEN to be configured");
+    }
+    let limit = args.max_results.unwrap_or(5).clamp(1, 10);
+
+    let mut request = reqwest::Client::new()
+        .get(format!("{}/search/{kind}", api_url()))
+        .query(&[("q", query), ("per_page", &limit.to_string())])
+        // text-match adds the matching code fragments to code search results.
+        .header("Accept", "application/vnd.github.text-match+json")
+        .header("X-GitHub-Api-Version", "2022-11-28")
+        .header(
+            "User-Agent",
+            concat!("chat-llama/", env!("CARGO_PKG_VERSION")),
+        );
+    if let Some(token) = &token {
+        request = request.bearer_auth(token);
+    }
+    let resp = request.send().await?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("github api error {}: {}", status, body);
+    }
+    let parsed: SearchResponse = resp.json().await?;
+
+    let (entries, sources): (Vec<_>, Vec<_>) = parsed
+        .items
+        .iter()
+        .take(limit)
+        .enumerate()
+        .map(|(i, item)| match kind {
+            "code" => code_entry(i + 1, item),
+            "issues" => issue_entry(i + 1, item),
+            _ => repo_entry(i + 1, item),
+        })
+        .unzip();
+
+    let content = serde_json::json!({
+        "query": query,
+        "kind": kind,
+        "total_count": parsed.total_count,
+        "results": entries,
+        "instructions": "Cite results like [id] and link to them when referencing code or discussions."
+    })
+    .to_string();
+    let mut output = ToolOutput::text(content);
+    output.sources = Some(sources);
+    Ok(output)
+}
+
+fn str_field(item: &serde_json::Value, key: &str) -> String {
+    item[key].as_str().unwrap_or_default().to_string()
+}
+
+fn excerpt(text: &str, max_chars: usize) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.chars().count() > max_chars {
+        let cut: String = collapsed.chars().take(max_chars).collect();
+        format!("{cut}…")
+    } else {
+        collapsed
+    }
+}
+
+fn repo_entry(id: usize, item: &serde_json::Value) -> (serde_json::Value, SearchResult) {
+    let name = str_field(item, "full_name");
+    let description = str_field(item, "description");
+    let url = str_field(item, "html_url");
+    let entry = serde_json::json!({
+        "id": id,
+        "repository": name,
+        "description": description,
+        "stars": item["stargazers_count"],
+        "forks": item["forks_count"],
+        "language": item["language"],
+        "topics": item["topics"],
+        "updated_at": item["pushed_at"],
+        "archived": item["archived"],
+        "url": url,
+    });
+    let source = SearchResult {
+        title: name,
+        snippet: format!(
+            "★ {} · {}",
+            item["stargazers_count"].as_u64().unwrap_or(0),
+            description
+        ),
+        url,
+        published: item["pushed_at"].as_str().map(str::to_string),
+        citation: None,
+        provider: None,
+    };
+    (entry, source)
+}
+
+fn code_entry(id: usize, item: &serde_json::Value) -> (serde_json::Value, SearchResult) {
+    let repo = item["repository"]["full_name"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let path = str_field(item, "path");
+    let url = str_field(item, "html_url");
+    let fragments: Vec<String> = item["text_matches"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m["fragment"].as_str())
+        .map(|f| f.trim().to_string())
+        .take(3)
+        .collect();
+    let entry = serde_json::json!({
+        "id": id,
+        "repository": repo,
+        "path": path,
+        "url": url,
+        "fragments": fragments,
+    });
+    let source = SearchResult {
+        title: format!("{repo}: {path}"),
+        snippet: excerpt(&fragments.join(" … "), 300),
+        url,
+        published: None,
+        citation: None,
+        provider: None,
+    };
+    (entry, source)
+}
+
+fn issue_entry(id: usize, item: &serde_json::Value) -> (serde_json::Value, SearchResult) {
+    // repository_url looks like https://api.github.com/repos/owner/name
+    let repo = item["repository_url"]
+        .as_str()
+        .and_then(|u| u.split("/repos/").nth(1))
+        .unwrap_or_default()
+        .to_string();
+    let title = str_field(item, "title");
+    let url = str_field(item, "html_url");
+    let body = excerpt(item["body"].as_str().unwrap_or_default(), 800);
+    let entry = serde_json::json!({
+        "id": id,
+        "repository": repo,
+        "number": item["number"],
+        "title": title,
+        "type": if item.get("pull_request").is_some() { "pull_request" } else { "issue" },
+        "state": item["state"],
+        "comments": item["comments"],
+        "created_at": item["created_at"],
+        "body": body,
+        "url": url,
+    });
+    let source = SearchResult {
+        title: format!("{repo}#{}: {title}", item["number"]),
+        snippet: excerpt(&body, 300),
+        url,
+        published: item["created_at"].as_str().map(str::to_string),
+        citation: None,
+        provider: None,
+    };
+    (entry, source)
+}
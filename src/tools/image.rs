@@ -0,0 +1,368 @@
+use base64::Engine;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{Tool, ToolOutput};
+
+pub const TOOL_NAME: &str = "generate_image";
+
+// Public URL prefix generated images are served under.
+pub const IMAGE_ROUTE: &str = "/api/images";
+
+// ---------- Config ----------
+
+#[derive(Clone, Debug)]
+pub enum ImageBackend {
+    // AUTOMATIC1111 / Forge / SD.Next `/sdapi/v1/txt2img`.
+    Automatic1111,
+    // ComfyUI `/prompt` with an API-format workflow containing {{prompt}} placeholders.
+    ComfyUi { workflow: serde_json::Value },
+    // OpenAI-style `/v1/images/generations` (LocalAI, sd-server wrappers).
+    OpenAi,
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageGenConfig {
+    pub url: String,
+    pub backend: ImageBackend,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub steps: Option<u32>,
+    pub output_dir: PathBuf,
+}
+
+pub fn image_dir_from_env() -> PathBuf {
+    crate::config::var("IMAGE_DIR")
+        .ok()
+        .filter(|d| !d.trim().is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("data/images"))
+}
+
+fn env_u32(name: &str) -> Option<u32> {
+    crate::config::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+impl ImageGenConfig {
+    pub fn from_env() -> Option<Self> {
+        let url = crate::config::var("IMAGE_GEN_URL")
+            .ok()
+            .filter(|u| !u.trim().is_empty())?;
+
+        let backend = match crate::config::var("IMAGE_GEN_BACKEND")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "a1111" | "automatic1111" | "sdapi" => ImageBackend::Automatic1111,
+            "openai" => ImageBackend::OpenAi,
+            "comfyui" | "comfy" => {
+                let path = crate::config::var("IMAGE_GEN_WORKFLOW").unwrap_or_default();
+                let workflow = std::fs::read_to_string(&path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|raw| Ok(serde_json::from_str(&raw)?));
+                match workflow {
+                    Ok(workflow) => ImageBackend::ComfyUi { workflow },
+                    Err(err) => {
+                        log!(
+                            "image generation disabled: cannot load IMAGE_GEN_WORKFLOW {path:?}: {err}"
+                        );
+                        return None;
+                    }
+                }
+            }
+            other => {
+                log!("image generation disabled: unknown IMAGE_GEN_BACKEND {other:?}");
+                return None;
+            }
+        };
+
+        Some(Self {
+            url: url.trim().trim_end_matches('/').to_string(),
+            backend,
+            model: crate::config::var("IMAGE_GEN_MODEL").ok(),
+            api_key: crate::config::var("IMAGE_GEN_API_KEY").ok(),
+            width: env_u32("IMAGE_GEN_WIDTH"),
+            height: env_u32("IMAGE_GEN_HEIGHT"),
+            steps: env_u32("IMAGE_GEN_STEPS"),
+            output_dir: image_dir_from_env(),
+        })
+    }
+}
+
+// ---------- Tool ----------
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Generates an image from a text description and shows it to the user.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "prompt": {
+                    "type": "string",
+                    "description": "Detailed description of the image: subject, style, composition, lighting"
+                },
+                "negative_prompt": {
+                    "type": "string",
+                    "description": "Optional things to avoid in the image"
+                }
+            },
+            "required": ["prompt"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct GenerateImageArgs {
+    prompt: String,
+    #[serde(default)]
+    negative_prompt: Option<String>,
+}
+
+pub async fn run(config: &ImageGenConfig, arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: GenerateImageArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid generate_image args: {e}"))?;
+    let prompt = args.prompt.trim();
+    if prompt.is_empty() {
+        anyhow::bail!("image prompt missing");
+    }
+    let negative = args.negative_prompt.as_deref().unwrap_or("").trim();
+
+    let bytes = generate(config, prompt, negative).await?;
+    let url = store(config, &bytes).await?;
+
+    let mut output = ToolOutput::text(
+        serde_json::json!({
+            "status": "generated",
+            "url": url,
+            "instructions": "The image is already displayed to the user. Briefly describe what was generated; do not repeat the URL."
+        })
+        .to_string(),
+    );
+    output
+        .events
+        .push(("image", serde_json::json!({ "url": url, "prompt": prompt })));
+    Ok(output)
+}
+
+// Writes PNG bytes under the image dir and returns the URL they are served from.
+async fn store(config: &ImageGenConfig, bytes: &[u8]) -> anyhow::Result<String> {
+    tokio::fs::create_dir_all(&config.output_dir).await?;
+    let file_name = format!("{}.png", uuid::Uuid::new_v4());
+    tokio::fs::write(config.output_dir.join(&file_name), bytes).await?;
+    Ok(format!("{IMAGE_ROUTE}/{file_name}"))
+}
+
+// ---------- Backends ----------
+
+async fn generate(
+    config: &ImageGenConfig,
+    prompt: &str,
+    negative: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(300))
+        .build()?;
+    match &config.backend {
+        ImageBackend::Automatic1111 => generate_a1111(&client, config, prompt, negative).await,
+        ImageBackend::OpenAi => generate_openai(&client, config, prompt).await,
+        ImageBackend::ComfyUi { workflow } => {
+            generate_comfyui(&client, config, workflow, prompt, negative).await
+        }
+    }
+}
+
+async fn post_json(
+    client: &reqwest::Client,
+    config: &ImageGenConfig,
+    url: &str,
+    body: &serde_json::Value,
+) -> anyhow::Result<serde_json::Value> {
+    let resp = client
+        .post(url)
+        .bearer_auth(config.api_key.as_deref().unwrap_or("no-key"))
+        .json(body)
+        .send()
+        .await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("image backend error {}: {}", status, body);
+    }
+    Ok(resp.json().await?)
+}
+
+fn decode_base64_image(data: &str) -> anyhow::Result<Vec<u8>> {
+    let raw = match data.split_once(";base64,") {
+        Some((_, b64)) => b64,
+        None => data,
+    };
+    Ok(base64::engine::general_purpose::STANDARD.decode(raw.trim())?)
+}
+
+async fn generate_a1111(
+    client: &reqwest::Client,
+    config: &ImageGenConfig,
+    prompt: &str,
+    negative: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let mut body = serde_json::json!({
+        "prompt": prompt,
+        "negative_prompt": negative,
+        "batch_size": 1,
+    });
+    if let Some(width) = config.width {
+        body["width"] = width.into();
+    }
+    if let Some(height) = config.height {
+        body["height"] = height.into();
+    }
+    if let Some(steps) = config.steps {
+        body["steps"] = steps.into();
+    }
+    if let Some(model) = &config.model {
+        body["override_settings"] = serde_json::json!({ "sd_model_checkpoint": model });
+    }
+
+    let url = format!("{}/sdapi/v1/txt2img", config.url);
+    let parsed = post_json(client, config, &url, &body).await?;
+    let image = parsed["images"][0]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("txt2img response contained no images"))?;
+    decode_base64_image(image)
+}
+
+async fn generate_openai(
+    client: &reqwest::Client,
+    config: &ImageGenConfig,
+    prompt: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let mut body = serde_json::json!({
+        "prompt": prompt,
+        "n": 1,
+        "response_format": "b64_json",
+    });
+    if let Some(model) = &config.model {
+        body["model"] = model.clone().into();
+    }
+    if let (Some(width), Some(height)) = (config.width, config.height) {
+        body["size"] = format!("{width}x{height}").into();
+    }
+
+    let url = format!("{}/v1/images/generations", config.url);
+    let parsed = post_json(client, config, &url, &body).await?;
+    let image = &parsed["data"][0];
+    if let Some(b64) = image["b64_json"].as_str() {
+        return decode_base64_image(b64);
+    }
+    let Some(image_url) = image["url"].as_str() else {
+        anyhow::bail!("image response contained no data");
+    };
+    let resp = client.get(image_url).send().await?.error_for_status()?;
+    Ok(resp.bytes().await?.to_vec())
+}
+
+// Replaces "{{prompt}}", "{{negative_prompt}}" and "{{seed}}" anywhere in the workflow.
+fn fill_workflow(value: &mut serde_json::Value, prompt: &str, negative: &str, seed: u64) {
+    match value {
+        serde_json::Value::String(s) if s == "{{seed}}" => *value = seed.into(),
+        serde_json::Value::String(s) => {
+            *s = s
+                .replace("{{prompt}}", prompt)
+                .replace("{{negative_prompt}}", negative);
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                fill_workflow(item, prompt, negative, seed);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for item in map.values_mut() {
+                fill_workflow(item, prompt, negative, seed);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Deserialize)]
+struct ComfyOutputImage {
+    filename: String,
+    #[serde(default)]
+    subfolder: String,
+    #[serde(rename = "type", default)]
+    image_type: String,
+}
+
+async fn generate_comfyui(
+    client: &reqwest::Client,
+    config: &ImageGenConfig,
+    workflow: &serde_json::Value,
+    prompt: &str,
+    negative: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let mut graph = workflow.clone();
+    let seed = uuid::Uuid::new_v4().as_u64_pair().0 >> 16;
+    fill_workflow(&mut graph, prompt, negative, seed);
+
+    let url = format!("{}/prompt", config.url);
+    let queued = post_json(
+        client,
+        config,
+        &url,
+        &serde_json::json!({ "prompt": graph }),
+    )
+    .await?;
+    let prompt_id = queued["prompt_id"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("comfyui did not return a prompt_id"))?
+        .to_string();
+
+    // ComfyUI has no blocking endpoint; poll the history until outputs appear.
+    let history_url = format!("{}/history/{prompt_id}", config.url);
+    let mut images = Vec::new();
+    for _ in 0..300 {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let history: serde_json::Value = client
+            .get(&history_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let Some(outputs) = history[&prompt_id]["outputs"].as_object() else {
+            continue;
+        };
+        images = outputs
+            .values()
+            .filter_map(|node| node.get("images"))
+            .filter_map(|v| serde_json::from_value::<Vec<ComfyOutputImage>>(v.clone()).ok())
+            .flatten()
+            .collect();
+        break;
+    }
+    let image = images
+        .into_iter()
+        .find(|img| img.image_type != "temp")
+        .ok_or_else(|| anyhow::anyhow!("comfyui workflow produced no images"))?;
+
+    let resp = client
+        .get(format!("{}/view", config.url))
+        .query(&[
+            ("filename", image.filename.as_str()),
+            ("subfolder", image.subfolder.as_str()),
+            ("type", image.image_type.as_str()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(resp.bytes().await?.to_vec())
+}
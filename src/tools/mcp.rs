@@ -0,0 +1,497 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{Mutex, oneshot};
+
+use super::{Tool, ToolOutput};
+
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+// ---------- Config ----------
+
+// Same shape as the `mcpServers` block other MCP clients use:
+// { "mcpServers": { "files": { "command": "npx", "args": [...] }, "ha": { "url": "http://..." } } }
+#[derive(Deserialize, Default)]
+struct McpConfigFile {
+    #[serde(default, rename = "mcpServers")]
+    mcp_servers: HashMap<String, McpServerConfig>,
+}
+
+#[derive(Deserialize, Clone)]
+struct McpServerConfig {
+    #[serde(default)]
+    command: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    // When false the server's tools are only sent if a request names them in `tools`.
+    #[serde(default = "default_true")]
+    auto: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn env_secs(name: &str, default: u64) -> Duration {
+    Duration::from_secs(
+        crate::config::var(name)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(default),
+    )
+}
+
+// ---------- Transports ----------
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<serde_json::Value>>>>;
+
+enum Transport {
+    // Newline-delimited JSON-RPC over a child process's stdin/stdout.
+    Stdio {
+        stdin: Arc<Mutex<tokio::process::ChildStdin>>,
+        pending: Pending,
+        _child: tokio::process::Child,
+    },
+    // Streamable HTTP: each message is a POST; replies come back as JSON or an SSE stream.
+    Http {
+        client: reqwest::Client,
+        url: String,
+        headers: HashMap<String, String>,
+        session_id: Mutex<Option<String>>,
+    },
+}
+
+pub struct McpClient {
+    name: String,
+    transport: Transport,
+    next_id: AtomicU64,
+    timeout: Duration,
+}
+
+impl McpClient {
+    async fn connect(name: &str, config: &McpServerConfig) -> anyhow::Result<Self> {
+        let transport = if let Some(command) = &config.command {
+            spawn_stdio(command, config)?
+        } else if let Some(url) = &config.url {
+            Transport::Http {
+                client: reqwest::Client::new(),
+                url: url.clone(),
+                headers: config.headers.clone(),
+                session_id: Mutex::new(None),
+            }
+        } else {
+            anyhow::bail!("needs either `command` or `url`");
+        };
+
+        let client = Self {
+            name: name.to_string(),
+            transport,
+            next_id: AtomicU64::new(1),
+            timeout: env_secs("MCP_TIMEOUT_SECS", 60),
+        };
+        client
+            .request(
+                "initialize",
+                serde_json::json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": "chat-llama",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }),
+            )
+            .await?;
+        client
+            .notify("notifications/initialized", serde_json::json!({}))
+            .await?;
+        Ok(client)
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+
+        let response = match &self.transport {
+            Transport::Stdio { stdin, pending, .. } => {
+                let (tx, rx) = oneshot::channel();
+                pending.lock().await.insert(id, tx);
+                if let Err(err) = write_line(stdin, &message).await {
+                    pending.lock().await.remove(&id);
+                    return Err(err);
+                }
+                match tokio::time::timeout(self.timeout, rx).await {
+                    Ok(Ok(response)) => response,
+                    Ok(Err(_)) => anyhow::bail!("server exited"),
+                    Err(_) => {
+                        pending.lock().await.remove(&id);
+                        anyhow::bail!("{method} timed out after {}s", self.timeout.as_secs());
+                    }
+                }
+            }
+            Transport::Http { .. } => self.post(&message, Some(id)).await?,
+        };
+
+        if let Some(error) = response.get("error") {
+            anyhow::bail!(
+                "{method} failed: {}",
+                error["message"].as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(response["result"].clone())
+    }
+
+    async fn notify(&self, method: &str, params: serde_json::Value) -> anyhow::Result<()> {
+        let message = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        match &self.transport {
+            Transport::Stdio { stdin, .. } => write_line(stdin, &message).await,
+            Transport::Http { .. } => self.post(&message, None).await.map(|_| ()),
+        }
+    }
+
+    async fn post(
+        &self,
+        message: &serde_json::Value,
+        id: Option<u64>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let Transport::Http {
+            client,
+            url,
+            headers,
+            session_id,
+        } = &self.transport
+        else {
+            unreachable!("post is only used by the HTTP transport");
+        };
+
+        let mut request = client
+            .post(url)
+            .timeout(self.timeout)
+            .header("Accept", "application/json, text/event-stream")
+            .header("MCP-Protocol-Version", PROTOCOL_VERSION)
+            .json(message);
+        for (key, value) in headers {
+            request = request.header(key, value);
+        }
+        if let Some(session) = session_id.lock().await.as_deref() {
+            request = request.header("Mcp-Session-Id", session);
+        }
+        let resp = request.send().await?;
+        let status = resp.status();
+        if let Some(session) = resp
+            .headers()
+            .get("mcp-session-id")
+            .and_then(|v| v.to_str().ok())
+        {
+            *session_id.lock().await = Some(session.to_string());
+        }
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            anyhow::bail!("http {status}: {body}");
+        }
+        let Some(id) = id else {
+            return Ok(serde_json::Value::Null);
+        };
+
+        let is_sse = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with("text/event-stream"));
+        let body = resp.text().await?;
+        if !is_sse {
+            return Ok(serde_json::from_str(&body)?);
+        }
+        // The stream may carry notifications before the response we are waiting for.
+        body.lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .filter_map(|data| serde_json::from_str::<serde_json::Value>(data.trim()).ok())
+            .find(|msg| msg["id"].as_u64() == Some(id))
+            .ok_or_else(|| anyhow::anyhow!("no response in event stream"))
+    }
+
+    async fn list_tools(&self) -> anyhow::Result<Vec<serde_json::Value>> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => serde_json::json!({ "cursor": cursor }),
+                None => serde_json::json!({}),
+            };
+            let result = self.request("tools/list", params).await?;
+            tools.extend(result["tools"].as_array().cloned().unwrap_or_default());
+            match result["nextCursor"].as_str() {
+                Some(next) if !next.is_empty() => cursor = Some(next.to_string()),
+                _ => return Ok(tools),
+            }
+        }
+    }
+}
+
+fn spawn_stdio(command: &str, config: &McpServerConfig) -> anyhow::Result<Transport> {
+    let mut cmd = tokio::process::Command::new(command);
+    cmd.args(&config.args)
+        .envs(&config.env)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::inherit())
+        .kill_on_drop(true);
+    if let Some(cwd) = &config.cwd {
+        cmd.current_dir(cwd);
+    }
+    let mut child = cmd.spawn()?;
+    let stdin = Arc::new(Mutex::new(child.stdin.take().expect("piped stdin")));
+    let stdout = child.stdout.take().expect("piped stdout");
+    let pending: Pending = Arc::default();
+
+    let (reader_stdin, reader_pending) = (stdin.clone(), pending.clone());
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) else {
+                continue;
+            };
+            match (message.get("id"), message.get("method")) {
+                // Response to one of our requests.
+                (Some(id), None) => {
+                    if let Some(id) = id.as_u64()
+                        && let Some(tx) = reader_pending.lock().await.remove(&id)
+                    {
+                        let _ = tx.send(message);
+                    }
+                }
+                // Request from the server; we only support ping.
+                (Some(id), Some(method)) => {
+                    let reply = if method == "ping" {
+                        serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                    } else {
+                        serde_json::json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": { "code": -32601, "message": "method not supported" },
+                        })
+                    };
+                    let _ = write_line(&reader_stdin, &reply).await;
+                }
+                // Notifications (logging, list_changed, progress) are ignored.
+                _ => {}
+            }
+        }
+        // Dropping the senders fails every request still waiting on this server.
+        reader_pending.lock().await.clear();
+    });
+
+    Ok(Transport::Stdio {
+        stdin,
+        pending,
+        _child: child,
+    })
+}
+
+async fn write_line(
+    stdin: &Mutex<tokio::process::ChildStdin>,
+    message: &serde_json::Value,
+) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    let mut stdin = stdin.lock().await;
+    stdin.write_all(&line).await?;
+    stdin.flush().await?;
+    Ok(())
+}
+
+// ---------- Registry ----------
+
+struct McpTool {
+    definition: Tool,
+    server: Arc<McpClient>,
+    remote_name: String,
+    auto: bool,
+}
+
+#[derive(Default)]
+pub struct McpRegistry {
+    tools: Vec<McpTool>,
+}
+
+// Tool names sent to llama-server are `<server>__<tool>`, limited to the characters
+// OpenAI-style function names allow.
+fn exposed_name(server: &str, tool: &str) -> String {
+    format!("{server}__{tool}")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect()
+}
+
+impl McpRegistry {
+    // Reads MCP_CONFIG (default data/mcp.json), connects to every server and lists its
+    // tools. Servers that fail to start are logged and skipped.
+    pub async fn from_env() -> Self {
+        let path = crate::config::var("MCP_CONFIG").unwrap_or_else(|_| "data/mcp.json".into());
+        let Ok(raw) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        let config: McpConfigFile = match serde_json::from_str(&raw) {
+            Ok(config) => config,
+            Err(err) => {
+                log!("failed to parse {path}: {err}");
+                return Self::default();
+            }
+        };
+
+        let startup_timeout = env_secs("MCP_STARTUP_TIMEOUT_SECS", 30);
+        let mut registry = Self::default();
+        let mut servers: Vec<_> = config.mcp_servers.into_iter().collect();
+        servers.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, server_config) in servers {
+            let connect = async {
+                let client = McpClient::connect(&name, &server_config).await?;
+                let tools = client.list_tools().await?;
+                anyhow::Ok((client, tools))
+            };
+            let (client, tools) = match tokio::time::timeout(startup_timeout, connect).await {
+                Ok(Ok(connected)) => connected,
+                Ok(Err(err)) => {
+                    log!("mcp server {name} unavailable: {err}");
+                    continue;
+                }
+                Err(_) => {
+                    log!("mcp server {name} did not start within {startup_timeout:?}");
+                    continue;
+                }
+            };
+
+            let client = Arc::new(client);
+            for tool in tools {
+                let Some(remote_name) = tool["name"].as_str() else {
+                    continue;
+                };
+                let parameters = match &tool["inputSchema"] {
+                    serde_json::Value::Object(schema) => serde_json::Value::Object(schema.clone()),
+                    _ => serde_json::json!({ "type": "object", "properties": {} }),
+                };
+                let definition = Tool::function(
+                    &exposed_name(&name, remote_name),
+                    tool["description"].as_str().unwrap_or_default(),
+                    parameters,
+                );
+                registry.tools.push(McpTool {
+                    definition,
+                    server: client.clone(),
+                    remote_name: remote_name.to_string(),
+                    auto: server_config.auto,
+                });
+            }
+            log!(
+                "mcp server {name}: {} tools",
+                registry
+                    .tools
+                    .iter()
+                    .filter(|t| Arc::ptr_eq(&t.server, &client))
+                    .count()
+            );
+        }
+        registry
+    }
+
+    // Every registered tool with its `auto` flag (sent to every chat when set).
+    pub fn definitions(&self) -> impl Iterator<Item = (&Tool, bool)> {
+        self.tools.iter().map(|t| (&t.definition, t.auto))
+    }
+
+    pub fn tool_definition(&self, name: &str) -> Option<Tool> {
+        self.find(name).map(|t| t.definition.clone())
+    }
+
+    fn find(&self, name: &str) -> Option<&McpTool> {
+        self.tools
+            .iter()
+            .find(|t| t.definition.function.name == name)
+    }
+
+    pub fn has_tool(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    pub async fn call(&self, name: &str, arguments: &str) -> anyhow::Result<ToolOutput> {
+        let tool = self
+            .find(name)
+            .ok_or_else(|| anyhow::anyhow!("unknown mcp tool {name}"))?;
+        let arguments: serde_json::Value = if arguments.trim().is_empty() {
+            serde_json::json!({})
+        } else {
+            serde_json::from_str(arguments)
+                .map_err(|e| anyhow::anyhow!("invalid {name} args: {e}"))?
+        };
+        let result = tool
+            .server
+            .request(
+                "tools/call",
+                serde_json::json!({ "name": tool.remote_name, "arguments": arguments }),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("mcp server {}: {e}", tool.server.name))?;
+
+        let mut parts = Vec::new();
+        for item in result["content"].as_array().into_iter().flatten() {
+            match item["type"].as_str() {
+                Some("text") => parts.push(item["text"].as_str().unwrap_or_default().to_string()),
+                Some("resource") => {
+                    let resource = &item["resource"];
+                    match resource["text"].as_str() {
+                        Some(text) => parts.push(text.to_string()),
+                        None => parts.push(format!(
+                            "[binary resource {}]",
+                            resource["uri"].as_str().unwrap_or_default()
+                        )),
+                    }
+                }
+                Some("resource_link") => parts.push(format!(
+                    "[resource {}]",
+                    item["uri"].as_str().unwrap_or_default()
+                )),
+                Some(other) => parts.push(format!("[{other} content omitted]")),
+                None => {}
+            }
+        }
+        if parts.is_empty()
+            && let Some(structured) = result.get("structuredContent")
+        {
+            parts.push(structured.to_string());
+        }
+        let text = parts.join("\n");
+
+        if result["isError"].as_bool() == Some(true) {
+            anyhow::bail!("{text}");
+        }
+        Ok(ToolOutput::text(text))
+    }
+}
@@ -0,0 +1,734 @@
+use axum::{Extension, Json, extract::State};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::rbac::Role;
+use crate::users::Identity;
+use crate::{AppState, SearchResult, ToolCall, kb, memory, tables, web_search};
+
+pub mod approval;
+pub mod arxiv;
+pub mod budget;
+pub mod calculator;
+pub mod client;
+pub mod code;
+pub mod condense;
+pub mod files;
+pub mod github;
+pub mod image;
+pub mod mcp;
+pub mod open_url;
+pub mod pending;
+pub mod plugins;
+pub mod sanitize;
+pub mod shell;
+pub mod sql;
+pub mod stackexchange;
+pub mod time;
+pub mod weather;
+pub mod webhook;
+pub mod wikipedia;
+pub mod youtube;
+
+// ---------- Tool types (OpenAI function-calling schema) ----------
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: ToolFunction,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ToolFunction {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+impl Tool {
+    pub fn function(name: &str, description: &str, parameters: serde_json::Value) -> Self {
+        Tool {
+            tool_type: "function".into(),
+            function: ToolFunction {
+                name: name.into(),
+                description: description.into(),
+                parameters,
+            },
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum ToolChoice {
+    Simple(String),
+    Detailed(serde_json::Value),
+}
+
+// Per-request settings that tool executions need.
+#[derive(Clone, Default)]
+pub struct ToolContext {
+    pub kb_collections: Vec<String>,
+    pub tables: Vec<Arc<tables::Table>>,
+    // The user's message, for steps that focus tool output on it.
+    pub question: String,
+    // Most tokens one tool result may take up this turn (TOOL_RESULT_CONTEXT_FRACTION).
+    pub token_budget: Option<usize>,
+    // How much page text web_search adds to its results.
+    pub excerpts: crate::search::ExcerptConfig,
+    // The chat's language tag, for search results and Wikipedia.
+    pub language: Option<String>,
+    // The caller's role when signed in; calls to tools it may not use are refused.
+    pub role: Option<Role>,
+    // The signed-in account, whose memories remember and recall use.
+    pub account: Option<String>,
+    // Who and which chat the audit log attributes tool calls and fetches to.
+    pub audit: crate::audit::Scope,
+}
+
+// What a tool hands back: the message for the model, plus anything to stream to the client.
+pub struct ToolOutput {
+    pub content: String,
+    // Replaces the sources shown for this reply.
+    pub sources: Option<Vec<SearchResult>>,
+    // Added to the sources already shown.
+    pub extra_sources: Vec<SearchResult>,
+    // Extra SSE events as (event name, JSON payload).
+    pub events: Vec<(&'static str, serde_json::Value)>,
+}
+
+impl ToolOutput {
+    pub fn text(content: String) -> Self {
+        Self {
+            content,
+            sources: None,
+            extra_sources: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+}
+
+// ---------- Tool selection ----------
+
+// Built-in tools a chat can name in `ChatRequest::tools`. query_table is offered when
+// tables are attached instead.
+pub const BUILTIN_TOOLS: &[&str] = &[
+    "web_search",
+    NEWS_SEARCH,
+    "kb_search",
+    memory::REMEMBER,
+    memory::RECALL,
+    calculator::TOOL_NAME,
+    time::TOOL_NAME,
+    weather::TOOL_NAME,
+    wikipedia::TOOL_NAME,
+    arxiv::TOOL_NAME,
+    youtube::TOOL_NAME,
+    github::TOOL_NAME,
+    stackexchange::TOOL_NAME,
+    open_url::TOOL_NAME,
+    code::TOOL_NAME,
+    shell::TOOL_NAME,
+    files::TOOL_NAME,
+    sql::TOOL_NAME,
+    image::TOOL_NAME,
+];
+
+// Tools whose arguments go to someone else's service or are stored; PII_REDACTION masks
+// personal data in them. MCP servers and HTTP tools count as third parties.
+const REDACTED_ARGUMENT_TOOLS: &[&str] = &[
+    "web_search",
+    NEWS_SEARCH,
+    memory::REMEMBER,
+    weather::TOOL_NAME,
+    wikipedia::TOOL_NAME,
+    arxiv::TOOL_NAME,
+    youtube::TOOL_NAME,
+    github::TOOL_NAME,
+    stackexchange::TOOL_NAME,
+    open_url::TOOL_NAME,
+    image::TOOL_NAME,
+];
+
+fn redacts_arguments(state: &AppState, name: &str) -> bool {
+    REDACTED_ARGUMENT_TOOLS.contains(&name)
+        || state.mcp.has_tool(name)
+        || state.webhooks.has_tool(name)
+}
+
+// Tools switched off for every chat via DISABLED_TOOLS (comma-separated names).
+pub fn disabled_tools_from_env() -> Vec<String> {
+    crate::config::var("DISABLED_TOOLS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+pub fn is_disabled(state: &AppState, name: &str) -> bool {
+    state
+        .config
+        .tool_disabled(name)
+        .unwrap_or_else(|| state.disabled_tools.iter().any(|t| t == name))
+}
+
+// Looks up a tool listed in `ChatRequest::tools`; errors if unknown, disabled, or not configured.
+pub fn tool_definition(state: &AppState, name: &str) -> Result<Tool, String> {
+    if is_disabled(state, name) {
+        return Err(format!("tool {name} is disabled"));
+    }
+    match name {
+        "web_search" => Ok(web_search_tool_definition()),
+        "kb_search" => Ok(kb_search_tool_definition()),
+        "query_table" => Err("query_table is enabled by attaching tables".into()),
+        memory::REMEMBER => Ok(memory::remember_tool_definition()),
+        memory::RECALL => Ok(memory::recall_tool_definition()),
+        image::TOOL_NAME => match &state.image_gen {
+            Some(_) => Ok(image::tool_definition()),
+            None => Err("generate_image requires IMAGE_GEN_URL to be configured".into()),
+        },
+        calculator::TOOL_NAME => Ok(calculator::tool_definition()),
+        time::TOOL_NAME => Ok(time::tool_definition()),
+        weather::TOOL_NAME => Ok(weather::tool_definition()),
+        wikipedia::TOOL_NAME => Ok(wikipedia::tool_definition()),
+        arxiv::TOOL_NAME => Ok(arxiv::tool_definition()),
+        youtube::TOOL_NAME => Ok(youtube::tool_definition()),
+        github::TOOL_NAME => Ok(github::tool_definition()),
+        stackexchange::TOOL_NAME => Ok(stackexchange::tool_definition()),
+        open_url::TOOL_NAME => Ok(open_url::tool_definition()),
+        code::TOOL_NAME => match &state.code_exec {
+            Some(_) => Ok(code::tool_definition()),
+            None => Err("run_code requires CODE_EXEC_BACKEND to be configured".into()),
+        },
+        shell::TOOL_NAME => match &state.shell {
+            Some(config) => Ok(shell::tool_definition(config)),
+            None => Err("shell requires SHELL_TOOL_ALLOWLIST to be configured".into()),
+        },
+        files::TOOL_NAME => match &state.file_read {
+            Some(_) => Ok(files::tool_definition()),
+            None => Err("read_file requires READ_FILE_ROOT to be configured".into()),
+        },
+        sql::TOOL_NAME => match &state.sql {
+            Some(config) => Ok(sql::tool_definition(config)),
+            None => Err("sql_query requires SQL_TOOL_DSN to be configured".into()),
+        },
+        NEWS_SEARCH => Ok(news_search_tool_definition()),
+        other => state
+            .mcp
+            .tool_definition(other)
+            .or_else(|| state.plugins.tool_definition(other))
+            .or_else(|| state.webhooks.tool_definition(other))
+            .ok_or_else(|| format!("unknown tool: {other}")),
+    }
+}
+
+#[derive(Serialize)]
+pub struct ToolInfo {
+    name: String,
+    description: String,
+    // Sent to every chat without being named in `tools`.
+    auto: bool,
+}
+
+// Every tool a chat can currently enable, for the frontend's tool toggles.
+pub async fn list_tools_handler(
+    State(state): State<Arc<AppState>>,
+    identity: Option<Extension<Identity>>,
+) -> Json<Vec<ToolInfo>> {
+    let role = identity.map(|Extension(identity)| identity.role);
+    let builtin = BUILTIN_TOOLS
+        .iter()
+        .filter_map(|name| tool_definition(&state, name).ok())
+        .map(|tool| (tool, false));
+    let extensions = state
+        .mcp
+        .definitions()
+        .chain(state.plugins.definitions())
+        .chain(state.webhooks.definitions())
+        .filter(|(tool, _)| !is_disabled(&state, &tool.function.name))
+        .map(|(tool, auto)| (tool.clone(), auto));
+    Json(
+        builtin
+            .chain(extensions)
+            .filter(|(tool, _)| {
+                role.is_none_or(|role| state.roles.may_use_tool(&state, role, &tool.function.name))
+            })
+            .map(|(tool, auto)| ToolInfo {
+                name: tool.function.name,
+                description: tool.function.description,
+                auto,
+            })
+            .collect(),
+    )
+}
+
+// ---------- Built-in tool definitions ----------
+
+pub const NEWS_SEARCH: &str = "news_search";
+
+pub fn web_search_tool_definition() -> Tool {
+    Tool::function(
+        "web_search",
+        "Searches the web and returns the top results.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "Short search query describing what you need to know"
+                },
+                "queries": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "maxItems": 3,
+                    "description": "Optional other phrasings of the same question, searched together with query to catch pages worded differently"
+                },
+                "time_range": {
+                    "type": "string",
+                    "enum": crate::search::TIME_RANGES,
+                    "description": "Optional recency filter; use it for recent events so stale pages are skipped"
+                },
+                "site": {
+                    "type": "string",
+                    "description": "Optional domain to search within, e.g. docs.rs or reddit.com"
+                },
+                "category": {
+                    "type": "string",
+                    "enum": crate::search::CATEGORIES,
+                    "description": "Optional topic to search within: it for programming and tech, science for papers and research, news for current events (default general)"
+                },
+                "max_results": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 5,
+                    "description": "Optional maximum number of results to return (default 5)"
+                }
+            },
+            "required": ["query"]
+        }),
+    )
+}
+
+pub fn news_search_tool_definition() -> Tool {
+    Tool::function(
+        NEWS_SEARCH,
+        "Searches recent news articles and returns them with publication dates. Use it for \
+current events and anything that may have changed recently.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "Short news search query"
+                },
+                "max_results": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 5,
+                    "description": "Optional maximum number of articles to return (default 5)"
+                }
+            },
+            "required": ["query"]
+        }),
+    )
+}
+
+pub fn kb_search_tool_definition() -> Tool {
+    Tool::function(
+        "kb_search",
+        "Searches the user's uploaded documents and returns the most relevant passages.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "Keywords describing the information to look up"
+                },
+                "max_results": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 8,
+                    "description": "Optional maximum number of passages to return (default 5)"
+                }
+            },
+            "required": ["query"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct WebSearchToolArgs {
+    query: String,
+    #[serde(default)]
+    queries: Vec<String>,
+    #[serde(default)]
+    max_results: Option<usize>,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    time_range: Option<String>,
+    #[serde(default)]
+    site: Option<String>,
+}
+
+// ---------- Timeouts ----------
+
+// Wall-clock limit per tool call: TOOL_TIMEOUT_SECS (default 60) with per-tool overrides in
+// TOOL_TIMEOUTS, e.g. "open_url=20,generate_image=600".
+#[derive(Clone, Debug)]
+pub struct ToolTimeouts {
+    default: Duration,
+    overrides: HashMap<String, Duration>,
+}
+
+impl ToolTimeouts {
+    pub fn from_env() -> Self {
+        let default = Duration::from_secs(
+            crate::config::var("TOOL_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(60),
+        );
+        // Image backends routinely need minutes on modest GPUs.
+        let mut overrides =
+            HashMap::from([(image::TOOL_NAME.to_string(), Duration::from_secs(300))]);
+        for entry in crate::config::var("TOOL_TIMEOUTS")
+            .unwrap_or_default()
+            .split(',')
+        {
+            let Some((name, secs)) = entry.split_once('=') else {
+                continue;
+            };
+            match secs.trim().parse() {
+                Ok(secs) => {
+                    overrides.insert(name.trim().to_string(), Duration::from_secs(secs));
+                }
+                Err(_) => log!("ignoring invalid TOOL_TIMEOUTS entry {entry:?}"),
+            }
+        }
+        Self { default, overrides }
+    }
+
+    pub fn for_tool(&self, name: &str) -> Duration {
+        self.overrides.get(name).copied().unwrap_or(self.default)
+    }
+}
+
+// Returned (inside anyhow) when a tool call exceeds its limit.
+#[derive(Debug)]
+pub struct ToolTimedOut {
+    pub secs: u64,
+}
+
+impl std::fmt::Display for ToolTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out after {}s", self.secs)
+    }
+}
+
+impl std::error::Error for ToolTimedOut {}
+
+// ---------- Loop limits ----------
+
+// Stops a chat whose model keeps calling tools: at most TOOL_MAX_ITERATIONS rounds (default
+// 8), and no call repeated with identical arguments more than TOOL_MAX_REPEATS times (2).
+#[derive(Clone, Debug)]
+pub struct ToolLoopLimits {
+    pub max_iterations: usize,
+    pub max_repeats: usize,
+}
+
+impl ToolLoopLimits {
+    pub fn from_env() -> Self {
+        let env_usize = |name: &str, default: usize| {
+            crate::config::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            max_iterations: env_usize("TOOL_MAX_ITERATIONS", 8),
+            max_repeats: env_usize("TOOL_MAX_REPEATS", 2),
+        }
+    }
+}
+
+// Name plus arguments, with JSON arguments re-serialized so whitespace and key order
+// do not hide a repeat.
+pub fn call_signature(call: &ToolCall) -> String {
+    let arguments = serde_json::from_str::<serde_json::Value>(&call.function.arguments)
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| call.function.arguments.trim().to_string());
+    format!("{}\n{arguments}", call.function.name)
+}
+
+// ---------- Dispatch ----------
+
+pub async fn handle_tool_call(
+    state: &AppState,
+    ctx: &ToolContext,
+    call: &ToolCall,
+) -> anyhow::Result<ToolOutput> {
+    let mut redacted = call.clone();
+    let call = match &state.pii {
+        Some(pii) if redacts_arguments(state, &call.function.name) => {
+            redacted.function.arguments = pii.redact_json(&call.function.arguments);
+            &redacted
+        }
+        _ => call,
+    };
+    if let Some(role) = ctx.role
+        && !state.roles.may_use_tool(state, role, &call.function.name)
+    {
+        anyhow::bail!(
+            "{} is not available to the {} role",
+            call.function.name,
+            role.name()
+        );
+    }
+    let timeout = state.tool_timeouts.for_tool(&call.function.name);
+    let mut output = match tokio::time::timeout(timeout, dispatch_tool_call(state, ctx, call)).await
+    {
+        Ok(result) => result?,
+        Err(_) => {
+            return Err(ToolTimedOut {
+                secs: timeout.as_secs(),
+            }
+            .into());
+        }
+    };
+    if let Some(guard) = &state.injection_guard {
+        output.content = guard
+            .screen(state, &call.function.name, output.content)
+            .await;
+    }
+    if let Some(budget) = ctx.token_budget {
+        output.content = budget::fit_text(state, budget, output.content).await;
+    }
+    Ok(output)
+}
+
+async fn dispatch_tool_call(
+    state: &AppState,
+    ctx: &ToolContext,
+    call: &ToolCall,
+) -> anyhow::Result<ToolOutput> {
+    match call.function.name.as_str() {
+        "web_search" | NEWS_SEARCH => {
+            let args: WebSearchToolArgs = serde_json::from_str(&call.function.arguments)
+                .map_err(|e| anyhow::anyhow!("invalid search args: {e}"))?;
+            let category = match args.category.as_deref().map(str::trim) {
+                _ if call.function.name == NEWS_SEARCH => "news",
+                None | Some("") => "general",
+                Some(category) => crate::search::CATEGORIES
+                    .iter()
+                    .copied()
+                    .find(|c| *c == category)
+                    .ok_or_else(|| anyhow::anyhow!("unknown search category {category:?}"))?,
+            };
+            let trimmed_query = args.query.trim();
+            if trimmed_query.is_empty() {
+                anyhow::bail!("search query missing");
+            }
+            let time_range = match args.time_range.as_deref().map(str::trim) {
+                None | Some("") => None,
+                Some(range) => Some(
+                    crate::search::TIME_RANGES
+                        .iter()
+                        .copied()
+                        .find(|r| *r == range)
+                        .ok_or_else(|| anyhow::anyhow!("unknown time_range {range:?}"))?,
+                ),
+            };
+            let site = match args.site.as_deref().map(str::trim) {
+                None | Some("") => None,
+                Some(site) => Some(
+                    crate::search::normalize_site(site)
+                        .ok_or_else(|| anyhow::anyhow!("invalid site {site:?}; give a domain"))?,
+                ),
+            };
+            let search = crate::search::SearchQuery {
+                query: trimmed_query,
+                category,
+                time_range,
+                site: site.as_deref(),
+                language: ctx.language.as_deref(),
+            };
+            let mut extra_queries: Vec<String> = Vec::new();
+            for query in args.queries.iter().map(|q| q.trim()) {
+                if !query.is_empty()
+                    && !query.eq_ignore_ascii_case(trimmed_query)
+                    && !extra_queries.iter().any(|q| q.eq_ignore_ascii_case(query))
+                {
+                    extra_queries.push(query.to_string());
+                }
+            }
+            extra_queries.truncate(3);
+            let mut results = web_search(state, ctx, &search, &extra_queries).await?;
+            let limit = args.max_results.unwrap_or(5).clamp(1, 7);
+            if results.len() > limit {
+                results.truncate(limit);
+            }
+            let mut output = ToolOutput::text(
+                format_search_results_for_tool(state, ctx, &results, trimmed_query).await,
+            );
+            output.sources = Some(results);
+            Ok(output)
+        }
+        "kb_search" => {
+            let args: WebSearchToolArgs = serde_json::from_str(&call.function.arguments)
+                .map_err(|e| anyhow::anyhow!("invalid kb_search args: {e}"))?;
+            let trimmed_query = args.query.trim();
+            if trimmed_query.is_empty() {
+                anyhow::bail!("search query missing");
+            }
+            let limit = args.max_results.unwrap_or(5).clamp(1, 8);
+            let results = kb::kb_search(state, trimmed_query, &ctx.kb_collections, limit).await;
+            let mut output = ToolOutput::text(
+                format_search_results_for_tool(state, ctx, &results, trimmed_query).await,
+            );
+            output.sources = Some(results);
+            Ok(output)
+        }
+        "query_table" => {
+            let args: tables::QueryTableArgs = serde_json::from_str(&call.function.arguments)
+                .map_err(|e| anyhow::anyhow!("invalid query_table args: {e}"))?;
+            let table = ctx
+                .tables
+                .iter()
+                .find(|t| t.name == args.table || t.id == args.table)
+                .cloned()
+                .ok_or_else(|| {
+                    anyhow::anyhow!("table {:?} is not attached to this chat", args.table)
+                })?;
+            let result = tokio::task::spawn_blocking(move || tables::run_query(&table, &args))
+                .await
+                .map_err(|e| anyhow::anyhow!("table query panicked: {e}"))??;
+            Ok(ToolOutput::text(result.to_string()))
+        }
+        memory::REMEMBER => {
+            memory::run_remember(
+                &state.memory,
+                ctx.account.as_deref(),
+                &call.function.arguments,
+            )
+            .await
+        }
+        memory::RECALL => memory::run_recall(
+            &state.memory,
+            ctx.account.as_deref(),
+            &call.function.arguments,
+        ),
+        calculator::TOOL_NAME => calculator::run(&call.function.arguments),
+        time::TOOL_NAME => time::run(&call.function.arguments),
+        weather::TOOL_NAME => weather::run(&call.function.arguments).await,
+        wikipedia::TOOL_NAME => wikipedia::run(state, ctx, &call.function.arguments).await,
+        arxiv::TOOL_NAME => arxiv::run(&call.function.arguments).await,
+        youtube::TOOL_NAME => youtube::run(&call.function.arguments).await,
+        github::TOOL_NAME => github::run(&call.function.arguments).await,
+        stackexchange::TOOL_NAME => stackexchange::run(&call.function.arguments).await,
+        open_url::TOOL_NAME => open_url::run(state, ctx, &call.function.arguments).await,
+        code::TOOL_NAME => {
+            let Some(config) = &state.code_exec else {
+                anyhow::bail!("code execution is not configured");
+            };
+            code::run(config, &call.function.arguments).await
+        }
+        shell::TOOL_NAME => {
+            let Some(config) = &state.shell else {
+                anyhow::bail!("shell tool is not configured");
+            };
+            shell::run(config, &call.function.arguments).await
+        }
+        files::TOOL_NAME => {
+            let Some(config) = &state.file_read else {
+                anyhow::bail!("read_file is not configured");
+            };
+            files::run(config, &call.function.arguments).await
+        }
+        sql::TOOL_NAME => {
+            let Some(config) = &state.sql else {
+                anyhow::bail!("sql tool is not configured");
+            };
+            sql::run(config, &call.function.arguments).await
+        }
+        image::TOOL_NAME => {
+            let Some(config) = &state.image_gen else {
+                anyhow::bail!("image generation is not configured");
+            };
+            image::run(config, &call.function.arguments).await
+        }
+        other if state.mcp.has_tool(other) => state.mcp.call(other, &call.function.arguments).await,
+        other if state.plugins.has_tool(other) => {
+            state.plugins.call(other, &call.function.arguments).await
+        }
+        other if state.webhooks.has_tool(other) => {
+            state.webhooks.call(other, &call.function.arguments).await
+        }
+        other => {
+            anyhow::bail!("unknown tool call: {other}");
+        }
+    }
+}
+
+// Trims snippets in proportion to their length when the chat has a token budget.
+async fn format_search_results_for_tool(
+    state: &AppState,
+    ctx: &ToolContext,
+    results: &[SearchResult],
+    query: &str,
+) -> String {
+    let Some(budget) = ctx.token_budget else {
+        return render_search_results(results, query);
+    };
+    let mut snippets: Vec<String> = results.iter().map(|r| r.snippet.clone()).collect();
+    budget::fit_pieces(state, budget, &mut snippets, |snippets| {
+        let trimmed: Vec<SearchResult> = results
+            .iter()
+            .zip(snippets)
+            .map(|(r, snippet)| SearchResult {
+                snippet: snippet.clone(),
+                ..r.clone()
+            })
+            .collect();
+        render_search_results(&trimmed, query)
+    })
+    .await
+}
+
+fn render_search_results(results: &[SearchResult], query: &str) -> String {
+    let entries: Vec<_> = results
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let mut entry = serde_json::json!({
+                "id": i + 1,
+                "title": r.title,
+                "snippet": r.snippet,
+                "url": r.url,
+            });
+            if let Some(published) = &r.published {
+                entry["published"] = published.clone().into();
+            }
+            entry
+        })
+        .collect();
+
+    let instructions = if results.iter().any(|r| r.published.is_some()) {
+        "Use the snippets and cite sources like [id] in your response. Check published dates and prefer recent results for time-sensitive questions."
+    } else {
+        "Use the snippets and cite sources like [id] in your response."
+    };
+    serde_json::json!({
+        "query": query,
+        "results": entries,
+        "instructions": instructions,
+    })
+    .to_string()
+}
@@ -0,0 +1,83 @@
+use serde::Deserialize;
+
+use super::{Tool, ToolContext, ToolOutput};
+use crate::{AppState, SearchResult, fetch_page, scrape_client};
+
+pub const TOOL_NAME: &str = "open_url";
+
+// Pages the user points at get a much larger budget than search excerpts.
+fn max_chars() -> usize {
+    crate::config::var("OPEN_URL_MAX_CHARS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(20_000)
+}
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Fetches a web page or PDF by URL and returns its text. Use it when the user shares a \
+link or asks about a specific page.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "url": {
+                    "type": "string",
+                    "description": "Absolute http(s) URL to open"
+                }
+            },
+            "required": ["url"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct OpenUrlArgs {
+    url: String,
+}
+
+pub async fn run(
+    state: &AppState,
+    ctx: &ToolContext,
+    arguments: &str,
+) -> anyhow::Result<ToolOutput> {
+    let args: OpenUrlArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid open_url args: {e}"))?;
+    let url = reqwest::Url::parse(args.url.trim())
+        .map_err(|e| anyhow::anyhow!("invalid url {:?}: {e}", args.url))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("only http(s) URLs can be opened");
+    }
+
+    state
+        .network_guard
+        .check_url(&url)
+        .map_err(|reason| anyhow::anyhow!("cannot open {url}: {reason}"))?;
+
+    let client = scrape_client(&state.network_guard)?;
+    let Some(page) = fetch_page(state, ctx, &client, url.as_str(), max_chars()).await else {
+        anyhow::bail!("could not fetch readable content from {url}");
+    };
+    let title = page.title.unwrap_or_else(|| url.to_string());
+
+    let mut content = serde_json::json!({
+        "url": url.as_str(),
+        "title": title,
+        "content": page.text,
+        "instructions": "Answer from this page's content and link to the URL when citing it."
+    });
+    if let Some(byline) = &page.byline {
+        content["byline"] = byline.as_str().into();
+    }
+    let content = content.to_string();
+    let mut output = ToolOutput::text(content);
+    output.extra_sources.push(SearchResult {
+        title,
+        snippet: page.text.chars().take(300).collect(),
+        url: url.to_string(),
+        published: None,
+        citation: None,
+        provider: None,
+    });
+    Ok(output)
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+// Replies the chat stream waits on from a separate HTTP request (client tool results,
+// approvals), keyed by (chat id, tool call id).
+
+type ReplyKey = (String, String);
+
+pub struct ReplyBroker<T> {
+    pending: Mutex<HashMap<ReplyKey, oneshot::Sender<T>>>,
+    timeout: Duration,
+}
+
+// A registered wait; dropping it (e.g. when the client disconnects) forgets the call.
+pub struct PendingReply<T> {
+    broker: Arc<ReplyBroker<T>>,
+    key: ReplyKey,
+    rx: Option<oneshot::Receiver<T>>,
+}
+
+impl<T> ReplyBroker<T> {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+            timeout,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // Register before emitting the event that asks for the reply so a fast one cannot be lost.
+    pub fn register(self: &Arc<Self>, chat_id: &str, call_id: &str) -> PendingReply<T> {
+        let (tx, rx) = oneshot::channel();
+        let key = (chat_id.to_string(), call_id.to_string());
+        self.pending.lock().unwrap().insert(key.clone(), tx);
+        PendingReply {
+            broker: self.clone(),
+            key,
+            rx: Some(rx),
+        }
+    }
+
+    // False when nothing is waiting on that call.
+    pub fn resolve(&self, chat_id: &str, call_id: &str, value: T) -> bool {
+        let key = (chat_id.to_string(), call_id.to_string());
+        let Some(tx) = self.pending.lock().unwrap().remove(&key) else {
+            return false;
+        };
+        tx.send(value).is_ok()
+    }
+}
+
+impl<T> PendingReply<T> {
+    // None once the broker's timeout passes without a reply.
+    pub async fn wait(mut self) -> Option<T> {
+        let rx = self.rx.take()?;
+        tokio::time::timeout(self.broker.timeout, rx)
+            .await
+            .ok()
+            .and_then(Result::ok)
+    }
+}
+
+impl<T> Drop for PendingReply<T> {
+    fn drop(&mut self) {
+        self.broker.pending.lock().unwrap().remove(&self.key);
+    }
+}
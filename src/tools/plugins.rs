@@ -0,0 +1,242 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use wasmtime::{
+    Config, Engine, InstancePre, Linker, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+use wasmtime_wasi::WasiCtxBuilder;
+use wasmtime_wasi::p1::WasiP1Ctx;
+use wasmtime_wasi::p2::pipe::{MemoryInputPipe, MemoryOutputPipe};
+
+use super::{Tool, ToolOutput};
+
+// Plugins are WASI preview1 command modules. Each `<plugin>.wasm` in PLUGIN_DIR needs a
+// `<plugin>.json` manifest next to it:
+//   { "name": "...", "description": "...", "parameters": { JSON schema }, "auto": true }
+// On a call the module's `_start` runs with the arguments JSON on stdin; whatever it writes
+// to stdout goes back to the model. Modules get no preopened directories, environment
+// variables, or sockets, and run with bounded memory and wall time.
+
+const EPOCH_TICK: Duration = Duration::from_millis(100);
+
+#[derive(Deserialize)]
+struct PluginManifest {
+    #[serde(default)]
+    name: Option<String>,
+    description: String,
+    #[serde(default = "empty_schema")]
+    parameters: serde_json::Value,
+    // When false the tool is only sent if a request names it in `tools`.
+    #[serde(default = "default_true")]
+    auto: bool,
+}
+
+fn empty_schema() -> serde_json::Value {
+    serde_json::json!({ "type": "object", "properties": {} })
+}
+
+fn default_true() -> bool {
+    true
+}
+
+struct PluginState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+struct Plugin {
+    definition: Tool,
+    pre: InstancePre<PluginState>,
+    auto: bool,
+}
+
+pub struct PluginRegistry {
+    engine: Option<Engine>,
+    plugins: Vec<Plugin>,
+    timeout: Duration,
+    memory_bytes: usize,
+    max_output_bytes: usize,
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    crate::config::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+impl Default for PluginRegistry {
+    fn default() -> Self {
+        Self {
+            engine: None,
+            plugins: Vec::new(),
+            timeout: Duration::from_secs(env_parse("PLUGIN_TIMEOUT_SECS").unwrap_or(10)),
+            memory_bytes: env_parse::<usize>("PLUGIN_MEMORY_MB").unwrap_or(64) * 1024 * 1024,
+            max_output_bytes: env_parse("PLUGIN_MAX_OUTPUT").unwrap_or(64 * 1024),
+        }
+    }
+}
+
+impl PluginRegistry {
+    // Compiles every plugin in PLUGIN_DIR (default data/plugins). Broken plugins are
+    // logged and skipped.
+    pub fn from_env() -> Self {
+        let dir = PathBuf::from(
+            crate::config::var("PLUGIN_DIR").unwrap_or_else(|_| "data/plugins".to_string()),
+        );
+        let mut registry = Self::default();
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return registry;
+        };
+        let mut modules: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "wasm"))
+            .collect();
+        if modules.is_empty() {
+            return registry;
+        }
+        modules.sort();
+
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        let engine = match Engine::new(&config) {
+            Ok(engine) => engine,
+            Err(err) => {
+                log!("plugins disabled: {err}");
+                return registry;
+            }
+        };
+        // Advances the epoch so running plugins hit their deadline.
+        let ticker = engine.clone();
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(EPOCH_TICK);
+                ticker.increment_epoch();
+            }
+        });
+
+        for path in modules {
+            match load_plugin(&engine, &path) {
+                Ok(plugin) => {
+                    log!(
+                        "loaded plugin {} from {}",
+                        plugin.definition.function.name,
+                        path.display()
+                    );
+                    registry.plugins.push(plugin);
+                }
+                Err(err) => log!("skipping plugin {}: {err:#}", path.display()),
+            }
+        }
+        registry.engine = Some(engine);
+        registry
+    }
+
+    // Every registered tool with its `auto` flag (sent to every chat when set).
+    pub fn definitions(&self) -> impl Iterator<Item = (&Tool, bool)> {
+        self.plugins.iter().map(|p| (&p.definition, p.auto))
+    }
+
+    pub fn tool_definition(&self, name: &str) -> Option<Tool> {
+        self.find(name).map(|p| p.definition.clone())
+    }
+
+    pub fn has_tool(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    fn find(&self, name: &str) -> Option<&Plugin> {
+        self.plugins
+            .iter()
+            .find(|p| p.definition.function.name == name)
+    }
+
+    pub async fn call(&self, name: &str, arguments: &str) -> anyhow::Result<ToolOutput> {
+        let (Some(engine), Some(plugin)) = (&self.engine, self.find(name)) else {
+            anyhow::bail!("unknown plugin {name}");
+        };
+        let (engine, pre) = (engine.clone(), plugin.pre.clone());
+        let (name, input) = (name.to_string(), arguments.to_string());
+        let (timeout, memory_bytes, max_output_bytes) =
+            (self.timeout, self.memory_bytes, self.max_output_bytes);
+
+        tokio::task::spawn_blocking(move || {
+            let stdout = MemoryOutputPipe::new(max_output_bytes);
+            let stderr = MemoryOutputPipe::new(4096);
+            let wasi = WasiCtxBuilder::new()
+                .stdin(MemoryInputPipe::new(input))
+                .stdout(stdout.clone())
+                .stderr(stderr.clone())
+                .args(&[name.as_str()])
+                .build_p1();
+            let mut store = Store::new(
+                &engine,
+                PluginState {
+                    wasi,
+                    limits: StoreLimitsBuilder::new()
+                        .memory_size(memory_bytes)
+                        .instances(1)
+                        .build(),
+                },
+            );
+            store.limiter(|state| &mut state.limits);
+            store.set_epoch_deadline((timeout.as_millis() / EPOCH_TICK.as_millis()).max(1) as u64);
+
+            let outcome = pre
+                .instantiate(&mut store)
+                .and_then(|instance| instance.get_typed_func::<(), ()>(&mut store, "_start"))
+                .and_then(|start| start.call(&mut store, ()));
+            let exit_code = match outcome {
+                Ok(()) => 0,
+                Err(err) => match err.downcast_ref::<wasmtime_wasi::I32Exit>() {
+                    Some(exit) => exit.0,
+                    None if err.downcast_ref::<wasmtime::Trap>()
+                        == Some(&wasmtime::Trap::Interrupt) =>
+                    {
+                        anyhow::bail!("plugin {name} timed out after {}s", timeout.as_secs())
+                    }
+                    None => anyhow::bail!("plugin {name} crashed: {err:#}"),
+                },
+            };
+
+            let output = String::from_utf8_lossy(&stdout.contents()).into_owned();
+            if exit_code != 0 {
+                let stderr = String::from_utf8_lossy(&stderr.contents()).into_owned();
+                anyhow::bail!("plugin {name} exited with status {exit_code}: {stderr}");
+            }
+            Ok(ToolOutput::text(output))
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("plugin task failed: {e}"))?
+    }
+}
+
+fn load_plugin(engine: &Engine, path: &Path) -> anyhow::Result<Plugin> {
+    let manifest_path = path.with_extension("json");
+    let manifest: PluginManifest = serde_json::from_str(
+        &std::fs::read_to_string(&manifest_path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", manifest_path.display()))?,
+    )?;
+    let name = manifest.name.unwrap_or_else(|| {
+        path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        anyhow::bail!("invalid tool name {name:?}");
+    }
+
+    let module = Module::from_file(engine, path)?;
+    let mut linker: Linker<PluginState> = Linker::new(engine);
+    wasmtime_wasi::p1::add_to_linker_sync(&mut linker, |state| &mut state.wasi)?;
+    let pre = linker.instantiate_pre(&module)?;
+
+    Ok(Plugin {
+        definition: Tool::function(&name, &manifest.description, manifest.parameters),
+        pre,
+        auto: manifest.auto,
+    })
+}
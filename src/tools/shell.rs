@@ -0,0 +1,217 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{Tool, ToolOutput, code};
+
+pub const TOOL_NAME: &str = "shell";
+
+// ---------- Config ----------
+
+#[derive(Clone, Debug)]
+pub struct ShellConfig {
+    // Allowed command name -> resolved executable path.
+    pub allowlist: HashMap<String, PathBuf>,
+    // Commands run here, and path arguments may not point outside it.
+    pub root: PathBuf,
+    pub timeout: Duration,
+    pub memory_mb: u64,
+    pub max_output_chars: usize,
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    crate::config::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+fn resolve_binary(entry: &str) -> Option<PathBuf> {
+    let path = Path::new(entry);
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(entry))
+        .find(|candidate| candidate.is_file())
+}
+
+impl ShellConfig {
+    // Disabled unless SHELL_TOOL_ALLOWLIST names at least one binary.
+    pub fn from_env() -> Option<Self> {
+        let raw = crate::config::var("SHELL_TOOL_ALLOWLIST").unwrap_or_default();
+        let mut allowlist = HashMap::new();
+        for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let Some(resolved) = resolve_binary(entry) else {
+                log!("shell tool: {entry:?} not found, leaving it out of the allowlist");
+                continue;
+            };
+            let name = Path::new(entry)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| entry.to_string());
+            allowlist.insert(name, resolved);
+        }
+        if allowlist.is_empty() {
+            return None;
+        }
+
+        let root = PathBuf::from(
+            crate::config::var("SHELL_TOOL_ROOT").unwrap_or_else(|_| "data/shell".to_string()),
+        );
+        if let Err(err) = std::fs::create_dir_all(&root) {
+            log!(
+                "shell tool disabled: cannot create {}: {err}",
+                root.display()
+            );
+            return None;
+        }
+        let root = match root.canonicalize() {
+            Ok(root) => root,
+            Err(err) => {
+                log!(
+                    "shell tool disabled: cannot resolve {}: {err}",
+                    root.display()
+                );
+                return None;
+            }
+        };
+
+        Some(Self {
+            allowlist,
+            root,
+            timeout: Duration::from_secs(env_parse("SHELL_TOOL_TIMEOUT_SECS").unwrap_or(15)),
+            memory_mb: env_parse("SHELL_TOOL_MEMORY_MB").unwrap_or(512),
+            max_output_chars: env_parse("SHELL_TOOL_MAX_OUTPUT").unwrap_or(8000),
+        })
+    }
+
+    fn allowed_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.allowlist.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+// ---------- Tool ----------
+
+pub fn tool_definition(config: &ShellConfig) -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        &format!(
+            "Runs one allowlisted command on the host and returns its stdout and stderr. \
+Arguments are passed directly, without a shell: no pipes, redirects, globbing, or variable \
+expansion. Paths are relative to the working directory. Allowed commands: {}.",
+            config.allowed_names().join(", ")
+        ),
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "command": {
+                    "type": "string",
+                    "enum": config.allowed_names(),
+                    "description": "Command to run"
+                },
+                "args": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Arguments, one per item"
+                }
+            },
+            "required": ["command"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct ShellArgs {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+// Every argument may carry a path: whole (`notes.txt`), after `=` (`--file=notes.txt`) or
+// glued to a short option (`-fnotes.txt`, `-xvfnotes.txt`). None of those may be absolute or
+// contain `..`, and each one is resolved against the root, symlinks included, and must stay
+// inside it.
+fn check_arg(root: &Path, arg: &str) -> anyhow::Result<()> {
+    let mut values = vec![arg];
+    if let Some((_, value)) = arg.split_once('=') {
+        values.push(value);
+    }
+    if let Some(options) = arg.strip_prefix('-')
+        && !options.starts_with('-')
+    {
+        values.extend(options.char_indices().skip(1).map(|(i, _)| &options[i..]));
+    }
+    for value in values.into_iter().filter(|v| !v.is_empty()) {
+        if value.contains("..") {
+            anyhow::bail!("argument {arg:?} refers to a parent directory");
+        }
+        if value.starts_with('/') || Path::new(value).is_absolute() {
+            anyhow::bail!("argument {arg:?} is outside the working directory");
+        }
+        // The deepest part that exists, so paths the command is about to create are checked
+        // through their parent directory.
+        let joined = root.join(value);
+        let Some(existing) = joined.ancestors().find(|p| p.exists()) else {
+            continue;
+        };
+        let resolved = existing
+            .canonicalize()
+            .map_err(|e| anyhow::anyhow!("cannot resolve argument {arg:?}: {e}"))?;
+        if !resolved.starts_with(root) {
+            anyhow::bail!("argument {arg:?} is outside the working directory");
+        }
+    }
+    Ok(())
+}
+
+pub async fn run(config: &ShellConfig, arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: ShellArgs =
+        serde_json::from_str(arguments).map_err(|e| anyhow::anyhow!("invalid shell args: {e}"))?;
+    let command = args.command.trim();
+    let Some(binary) = config.allowlist.get(command) else {
+        anyhow::bail!(
+            "command {command:?} is not allowed; allowed commands: {}",
+            config.allowed_names().join(", ")
+        );
+    };
+    for arg in &args.args {
+        check_arg(&config.root, arg)?;
+    }
+
+    let mut cmd = tokio::process::Command::new(binary);
+    cmd.args(&args.args)
+        .current_dir(&config.root)
+        .env_clear()
+        .env("PATH", std::env::var("PATH").unwrap_or_default())
+        .env("HOME", &config.root)
+        .env("LANG", "C.UTF-8")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+
+    let result = code::run_limited(
+        cmd,
+        config.timeout,
+        config.memory_mb,
+        config.max_output_chars,
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("failed to run {command}: {e}"))?;
+
+    Ok(ToolOutput::text(
+        serde_json::json!({
+            "command": command,
+            "args": args.args,
+            "exit_code": result.exit_code,
+            "timed_out": result.timed_out,
+            "stdout": result.stdout,
+            "stderr": result.stderr,
+            "truncated": result.truncated,
+        })
+        .to_string(),
+    ))
+}
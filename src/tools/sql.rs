@@ -0,0 +1,339 @@
+use futures_util::TryStreamExt;
+use serde::Deserialize;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::{AssertSqlSafe, Row, TypeInfo, ValueRef};
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::{Tool, ToolOutput};
+
+pub const TOOL_NAME: &str = "sql_query";
+
+// ---------- Config ----------
+
+#[derive(Clone, Debug)]
+pub enum SqlPool {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
+
+#[derive(Clone, Debug)]
+pub struct SqlToolConfig {
+    pub pool: SqlPool,
+    pub max_rows: usize,
+    pub timeout: Duration,
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    crate::config::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+impl SqlToolConfig {
+    // Disabled unless SQL_TOOL_DSN is set. Connections are opened lazily on first use.
+    pub fn from_env() -> Option<Self> {
+        let dsn = crate::config::var("SQL_TOOL_DSN")
+            .ok()
+            .filter(|d| !d.trim().is_empty())?;
+        let dsn = dsn.trim();
+        let pool = if dsn.starts_with("postgres://") || dsn.starts_with("postgresql://") {
+            match PgPoolOptions::new().max_connections(2).connect_lazy(dsn) {
+                Ok(pool) => SqlPool::Postgres(pool),
+                Err(err) => {
+                    log!("sql tool disabled: invalid SQL_TOOL_DSN: {err}");
+                    return None;
+                }
+            }
+        } else if dsn.starts_with("sqlite:") {
+            // Opened read-only at the driver level, with query_only as a second guard.
+            match SqliteConnectOptions::from_str(dsn) {
+                Ok(options) => SqlPool::Sqlite(
+                    SqlitePoolOptions::new()
+                        .max_connections(2)
+                        .connect_lazy_with(options.read_only(true).pragma("query_only", "ON")),
+                ),
+                Err(err) => {
+                    log!("sql tool disabled: invalid SQL_TOOL_DSN: {err}");
+                    return None;
+                }
+            }
+        } else {
+            log!("sql tool disabled: SQL_TOOL_DSN must be a postgres:// or sqlite: URL");
+            return None;
+        };
+
+        Some(Self {
+            pool,
+            max_rows: env_parse("SQL_TOOL_MAX_ROWS").unwrap_or(100),
+            timeout: Duration::from_secs(env_parse("SQL_TOOL_TIMEOUT_SECS").unwrap_or(10)),
+        })
+    }
+
+    fn dialect(&self) -> &'static str {
+        match self.pool {
+            SqlPool::Postgres(_) => "PostgreSQL",
+            SqlPool::Sqlite(_) => "SQLite",
+        }
+    }
+}
+
+// ---------- Tool ----------
+
+pub fn tool_definition(config: &SqlToolConfig) -> Tool {
+    let schema_hint = match config.pool {
+        SqlPool::Postgres(_) => {
+            "information_schema.tables and information_schema.columns (schema 'public')"
+        }
+        SqlPool::Sqlite(_) => "sqlite_master and pragma_table_info('<table>')",
+    };
+    Tool::function(
+        TOOL_NAME,
+        &format!(
+            "Runs a read-only SQL query against the user's {} database and returns the rows as \
+JSON. Only a single SELECT (or WITH ... SELECT) statement is allowed, and at most {} rows are \
+returned. Look up table and column names first via {schema_hint}.",
+            config.dialect(),
+            config.max_rows
+        ),
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "A single read-only SQL statement"
+                }
+            },
+            "required": ["query"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct SqlQueryArgs {
+    query: String,
+}
+
+// ---------- Statement inspection ----------
+
+const ALLOWED_START: &[&str] = &["SELECT", "WITH", "VALUES", "TABLE"];
+
+// Keywords that write data, change schema or session state, or escape the transaction.
+// `INTO` covers SELECT ... INTO, `UPDATE` also covers SELECT ... FOR UPDATE. Only words used
+// as keywords count: a function call (`replace(...)`) or a qualified name (`t.set`) is fine,
+// and the read-only transaction stops any write that gets through.
+const FORBIDDEN: &str = "INSERT UPDATE DELETE MERGE UPSERT REPLACE DROP ALTER CREATE TRUNCATE \
+GRANT REVOKE COPY ATTACH DETACH PRAGMA VACUUM REINDEX ANALYZE CALL DO LOCK SET RESET COMMIT \
+ROLLBACK BEGIN INTO NOTIFY LISTEN PREPARE EXECUTE DEALLOCATE DISCARD SAVEPOINT RELEASE";
+
+// Returns the statement without a trailing semicolon, or why it was rejected. String
+// literals, quoted identifiers, dollar quotes and comments are skipped so their contents
+// never count as keywords.
+fn check_read_only(sql: &str) -> Result<&str, String> {
+    let bytes = sql.as_bytes();
+    let mut words = Vec::new();
+    let mut end = sql.len();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match c {
+            b'\'' | b'"' | b'`' => {
+                i += 1;
+                while i < bytes.len() {
+                    if bytes[i] == c {
+                        // Doubled quote is an escaped quote.
+                        if bytes.get(i + 1) == Some(&c) {
+                            i += 2;
+                            continue;
+                        }
+                        break;
+                    }
+                    i += 1;
+                }
+                if i >= bytes.len() {
+                    return Err("unterminated quoted string".into());
+                }
+                i += 1;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let Some(close) = sql[i + 2..].find("*/") else {
+                    return Err("unterminated comment".into());
+                };
+                i += close + 4;
+            }
+            b'$' => {
+                // $tag$ ... $tag$ (PostgreSQL); `$1` placeholders are left alone.
+                let tag_len = sql[i + 1..]
+                    .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                    .unwrap_or(sql.len() - i - 1);
+                if bytes.get(i + 1 + tag_len) == Some(&b'$')
+                    && !sql[i + 1..].starts_with(|ch: char| ch.is_ascii_digit())
+                {
+                    let tag = &sql[i..i + tag_len + 2];
+                    let body_start = i + tag.len();
+                    let Some(close) = sql[body_start..].find(tag) else {
+                        return Err("unterminated dollar-quoted string".into());
+                    };
+                    i = body_start + close + tag.len();
+                } else {
+                    i += 1;
+                }
+            }
+            b';' => {
+                if !sql[i + 1..].trim().is_empty() {
+                    return Err("only a single statement is allowed".into());
+                }
+                end = i;
+                break;
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                let start = i;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                let keyword = !sql[..start].trim_end().ends_with('.')
+                    && !sql[i..].trim_start().starts_with('(');
+                words.push((sql[start..i].to_ascii_uppercase(), keyword));
+            }
+            _ => i += 1,
+        }
+    }
+
+    let Some((first, _)) = words.first() else {
+        return Err("query is empty".into());
+    };
+    if !ALLOWED_START.contains(&first.as_str()) {
+        return Err(format!("only SELECT queries are allowed, got {first}"));
+    }
+    if let Some((word, _)) = words
+        .iter()
+        .find(|(w, keyword)| *keyword && FORBIDDEN.split_whitespace().any(|k| k == w.as_str()))
+    {
+        return Err(format!("{word} is not allowed in a read-only query"));
+    }
+    Ok(sql[..end].trim())
+}
+
+// ---------- Execution ----------
+
+pub async fn run(config: &SqlToolConfig, arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: SqlQueryArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid sql_query args: {e}"))?;
+    let query = check_read_only(args.query.trim()).map_err(|e| anyhow::anyhow!(e))?;
+
+    let fetch = async {
+        match &config.pool {
+            SqlPool::Postgres(pool) => query_postgres(pool, query, config).await,
+            SqlPool::Sqlite(pool) => query_sqlite(pool, query, config.max_rows).await,
+        }
+    };
+    let mut rows = tokio::time::timeout(config.timeout, fetch)
+        .await
+        .map_err(|_| anyhow::anyhow!("query timed out after {}s", config.timeout.as_secs()))??;
+    let truncated = rows.len() > config.max_rows;
+    rows.truncate(config.max_rows);
+
+    Ok(ToolOutput::text(
+        serde_json::json!({
+            "query": query,
+            "row_count": rows.len(),
+            "truncated": truncated,
+            "rows": rows,
+        })
+        .to_string(),
+    ))
+}
+
+// Runs inside a READ ONLY transaction that is always rolled back. Wrapping the query in
+// row_to_json lets PostgreSQL serialize every column type, and the LIMIT stops it from
+// producing more rows than we keep. The newline keeps a trailing `--` comment inside.
+async fn query_postgres(
+    pool: &PgPool,
+    query: &str,
+    config: &SqlToolConfig,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(AssertSqlSafe(format!(
+        "SET LOCAL statement_timeout = {}",
+        config.timeout.as_millis()
+    )))
+    .execute(&mut *tx)
+    .await?;
+
+    let wrapped = format!(
+        "SELECT row_to_json(q)::text FROM ({query}\n) AS q LIMIT {}",
+        config.max_rows + 1
+    );
+    let rows = sqlx::query(AssertSqlSafe(wrapped))
+        .fetch_all(&mut *tx)
+        .await;
+    tx.rollback().await?;
+
+    rows?
+        .iter()
+        .map(|row| {
+            let text: String = row.try_get(0)?;
+            Ok(serde_json::from_str(&text)?)
+        })
+        .collect()
+}
+
+async fn query_sqlite(
+    pool: &SqlitePool,
+    query: &str,
+    max_rows: usize,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    let mut stream = sqlx::query(AssertSqlSafe(query.to_string())).fetch(pool);
+    let mut rows = Vec::new();
+    while rows.len() <= max_rows
+        && let Some(row) = stream.try_next().await?
+    {
+        let mut object = serde_json::Map::new();
+        for (i, column) in row.columns().iter().enumerate() {
+            let raw = row.try_get_raw(i)?;
+            // Decode by the value's storage class; SQLite columns are dynamically typed.
+            let value = if raw.is_null() {
+                serde_json::Value::Null
+            } else {
+                match raw.type_info().name() {
+                    "INTEGER" => row.try_get_unchecked::<i64, _>(i)?.into(),
+                    "REAL" => row.try_get_unchecked::<f64, _>(i)?.into(),
+                    "BLOB" => {
+                        format!("<{} bytes>", row.try_get_unchecked::<Vec<u8>, _>(i)?.len()).into()
+                    }
+                    _ => row.try_get_unchecked::<String, _>(i)?.into(),
+                }
+            };
+            object.insert(sqlx::Column::name(column).to_string(), value);
+        }
+        rows.push(serde_json::Value::Object(object));
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_read_only;
+
+    #[test]
+    fn allows_functions_and_columns_named_like_keywords() {
+        let sql = "SELECT replace(name, 'a', 'b'), t.set FROM t";
+        assert_eq!(check_read_only(sql), Ok(sql));
+    }
+
+    #[test]
+    fn rejects_keywords() {
+        assert!(check_read_only("SELECT * INTO copy FROM t").is_err());
+        assert!(check_read_only("WITH d AS (DELETE FROM t RETURNING *) SELECT * FROM d").is_err());
+        assert!(check_read_only("SELECT * FROM t FOR UPDATE").is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use super::{Tool, ToolOutput};
+use crate::SearchResult;
+
+pub const TOOL_NAME: &str = "stackexchange_search";
+
+fn api_url() -> String {
+    crate::config::var("STACKEXCHANGE_API_URL")
+        .unwrap_or_else(|_| "https://api.stackexchange.com/2.3".into())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Searches Stack Overflow (or another Stack Exchange site) and returns matching \
+questions with scores and an excerpt of the accepted or top-voted answer.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "Question keywords, e.g. \"rust borrow checker closure move\""
+                },
+                "site": {
+                    "type": "string",
+                    "description": "Stack Exchange site, e.g. \"stackoverflow\", \"superuser\", \"unix\", \"math\" (default stackoverflow)"
+                },
+                "tagged": {
+                    "type": "string",
+                    "description": "Optional semicolon-separated tags, e.g. \"rust;tokio\""
+                },
+                "max_results": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 8,
+                    "description": "Optional maximum number of questions to return (default 4)"
+                }
+            },
+            "required": ["query"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct StackExchangeArgs {
+    query: String,
+    #[serde(default)]
+    site: Option<String>,
+    #[serde(default)]
+    tagged: Option<String>,
+    #[serde(default)]
+    max_results: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    #[serde(default = "Vec::new")]
+    items: Vec<T>,
+    #[serde(default)]
+    error_message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Question {
+    question_id: u64,
+    title: String,
+    link: String,
+    score: i64,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    is_answered: bool,
+    #[serde(default)]
+    answer_count: u64,
+    #[serde(default)]
+    accepted_answer_id: Option<u64>,
+    #[serde(default)]
+    creation_date: Option<i64>,
+    #[serde(default)]
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct Answer {
+    answer_id: u64,
+    question_id: u64,
+    score: i64,
+    #[serde(default)]
+    is_accepted: bool,
+    #[serde(default)]
+    body: String,
+}
+
+// Bodies come back as HTML; keep the text (including code) and collapse whitespace.
+fn html_to_text(html: &str, max_chars: usize) -> String {
+    let fragment = scraper::Html::parse_fragment(html);
+    let text = fragment.root_element().text().collect::<Vec<_>>().join(" ");
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.chars().count() > max_chars {
+        let cut: String = collapsed.chars().take(max_chars).collect();
+        format!("{cut}…")
+    } else {
+        collapsed
+    }
+}
+
+async fn get<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    path: &str,
+    query: &[(&str, &str)],
+) -> anyhow::Result<Vec<T>> {
+    let mut request = client.get(format!("{}{path}", api_url())).query(query);
+    // An app key raises the daily quota from 300 to 10k requests per IP.
+    if let Ok(key) = crate::config::var("STACKEXCHANGE_KEY") {
+        request = request.query(&[("key", key)]);
+    }
+    let resp = request.send().await?;
+    let status = resp.status();
+    let parsed: ApiResponse<T> = resp.json().await?;
+    if let Some(message) = parsed.error_message {
+        anyhow::bail!("stackexchange api error {}: {}", status, message);
+    }
+    Ok(parsed.items)
+}
+
+pub async fn run(arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: StackExchangeArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid stackexchange_search args: {e}"))?;
+    let query = args.query.trim();
+    if query.is_empty() {
+        anyhow::bail!("search query missing");
+    }
+    let site = args
+        .site
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .unwrap_or("stackoverflow");
+    let limit = args.max_results.unwrap_or(4).clamp(1, 8).to_string();
+
+    let client = reqwest::Client::new();
+    let mut search_query = vec![
+        ("q", query),
+        ("site", site),
+        ("order", "desc"),
+        ("sort", "relevance"),
+        ("pagesize", limit.as_str()),
+        ("filter", "withbody"),
+    ];
+    if let Some(tagged) = args.tagged.as_deref().filter(|t| !t.trim().is_empty()) {
+        search_query.push(("tagged", tagged));
+    }
+    let questions: Vec<Question> = get(&client, "/search/advanced", &search_query).await?;
+
+    // One request for all answers; pick the accepted one, else the top-voted.
+    let mut best_answers: HashMap<u64, Answer> = HashMap::new();
+    let ids = questions
+        .iter()
+        .filter(|q| q.answer_count > 0)
+        .map(|q| q.question_id.to_string())
+        .collect::<Vec<_>>()
+        .join(";");
+    if !ids.is_empty() {
+        let answers: Vec<Answer> = get(
+            &client,
+            &format!("/questions/{ids}/answers"),
+            &[
+                ("site", site),
+                ("order", "desc"),
+                ("sort", "votes"),
+                ("pagesize", "100"),
+                ("filter", "withbody"),
+            ],
+        )
+        .await?;
+        for answer in answers {
+            let replace = match best_answers.get(&answer.question_id) {
+                None => true,
+                Some(best) => {
+                    !best.is_accepted && (answer.is_accepted || answer.score > best.score)
+                }
+            };
+            if replace {
+                best_answers.insert(answer.question_id, answer);
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut sources = Vec::new();
+    for (i, question) in questions.iter().enumerate() {
+        let title = html_to_text(&question.title, 300);
+        let answer = best_answers.get(&question.question_id);
+        let answer_text = answer.map(|a| html_to_text(&a.body, 1500));
+        entries.push(serde_json::json!({
+            "id": i + 1,
+            "title": title,
+            "score": question.score,
+            "tags": question.tags,
+            "answered": question.is_answered,
+            "answer_count": question.answer_count,
+            "question": html_to_text(&question.body, 500),
+            "answer": answer.map(|a| serde_json::json!({
+                "accepted": a.is_accepted || question.accepted_answer_id == Some(a.answer_id),
+                "score": a.score,
+                "excerpt": answer_text,
+            })),
+            "url": question.link,
+        }));
+        sources.push(SearchResult {
+            title,
+            snippet: answer_text.unwrap_or_else(|| html_to_text(&question.body, 300)),
+            url: question.link.clone(),
+            published: question
+                .creation_date
+                .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+                .map(|d| d.format("%Y-%m-%d").to_string()),
+            citation: None,
+            provider: None,
+        });
+    }
+
+    let content = serde_json::json!({
+        "query": query,
+        "site": site,
+        "results": entries,
+        "instructions": "Cite questions like [id]. Prefer accepted and high-scoring answers, and note when an answer may be outdated."
+    })
+    .to_string();
+    let mut output = ToolOutput::text(content);
+    output.sources = Some(sources);
+    Ok(output)
+}
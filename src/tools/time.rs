@@ -0,0 +1,67 @@
+use chrono::{DateTime, Local, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use super::{Tool, ToolOutput};
+
+pub const TOOL_NAME: &str = "current_time";
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Returns the current date and time. Call this whenever the answer depends on today's \
+date, the day of the week, or how long ago something happened.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "timezone": {
+                    "type": "string",
+                    "description": "Optional IANA timezone, e.g. \"Europe/Berlin\" or \"America/New_York\" (default: server time)"
+                }
+            }
+        }),
+    )
+}
+
+#[derive(Deserialize, Default)]
+struct CurrentTimeArgs {
+    #[serde(default)]
+    timezone: Option<String>,
+}
+
+fn describe<T: TimeZone>(now: DateTime<T>, timezone: &str) -> serde_json::Value
+where
+    T::Offset: std::fmt::Display,
+{
+    serde_json::json!({
+        "timezone": timezone,
+        "iso8601": now.to_rfc3339_opts(SecondsFormat::Secs, false),
+        "date": now.format("%Y-%m-%d").to_string(),
+        "time": now.format("%H:%M:%S").to_string(),
+        "weekday": now.format("%A").to_string(),
+        "utc_offset": now.format("%:z").to_string(),
+        "unix": now.timestamp(),
+    })
+}
+
+pub fn run(arguments: &str) -> anyhow::Result<ToolOutput> {
+    // Models often send "" or "{}" for tools without required params.
+    let args: CurrentTimeArgs = if arguments.trim().is_empty() {
+        CurrentTimeArgs::default()
+    } else {
+        serde_json::from_str(arguments)
+            .map_err(|e| anyhow::anyhow!("invalid current_time args: {e}"))?
+    };
+
+    let now = Utc::now();
+    let payload = match args.timezone.as_deref().map(str::trim) {
+        None | Some("") => describe(now.with_timezone(&Local), "server local time"),
+        Some(name) => {
+            let tz: Tz = name.parse().map_err(|_| {
+                anyhow::anyhow!("unknown timezone {name:?}; use an IANA name like Europe/London")
+            })?;
+            describe(now.with_timezone(&tz), tz.name())
+        }
+    };
+    Ok(ToolOutput::text(payload.to_string()))
+}
@@ -0,0 +1,247 @@
+use serde::Deserialize;
+
+use super::{Tool, ToolOutput};
+
+pub const TOOL_NAME: &str = "get_weather";
+
+// Open-Meteo is free and keyless; both URLs can point at a self-hosted instance.
+fn forecast_url() -> String {
+    crate::config::var("OPEN_METEO_URL")
+        .unwrap_or_else(|_| "https://api.open-meteo.com".into())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn geocoding_url() -> String {
+    crate::config::var("OPEN_METEO_GEOCODING_URL")
+        .unwrap_or_else(|_| "https://geocoding-api.open-meteo.com".into())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Gets current weather conditions and a daily forecast for a place.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "location": {
+                    "type": "string",
+                    "description": "City or place name, optionally with region or country, e.g. \"Portland, Oregon\""
+                },
+                "days": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 7,
+                    "description": "Number of forecast days including today (default 3)"
+                },
+                "units": {
+                    "type": "string",
+                    "enum": ["metric", "imperial"],
+                    "description": "Unit system (default metric)"
+                }
+            },
+            "required": ["location"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct WeatherArgs {
+    location: String,
+    #[serde(default)]
+    days: Option<u32>,
+    #[serde(default)]
+    units: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GeocodingResponse {
+    #[serde(default)]
+    results: Vec<Place>,
+}
+
+#[derive(Deserialize)]
+struct Place {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    #[serde(default)]
+    country: Option<String>,
+    #[serde(default)]
+    admin1: Option<String>,
+}
+
+pub async fn run(arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: WeatherArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid get_weather args: {e}"))?;
+    let location = args.location.trim();
+    if location.is_empty() {
+        anyhow::bail!("location missing");
+    }
+    let days = args.days.unwrap_or(3).clamp(1, 7);
+    let imperial = args.units.as_deref() == Some("imperial");
+
+    let client = reqwest::Client::new();
+    let place = geocode(&client, location).await?;
+    let forecast = fetch_forecast(&client, &place, days, imperial).await?;
+
+    let mut output = ToolOutput::text(forecast.to_string());
+    output.events.push(("weather", forecast));
+    Ok(output)
+}
+
+// Open-Meteo matches on the place name only, so "Paris, Texas" searches "Paris"
+// and uses the rest to pick between candidates.
+async fn geocode(client: &reqwest::Client, location: &str) -> anyhow::Result<Place> {
+    let (name, qualifier) = match location.split_once(',') {
+        Some((name, rest)) => (name.trim(), rest.trim().to_lowercase()),
+        None => (location, String::new()),
+    };
+
+    let resp = client
+        .get(format!("{}/v1/search", geocoding_url()))
+        .query(&[("name", name), ("count", "10"), ("format", "json")])
+        .send()
+        .await?
+        .error_for_status()?;
+    let parsed: GeocodingResponse = resp.json().await?;
+
+    let mut places = parsed.results;
+    if places.is_empty() {
+        anyhow::bail!("no place found for {location:?}");
+    }
+    let matches_qualifier = |p: &Place| {
+        [p.country.as_deref(), p.admin1.as_deref()]
+            .into_iter()
+            .flatten()
+            .any(|field| {
+                let field = field.to_lowercase();
+                field.contains(&qualifier) || qualifier.contains(&field)
+            })
+    };
+    let index = if qualifier.is_empty() {
+        0
+    } else {
+        places.iter().position(matches_qualifier).unwrap_or(0)
+    };
+    Ok(places.swap_remove(index))
+}
+
+async fn fetch_forecast(
+    client: &reqwest::Client,
+    place: &Place,
+    days: u32,
+    imperial: bool,
+) -> anyhow::Result<serde_json::Value> {
+    let mut query = vec![
+        ("latitude", place.latitude.to_string()),
+        ("longitude", place.longitude.to_string()),
+        (
+            "current",
+            "temperature_2m,apparent_temperature,relative_humidity_2m,precipitation,weather_code,wind_speed_10m,is_day".into(),
+        ),
+        (
+            "daily",
+            "weather_code,temperature_2m_max,temperature_2m_min,precipitation_sum,precipitation_probability_max,wind_speed_10m_max".into(),
+        ),
+        ("timezone", "auto".into()),
+        ("forecast_days", days.to_string()),
+    ];
+    if imperial {
+        query.push(("temperature_unit", "fahrenheit".into()));
+        query.push(("wind_speed_unit", "mph".into()));
+        query.push(("precipitation_unit", "inch".into()));
+    }
+
+    let resp = client
+        .get(format!("{}/v1/forecast", forecast_url()))
+        .query(&query)
+        .send()
+        .await?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!("weather backend error {}: {}", status, body);
+    }
+    let raw: serde_json::Value = resp.json().await?;
+
+    let current = &raw["current"];
+    let daily = &raw["daily"];
+    let day_count = daily["time"].as_array().map_or(0, Vec::len);
+    let forecast: Vec<serde_json::Value> = (0..day_count)
+        .map(|i| {
+            serde_json::json!({
+                "date": daily["time"][i],
+                "conditions": describe_code(daily["weather_code"][i].as_u64()),
+                "weather_code": daily["weather_code"][i],
+                "temperature_max": daily["temperature_2m_max"][i],
+                "temperature_min": daily["temperature_2m_min"][i],
+                "precipitation": daily["precipitation_sum"][i],
+                "precipitation_probability": daily["precipitation_probability_max"][i],
+                "wind_speed_max": daily["wind_speed_10m_max"][i],
+            })
+        })
+        .collect();
+
+    let location = [
+        Some(place.name.as_str()),
+        place.admin1.as_deref(),
+        place.country.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(", ");
+
+    Ok(serde_json::json!({
+        "location": location,
+        "latitude": place.latitude,
+        "longitude": place.longitude,
+        "timezone": raw["timezone"],
+        "units": {
+            "temperature": if imperial { "°F" } else { "°C" },
+            "wind_speed": if imperial { "mph" } else { "km/h" },
+            "precipitation": if imperial { "in" } else { "mm" },
+        },
+        "current": {
+            "time": current["time"],
+            "conditions": describe_code(current["weather_code"].as_u64()),
+            "weather_code": current["weather_code"],
+            "is_day": current["is_day"].as_u64() == Some(1),
+            "temperature": current["temperature_2m"],
+            "apparent_temperature": current["apparent_temperature"],
+            "humidity": current["relative_humidity_2m"],
+            "precipitation": current["precipitation"],
+            "wind_speed": current["wind_speed_10m"],
+        },
+        "daily": forecast,
+    }))
+}
+
+// WMO weather interpretation codes as used by Open-Meteo.
+fn describe_code(code: Option<u64>) -> &'static str {
+    match code {
+        Some(0) => "clear sky",
+        Some(1) => "mainly clear",
+        Some(2) => "partly cloudy",
+        Some(3) => "overcast",
+        Some(45 | 48) => "fog",
+        Some(51 | 53 | 55) => "drizzle",
+        Some(56 | 57) => "freezing drizzle",
+        Some(61) => "light rain",
+        Some(63) => "rain",
+        Some(65) => "heavy rain",
+        Some(66 | 67) => "freezing rain",
+        Some(71) => "light snow",
+        Some(73) => "snow",
+        Some(75) => "heavy snow",
+        Some(77) => "snow grains",
+        Some(80..=82) => "rain showers",
+        Some(85 | 86) => "snow showers",
+        Some(95) => "thunderstorm",
+        Some(96 | 99) => "thunderstorm with hail",
+        _ => "unknown",
+    }
+}
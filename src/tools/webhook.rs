@@ -0,0 +1,207 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::{Tool, ToolOutput};
+
+// Tools defined in HTTP_TOOLS_CONFIG (default data/http_tools.json) as a JSON array:
+//   [{ "name": "create_ticket", "description": "...", "parameters": { JSON schema },
+//      "url": "https://example.com/tickets", "method": "POST",
+//      "headers": { "Authorization": "Bearer {{env.TICKET_TOKEN}}" } }]
+// Calls send the arguments as a JSON body (or as query parameters for GET/DELETE) and hand
+// the response body back to the model. `{{args.<field>}}` and `{{env.<VAR>}}` are expanded
+// in the URL and header values.
+
+const MAX_RESPONSE_CHARS: usize = 20_000;
+
+#[derive(Deserialize)]
+struct WebhookToolConfig {
+    name: String,
+    description: String,
+    #[serde(default = "empty_schema")]
+    parameters: serde_json::Value,
+    url: String,
+    #[serde(default = "default_method")]
+    method: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+    // When false the tool is only sent if a request names it in `tools`.
+    #[serde(default = "default_true")]
+    auto: bool,
+}
+
+fn empty_schema() -> serde_json::Value {
+    serde_json::json!({ "type": "object", "properties": {} })
+}
+
+fn default_method() -> String {
+    "POST".into()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+struct WebhookTool {
+    definition: Tool,
+    method: reqwest::Method,
+    url: String,
+    headers: HashMap<String, String>,
+    timeout: Duration,
+    auto: bool,
+}
+
+#[derive(Default)]
+pub struct WebhookRegistry {
+    tools: Vec<WebhookTool>,
+}
+
+impl WebhookRegistry {
+    pub fn from_env() -> Self {
+        let path = crate::config::var("HTTP_TOOLS_CONFIG")
+            .unwrap_or_else(|_| "data/http_tools.json".into());
+        let Ok(raw) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        let configs: Vec<WebhookToolConfig> = match serde_json::from_str(&raw) {
+            Ok(configs) => configs,
+            Err(err) => {
+                log!("failed to parse {path}: {err}");
+                return Self::default();
+            }
+        };
+
+        let mut registry = Self::default();
+        for config in configs {
+            let Ok(method) = config
+                .method
+                .to_ascii_uppercase()
+                .parse::<reqwest::Method>()
+            else {
+                log!(
+                    "skipping http tool {}: invalid method {:?}",
+                    config.name,
+                    config.method
+                );
+                continue;
+            };
+            if !config.url.starts_with("http://") && !config.url.starts_with("https://") {
+                log!("skipping http tool {}: url must be http(s)", config.name);
+                continue;
+            }
+            registry.tools.push(WebhookTool {
+                definition: Tool::function(&config.name, &config.description, config.parameters),
+                method,
+                url: config.url,
+                headers: config.headers,
+                timeout: Duration::from_secs(config.timeout_secs.unwrap_or(30)),
+                auto: config.auto,
+            });
+        }
+        if !registry.tools.is_empty() {
+            log!("loaded {} http tools from {path}", registry.tools.len());
+        }
+        registry
+    }
+
+    // Every registered tool with its `auto` flag (sent to every chat when set).
+    pub fn definitions(&self) -> impl Iterator<Item = (&Tool, bool)> {
+        self.tools.iter().map(|t| (&t.definition, t.auto))
+    }
+
+    pub fn tool_definition(&self, name: &str) -> Option<Tool> {
+        self.find(name).map(|t| t.definition.clone())
+    }
+
+    pub fn has_tool(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    fn find(&self, name: &str) -> Option<&WebhookTool> {
+        self.tools
+            .iter()
+            .find(|t| t.definition.function.name == name)
+    }
+
+    pub async fn call(&self, name: &str, arguments: &str) -> anyhow::Result<ToolOutput> {
+        let tool = self
+            .find(name)
+            .ok_or_else(|| anyhow::anyhow!("unknown http tool {name}"))?;
+        let args: serde_json::Value = if arguments.trim().is_empty() {
+            serde_json::json!({})
+        } else {
+            serde_json::from_str(arguments)
+                .map_err(|e| anyhow::anyhow!("invalid {name} args: {e}"))?
+        };
+
+        let url = expand(&tool.url, &args, true);
+        let mut request = reqwest::Client::new()
+            .request(tool.method.clone(), &url)
+            .timeout(tool.timeout);
+        for (key, value) in &tool.headers {
+            request = request.header(key, expand(value, &args, false));
+        }
+        request = if matches!(tool.method, reqwest::Method::GET | reqwest::Method::DELETE) {
+            let query: Vec<(String, String)> = args
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(k, v)| (k.clone(), value_text(v)))
+                .collect();
+            request.query(&query)
+        } else {
+            request.json(&args)
+        };
+
+        let resp = request.send().await?;
+        let status = resp.status();
+        let body = resp.text().await?;
+        let mut body: String = body.chars().take(MAX_RESPONSE_CHARS).collect();
+        if !status.is_success() {
+            anyhow::bail!("{name} returned {status}: {body}");
+        }
+        if body.trim().is_empty() {
+            body = serde_json::json!({ "status": status.as_u16() }).to_string();
+        }
+        Ok(ToolOutput::text(body))
+    }
+}
+
+fn value_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+// Expands `{{args.field}}` and `{{env.VAR}}`; unknown placeholders become empty.
+fn expand(template: &str, args: &serde_json::Value, url_encode: bool) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let key = rest[start + 2..start + end].trim();
+        let value = if let Some(field) = key.strip_prefix("args.") {
+            args.get(field).map(value_text).unwrap_or_default()
+        } else if let Some(var) = key.strip_prefix("env.") {
+            crate::config::var(var).unwrap_or_default()
+        } else {
+            String::new()
+        };
+        if url_encode && key.starts_with("args.") {
+            out.push_str(&urlencoding::encode(&value));
+        } else {
+            out.push_str(&value);
+        }
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,216 @@
+use serde::Deserialize;
+
+use super::{Tool, ToolContext, ToolOutput, format_search_results_for_tool};
+use crate::{AppState, SearchResult};
+
+pub const TOOL_NAME: &str = "wikipedia";
+
+const MAX_EXTRACT_CHARS: usize = 6000;
+
+// WIKIPEDIA_API_URL overrides the endpoint entirely (mirrors, other MediaWiki sites).
+fn api_url(language: &str) -> String {
+    crate::config::var("WIKIPEDIA_API_URL")
+        .ok()
+        .filter(|u| !u.trim().is_empty())
+        .unwrap_or_else(|| format!("https://{language}.wikipedia.org/w/api.php"))
+}
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Looks up a topic on Wikipedia and returns the article summary. Prefer this over web \
+search for encyclopedic facts about people, places, events, and concepts.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "topic": {
+                    "type": "string",
+                    "description": "Article title or search terms"
+                },
+                "full_article": {
+                    "type": "boolean",
+                    "description": "Return the start of the full article instead of just the introduction (default false)"
+                },
+                "language": {
+                    "type": "string",
+                    "description": "Wikipedia language code, e.g. \"de\" (defaults to the chat language, else server config, usually \"en\")"
+                }
+            },
+            "required": ["topic"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct WikipediaArgs {
+    topic: String,
+    #[serde(default)]
+    full_article: bool,
+    #[serde(default)]
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    query: SearchQuery,
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    search: Vec<SearchHit>,
+}
+
+#[derive(Deserialize)]
+struct SearchHit {
+    title: String,
+    #[serde(default)]
+    snippet: String,
+}
+
+#[derive(Deserialize)]
+struct ExtractResponse {
+    query: ExtractQuery,
+}
+
+#[derive(Deserialize)]
+struct ExtractQuery {
+    #[serde(default)]
+    pages: Vec<ExtractPage>,
+}
+
+#[derive(Deserialize)]
+struct ExtractPage {
+    title: String,
+    #[serde(default)]
+    extract: String,
+    #[serde(default)]
+    fullurl: Option<String>,
+}
+
+pub async fn run(
+    state: &AppState,
+    ctx: &ToolContext,
+    arguments: &str,
+) -> anyhow::Result<ToolOutput> {
+    let args: WikipediaArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid wikipedia args: {e}"))?;
+    let topic = args.topic.trim();
+    if topic.is_empty() {
+        anyhow::bail!("topic missing");
+    }
+    let language = args
+        .language
+        .filter(|l| !l.is_empty() && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        .or_else(|| {
+            ctx.language
+                .as_deref()
+                .map(|l| crate::locale::primary(l).to_lowercase())
+        })
+        .or_else(|| crate::config::var("WIKIPEDIA_LANGUAGE").ok())
+        .unwrap_or_else(|| "en".into());
+    let url = api_url(&language);
+
+    // Wikimedia rejects requests without a descriptive User-Agent.
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("chat-llama/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+
+    let search: SearchResponse = client
+        .get(&url)
+        .query(&[
+            ("action", "query"),
+            ("list", "search"),
+            ("srsearch", topic),
+            ("srlimit", "3"),
+            ("format", "json"),
+            ("formatversion", "2"),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let Some(best) = search.query.search.first() else {
+        anyhow::bail!("no Wikipedia article found for {topic:?}");
+    };
+
+    let mut query = vec![
+        ("action", "query"),
+        ("prop", "extracts|info"),
+        ("inprop", "url"),
+        ("explaintext", "1"),
+        ("redirects", "1"),
+        ("titles", best.title.as_str()),
+        ("format", "json"),
+        ("formatversion", "2"),
+    ];
+    if !args.full_article {
+        query.push(("exintro", "1"));
+    }
+    let extract: ExtractResponse = client
+        .get(&url)
+        .query(&query)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let Some(page) = extract.query.pages.into_iter().next() else {
+        anyhow::bail!("Wikipedia returned no page for {:?}", best.title);
+    };
+
+    let page_url = page.fullurl.clone().unwrap_or_else(|| {
+        format!(
+            "https://{language}.wikipedia.org/wiki/{}",
+            urlencoding::encode(&page.title.replace(' ', "_"))
+        )
+    });
+    let mut results = vec![SearchResult {
+        title: page.title.clone(),
+        snippet: page
+            .extract
+            .trim()
+            .chars()
+            .take(MAX_EXTRACT_CHARS)
+            .collect(),
+        url: page_url,
+        published: None,
+        citation: None,
+        provider: None,
+    }];
+    // Other matches let the model notice ambiguous topics.
+    for hit in search.query.search.iter().skip(1) {
+        results.push(SearchResult {
+            title: hit.title.clone(),
+            snippet: strip_search_markup(&hit.snippet),
+            url: format!(
+                "https://{language}.wikipedia.org/wiki/{}",
+                urlencoding::encode(&hit.title.replace(' ', "_"))
+            ),
+            published: None,
+            citation: None,
+            provider: None,
+        });
+    }
+
+    let mut output =
+        ToolOutput::text(format_search_results_for_tool(state, ctx, &results, topic).await);
+    output.sources = Some(results);
+    Ok(output)
+}
+
+// Search snippets come with <span class="searchmatch"> highlighting.
+fn strip_search_markup(snippet: &str) -> String {
+    let mut out = String::with_capacity(snippet.len());
+    let mut in_tag = false;
+    for c in snippet.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&quot;", "\"").replace("&amp;", "&")
+}
@@ -0,0 +1,304 @@
+use serde::Deserialize;
+
+use super::{Tool, ToolOutput};
+use crate::SearchResult;
+
+pub const TOOL_NAME: &str = "youtube_transcript";
+
+// Characters per transcript part; long videos are read one part per call.
+fn chunk_chars() -> usize {
+    crate::config::var("YOUTUBE_TRANSCRIPT_CHUNK_CHARS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n >= 1000)
+        .unwrap_or(12_000)
+}
+
+fn base_url() -> String {
+    crate::config::var("YOUTUBE_BASE_URL")
+        .unwrap_or_else(|_| "https://www.youtube.com".into())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+pub fn tool_definition() -> Tool {
+    Tool::function(
+        TOOL_NAME,
+        "Fetches the captions of a YouTube video as a timestamped transcript. Long transcripts \
+are split into parts; the result says how many parts exist, and you can request the next one \
+with `part`.",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "video": {
+                    "type": "string",
+                    "description": "YouTube video URL or 11-character video id"
+                },
+                "part": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Which part of the transcript to return (default 1)"
+                },
+                "language": {
+                    "type": "string",
+                    "description": "Preferred caption language code (default \"en\")"
+                }
+            },
+            "required": ["video"]
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+struct TranscriptArgs {
+    video: String,
+    #[serde(default)]
+    part: Option<usize>,
+    #[serde(default)]
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CaptionTrack {
+    base_url: String,
+    #[serde(default)]
+    language_code: String,
+    #[serde(default)]
+    kind: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct VideoDetails {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    length_seconds: Option<String>,
+}
+
+fn video_id(input: &str) -> Option<String> {
+    let input = input.trim();
+    let is_id = |s: &str| {
+        s.len() == 11
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+    if is_id(input) {
+        return Some(input.to_string());
+    }
+
+    let url = reqwest::Url::parse(input).ok()?;
+    let host = url
+        .host_str()?
+        .trim_start_matches("www.")
+        .trim_start_matches("m.");
+    let candidate = match host {
+        "youtu.be" => url.path_segments()?.next()?.to_string(),
+        "youtube.com" | "music.youtube.com" | "youtube-nocookie.com" => {
+            let mut segments = url.path_segments()?;
+            match segments.next()? {
+                "watch" => url
+                    .query_pairs()
+                    .find(|(k, _)| k == "v")
+                    .map(|(_, v)| v.into_owned())?,
+                "shorts" | "embed" | "live" | "v" => segments.next()?.to_string(),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    is_id(&candidate).then_some(candidate)
+}
+
+// Parses the JSON value that follows `"key":` somewhere in the watch page.
+fn embedded_json<T: serde::de::DeserializeOwned>(html: &str, key: &str) -> Option<T> {
+    let start = html.find(&format!("\"{key}\":"))? + key.len() + 3;
+    serde_json::Deserializer::from_str(&html[start..])
+        .into_iter::<T>()
+        .next()?
+        .ok()
+}
+
+pub async fn run(arguments: &str) -> anyhow::Result<ToolOutput> {
+    let args: TranscriptArgs = serde_json::from_str(arguments)
+        .map_err(|e| anyhow::anyhow!("invalid youtube_transcript args: {e}"))?;
+    let Some(id) = video_id(&args.video) else {
+        anyhow::bail!("not a YouTube video URL or id: {:?}", args.video);
+    };
+    let language = args.language.unwrap_or_else(|| "en".into());
+
+    let client = reqwest::Client::builder()
+        .user_agent(
+            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) \
+Chrome/123.0.0.0 Safari/537.36",
+        )
+        .build()?;
+    let watch_url = format!("{}/watch?v={id}", base_url());
+    let html = client
+        .get(&watch_url)
+        .header("Accept-Language", "en-US,en;q=0.8")
+        // Skips the EU cookie consent interstitial.
+        .header("Cookie", "CONSENT=YES+1")
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let details: VideoDetails = embedded_json(&html, "videoDetails").unwrap_or_default();
+    let tracks: Vec<CaptionTrack> = embedded_json(&html, "captionTracks").unwrap_or_default();
+    if tracks.is_empty() {
+        anyhow::bail!("video {id} has no captions available");
+    }
+
+    // Prefer human captions in the requested language, then auto-generated ones.
+    let lang_matches = |t: &&CaptionTrack| {
+        t.language_code == language || t.language_code.starts_with(&format!("{language}-"))
+    };
+    let is_asr = |t: &&CaptionTrack| t.kind.as_deref() == Some("asr");
+    let track = tracks
+        .iter()
+        .find(|t| lang_matches(t) && !is_asr(t))
+        .or_else(|| tracks.iter().find(|t| lang_matches(t)))
+        .or_else(|| tracks.iter().find(|t| !is_asr(t)))
+        .unwrap_or(&tracks[0]);
+
+    let xml = client
+        .get(&track.base_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let lines = parse_timedtext(&xml)?;
+    if lines.is_empty() {
+        anyhow::bail!("captions for video {id} are empty");
+    }
+
+    let parts = split_parts(&lines, chunk_chars());
+    let part = args.part.unwrap_or(1).clamp(1, parts.len());
+    let text = &parts[part - 1];
+
+    let url = format!("https://www.youtube.com/watch?v={id}");
+    let title = if details.title.is_empty() {
+        url.clone()
+    } else {
+        details.title.clone()
+    };
+    let mut payload = serde_json::json!({
+        "video_id": id,
+        "title": title,
+        "channel": details.author,
+        "url": url,
+        "caption_language": track.language_code,
+        "auto_generated": is_asr(&track),
+        "part": part,
+        "total_parts": parts.len(),
+        "transcript": text,
+    });
+    if let Some(seconds) = details.length_seconds.and_then(|s| s.parse::<u64>().ok()) {
+        payload["duration"] = format_timestamp(seconds as f64).into();
+    }
+    if part < parts.len() {
+        payload["instructions"] = format!(
+            "This is part {part} of {}. Call {TOOL_NAME} again with part={} if you need the rest.",
+            parts.len(),
+            part + 1
+        )
+        .into();
+    }
+
+    let mut output = ToolOutput::text(payload.to_string());
+    output.sources = Some(vec![SearchResult {
+        title,
+        snippet: parts[0].chars().take(300).collect(),
+        url,
+        published: None,
+        citation: None,
+        provider: None,
+    }]);
+    Ok(output)
+}
+
+fn format_timestamp(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    let (h, m, s) = (total / 3600, (total % 3600) / 60, total % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
+
+// Groups caption lines into parts of at most `max_chars`, never splitting a line.
+fn split_parts(lines: &[(f64, String)], max_chars: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for (start, text) in lines {
+        let line = format!("[{}] {}\n", format_timestamp(*start), text);
+        if !current.is_empty() && current.len() + line.len() > max_chars {
+            parts.push(std::mem::take(&mut current));
+        }
+        current.push_str(&line);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+// Handles both the legacy `<text start="s">` and srv3 `<p t="ms">` timedtext formats.
+fn parse_timedtext(xml: &str) -> anyhow::Result<Vec<(f64, String)>> {
+    use quick_xml::events::Event as XmlEvent;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut lines = Vec::new();
+    let mut current: Option<(f64, String)> = None;
+
+    loop {
+        match reader.read_event()? {
+            XmlEvent::Start(e) => {
+                let attr = |key: &[u8]| {
+                    e.attributes()
+                        .flatten()
+                        .find(|a| a.key.as_ref() == key)
+                        .and_then(|a| a.unescape_value().ok())
+                        .and_then(|v| v.parse::<f64>().ok())
+                };
+                match e.local_name().as_ref() {
+                    b"text" => current = Some((attr(b"start").unwrap_or(0.0), String::new())),
+                    b"p" => {
+                        current = Some((attr(b"t").unwrap_or(0.0) / 1000.0, String::new()));
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::Text(t) => {
+                if let Some((_, text)) = current.as_mut() {
+                    text.push_str(&t.unescape()?);
+                }
+            }
+            XmlEvent::End(e) if matches!(e.local_name().as_ref(), b"text" | b"p") => {
+                if let Some((start, text)) = current.take() {
+                    // Caption text is HTML-escaped a second time inside the XML.
+                    let cleaned = text
+                        .replace("&#39;", "'")
+                        .replace("&quot;", "\"")
+                        .replace("&lt;", "<")
+                        .replace("&gt;", ">")
+                        .replace("&amp;", "&");
+                    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if !cleaned.is_empty() {
+                        lines.push((start, cleaned));
+                    }
+                }
+            }
+            XmlEvent::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(lines)
+}
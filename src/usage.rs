@@ -0,0 +1,437 @@
+use axum::{
+    Extension, Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tokio::io::AsyncWriteExt;
+
+use crate::AppState;
+use crate::rbac::Role;
+use crate::users::Identity;
+
+// Who and what is using the GPU. Every chat request appends one line to USAGE_LOG (default
+// data/usage.jsonl; empty keeps the log in memory only) with its user, conversation, model,
+// prompt and completion tokens over all rounds, tool calls and wall time. Token counts come
+// from llama-server's `usage`, or its `timings` where usage is not sent. GET /api/usage sums
+// them over a date range, optionally grouped.
+//
+// For traffic routed to paid upstreams, MODEL_PRICING (default data/pricing.json) gives token
+// prices per million for each upstream model name:
+//   { "currency": "USD", "models": { "gpt-4o-mini": { "input": 0.15, "output": 0.60 } } }
+// Each request's cost is worked out with the prices in force when it ran and stored with it.
+// Models without a price (local ones, typically) have no cost rather than a cost of zero.
+
+#[derive(Deserialize)]
+struct Pricing {
+    #[serde(default = "default_currency")]
+    currency: String,
+    #[serde(default)]
+    models: HashMap<String, ModelPrice>,
+}
+
+#[derive(Deserialize)]
+struct ModelPrice {
+    // Per million tokens.
+    #[serde(default)]
+    input: f64,
+    #[serde(default)]
+    output: f64,
+}
+
+fn default_currency() -> String {
+    "USD".into()
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Self {
+            currency: default_currency(),
+            models: HashMap::new(),
+        }
+    }
+}
+
+impl Pricing {
+    fn from_env() -> Self {
+        let path =
+            crate::config::var("MODEL_PRICING").unwrap_or_else(|_| "data/pricing.json".into());
+        let Ok(raw) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&raw).unwrap_or_else(|err| {
+            log!("failed to parse {path}: {err}");
+            Self::default()
+        })
+    }
+
+    fn cost(&self, model: &str, prompt_tokens: u64, completion_tokens: u64) -> Option<f64> {
+        let price = self.models.get(model)?;
+        Some((prompt_tokens as f64 * price.input + completion_tokens as f64 * price.output) / 1e6)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageRecord {
+    pub at: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub conversation_id: Option<String>,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub tool_calls: u64,
+    pub wall_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+pub struct UsageLog {
+    records: RwLock<Vec<UsageRecord>>,
+    pricing: Pricing,
+    path: Option<PathBuf>,
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl UsageLog {
+    pub fn from_env() -> Self {
+        let path = match crate::config::var("USAGE_LOG") {
+            Ok(p) if p.trim().is_empty() => None,
+            Ok(p) => Some(PathBuf::from(p)),
+            Err(_) => Some(PathBuf::from("data/usage.jsonl")),
+        };
+        let mut records = Vec::new();
+        if let Some(raw) = path.as_ref().and_then(|p| std::fs::read_to_string(p).ok()) {
+            let mut skipped = 0;
+            for line in raw.lines().filter(|l| !l.trim().is_empty()) {
+                match serde_json::from_str(line) {
+                    Ok(record) => records.push(record),
+                    Err(_) => skipped += 1,
+                }
+            }
+            if skipped > 0 {
+                log!("usage log: skipped {skipped} unreadable lines");
+            }
+        }
+        Self {
+            records: RwLock::new(records),
+            pricing: Pricing::from_env(),
+            path,
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    async fn append(&self, record: UsageRecord) -> anyhow::Result<()> {
+        let line = serde_json::to_string(&record)? + "\n";
+        self.records.write().unwrap().push(record);
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let _guard = self.write_lock.lock().await;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    pub fn records(&self) -> Vec<UsageRecord> {
+        self.records.read().unwrap().clone()
+    }
+
+    // One user's usage in the day or month whose timestamps start with `period`.
+    // `user` None counts every chat; Some(None) only the chats without a user.
+    pub fn period_totals(&self, user: Option<Option<&str>>, period: &str) -> PeriodTotals {
+        let mut totals = PeriodTotals::default();
+        for record in self.records.read().unwrap().iter() {
+            if user.is_none_or(|user| record.user.as_deref() == user)
+                && record.at.starts_with(period)
+            {
+                totals.requests += 1;
+                totals.tokens += record.prompt_tokens + record.completion_tokens;
+                totals.cost += record.cost.unwrap_or(0.0);
+            }
+        }
+        totals
+    }
+}
+
+#[derive(Default)]
+pub struct PeriodTotals {
+    pub requests: u64,
+    pub tokens: u64,
+    pub cost: f64,
+}
+
+// ---------- Tracking a chat ----------
+
+// Collects one chat's usage as it streams and logs it when dropped, so chats that end early
+// (errors, blocked output, a closed browser tab) are counted too.
+pub struct UsageTracker {
+    log: Arc<UsageLog>,
+    record: UsageRecord,
+    started: Instant,
+    // Counts for the round in progress: (prompt, completion, from `usage`).
+    round: Option<(u64, u64, bool)>,
+}
+
+impl UsageTracker {
+    pub fn start(
+        log: &Arc<UsageLog>,
+        user: Option<String>,
+        conversation_id: Option<String>,
+        model: &str,
+    ) -> Self {
+        Self {
+            log: Arc::clone(log),
+            record: UsageRecord {
+                at: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                user,
+                conversation_id,
+                model: model.to_string(),
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                tool_calls: 0,
+                wall_ms: 0,
+                cost: None,
+            },
+            started: Instant::now(),
+            round: None,
+        }
+    }
+
+    pub fn set_model(&mut self, model: &str) {
+        self.record.model = model.to_string();
+    }
+
+    // Looks for counts in one streamed chunk. `usage` wins over `timings` when a server sends
+    // both, possibly in different chunks of the same round.
+    pub fn observe_chunk(&mut self, chunk: &serde_json::Value) {
+        let count = |v: &serde_json::Value| v.as_u64().unwrap_or(0);
+        let usage = &chunk["usage"];
+        if usage.is_object() {
+            self.round = Some((
+                count(&usage["prompt_tokens"]),
+                count(&usage["completion_tokens"]),
+                true,
+            ));
+            return;
+        }
+        let timings = &chunk["timings"];
+        if timings.is_object() && !self.round.is_some_and(|(_, _, from_usage)| from_usage) {
+            self.round = Some((
+                count(&timings["prompt_n"]),
+                count(&timings["predicted_n"]),
+                false,
+            ));
+        }
+    }
+
+    // Adds the counts of the round that just ended to the chat's totals.
+    pub fn end_round(&mut self) {
+        if let Some((prompt, completion, _)) = self.round.take() {
+            self.record.prompt_tokens += prompt;
+            self.record.completion_tokens += completion;
+        }
+    }
+
+    pub fn add_tool_calls(&mut self, calls: usize) {
+        self.record.tool_calls += calls as u64;
+    }
+
+    fn finish_record(&mut self) -> UsageRecord {
+        self.end_round();
+        let mut record = self.record.clone();
+        record.wall_ms = self.started.elapsed().as_millis() as u64;
+        record.cost = self.log.pricing.cost(
+            &record.model,
+            record.prompt_tokens,
+            record.completion_tokens,
+        );
+        record
+    }
+
+    // Payload of the `usage` event sent as a chat finishes.
+    pub fn event_payload(&mut self) -> serde_json::Value {
+        let record = self.finish_record();
+        serde_json::json!({
+            "model": record.model,
+            "prompt_tokens": record.prompt_tokens,
+            "completion_tokens": record.completion_tokens,
+            "total_tokens": record.prompt_tokens + record.completion_tokens,
+            "tool_calls": record.tool_calls,
+            "wall_ms": record.wall_ms,
+            "cost": record.cost,
+            "currency": record.cost.map(|_| &self.log.pricing.currency),
+        })
+    }
+}
+
+impl Drop for UsageTracker {
+    fn drop(&mut self) {
+        let record = self.finish_record();
+        let log = Arc::clone(&self.log);
+        tokio::spawn(async move {
+            if let Err(err) = log.append(record).await {
+                log!("failed to write usage log: {err:?}");
+            }
+        });
+    }
+}
+
+// ---------- Query ----------
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    // YYYY-MM-DD (whole day, UTC) or an RFC 3339 timestamp; both ends are inclusive.
+    #[serde(default)]
+    from: Option<String>,
+    #[serde(default)]
+    to: Option<String>,
+    // user, conversation, model, day or month.
+    #[serde(default)]
+    group_by: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+}
+
+#[derive(Default, Serialize)]
+struct Totals {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    total_tokens: u64,
+    tool_calls: u64,
+    wall_ms: u64,
+    cost: f64,
+    // Requests on models without a price, which `cost` leaves out.
+    unpriced_requests: u64,
+}
+
+impl Totals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.prompt_tokens += record.prompt_tokens;
+        self.completion_tokens += record.completion_tokens;
+        self.total_tokens += record.prompt_tokens + record.completion_tokens;
+        self.tool_calls += record.tool_calls;
+        self.wall_ms += record.wall_ms;
+        match record.cost {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced_requests += 1,
+        }
+    }
+}
+
+pub fn parse_bound(raw: &str, end_of_day: bool) -> Result<DateTime<Utc>, String> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .map_err(|_| format!("invalid date {raw:?}: use YYYY-MM-DD or RFC 3339"))?;
+    let time = if end_of_day {
+        date.and_hms_opt(23, 59, 59)
+    } else {
+        date.and_hms_opt(0, 0, 0)
+    };
+    Ok(time.expect("valid time").and_utc())
+}
+
+pub async fn usage_handler(
+    State(state): State<Arc<AppState>>,
+    identity: Option<Extension<Identity>>,
+    Query(mut query): Query<UsageQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    // Signed-in users only see their own usage; admins see everyone's.
+    if let Some(Extension(identity)) = identity
+        && identity.role != Role::Admin
+    {
+        query.user = Some(identity.username);
+    }
+    let bad_request = |msg: String| (StatusCode::BAD_REQUEST, msg);
+    let from = query
+        .from
+        .as_deref()
+        .map(|raw| parse_bound(raw, false))
+        .transpose()
+        .map_err(bad_request)?;
+    let to = query
+        .to
+        .as_deref()
+        .map(|raw| parse_bound(raw, true))
+        .transpose()
+        .map_err(bad_request)?;
+    let group_key: Option<fn(&UsageRecord) -> Option<String>> = match query.group_by.as_deref() {
+        None => None,
+        Some("user") => Some(|r| r.user.clone()),
+        Some("conversation") => Some(|r| r.conversation_id.clone()),
+        Some("model") => Some(|r| Some(r.model.clone())),
+        Some("day") => Some(|r| r.at.get(..10).map(String::from)),
+        Some("month") => Some(|r| r.at.get(..7).map(String::from)),
+        Some(other) => {
+            return Err(bad_request(format!(
+                "unknown group_by {other:?}: use user, conversation, model, day or month"
+            )));
+        }
+    };
+
+    let mut totals = Totals::default();
+    let mut groups: BTreeMap<Option<String>, Totals> = BTreeMap::new();
+    for record in state.usage.records() {
+        let Ok(at) = DateTime::parse_from_rfc3339(&record.at) else {
+            continue;
+        };
+        let in_range = from.is_none_or(|from| at >= from) && to.is_none_or(|to| at <= to);
+        let matches = |filter: &Option<String>, value: Option<&str>| {
+            filter.as_deref().is_none_or(|f| value == Some(f))
+        };
+        if !in_range
+            || !matches(&query.user, record.user.as_deref())
+            || !matches(&query.conversation_id, record.conversation_id.as_deref())
+            || !matches(&query.model, Some(&record.model))
+        {
+            continue;
+        }
+        totals.add(&record);
+        if let Some(key) = group_key {
+            groups.entry(key(&record)).or_default().add(&record);
+        }
+    }
+
+    let mut groups: Vec<(Option<String>, Totals)> = groups.into_iter().collect();
+    // Time groups read best in order; the others with the heaviest users first.
+    if !matches!(query.group_by.as_deref(), Some("day" | "month")) {
+        groups.sort_by_key(|(_, totals)| std::cmp::Reverse(totals.wall_ms));
+    }
+    let groups: Vec<serde_json::Value> = groups
+        .into_iter()
+        .map(|(key, totals)| {
+            let mut group = serde_json::to_value(totals).unwrap_or_default();
+            group["key"] = key.into();
+            group
+        })
+        .collect();
+    Ok(Json(serde_json::json!({
+        "from": from.map(|t| t.to_rfc3339()),
+        "to": to.map(|t| t.to_rfc3339()),
+        "group_by": query.group_by,
+        "currency": state.usage.pricing.currency,
+        "totals": totals,
+        "groups": groups,
+    })))
+}
@@ -0,0 +1,549 @@
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+};
+use axum::{
+    Extension, Json,
+    extract::{Path, Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::AppState;
+use crate::oidc;
+use crate::rbac::{self, Role};
+
+// Accounts, on with ACCOUNTS=1. Every /api route except /api/auth/* then needs a session: the
+// `chat_llama_session` cookie set by login, or the same token as `Authorization: Bearer`.
+// The signed-in user is who chats, usage and quotas are recorded against (a request's own
+// `user` is ignored), /api/usage and /api/usage/quota only show that user, and their saved
+// settings fill in language, persona, user_name and location when a chat leaves them out.
+// Conversations themselves stay in the browser. Accounts, OIDC users and API keys have roles;
+// see rbac.rs.
+//   USERS_PATH          account file (default data/users.json), passwords hashed with argon2
+//   ALLOW_REGISTRATION  let anyone create an account; the first account can always be made
+//   SESSION_TTL_HOURS   how long a login lasts (default 720); sessions end on restart
+//   SESSION_COOKIE_SECURE=1 marks the cookie Secure, for servers behind HTTPS
+
+const SESSION_COOKIE: &str = "chat_llama_session";
+const MIN_PASSWORD_CHARS: usize = 8;
+
+// ---------- Store ----------
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UserSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct User {
+    username: String,
+    password_hash: String,
+    created_at: String,
+    #[serde(default)]
+    role: Role,
+    #[serde(default)]
+    settings: UserSettings,
+}
+
+#[derive(Default, Deserialize)]
+struct UserFile {
+    users: Vec<User>,
+}
+
+struct Session {
+    username: String,
+    expires: Instant,
+}
+
+// Who a request is made by (an account, an OIDC token's user or an API key); added to the
+// request extensions by `require_session`.
+#[derive(Clone)]
+pub struct Identity {
+    pub username: String,
+    pub role: Role,
+}
+
+pub struct UserStore {
+    users: RwLock<Vec<User>>,
+    sessions: RwLock<HashMap<String, Session>>,
+    path: Option<PathBuf>,
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl UserStore {
+    pub fn from_env() -> Self {
+        let path = match crate::config::var("USERS_PATH") {
+            Ok(p) if p.trim().is_empty() => None,
+            Ok(p) => Some(PathBuf::from(p)),
+            Err(_) => Some(PathBuf::from("data/users.json")),
+        };
+        let file: UserFile = path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|raw| match serde_json::from_str(&raw) {
+                Ok(file) => Some(file),
+                Err(err) => {
+                    log!("failed to parse users file: {err:?}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            users: RwLock::new(file.users),
+            sessions: RwLock::new(HashMap::new()),
+            path,
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    async fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let _guard = self.write_lock.lock().await;
+        let json = {
+            let users = self.users.read().unwrap();
+            serde_json::to_vec_pretty(&serde_json::json!({ "users": &*users }))?
+        };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.users.read().unwrap().is_empty()
+    }
+
+    pub fn settings(&self, username: &str) -> Option<UserSettings> {
+        self.users
+            .read()
+            .unwrap()
+            .iter()
+            .find(|u| u.username == username)
+            .map(|u| u.settings.clone())
+    }
+
+    fn role(&self, username: &str) -> Option<Role> {
+        self.users
+            .read()
+            .unwrap()
+            .iter()
+            .find(|u| u.username == username)
+            .map(|u| u.role)
+    }
+
+    // The first account becomes the admin.
+    async fn register(&self, username: &str, password: &str) -> Result<(), (StatusCode, String)> {
+        let hash = hash_password(password.to_string()).await?;
+        {
+            let mut users = self.users.write().unwrap();
+            if users
+                .iter()
+                .any(|u| u.username.eq_ignore_ascii_case(username))
+            {
+                return Err((StatusCode::CONFLICT, "username is taken".into()));
+            }
+            let role = if users.is_empty() {
+                Role::Admin
+            } else {
+                Role::User
+            };
+            users.push(User {
+                username: username.to_string(),
+                password_hash: hash,
+                created_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                role,
+                settings: UserSettings::default(),
+            });
+        }
+        self.persist().await.map_err(internal("user save"))
+    }
+
+    // The account's username (as stored) when the password matches. Unknown usernames are
+    // checked against DUMMY_HASH, so they take as long to refuse as a wrong password.
+    async fn verify(&self, username: &str, password: &str) -> Option<String> {
+        let (stored, hash) = {
+            let users = self.users.read().unwrap();
+            match users
+                .iter()
+                .find(|u| u.username.eq_ignore_ascii_case(username))
+            {
+                Some(user) => (Some(user.username.clone()), user.password_hash.clone()),
+                None => (None, DUMMY_HASH.to_string()),
+            }
+        };
+        let password = password.to_string();
+        let matches = tokio::task::spawn_blocking(move || {
+            PasswordHash::new(&hash).is_ok_and(|h| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &h)
+                    .is_ok()
+            })
+        })
+        .await
+        .unwrap_or(false);
+        stored.filter(|_| matches)
+    }
+
+    // Users signed in through OIDC get an entry without a password the first time they save.
+    async fn update_settings(&self, username: &str, settings: UserSettings) -> anyhow::Result<()> {
+        {
+            let mut users = self.users.write().unwrap();
+            match users.iter_mut().find(|u| u.username == username) {
+                Some(user) => user.settings = settings,
+                None => users.push(User {
+                    username: username.to_string(),
+                    password_hash: String::new(),
+                    created_at: chrono::Utc::now()
+                        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                    role: Role::default(),
+                    settings,
+                }),
+            }
+        }
+        self.persist().await
+    }
+
+    async fn set_role(&self, username: &str, role: Role) -> anyhow::Result<bool> {
+        {
+            let mut users = self.users.write().unwrap();
+            let Some(user) = users.iter_mut().find(|u| u.username == username) else {
+                return Ok(false);
+            };
+            user.role = role;
+        }
+        self.persist().await?;
+        Ok(true)
+    }
+
+    fn start_session(&self, username: String) -> String {
+        let token = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let mut sessions = self.sessions.write().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, s| s.expires > now);
+        sessions.insert(
+            token.clone(),
+            Session {
+                username,
+                expires: now + session_ttl(),
+            },
+        );
+        token
+    }
+
+    fn session_user(&self, token: &str) -> Option<String> {
+        let sessions = self.sessions.read().unwrap();
+        let session = sessions.get(token)?;
+        (session.expires > Instant::now()).then(|| session.username.clone())
+    }
+
+    fn end_session(&self, token: &str) {
+        self.sessions.write().unwrap().remove(token);
+    }
+}
+
+fn session_ttl() -> Duration {
+    let hours = crate::config::var("SESSION_TTL_HOURS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(720u64);
+    Duration::from_secs(hours * 3600)
+}
+
+// An argon2 hash with the default parameters, of a password no account has.
+const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$Y2hhdC1sbGFtYS1kdW1teQ$FidjgFkpOZpfRKnZx7ueyZhTxlTimM59b8eLUzwtFaQ";
+
+async fn hash_password(password: String) -> Result<String, (StatusCode, String)> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::encode_b64(uuid::Uuid::new_v4().as_bytes())?;
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|h| h.to_string())
+    })
+    .await
+    .map_err(|err| anyhow::anyhow!(err))
+    .and_then(|hashed| hashed.map_err(|err| anyhow::anyhow!("{err}")))
+    .map_err(internal("password hashing"))
+}
+
+fn internal(what: &'static str) -> impl Fn(anyhow::Error) -> (StatusCode, String) {
+    move |err| {
+        log!("{what} failed: {err:?}");
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("{what} failed"))
+    }
+}
+
+// ---------- Middleware ----------
+
+// The session token from the cookie or an Authorization: Bearer header.
+fn session_token(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let cookie = || {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == SESSION_COOKIE)
+            .map(|(_, value)| value)
+    };
+    bearer.or_else(cookie).map(|t| t.trim().to_string())
+}
+
+// Whether /api needs a signed-in caller at all.
+pub fn sign_in_enabled(state: &AppState) -> bool {
+    state.accounts || state.oidc.is_some() || state.roles.has_api_keys()
+}
+
+// The caller behind a session, API key or OIDC token; None when the request carries none.
+// An OIDC token that fails validation is an error rather than an anonymous request.
+pub async fn identify(state: &AppState, headers: &HeaderMap) -> Result<Option<Identity>, Response> {
+    let Some(token) = session_token(headers) else {
+        return Ok(None);
+    };
+    if let Some(username) = state.users.session_user(&token) {
+        let role = state.users.role(&username).unwrap_or_default();
+        return Ok(Some(Identity { username, role }));
+    }
+    if let Some((username, role)) = state.roles.api_key(&token) {
+        return Ok(Some(Identity { username, role }));
+    }
+    if let Some(oidc) = &state.oidc
+        && oidc::looks_like_jwt(&token)
+    {
+        return match oidc.verify(&token).await {
+            Ok((username, role)) => Ok(Some(Identity { username, role })),
+            Err(err) => {
+                log!("rejected bearer token: {err:?}");
+                Err((StatusCode::UNAUTHORIZED, "invalid token").into_response())
+            }
+        };
+    }
+    Ok(None)
+}
+
+pub async fn require_session(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    if !sign_in_enabled(&state) {
+        return next.run(req).await;
+    }
+    let identity = match identify(&state, req.headers()).await {
+        Ok(Some(identity)) => identity,
+        Ok(None) => return (StatusCode::UNAUTHORIZED, "sign in required").into_response(),
+        Err(response) => return response,
+    };
+    if identity.role == Role::Readonly && !rbac::readonly_allows(req.method(), req.uri().path()) {
+        return (StatusCode::FORBIDDEN, "read-only access").into_response();
+    }
+    req.extensions_mut().insert(identity);
+    next.run(req).await
+}
+
+// ---------- HTTP handlers ----------
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    username: String,
+    password: String,
+}
+
+fn session_cookie(token: &str, max_age: u64) -> String {
+    let secure = if crate::env_flag("SESSION_COOKIE_SECURE") {
+        "; Secure"
+    } else {
+        ""
+    };
+    format!("{SESSION_COOKIE}={token}; Path=/; HttpOnly; SameSite=Lax; Max-Age={max_age}{secure}")
+}
+
+fn signed_in(state: &AppState, username: String) -> Response {
+    let token = state.users.start_session(username.clone());
+    (
+        [(
+            header::SET_COOKIE,
+            session_cookie(&token, session_ttl().as_secs()),
+        )],
+        Json(serde_json::json!({ "username": username, "token": token })),
+    )
+        .into_response()
+}
+
+fn accounts_enabled(state: &AppState) -> Result<(), (StatusCode, String)> {
+    if state.accounts {
+        Ok(())
+    } else {
+        Err((StatusCode::NOT_FOUND, "accounts are disabled".into()))
+    }
+}
+
+pub async fn register_handler(
+    State(state): State<Arc<AppState>>,
+    Json(creds): Json<Credentials>,
+) -> Result<Response, (StatusCode, String)> {
+    accounts_enabled(&state)?;
+    if !state.users.is_empty() && !crate::env_flag("ALLOW_REGISTRATION") {
+        return Err((StatusCode::FORBIDDEN, "registration is closed".into()));
+    }
+    let username = creds.username.trim();
+    let valid_name = (1..=64).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '@'));
+    if !valid_name {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "username must be 1-64 letters, digits or . _ - @".into(),
+        ));
+    }
+    if creds.password.chars().count() < MIN_PASSWORD_CHARS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("password must be at least {MIN_PASSWORD_CHARS} characters"),
+        ));
+    }
+    state.users.register(username, &creds.password).await?;
+    let mut response = signed_in(&state, username.to_string());
+    *response.status_mut() = StatusCode::CREATED;
+    Ok(response)
+}
+
+pub async fn login_handler(
+    State(state): State<Arc<AppState>>,
+    Json(creds): Json<Credentials>,
+) -> Result<Response, (StatusCode, String)> {
+    accounts_enabled(&state)?;
+    match state
+        .users
+        .verify(creds.username.trim(), &creds.password)
+        .await
+    {
+        Some(username) => Ok(signed_in(&state, username)),
+        None => Err((
+            StatusCode::UNAUTHORIZED,
+            "wrong username or password".into(),
+        )),
+    }
+}
+
+pub async fn logout_handler(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if let Some(token) = session_token(&headers) {
+        state.users.end_session(&token);
+    }
+    (
+        StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, session_cookie("", 0))],
+    )
+        .into_response()
+}
+
+// Identity is only there while accounts are on.
+fn signed_in_user(identity: Option<Extension<Identity>>) -> Result<Identity, (StatusCode, String)> {
+    identity
+        .map(|Extension(identity)| identity)
+        .ok_or((StatusCode::NOT_FOUND, "sign-in is not enabled".into()))
+}
+
+pub async fn me_handler(
+    State(state): State<Arc<AppState>>,
+    identity: Option<Extension<Identity>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let identity = signed_in_user(identity)?;
+    Ok(Json(serde_json::json!({
+        "username": identity.username,
+        "role": identity.role,
+        "settings": state.users.settings(&identity.username).unwrap_or_default(),
+    })))
+}
+
+pub async fn update_settings_handler(
+    State(state): State<Arc<AppState>>,
+    identity: Option<Extension<Identity>>,
+    Json(settings): Json<UserSettings>,
+) -> Result<Json<UserSettings>, (StatusCode, String)> {
+    let identity = signed_in_user(identity)?;
+    if let Some(persona) = &settings.persona
+        && state.personas.get(persona).is_none()
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("unknown persona: {persona}"),
+        ));
+    }
+    state
+        .locales
+        .resolve(settings.language.as_deref(), None)
+        .map_err(|msg| (StatusCode::BAD_REQUEST, msg))?;
+    state
+        .users
+        .update_settings(&identity.username, settings.clone())
+        .await
+        .map_err(internal("settings save"))?;
+    Ok(Json(settings))
+}
+
+// ---------- Admin handlers ----------
+
+pub async fn list_users_handler(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let users = state.users.users.read().unwrap();
+    Json(serde_json::json!(
+        users
+            .iter()
+            .map(|u| serde_json::json!({
+                "username": u.username,
+                "role": u.role,
+                "created_at": u.created_at,
+                // Entries without a password belong to OIDC users who saved settings.
+                "password_login": !u.password_hash.is_empty(),
+            }))
+            .collect::<Vec<_>>()
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct RoleBody {
+    role: Role,
+}
+
+pub async fn set_role_handler(
+    State(state): State<Arc<AppState>>,
+    Path(username): Path<String>,
+    Json(body): Json<RoleBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    match state.users.set_role(&username, body.role).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::NOT_FOUND, "account not found".into())),
+        Err(err) => Err(internal("role save")(err)),
+    }
+}